@@ -1,34 +1,146 @@
 #![no_std]
 
+// [Transport] 所有事件共用一个 RingBuf (或 perf 降级模式下的一个 PerfEventArray)，
+// 每条记录的第一个字段 kind 标明类型，用户态据此分发
+pub const EVENT_PROCESS: u32 = 1;
+pub const EVENT_TCP: u32 = 2;
+pub const EVENT_FLOW: u32 = 3;
+
+// [Time] 每条记录都以 kind(4) + pid(4) + timestamp_ns(8) 开头，
+// 用户态不解析具体类型就能取出时间戳，在重排序缓冲区中按内核时间合并各 CPU 的事件
+pub const RECORD_TIMESTAMP_OFFSET: usize = 8;
+
 // 使用 #[repr(C)] 确保内存布局与 C 语言结构体一致
 // 这是 eBPF 内核态与用户态进行二进制数据交换的基础
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub struct ProcessEvent {
-    pub pid: u32,       // 进程 ID
-    pub cgroup_id: u64, // Cgroup ID，用于关联 K8s Pod (如: /kubepods/burstable/pod-uuid)
-    pub comm: [u8; 16], // 进程命令名称 (最多 16 字节，如 "nginx", "curl")
+    pub kind: u32,         // EVENT_PROCESS
+    pub pid: u32,          // 进程 ID
+    pub timestamp_ns: u64, // bpf_ktime_get_ns (CLOCK_MONOTONIC)
+    pub cgroup_id: u64,    // Cgroup ID，用于关联 K8s Pod (如: /kubepods/burstable/pod-uuid)
+    pub comm: [u8; 16],    // 进程命令名称 (最多 16 字节，如 "nginx", "curl")
 }
 
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub struct TcpEvent {
-    pub pid: u32,           // Process ID for correlation
-    pub fd: u32,            // Socket File Descriptor (syscall correlation)
-    pub cgroup_id: u64,     // 关联的 Pod Cgroup ID
-    pub saddr: u32,         // 源 IPv4 地址 (大端序)
-    pub daddr: u32,         // 目的 IPv4 地址 (大端序)
-    pub sport: u16,         // 源端口
-    pub dport: u16,         // 目的端口
-    pub family: u16,        // 协议族 (AF_INET = 2)
-    pub direction: u8,      // 数据流向: 0=Connect(出向), 1=Accept(入向), 3=Data(数据传输)
-    pub data_len: u32,      // 数据包载荷长度 (仅在 Data 事件有效)
-    pub payload: [u8; 128], // L7 应用层数据前缀 (用于解析 HTTP 方法和 URL)
+    pub kind: u32,         // EVENT_TCP
+    pub pid: u32,          // Process ID for correlation
+    pub timestamp_ns: u64, // bpf_ktime_get_ns: write/sendto 为进入系统调用时，read/recvfrom 为返回时
+    pub fd: u32,           // Socket File Descriptor (syscall correlation)
+    pub cgroup_id: u64,    // 关联的 Pod Cgroup ID
+    pub comm: [u8; 16],    // 触发事件的进程命令名称
+    pub saddr: [u8; 16],   // 源地址 (网络序，IPv4 以 IPv4-mapped ::ffff:a.b.c.d 存放)
+    pub daddr: [u8; 16],   // 目的地址 (同上)
+    pub sport: u16,        // 源端口
+    pub dport: u16,        // 目的端口
+    pub family: u16,       // 协议族 (AF_INET = 2, AF_INET6 = 10)
+    pub direction: u8,     // 数据流向: 0=Connect(出向), 1=Accept(入向), 2=TX(发送), 3=RX(接收)
+    pub data_len: u32,     // 系统调用实际传输的字节数 (仅在 Data 事件有效)
+    pub captured_len: u32, // 实际拷贝的载荷字节数，小于 data_len 说明载荷被截断
+}
+
+// [Capture] 单次最多拷贝的载荷字节数 (用户态 capture.payload_len 的上限)
+pub const MAX_PAYLOAD_LEN: usize = 8192;
+
+// 内核态组装 TCP 数据事件用的暂存区: 固定头部 + 载荷。
+// 8KB 远超 BPF 512 字节的栈限制，所以放在 PerCpuArray 中，每个 CPU 一份。
+// 记录按实际拷贝的字节数变长发送: 长度 = size_of::<TcpEvent>() + captured_len。
+// payload 只有前 MAX_PAYLOAD_LEN 字节会被使用，后一半是给 verifier 的余量:
+// 逐段拷贝 iovec 时写入偏移和长度分别有界，verifier 要求两者上界之和也落在数组内
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct TcpRecord {
+    pub event: TcpEvent,
+    pub payload: [u8; MAX_PAYLOAD_LEN * 2],
+}
+
+// [Conntrack] 内核态连接表 CONNECTIONS 的 Key/Value
+// write/read 等系统调用只有 FD，五元组由 connect/accept 时写入此表，数据事件在内核中直接查表补全
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ConnKey {
+    pub tgid: u32, // 进程 ID (FD 表属于进程，同一进程的线程共享)
+    pub fd: u32,   // Socket File Descriptor
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ConnInfo {
+    pub saddr: [u8; 16], // 本端地址 (网络序，IPv4 以 IPv4-mapped 存放)
+    pub daddr: [u8; 16], // 对端地址 (同上)
+    pub sport: u16,      // 本端端口 (大端序)
+    pub dport: u16,      // 对端端口 (大端序)
+    pub family: u16,     // 协议族 (AF_INET = 2, AF_INET6 = 10)
+    pub _pad: u16,
+    pub start_ns: u64,     // 连接建立时间 (bpf_ktime_get_ns，CLOCK_MONOTONIC)
+    pub bytes_sent: u64,   // 本端发送字节数 (write/sendto)
+    pub bytes_recv: u64,   // 本端接收字节数 (read/recvfrom)
+    pub packets_sent: u32, // 发送次数 (每次系统调用计一次)
+    pub packets_recv: u32, // 接收次数
+}
+
+// [Conntrack] 连接关闭事件，携带内核中累计的流量统计
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct FlowEvent {
+    pub kind: u32,         // EVENT_FLOW
+    pub pid: u32,          // 连接所属进程 (ConnKey.tgid)
+    pub timestamp_ns: u64, // 关闭时刻 (bpf_ktime_get_ns)
+    pub fd: u32,           // 连接所属 FD (ConnKey.fd)
+    pub cgroup_id: u64,    // 触发关闭的当前任务，仅 reason = 1 时可靠
+    pub comm: [u8; 16],    // 同上
+    pub conn: ConnInfo,    // 五元组 + 流量统计
+    pub duration_ns: u64,  // 关闭时刻 - start_ns
+    pub reason: u8,        // 1 = close() 系统调用, 2 = TCP 状态变为 TCP_CLOSE
+}
+
+pub const AF_INET: u16 = 2;
+pub const AF_INET6: u16 = 10;
+
+// [IPv6] 所有地址统一用 16 字节表示，IPv4 转为 IPv4-mapped (::ffff:a.b.c.d)。
+// 这样 IPv4、IPv6 以及双栈 socket 上的 IPv4 连接 (内核中本来就是 mapped 地址) 共用同一套 Key。
+// addr 为内核中的原始值 (网络序 __be32)
+#[inline(always)]
+pub fn ipv4_mapped(addr: u32) -> [u8; 16] {
+    let v4 = addr.to_ne_bytes();
+    [
+        0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, v4[0], v4[1], v4[2], v4[3],
+    ]
+}
+
+// __be32[4] (如 bpf_sock_ops.remote_ip6) -> 16 字节
+#[inline(always)]
+pub fn ipv6_from_words(words: [u32; 4]) -> [u8; 16] {
+    let mut addr = [0u8; 16];
+    let mut i = 0;
+    while i < 4 {
+        let w = words[i].to_ne_bytes();
+        addr[i * 4] = w[0];
+        addr[i * 4 + 1] = w[1];
+        addr[i * 4 + 2] = w[2];
+        addr[i * 4 + 3] = w[3];
+        i += 1;
+    }
+    addr
 }
 
+const _: () = assert!(
+    core::mem::offset_of!(ProcessEvent, timestamp_ns) == RECORD_TIMESTAMP_OFFSET
+        && core::mem::offset_of!(TcpEvent, timestamp_ns) == RECORD_TIMESTAMP_OFFSET
+        && core::mem::offset_of!(FlowEvent, timestamp_ns) == RECORD_TIMESTAMP_OFFSET
+);
+
 // 只有在用户态 (feature = "user") 编译时，才实现 aya::Pod trait
 // 这使得 aya 能够安全地从字节数组中转换出结构体
 #[cfg(feature = "user")]
 unsafe impl aya::Pod for ProcessEvent {}
 #[cfg(feature = "user")]
 unsafe impl aya::Pod for TcpEvent {}
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for ConnKey {}
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for ConnInfo {}
+#[cfg(feature = "user")]
+unsafe impl aya::Pod for FlowEvent {}
@@ -2,27 +2,31 @@
 #![no_main]
 
 use aya_ebpf::{
+    EbpfContext,
     helpers::{
         bpf_get_current_cgroup_id, bpf_get_current_comm, bpf_get_current_pid_tgid,
         bpf_msg_redirect_hash, bpf_sock_hash_update, r#gen,
     },
     macros::{kprobe, kretprobe, map, sk_msg, sock_ops, tracepoint},
-    maps::{PerfEventArray, SockHash},
+    maps::{LruHashMap, PerCpuArray, SockHash},
     programs::{ProbeContext, RetProbeContext, SkMsgContext, SockOpsContext, TracePointContext},
 };
 
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub struct SockKey {
-    pub sip: u32,
-    pub dip: u32,
+    pub sip: [u8; 16], // IPv4 以 IPv4-mapped 存放，与 IPv6 共用一个 Map
+    pub dip: [u8; 16],
     pub sport: u32,
     pub dport: u32,
 }
 
 #[map]
 static INTERCEPT_MAP: SockHash<SockKey> = SockHash::with_max_entries(65535, 0);
-use masdeepflow_common::{ProcessEvent, TcpEvent};
+use masdeepflow_common::{
+    AF_INET, AF_INET6, ConnInfo, ConnKey, EVENT_FLOW, EVENT_PROCESS, EVENT_TCP, FlowEvent,
+    MAX_PAYLOAD_LEN, ProcessEvent, TcpEvent, TcpRecord, ipv4_mapped, ipv6_from_words,
+};
 
 #[inline(always)]
 fn is_infra_process(comm: &[u8; 16]) -> bool {
@@ -85,12 +89,92 @@ fn is_infra_process(comm: &[u8; 16]) -> bool {
     false
 }
 
-// 定义两个 PerfEventArray Map，用于将内核态事件高性能地传输给用户态
+// [Transport] 内核态 -> 用户态的事件通道
+// 默认使用 BPF RingBuf (5.8+): 所有 CPU 共享一块缓冲区，记录按提交顺序全局有序，按实际长度变长存放。
+// 老内核不支持 BPF_MAP_TYPE_RINGBUF，以 feature "perf" 另外编译一份使用 PerfEventArray 的对象，
+// 由用户态在启动时按内核能力选择加载哪一份。
+// RingBuf 大小可由用户态通过 EbpfLoader::set_max_entries 覆盖
+#[cfg(not(feature = "perf"))]
+#[map]
+static EVENTS: aya_ebpf::maps::RingBuf = aya_ebpf::maps::RingBuf::with_byte_size(8 << 20, 0);
+
+#[cfg(feature = "perf")]
+#[map]
+static EVENTS: aya_ebpf::maps::PerfEventByteArray = aya_ebpf::maps::PerfEventByteArray::new(0);
+
+// RingBuf 写满时被丢弃的事件数 (每 CPU 一个计数器)；perf 模式的丢失由用户态读取时统计
 #[map]
-static PROCESS_EVENTS: PerfEventArray<ProcessEvent> = PerfEventArray::new(0);
+static DROPPED: PerCpuArray<u64> = PerCpuArray::with_max_entries(1, 0);
+
+// 发送 event 的前 len 个字节 (len 必须有编译期可证明的上界，否则 verifier 拒绝)
+#[inline(always)]
+fn emit<C: EbpfContext, T>(ctx: &C, event: &T, len: usize) {
+    let len = if len > core::mem::size_of::<T>() {
+        core::mem::size_of::<T>()
+    } else {
+        len
+    };
+    let data = unsafe { core::slice::from_raw_parts(event as *const T as *const u8, len) };
+
+    #[cfg(not(feature = "perf"))]
+    {
+        let _ = ctx;
+        if EVENTS.output(data, 0).is_err()
+            && let Some(dropped) = DROPPED.get_ptr_mut(0)
+        {
+            unsafe { *dropped += 1 };
+        }
+    }
+    #[cfg(feature = "perf")]
+    EVENTS.output(ctx, data, 0);
+}
+
+// [Capture] 数据事件的载荷拷贝长度上限，由用户态按 capture.payload_len 通过 set_global 写入
+#[unsafe(no_mangle)]
+static PAYLOAD_CAPTURE_LEN: u32 = 1024;
 
+// [Capture] 组装 TCP 数据事件的暂存区 (每 CPU 一份，同一 CPU 上的探针不会并发执行)
 #[map]
-static TCP_EVENTS: PerfEventArray<TcpEvent> = PerfEventArray::new(0);
+static TCP_SCRATCH: PerCpuArray<TcpRecord> = PerCpuArray::with_max_entries(1, 0);
+
+// 本次最多拷贝的字节数: min(data_len, PAYLOAD_CAPTURE_LEN, MAX_PAYLOAD_LEN)
+#[inline(always)]
+fn capture_limit(data_len: u32) -> usize {
+    let limit = unsafe { core::ptr::read_volatile(&PAYLOAD_CAPTURE_LEN) };
+    let len = if data_len < limit { data_len } else { limit } as usize;
+    // 显式上界，verifier 据此确认拷贝不会越过暂存区
+    if len > MAX_PAYLOAD_LEN {
+        MAX_PAYLOAD_LEN
+    } else {
+        len
+    }
+}
+
+// 发送一个数据事件: 头部 + 从用户态 buf 拷贝的载荷前缀。
+// 记录按实际拷贝长度变长发送，captured_len < data_len 时用户态知道看到的只是截断的前缀
+#[inline(always)]
+fn emit_data<C: EbpfContext>(ctx: &C, event: TcpEvent, buf_ptr: u64) {
+    let record = match TCP_SCRATCH.get_ptr_mut(0) {
+        Some(ptr) => unsafe { &mut *ptr },
+        None => return,
+    };
+    let mut len = capture_limit(event.data_len);
+    if len > 0
+        && unsafe {
+            r#gen::bpf_probe_read_user(
+                record.payload.as_mut_ptr() as *mut _,
+                len as u32,
+                buf_ptr as *const _,
+            )
+        } != 0
+    {
+        // 用户态页面不在内存中等情况下读取失败，只发送头部
+        len = 0;
+    }
+    record.event = event;
+    record.event.captured_len = len as u32;
+    emit(ctx, record, core::mem::size_of::<TcpEvent>() + len);
+}
 
 // [Phase 2.5] Struct to pass context from _enter to _exit probes
 #[repr(C)]
@@ -105,6 +189,223 @@ pub struct ReadInfo {
 static FILTER_PID: aya_ebpf::maps::HashMap<u32, u8> =
     aya_ebpf::maps::HashMap::with_max_entries(16, 0);
 
+// [Conntrack] 内核态连接表: (tgid, fd) -> 五元组
+// 只有 tcp_connect / inet_csk_accept 确认是 TCP 连接后才写入，数据事件 (write/read/sendto/recvfrom) 直接查表，离开内核时就带着正确的五元组。
+// Agent 启动前已存在的连接由用户态扫描 /proc 预先填充。
+// 使用 LRU: 没有观测到 close 的陈旧条目会被自动淘汰，不会把表撑满。
+#[map]
+static CONNECTIONS: LruHashMap<ConnKey, ConnInfo> = LruHashMap::with_max_entries(65536, 0);
+
+// 进行中的 connect: pid_tgid -> fd
+// sys_enter_connect 有 FD 但不知道是不是 TCP，kprobe/tcp_connect 有五元组但没有 FD，
+// 两者在同一线程的同一次系统调用中先后触发，用线程 ID 把它们串起来。
+// UDP 或在发出 SYN 之前就失败的 connect 不会触发 tcp_connect，条目由 sys_exit_connect 清除。
+#[map]
+static CONNECT_ARGS: LruHashMap<u64, u32> = LruHashMap::with_max_entries(4096, 0);
+
+// 进行中的 accept: pid_tgid -> 新连接的五元组
+// kretprobe/inet_csk_accept 拿到新 socket 但还没有 FD，FD 要等 sys_exit_accept(4) 的返回值。
+#[map]
+static ACCEPT_ARGS: LruHashMap<u64, ConnInfo> = LruHashMap::with_max_entries(4096, 0);
+
+// [BTF] struct sock 字段偏移量 (字节)
+// 加载前由用户态解析 /sys/kernel/btf/vmlinux 后通过 EbpfLoader::set_global 写入，
+// 位于 .rodata，verifier 会把它们当作常量处理。
+#[unsafe(no_mangle)]
+static SKC_DADDR_OFF: u32 = 0;
+#[unsafe(no_mangle)]
+static SKC_RCV_SADDR_OFF: u32 = 0;
+#[unsafe(no_mangle)]
+static SKC_DPORT_OFF: u32 = 0;
+#[unsafe(no_mangle)]
+static SKC_NUM_OFF: u32 = 0;
+#[unsafe(no_mangle)]
+static SKC_FAMILY_OFF: u32 = 0;
+// 内核未开启 IPv6 (CONFIG_IPV6=n) 时 struct sock 中没有下面两个字段，SKC_HAS_IPV6 为 0，偏移量无意义
+#[unsafe(no_mangle)]
+static SKC_HAS_IPV6: u32 = 0;
+#[unsafe(no_mangle)]
+static SKC_V6_DADDR_OFF: u32 = 0;
+#[unsafe(no_mangle)]
+static SKC_V6_RCV_SADDR_OFF: u32 = 0;
+
+#[inline(always)]
+fn sock_field<T: Default>(sk: *const u8, offset: &u32) -> T {
+    let mut val = T::default();
+    unsafe {
+        // read_volatile 防止编译器把全局常量折叠成编译期的 0
+        let off = core::ptr::read_volatile(offset);
+        let _ = r#gen::bpf_probe_read_kernel(
+            &mut val as *mut _ as *mut _,
+            core::mem::size_of::<T>() as u32,
+            sk.add(off as usize) as *const _,
+        );
+    }
+    val
+}
+
+// 从 struct sock 读取新连接的 ConnInfo (地址、端口均为网络序)
+// AF_INET6 socket 读 skc_v6_*，双栈 socket 上的 IPv4 连接在这里已经是 IPv4-mapped 地址
+#[inline(always)]
+fn read_sock_tuple(sk: *const u8) -> ConnInfo {
+    let family: u16 = sock_field(sk, &SKC_FAMILY_OFF);
+    let (saddr, daddr) =
+        if family == AF_INET6 && unsafe { core::ptr::read_volatile(&SKC_HAS_IPV6) } != 0 {
+            (
+                sock_field::<[u8; 16]>(sk, &SKC_V6_RCV_SADDR_OFF),
+                sock_field::<[u8; 16]>(sk, &SKC_V6_DADDR_OFF),
+            )
+        } else {
+            (
+                ipv4_mapped(sock_field(sk, &SKC_RCV_SADDR_OFF)),
+                ipv4_mapped(sock_field(sk, &SKC_DADDR_OFF)),
+            )
+        };
+    // skc_num 是主机字节序，统一转成大端序与 skc_dport / sockaddr_in 保持一致
+    let sport: u16 = sock_field::<u16>(sk, &SKC_NUM_OFF).to_be();
+    let dport: u16 = sock_field(sk, &SKC_DPORT_OFF);
+    new_conn(family, saddr, daddr, sport, dport)
+}
+
+// 五元组反查 (tgid, fd): inet_sock_set_state 只有 socket 地址和五元组，没有进程上下文
+// 仅在五元组完整 (tcp_connect / accept 之后) 时写入
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct TupleKey {
+    pub saddr: [u8; 16],
+    pub daddr: [u8; 16],
+    pub sport: u16, // 大端序
+    pub dport: u16, // 大端序
+}
+
+#[map]
+static SOCK_INDEX: LruHashMap<TupleKey, ConnKey> = LruHashMap::with_max_entries(65536, 0);
+
+// close 原因，对应 FlowEvent.reason
+const CLOSE_SYSCALL: u8 = 1;
+const CLOSE_TCP_STATE: u8 = 2;
+
+// 新建连接表条目，计数清零、记录建立时间
+#[inline(always)]
+fn new_conn(family: u16, saddr: [u8; 16], daddr: [u8; 16], sport: u16, dport: u16) -> ConnInfo {
+    ConnInfo {
+        saddr,
+        daddr,
+        sport,
+        dport,
+        family,
+        _pad: 0,
+        start_ns: unsafe { r#gen::bpf_ktime_get_ns() },
+        bytes_sent: 0,
+        bytes_recv: 0,
+        packets_sent: 0,
+        packets_recv: 0,
+    }
+}
+
+// 写入连接表，五元组完整时同时登记反查索引
+// (只看源端口: 比较 [u8; 16] 会生成 BPF 中不可用的 memcmp 调用)
+#[inline(always)]
+fn track_conn(key: &ConnKey, info: &ConnInfo) {
+    let _ = CONNECTIONS.insert(key, info, 0);
+    if info.sport != 0 {
+        let tuple = TupleKey {
+            saddr: info.saddr,
+            daddr: info.daddr,
+            sport: info.sport,
+            dport: info.dport,
+        };
+        let _ = SOCK_INDEX.insert(&tuple, key, 0);
+    }
+}
+
+// [Socket Filter] 数据探针只处理连接表中的 FD (tcp_connect / accept 确认的或启动时扫描 /proc 登记的 TCP socket)，
+// UDP socket、普通文件、管道、eventfd、tty 等在内核中直接丢弃，不会进入事件通道。
+// FD 关闭时由 sys_enter_close 移除条目 (始终挂载，与是否输出 FlowEvent 无关)，复用的 FD 不会误命中。
+// (fork 后由子进程继续使用的继承 socket 不在子进程的 (tgid, fd) 下，同样不会被观测)
+#[inline(always)]
+fn is_tracked_socket(tgid: u32, fd: u64) -> bool {
+    unsafe {
+        CONNECTIONS.get(&ConnKey {
+            tgid,
+            fd: fd as u32,
+        })
+    }
+    .is_some()
+}
+
+// 查表并累计流量，返回五元组；不是已跟踪的 TCP 连接时返回 None
+#[inline(always)]
+fn account_conn(tgid: u32, fd: u32, sent: u64, recv: u64) -> Option<ConnInfo> {
+    let key = ConnKey { tgid, fd };
+    match CONNECTIONS.get_ptr_mut(&key) {
+        Some(ptr) => {
+            // 同一连接被多个线程并发读写时计数可能有少量误差，可接受
+            let info = unsafe { &mut *ptr };
+            if sent > 0 {
+                info.bytes_sent += sent;
+                info.packets_sent += 1;
+            }
+            if recv > 0 {
+                info.bytes_recv += recv;
+                info.packets_recv += 1;
+            }
+            Some(*info)
+        }
+        None => None,
+    }
+}
+
+// 是否在连接关闭时发出 FlowEvent (close 探针组)，由用户态通过 set_global 写入。
+// 为 0 时 sys_enter_close 仍然挂载，只负责清理连接表
+#[unsafe(no_mangle)]
+static FLOW_EVENTS: u32 = 1;
+
+// [Conntrack] 组装 FlowEvent 的暂存区 (每 CPU 一份)。
+// FlowEvent 连同 ConnInfo 放在栈上会超出 512 字节的 BPF 栈限制 (sock_state 探针中尤其明显)
+#[map]
+static FLOW_SCRATCH: PerCpuArray<FlowEvent> = PerCpuArray::with_max_entries(1, 0);
+
+// 从连接表中移除并发出 FlowEvent；连接不存在 (已由另一条路径关闭) 时什么都不做
+#[inline(always)]
+fn close_conn<C: EbpfContext>(ctx: &C, key: &ConnKey, reason: u8) {
+    let event = match FLOW_SCRATCH.get_ptr_mut(0) {
+        Some(ptr) => unsafe { &mut *ptr },
+        None => return,
+    };
+    match unsafe { CONNECTIONS.get(key) } {
+        Some(info) => event.conn = *info,
+        None => return,
+    }
+    let _ = CONNECTIONS.remove(key);
+    let tuple = TupleKey {
+        saddr: event.conn.saddr,
+        daddr: event.conn.daddr,
+        sport: event.conn.sport,
+        dport: event.conn.dport,
+    };
+    let _ = SOCK_INDEX.remove(&tuple);
+    if unsafe { core::ptr::read_volatile(&FLOW_EVENTS) } == 0 {
+        return;
+    }
+
+    let now = unsafe { r#gen::bpf_ktime_get_ns() };
+    let start_ns = event.conn.start_ns;
+    event.kind = EVENT_FLOW;
+    event.pid = key.tgid;
+    event.timestamp_ns = now;
+    event.fd = key.fd;
+    event.cgroup_id = unsafe { bpf_get_current_cgroup_id() };
+    event.comm = bpf_get_current_comm().unwrap_or([0; 16]);
+    event.duration_ns = if start_ns > 0 && now > start_ns {
+        now - start_ns
+    } else {
+        0
+    };
+    event.reason = reason;
+    emit(ctx, event, core::mem::size_of::<FlowEvent>());
+}
+
 // --- 模块一：进程监控 (Process Monitoring) ---
 
 // 挂载点: tracepoint:sched/sched_process_exec
@@ -122,32 +423,25 @@ pub fn masdeepflow_exec(ctx: TracePointContext) -> u32 {
 
     // 构建事件结构体
     let event = ProcessEvent {
+        kind: EVENT_PROCESS,
         pid,
+        timestamp_ns: unsafe { r#gen::bpf_ktime_get_ns() },
         cgroup_id,
         comm,
     };
     // 发送事件到用户态
-    PROCESS_EVENTS.output(&ctx, &event, 0);
+    emit(&ctx, &event, core::mem::size_of::<ProcessEvent>());
     0
 }
 
 // --- 模块二：网络监控 (Network Monitoring) ---
 
-// 挂载点: kprobe/tcp_v4_connect
-// 触发时机: 进程发起 TCP 连接时 (Connect 阶段)
-// 挂载点: kprobe/tcp_v4_connect
-// 触发时机: 进程发起 TCP 连接时 (Connect 阶段)
-// 挂载点: tracepoint:syscalls/sys_enter_connect
-// 触发时机: 进程发起 connect 系统调用。相比 kprobe，这里能直接拿到 FD。
 // 挂载点: tracepoint:syscalls/sys_enter_connect
-// 触发时机: 应用程序调用 `connect` 系统调用发起 TCP 连接时。
-// 作用: 捕获连接的目标 IP、目标端口以及最重要的文件描述符 (FD)。
-//       这是唯一能将 FD 与目标地址关联起来的地方。
+// 触发时机: 应用程序调用 `connect` 系统调用时。
+// 作用: 记下 FD，等同一次系统调用中的 kprobe/tcp_connect 确认是 TCP 连接后再写入连接表。
+//       这是唯一能拿到 FD 的地方，但此时还不知道 socket 类型，不能直接写 CONNECTIONS。
 #[tracepoint]
 pub fn masdeepflow_tcp_connect(ctx: TracePointContext) -> u32 {
-    let pid = (bpf_get_current_pid_tgid() >> 32) as u32;
-    let cgroup_id = unsafe { bpf_get_current_cgroup_id() };
-
     // sys_enter_connect 的参数布局:
     // int connect(int sockfd, const struct sockaddr *addr, socklen_t addrlen);
     // 偏移量 (x86_64/arm64 通用 tracepoint 格式):
@@ -159,212 +453,209 @@ pub fn masdeepflow_tcp_connect(ctx: TracePointContext) -> u32 {
     let addr_ptr: u64 = unsafe { ctx.read_at::<u64>(24).unwrap_or(0) };
     let addr_len: u64 = unsafe { ctx.read_at::<u64>(32).unwrap_or(0) };
 
-    // 过滤: 只处理 IPv4 (sockaddr_in 的大小至少为此)
+    // 过滤: 至少要能容纳 sockaddr_in，且只处理 AF_INET / AF_INET6 (sockaddr_in6 为 28 字节)，
+    // Unix socket 等其他协议族不会经过 tcp_connect，没必要占用 CONNECT_ARGS
     if addr_len < 16 {
         return 0;
     }
-
-    // struct sockaddr_in {
-    //   short sin_family;        // 2 bytes (协议族)
-    //   ushort sin_port;         // 2 bytes (目标端口, 大端序)
-    //   struct in_addr sin_addr; // 4 bytes (目标IP, 大端序)
-    //   char sin_zero[8];        // 填充
-    // }
-
     let mut sin_family: u16 = 0;
-    let mut dport: u16 = 0;
-    let mut daddr: u32 = 0;
-
     unsafe {
-        // [步骤 1] 读取协议族 (Family)
-        // 从用户空间地址 (addr_ptr) 读取前 2 个字节
-        // 这是为了确认 socket 是否为 IPv4 (AF_INET = 2)
         let _ = r#gen::bpf_probe_read_user(
             &mut sin_family as *mut _ as *mut _,
             2,
             addr_ptr as *const _,
         );
-
-        // 过滤: 只处理 AF_INET (IPv4 = 2)
-        if sin_family != 2 {
-            return 0;
-        }
-
-        // [步骤 2] 读取目标端口 (Port)
-        // 偏移量 +2 (跳过 family)
-        // 读取 2 个字节 (u16)，注意这里是大端序 (Big Endian)
-        let _ = r#gen::bpf_probe_read_user(
-            &mut dport as *mut _ as *mut _,
-            2,
-            (addr_ptr + 2) as *const _,
-        );
-
-        // [步骤 3] 读取目标 IP (Addr)
-        // 偏移量 +4 (跳过 family 和 port)
-        // 读取 4 个字节 (u32)，即 IPv4 地址
-        let _ = r#gen::bpf_probe_read_user(
-            &mut daddr as *mut _ as *mut _,
-            4,
-            (addr_ptr + 4) as *const _,
-        );
+    }
+    if sin_family != AF_INET && !(sin_family == AF_INET6 && addr_len >= 28) {
+        return 0;
     }
 
-    let event = TcpEvent {
-        pid,
-        fd: fd as u32,
-        cgroup_id,
-        // [关键点] 为什么 source ip 是 0？
-        // 因为这是 connect 的入口点，内核还未进行路由选择和源地址绑定。
-        // 我们需要下面的 tcp_connect kprobe 来补充这个字段。
-        saddr: 0,
-        daddr,
-        sport: 0, // 源端口同理，稍后通过 kprobe 获取
-        dport,
-        family: 2,
-        direction: 0, // 0 = CONNECT 事件 (用于在用户态建立 FD 映射)
-        data_len: 0,
-        payload: [0; 128],
-    };
-    TCP_EVENTS.output(&ctx, &event, 0);
+    let _ = CONNECT_ARGS.insert(&bpf_get_current_pid_tgid(), &(fd as u32), 0);
+    0
+}
+
+// 挂载点: tracepoint:syscalls/sys_exit_connect
+// 触发时机: connect 系统调用返回时
+// 作用: 清除本次调用的 CONNECT_ARGS。TCP 的条目已被 tcp_connect 取走；
+//       UDP 或提前失败的 connect 留下的条目若不清除，会被同一线程之后由 sendto(MSG_FASTOPEN) 等
+//       非 connect 路径触发的 tcp_connect 误认领
+#[tracepoint]
+pub fn masdeepflow_connect_exit(_ctx: TracePointContext) -> u32 {
+    let _ = CONNECT_ARGS.remove(&bpf_get_current_pid_tgid());
     0
 }
 
 // 挂载点: kprobe/tcp_connect
 // 触发时机: 三次握手发送 SYN 包之前。此时内核已完成路由选择，分配了 Source IP/Port。
-// 作用: 补全 Source IP 信息。
+// 作用: 确认是 TCP 连接，把五元组写入 CONNECTIONS，并发出携带完整五元组的 CONNECT 事件。
 #[kprobe]
 pub fn masdeepflow_tcp_connect_detailed(ctx: ProbeContext) -> u32 {
     let cgroup_id = unsafe { bpf_get_current_cgroup_id() };
+    let comm = bpf_get_current_comm().unwrap_or([0; 16]);
 
     // tcp_connect(struct sock *sk)
-    let sk: *mut u8 = ctx.arg(0).unwrap_or(core::ptr::null_mut());
-
-    // offsets based on common kernel structs (check your kernel version if failing)
-    // struct sock_common {
-    //   ...
-    //   __be32 skc_daddr; // offset 0 (union) - sometimes?
-    //   __be32 skc_rcv_saddr; // offset 4 (union)
-    // }
-    // NOTE: Offsets vary by kernel. Using the ones that worked for `tcp_v4_connect` logic previously?
-    // In `tcp_v4_connect`, saddr was at offset 4.
-
-    let saddr: u32 = unsafe {
-        let mut val = 0u32;
-        let _ =
-            r#gen::bpf_probe_read_kernel(&mut val as *mut _ as *mut _, 4, sk.add(4) as *const _);
-        val
-    };
-
-    let sport: u16 = unsafe {
-        let mut val = 0u16;
-        let _ =
-            r#gen::bpf_probe_read_kernel(&mut val as *mut _ as *mut _, 2, sk.add(14) as *const _);
-        val
-    };
-
-    // Note: accessing daddr from sk directly here
-    // In many kernels skc_daddr is at offset 0 for struct sock_common?
-    // Let's rely on `tcp_v4_connect` behavior usually having daddr in external struct.
-    // But here we only have `sk`.
-    // Let's assume standard layout:
-    // skc_daddr is often at offset 0 inside checking logic?
-    // Let's try reading offset 0 for daddr.
-    let daddr: u32 = unsafe {
-        let mut val = 0u32;
-        // struct sock { struct sock_common ... }
-        // struct sock_common { union { ... skc_daddr } ... } -> offset 0 usually?
-        // CAUTION: If this is wrong, daddr might be garbage.
-        // But userspace has daddr from tracepoint. We primarily need saddr.
-        let _ = r#gen::bpf_probe_read_kernel(&mut val as *mut _ as *mut _, 4, sk as *const _);
-        val
+    let sk: *const u8 = ctx.arg(0).unwrap_or(core::ptr::null());
+
+    // 本端/对端地址与端口，偏移量由用户态从 BTF 计算
+    let info = read_sock_tuple(sk);
+
+    // 用线程 ID 找回 sys_enter_connect 记下的 FD，补全连接表
+    let pid_tgid = bpf_get_current_pid_tgid();
+    let pid = (pid_tgid >> 32) as u32;
+    let fd = match unsafe { CONNECT_ARGS.get(&pid_tgid) } {
+        Some(fd) => *fd,
+        None => 0, // 非 connect 系统调用触发 (如内核态发起的连接、TCP Fast Open)
     };
+    let _ = CONNECT_ARGS.remove(&pid_tgid);
+    if fd != 0 {
+        let key = ConnKey { tgid: pid, fd };
+        track_conn(&key, &info);
+    }
 
-    let pid = (bpf_get_current_pid_tgid() >> 32) as u32;
     let event = TcpEvent {
+        kind: EVENT_TCP,
         pid,
-        fd: 0, // Unknown in kprobe
+        timestamp_ns: unsafe { r#gen::bpf_ktime_get_ns() },
+        fd,
         cgroup_id,
-        saddr,
-        daddr,
-        sport,
-        dport: 0, // We have dport from tracepoint
-        family: 2,
-        direction: 4, // 4 = IP_INFO (Supplement)
+        comm,
+        saddr: info.saddr,
+        daddr: info.daddr,
+        sport: info.sport,
+        dport: info.dport,
+        family: info.family,
+        direction: 0, // 0 = CONNECT (五元组已完整)
         data_len: 0,
-        payload: [0; 128],
+        captured_len: 0,
     };
-    TCP_EVENTS.output(&ctx, &event, 0);
+    // 握手事件不带载荷，只发送固定部分
+    emit(&ctx, &event, core::mem::size_of::<TcpEvent>());
     0
 }
 
 // 挂载点: kretprobe/inet_csk_accept
 // 触发时机: 服务端成功 Accept 一个连接后返回时
+// 作用: 读取新 socket 的五元组，暂存到 ACCEPT_ARGS，等 sys_exit_accept 拿到 FD 后再写入连接表
 #[kretprobe]
 pub fn masdeepflow_tcp_accept(ctx: RetProbeContext) -> u32 {
-    let ret: *mut u8 = ctx.ret().unwrap_or(core::ptr::null_mut());
+    let ret: *const u8 = ctx.ret().unwrap_or(core::ptr::null());
 
     if !ret.is_null() {
-        let cgroup_id = unsafe { bpf_get_current_cgroup_id() };
         // ret 即为 struct sock *newsk
+        // Accept 时作为服务端: 本端 = 监听地址/端口，对端 = 客户端
         let sk = ret;
 
-        let daddr: u32 = unsafe {
-            let mut val = 0u32;
-            let _ = r#gen::bpf_probe_read_kernel(
-                &mut val as *mut _ as *mut _,
-                4,
-                sk.add(0) as *const _,
-            );
-            val
-        };
+        let info = read_sock_tuple(sk);
+        let _ = ACCEPT_ARGS.insert(&bpf_get_current_pid_tgid(), &info, 0);
+    }
+    0
+}
 
-        let saddr: u32 = unsafe {
-            let mut val = 0u32;
-            let _ = r#gen::bpf_probe_read_kernel(
-                &mut val as *mut _ as *mut _,
-                4,
-                sk.add(4) as *const _,
-            );
-            val
-        };
+// 挂载点: tracepoint:syscalls/sys_exit_accept 和 sys_exit_accept4
+// 触发时机: accept/accept4 系统调用返回时，返回值就是新连接的 FD
+// 作用: 把 FD 和 inet_csk_accept 暂存的五元组绑定，写入连接表，并发出 ACCEPT 事件
+#[tracepoint]
+pub fn masdeepflow_accept_exit(ctx: TracePointContext) -> u32 {
+    let pid_tgid = bpf_get_current_pid_tgid();
 
-        let dport: u16 = unsafe {
-            let mut val = 0u16;
-            let _ = r#gen::bpf_probe_read_kernel(
-                &mut val as *mut _ as *mut _,
-                2,
-                sk.add(12) as *const _,
-            );
-            val
-        };
+    let info = match unsafe { ACCEPT_ARGS.get(&pid_tgid) } {
+        Some(info) => *info,
+        None => return 0, // 非 TCP (如 Unix Socket) 的 accept
+    };
+    let _ = ACCEPT_ARGS.remove(&pid_tgid);
 
-        // Accept 时作为服务端，源端口是 Local Port (skc_num)
-        let sport: u16 = unsafe {
-            let mut val = 0u16;
-            let _ = r#gen::bpf_probe_read_kernel(
-                &mut val as *mut _ as *mut _,
-                2,
-                sk.add(14) as *const _,
-            );
-            val
-        };
+    // sys_exit_accept -> ret (新 FD) @ offset 16
+    let ret: i64 = unsafe { ctx.read_at::<i64>(16).unwrap_or(-1) };
+    if ret < 0 {
+        return 0;
+    }
+
+    let pid = (pid_tgid >> 32) as u32;
+    let key = ConnKey {
+        tgid: pid,
+        fd: ret as u32,
+    };
+    track_conn(&key, &info);
+
+    let event = TcpEvent {
+        kind: EVENT_TCP,
+        pid,
+        timestamp_ns: unsafe { r#gen::bpf_ktime_get_ns() },
+        fd: ret as u32,
+        cgroup_id: unsafe { bpf_get_current_cgroup_id() },
+        comm: bpf_get_current_comm().unwrap_or([0; 16]),
+        saddr: info.saddr,
+        daddr: info.daddr,
+        sport: info.sport,
+        dport: info.dport,
+        family: info.family,
+        direction: 1, // Accept
+        data_len: 0,
+        captured_len: 0,
+    };
+    // 握手事件不带载荷，只发送固定部分
+    emit(&ctx, &event, core::mem::size_of::<TcpEvent>());
+    0
+}
 
-        let pid = (bpf_get_current_pid_tgid() >> 32) as u32;
-        let event = TcpEvent {
-            pid,
-            fd: 0, // Not applicable for accept kretprobe
-            cgroup_id,
+// 挂载点: tracepoint:syscalls/sys_enter_close
+// 触发时机: 进程调用 close(fd) 时
+// 作用: FD 即将被回收复用，立即结束这条连接的跟踪，避免新 socket 继承旧的五元组
+#[tracepoint]
+pub fn masdeepflow_close(ctx: TracePointContext) -> u32 {
+    // sys_enter_close(unsigned int fd) -> fd @ offset 16
+    let fd: u64 = unsafe { ctx.read_at::<u64>(16).unwrap_or(0) };
+    let key = ConnKey {
+        tgid: (bpf_get_current_pid_tgid() >> 32) as u32,
+        fd: fd as u32,
+    };
+    close_conn(&ctx, &key, CLOSE_SYSCALL);
+    0
+}
+
+// 挂载点: tracepoint:sock/inet_sock_set_state
+// 触发时机: TCP 状态机迁移时 (可能在软中断中，没有可靠的进程上下文)
+// 作用: 覆盖没有 close() 的情况，如 RST、进程退出时内核回收 FD
+#[tracepoint]
+pub fn masdeepflow_sock_state(ctx: TracePointContext) -> u32 {
+    // inet_sock_set_state 的参数布局:
+    // 8: skaddr, 16: oldstate, 20: newstate, 24: sport, 26: dport (主机序),
+    // 28: family, 30: protocol, 32: saddr[4], 36: daddr[4], 40: saddr_v6[16], 56: daddr_v6[16]
+    let newstate: i32 = unsafe { ctx.read_at::<i32>(20).unwrap_or(0) };
+    let family: u16 = unsafe { ctx.read_at::<u16>(28).unwrap_or(0) };
+    let protocol: u16 = unsafe { ctx.read_at::<u16>(30).unwrap_or(0) };
+    // TCP_CLOSE = 7, IPPROTO_TCP = 6
+    if newstate != 7 || (family != AF_INET && family != AF_INET6) || protocol != 6 {
+        return 0;
+    }
+
+    // 与 read_sock_tuple 一致: AF_INET6 socket 用 v6 地址 (双栈 IPv4 连接即 mapped 地址)
+    let (saddr, daddr) = unsafe {
+        if family == AF_INET6 {
+            (
+                ctx.read_at::<[u8; 16]>(40).unwrap_or([0; 16]),
+                ctx.read_at::<[u8; 16]>(56).unwrap_or([0; 16]),
+            )
+        } else {
+            (
+                ipv4_mapped(ctx.read_at::<u32>(32).unwrap_or(0)),
+                ipv4_mapped(ctx.read_at::<u32>(36).unwrap_or(0)),
+            )
+        }
+    };
+    let tuple = unsafe {
+        TupleKey {
             saddr,
             daddr,
-            sport,
-            dport,
-            family: 2,
-            direction: 1, // Accept
-            data_len: 0,
-            payload: [0; 128],
-        };
-        TCP_EVENTS.output(&ctx, &event, 0);
-    }
+            sport: ctx.read_at::<u16>(24).unwrap_or(0).to_be(),
+            dport: ctx.read_at::<u16>(26).unwrap_or(0).to_be(),
+        }
+    };
+    let key = match unsafe { SOCK_INDEX.get(&tuple) } {
+        Some(key) => *key,
+        None => return 0,
+    };
+    close_conn(&ctx, &key, CLOSE_TCP_STATE);
     0
 }
 
@@ -375,7 +666,6 @@ pub fn masdeepflow_tcp_accept(ctx: RetProbeContext) -> u32 {
 // 相比 kprobe/tcp_sendmsg，拦截系统调用更容易直接读取用户态 buffer
 #[tracepoint]
 pub fn masdeepflow_write(ctx: TracePointContext) -> u32 {
-    let _pid = (bpf_get_current_pid_tgid() >> 32) as u32;
     let cgroup_id = unsafe { bpf_get_current_cgroup_id() };
 
     // 4. [Critical Fix] 防止自回环 (Self-Tracing Loop)
@@ -399,11 +689,7 @@ pub fn masdeepflow_write(ctx: TracePointContext) -> u32 {
     // 24: buf (8 bytes) -> 数据指针
     // 32: count (8 bytes) -> 数据长度
 
-    // [Critical Fix] Filter Stdout/Stderr (FD 0, 1, 2)
     let fd: u64 = unsafe { ctx.read_at::<u64>(16).unwrap_or(0) };
-    if fd <= 2 {
-        return 0;
-    }
 
     // 1. 获取 buffer 指针 (源数据地址)
     let buf_ptr: *const u8 = unsafe { ctx.read_at::<u64>(24).unwrap_or(0) as *const u8 };
@@ -411,37 +697,29 @@ pub fn masdeepflow_write(ctx: TracePointContext) -> u32 {
     // 2. 获取数据长度
     let count: u64 = unsafe { ctx.read_at::<u64>(32).unwrap_or(0) };
 
-    // 3. 读取用户态数据 (Payload Capture)
-    let mut payload = [0u8; 128];
-    // 只读取 min(count, 128)
-    let read_len = if count > 128 { 128 } else { count as usize };
-
-    // 使用 helper 从用户空间读取数据
-    if read_len > 0 {
-        unsafe {
-            let _ = r#gen::bpf_probe_read_user(
-                payload.as_mut_ptr() as *mut _,
-                read_len as u32,
-                buf_ptr as *const _,
-            );
-        }
-    }
-
-    let pid = (bpf_get_current_pid_tgid() >> 32) as u32;
+    // [Socket Filter] 只处理 TCP socket (stdout/stderr、文件、管道都不在连接表中)
+    // [Conntrack] 同一次查表完成过滤、流量累计和五元组补全
+    let Some(conn) = account_conn(tgid, fd as u32, count, 0) else {
+        return 0;
+    };
     let event = TcpEvent {
-        pid,
+        kind: EVENT_TCP,
+        pid: tgid,
+        timestamp_ns: unsafe { r#gen::bpf_ktime_get_ns() },
         fd: fd as u32,
         cgroup_id,
-        saddr: 0,
-        daddr: 0,
-        sport: 0,
-        dport: 0,
-        family: 2,
+        comm,
+        saddr: conn.saddr,
+        daddr: conn.daddr,
+        sport: conn.sport,
+        dport: conn.dport,
+        family: conn.family,
         direction: 2, // 2 = TX (Outgoing/Write)
         data_len: count as u32,
-        payload,
+        captured_len: 0,
     };
-    TCP_EVENTS.output(&ctx, &event, 0);
+    // 3. 读取用户态数据 (Payload Capture)，只拷贝前 PAYLOAD_CAPTURE_LEN 字节
+    emit_data(&ctx, event, buf_ptr as u64);
     0
 }
 
@@ -449,7 +727,6 @@ pub fn masdeepflow_write(ctx: TracePointContext) -> u32 {
 // 触发时机: 进程调用 sendto 系统调用发送数据时
 #[tracepoint]
 pub fn masdeepflow_sendto(ctx: TracePointContext) -> u32 {
-    let _pid = (bpf_get_current_pid_tgid() >> 32) as u32;
     let cgroup_id = unsafe { bpf_get_current_cgroup_id() };
 
     // Self-Tracing Loop Protection
@@ -470,44 +747,31 @@ pub fn masdeepflow_sendto(ctx: TracePointContext) -> u32 {
     // 24: buff
     // 32: len
 
-    // [Critical Fix] Filter Stdout/Stderr (FD 0, 1, 2)
-    // Same as write, fd is at offset 16
     let fd: u64 = unsafe { ctx.read_at::<u64>(16).unwrap_or(0) };
-    if fd <= 2 {
-        return 0;
-    }
-
     let buf_ptr: *const u8 = unsafe { ctx.read_at::<u64>(24).unwrap_or(0) as *const u8 };
     let count: u64 = unsafe { ctx.read_at::<u64>(32).unwrap_or(0) };
 
-    let mut payload = [0u8; 128];
-    let read_len = if count > 128 { 128 } else { count as usize };
-
-    if read_len > 0 {
-        unsafe {
-            let _ = r#gen::bpf_probe_read_user(
-                payload.as_mut_ptr() as *mut _,
-                read_len as u32,
-                buf_ptr as *const _,
-            );
-        }
-    }
-
-    let pid = (bpf_get_current_pid_tgid() >> 32) as u32;
+    // [Socket Filter] + [Conntrack] Same as write: 一次查表完成过滤和五元组补全
+    let Some(conn) = account_conn(tgid, fd as u32, count, 0) else {
+        return 0;
+    };
     let event = TcpEvent {
-        pid,
+        kind: EVENT_TCP,
+        pid: tgid,
+        timestamp_ns: unsafe { r#gen::bpf_ktime_get_ns() },
         fd: fd as u32,
         cgroup_id,
-        saddr: 0,
-        daddr: 0,
-        sport: 0,
-        dport: 0,
-        family: 2,
+        comm,
+        saddr: conn.saddr,
+        daddr: conn.daddr,
+        sport: conn.sport,
+        dport: conn.dport,
+        family: conn.family,
         direction: 2, // 2 = TX (Outgoing/Write) - Corrected from 3
         data_len: count as u32,
-        payload,
+        captured_len: 0,
     };
-    TCP_EVENTS.output(&ctx, &event, 0);
+    emit_data(&ctx, event, buf_ptr as u64);
     0
 }
 
@@ -515,17 +779,18 @@ pub fn masdeepflow_sendto(ctx: TracePointContext) -> u32 {
 // [难点] read/recvfrom 的数据是在系统调用返回时才填充的
 // 所以我们需要 "Enter" 探针记录参数(Buf地址)，"Exit" 探针记录返回值(读取长度)并行读取内容
 
-// 辅助 Map: 暂时存储 Enter 阶段的参数
+// 辅助 Map: 暂时存储 Enter 阶段的参数，Key 是 pid_tgid (线程粒度)。
+// 同一进程的多个线程会同时阻塞在 read 上，按 TGID 存放会互相覆盖 buf 指针和 FD。
+// 使用 LRU: 线程在 enter 与 exit 之间被杀死时留下的条目会被自动淘汰，不会占满表。
 #[map]
-static READ_ARGS: aya_ebpf::maps::HashMap<u32, ReadInfo> =
-    aya_ebpf::maps::HashMap::with_max_entries(1024, 0);
+static READ_ARGS: LruHashMap<u64, ReadInfo> = LruHashMap::with_max_entries(1024, 0);
 
 // 挂载点: tracepoint:syscalls/sys_enter_read
 // 触发时机: 调用 read 读取数据之前
 // 作用: 抢先记录 buffer 指针地址，因为 Exit 阶段拿不到这个指针了
 #[tracepoint]
 pub fn masdeepflow_read_enter(ctx: TracePointContext) -> u32 {
-    let pid = (bpf_get_current_pid_tgid() >> 32) as u32;
+    let pid_tgid = bpf_get_current_pid_tgid();
     // 自监控过滤 (Comm)
     let comm = bpf_get_current_comm().unwrap_or([0; 16]);
     if is_infra_process(&comm) {
@@ -533,7 +798,7 @@ pub fn masdeepflow_read_enter(ctx: TracePointContext) -> u32 {
     }
 
     // 自监控过滤 (PID)
-    let tgid = (bpf_get_current_pid_tgid() >> 32) as u32;
+    let tgid = (pid_tgid >> 32) as u32;
     if unsafe { FILTER_PID.get(&tgid).is_some() } {
         return 0;
     }
@@ -541,8 +806,9 @@ pub fn masdeepflow_read_enter(ctx: TracePointContext) -> u32 {
     // sys_enter_read(fd, buf, count)
     // 16: fd
     // 24: buf (指针)
+    // [Socket Filter] 非 socket 的 read 不记录参数，Exit 阶段也就不会产生事件
     let fd: u64 = unsafe { ctx.read_at::<u64>(16).unwrap_or(0) };
-    if fd <= 2 {
+    if !is_tracked_socket(tgid, fd) {
         return 0;
     }
     let buf_ptr: u64 = unsafe { ctx.read_at::<u64>(24).unwrap_or(0) };
@@ -552,8 +818,8 @@ pub fn masdeepflow_read_enter(ctx: TracePointContext) -> u32 {
             buf_ptr,
             fd: fd as u32,
         };
-        // 存入 Map，Key 是 PID。这假设同一线程的 enter/exit 是原子或顺序的
-        let _ = READ_ARGS.insert(&pid, &info, 0);
+        // 存入 Map，Key 是线程 ID: 同一线程的 enter/exit 一定成对、顺序发生
+        let _ = READ_ARGS.insert(&pid_tgid, &info, 0);
     }
     0
 }
@@ -563,15 +829,16 @@ pub fn masdeepflow_read_enter(ctx: TracePointContext) -> u32 {
 // 作用: 此时 Kernel 已经把数据写到 buffer 里了，且我们知道了实际读取的字节数 (ret)
 #[tracepoint]
 pub fn masdeepflow_read_exit(ctx: TracePointContext) -> u32 {
-    let pid = (bpf_get_current_pid_tgid() >> 32) as u32;
+    let pid_tgid = bpf_get_current_pid_tgid();
+    let pid = (pid_tgid >> 32) as u32;
 
     // 1. 取出 Enter 阶段存的上下文 (Buffer 地址)
-    let info = match unsafe { READ_ARGS.get(&pid) } {
+    let info = match unsafe { READ_ARGS.get(&pid_tgid) } {
         Some(ptr) => *ptr,
         None => return 0,
     };
     // 用完即焚，保证 Map 不泄露
-    let _ = READ_ARGS.remove(&pid);
+    let _ = READ_ARGS.remove(&pid_tgid);
 
     let fd = info.fd;
     let buf_ptr = info.buf_ptr;
@@ -586,35 +853,29 @@ pub fn masdeepflow_read_exit(ctx: TracePointContext) -> u32 {
 
     let count = ret as u64;
     let cgroup_id = unsafe { bpf_get_current_cgroup_id() };
+    let comm = bpf_get_current_comm().unwrap_or([0; 16]);
 
-    // 2. 读取 buffer 内容 (Payload Capture)
-    let mut payload = [0u8; 128];
-    let read_len = if count > 128 { 128 } else { count as usize };
-
-    if read_len > 0 {
-        unsafe {
-            let _ = r#gen::bpf_probe_read_user(
-                payload.as_mut_ptr() as *mut _,
-                read_len as u32,
-                buf_ptr as *const _,
-            );
-        }
-    }
-
+    let Some(conn) = account_conn(pid, fd, 0, count) else {
+        return 0;
+    };
     let event = TcpEvent {
+        kind: EVENT_TCP,
         pid,
+        timestamp_ns: unsafe { r#gen::bpf_ktime_get_ns() },
         fd,
         cgroup_id,
-        saddr: 0,
-        daddr: 0,
-        sport: 0,
-        dport: 0,
-        family: 2,
+        comm,
+        saddr: conn.saddr,
+        daddr: conn.daddr,
+        sport: conn.sport,
+        dport: conn.dport,
+        family: conn.family,
         direction: 3, // 3 = RX (Incoming/Read) - 用户态会看到这个
         data_len: count as u32,
-        payload,
+        captured_len: 0,
     };
-    TCP_EVENTS.output(&ctx, &event, 0);
+    // 2. 读取 buffer 内容 (Payload Capture)
+    emit_data(&ctx, event, buf_ptr as u64);
     0
 }
 
@@ -623,7 +884,7 @@ pub fn masdeepflow_read_exit(ctx: TracePointContext) -> u32 {
 // 逻辑同上: Enter 存指针，Exit 读数据
 #[tracepoint]
 pub fn masdeepflow_recvfrom_enter(ctx: TracePointContext) -> u32 {
-    let pid = (bpf_get_current_pid_tgid() >> 32) as u32;
+    let pid_tgid = bpf_get_current_pid_tgid();
     let comm = bpf_get_current_comm().unwrap_or([0; 16]);
     // Self-Tracing Check (Comm)
     if is_infra_process(&comm) {
@@ -631,7 +892,7 @@ pub fn masdeepflow_recvfrom_enter(ctx: TracePointContext) -> u32 {
     }
 
     // Self-Tracing Check (PID)
-    let tgid = (bpf_get_current_pid_tgid() >> 32) as u32;
+    let tgid = (pid_tgid >> 32) as u32;
     if unsafe { FILTER_PID.get(&tgid).is_some() } {
         return 0;
     }
@@ -640,13 +901,16 @@ pub fn masdeepflow_recvfrom_enter(ctx: TracePointContext) -> u32 {
     // 16: fd
     // 24: ubuf (指针)
     let fd: u64 = unsafe { ctx.read_at::<u64>(16).unwrap_or(0) };
+    if !is_tracked_socket(tgid, fd) {
+        return 0;
+    }
     let buf_ptr: u64 = unsafe { ctx.read_at::<u64>(24).unwrap_or(0) };
     if buf_ptr != 0 {
         let info = ReadInfo {
             buf_ptr,
             fd: fd as u32,
         };
-        let _ = READ_ARGS.insert(&pid, &info, 0);
+        let _ = READ_ARGS.insert(&pid_tgid, &info, 0);
     }
     0
 }
@@ -654,13 +918,14 @@ pub fn masdeepflow_recvfrom_enter(ctx: TracePointContext) -> u32 {
 // [补充支持] masdeepflow_recvfrom_exit
 #[tracepoint]
 pub fn masdeepflow_recvfrom_exit(ctx: TracePointContext) -> u32 {
-    let pid = (bpf_get_current_pid_tgid() >> 32) as u32;
+    let pid_tgid = bpf_get_current_pid_tgid();
+    let pid = (pid_tgid >> 32) as u32;
 
-    let info = match unsafe { READ_ARGS.get(&pid) } {
+    let info = match unsafe { READ_ARGS.get(&pid_tgid) } {
         Some(ptr) => *ptr,
         None => return 0,
     };
-    let _ = READ_ARGS.remove(&pid);
+    let _ = READ_ARGS.remove(&pid_tgid);
     let buf_ptr = info.buf_ptr;
     let fd = info.fd;
 
@@ -671,34 +936,278 @@ pub fn masdeepflow_recvfrom_exit(ctx: TracePointContext) -> u32 {
 
     let count = ret as u64;
     let cgroup_id = unsafe { bpf_get_current_cgroup_id() };
+    let comm = bpf_get_current_comm().unwrap_or([0; 16]);
 
-    let mut payload = [0u8; 128];
-    let read_len = if count > 128 { 128 } else { count as usize };
-
-    if read_len > 0 {
-        unsafe {
-            let _ = r#gen::bpf_probe_read_user(
-                payload.as_mut_ptr() as *mut _,
-                read_len as u32,
-                buf_ptr as *const _,
-            );
-        }
-    }
-
+    let Some(conn) = account_conn(pid, fd, 0, count) else {
+        return 0;
+    };
     let event = TcpEvent {
+        kind: EVENT_TCP,
         pid,
+        timestamp_ns: unsafe { r#gen::bpf_ktime_get_ns() },
         fd,
         cgroup_id,
-        saddr: 0,
-        daddr: 0,
-        sport: 0,
-        dport: 0,
-        family: 2,
+        comm,
+        saddr: conn.saddr,
+        daddr: conn.daddr,
+        sport: conn.sport,
+        dport: conn.dport,
+        family: conn.family,
         direction: 3, // 3 = RX (Incoming/Read)
         data_len: count as u32,
-        payload,
+        captured_len: 0,
     };
-    TCP_EVENTS.output(&ctx, &event, 0);
+    emit_data(&ctx, event, buf_ptr as u64);
+    0
+}
+
+// --- 模块五：向量 I/O (readv/writev、sendmsg/recvmsg、sendmmsg/recvmmsg) ---
+// Go net、tokio、nginx、Java NIO、libpq 等运行时主要通过这些系统调用收发数据。
+// 数据分散在 iovec 数组中: Enter 记下参数，Exit 拿到实际传输的字节数后逐段拷贝，
+// 最多遍历 MAX_IOV 段、拷贝 capture_limit 字节，拼成和 write/read 一样的 TcpEvent。
+
+// 最多遍历的 iovec 段数 / sendmmsg、recvmmsg 最多解析的消息数 (每条消息一个事件)
+const MAX_IOV: u32 = 8;
+const MAX_MMSG: u32 = 4;
+
+// Enter 阶段参数所指结构的布局
+const LAYOUT_IOV: u8 = 0; // readv/writev: struct iovec[vlen]
+const LAYOUT_MSGHDR: u8 = 1; // sendmsg/recvmsg: struct user_msghdr
+const LAYOUT_MMSGHDR: u8 = 2; // sendmmsg/recvmmsg: struct mmsghdr[vlen]
+
+// struct user_msghdr (x86_64/arm64):
+// 0: msg_name, 8: msg_namelen, 16: msg_iov, 24: msg_iovlen, 32: msg_control, 40: msg_controllen, 48: msg_flags
+const MSGHDR_IOV_OFF: u64 = 16;
+const MSGHDR_IOVLEN_OFF: u64 = 24;
+// struct mmsghdr { struct user_msghdr msg_hdr; unsigned int msg_len; } 共 64 字节
+const MMSGHDR_LEN_OFF: u64 = 56;
+const MMSGHDR_SIZE: u64 = 64;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct IoVec {
+    base: u64, // iov_base
+    len: u64,  // iov_len
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct MsgArgs {
+    pub vec_ptr: u64,      // 按 layout 解释的用户态指针
+    pub timestamp_ns: u64, // 进入系统调用的时间，TX 事件以此为准 (与 write 一致)
+    pub fd: u32,
+    pub vlen: u32,     // iovec 段数 / mmsghdr 个数 (msghdr 布局不使用)
+    pub direction: u8, // 2 = TX, 3 = RX
+    pub layout: u8,
+}
+
+// 进行中的向量 I/O: pid_tgid -> 参数，与 READ_ARGS 一样按线程存放并使用 LRU
+#[map]
+static MSG_ARGS: LruHashMap<u64, MsgArgs> = LruHashMap::with_max_entries(1024, 0);
+
+#[inline(always)]
+fn read_user<T: Copy>(ptr: u64, default: T) -> T {
+    let mut val = default;
+    unsafe {
+        let _ = r#gen::bpf_probe_read_user(
+            &mut val as *mut _ as *mut _,
+            core::mem::size_of::<T>() as u32,
+            ptr as *const _,
+        );
+    }
+    val
+}
+
+// 所有向量 I/O 系统调用的前三个参数布局相同: 16: fd, 24: 指针, 32: vlen (msghdr 布局下为 flags)
+#[inline(always)]
+fn msg_enter(ctx: &TracePointContext, direction: u8, layout: u8) -> u32 {
+    let pid_tgid = bpf_get_current_pid_tgid();
+    let comm = bpf_get_current_comm().unwrap_or([0; 16]);
+    if is_infra_process(&comm) {
+        return 0;
+    }
+    let tgid = (pid_tgid >> 32) as u32;
+    if unsafe { FILTER_PID.get(&tgid).is_some() } {
+        return 0;
+    }
+
+    let fd: u64 = unsafe { ctx.read_at::<u64>(16).unwrap_or(0) };
+    if !is_tracked_socket(tgid, fd) {
+        return 0;
+    }
+    let vec_ptr: u64 = unsafe { ctx.read_at::<u64>(24).unwrap_or(0) };
+    let vlen: u64 = unsafe { ctx.read_at::<u64>(32).unwrap_or(0) };
+    if vec_ptr != 0 {
+        let args = MsgArgs {
+            vec_ptr,
+            timestamp_ns: unsafe { r#gen::bpf_ktime_get_ns() },
+            fd: fd as u32,
+            vlen: vlen as u32,
+            direction,
+            layout,
+        };
+        let _ = MSG_ARGS.insert(&pid_tgid, &args, 0);
+    }
+    0
+}
+
+// 发送一个 iovec 数组描述的数据事件，count 为这组 iovec 上实际传输的字节数
+#[inline(always)]
+fn emit_iov<C: EbpfContext>(
+    ctx: &C,
+    pid: u32,
+    args: &MsgArgs,
+    iov_ptr: u64,
+    iovcnt: u64,
+    count: u64,
+) {
+    let (sent, recv) = if args.direction == 2 {
+        (count, 0)
+    } else {
+        (0, count)
+    };
+    let Some(conn) = account_conn(pid, args.fd, sent, recv) else {
+        return;
+    };
+    let record = match TCP_SCRATCH.get_ptr_mut(0) {
+        Some(ptr) => unsafe { &mut *ptr },
+        None => return,
+    };
+
+    let limit = capture_limit(count as u32);
+    let mut off: usize = 0;
+    for i in 0..MAX_IOV {
+        if i as u64 >= iovcnt || off >= limit {
+            break;
+        }
+        let iov = read_user(
+            iov_ptr + i as u64 * core::mem::size_of::<IoVec>() as u64,
+            IoVec { base: 0, len: 0 },
+        );
+        let remain = (limit - off) as u64;
+        let mut len = if iov.len < remain { iov.len } else { remain } as usize;
+        // verifier 不知道 off < limit，看到的 remain 可能为负；而 LLVM 能证明 len <= MAX_PAYLOAD_LEN
+        // 会删掉下面的上界检查。volatile 读让这次检查保留下来，verifier 据此得到 len 的范围
+        len = unsafe { core::ptr::read_volatile(&len) };
+        if len > MAX_PAYLOAD_LEN {
+            len = MAX_PAYLOAD_LEN;
+        }
+        // off < limit <= MAX_PAYLOAD_LEN，掩码只是让 verifier 看到上界
+        let dst = (off & (MAX_PAYLOAD_LEN - 1)) as isize;
+        if len > 0
+            && unsafe {
+                r#gen::bpf_probe_read_user(
+                    record.payload.as_mut_ptr().offset(dst) as *mut _,
+                    len as u32,
+                    iov.base as *const _,
+                )
+            } != 0
+        {
+            break;
+        }
+        off += len;
+    }
+
+    record.event = TcpEvent {
+        kind: EVENT_TCP,
+        pid,
+        timestamp_ns: if args.direction == 2 {
+            args.timestamp_ns
+        } else {
+            unsafe { r#gen::bpf_ktime_get_ns() }
+        },
+        fd: args.fd,
+        cgroup_id: unsafe { bpf_get_current_cgroup_id() },
+        comm: bpf_get_current_comm().unwrap_or([0; 16]),
+        saddr: conn.saddr,
+        daddr: conn.daddr,
+        sport: conn.sport,
+        dport: conn.dport,
+        family: conn.family,
+        direction: args.direction,
+        data_len: count as u32,
+        captured_len: off as u32,
+    };
+    emit(ctx, record, core::mem::size_of::<TcpEvent>() + off);
+}
+
+// 挂载点: tracepoint:syscalls/sys_enter_writev
+#[tracepoint]
+pub fn masdeepflow_writev_enter(ctx: TracePointContext) -> u32 {
+    msg_enter(&ctx, 2, LAYOUT_IOV)
+}
+
+// 挂载点: tracepoint:syscalls/sys_enter_readv
+#[tracepoint]
+pub fn masdeepflow_readv_enter(ctx: TracePointContext) -> u32 {
+    msg_enter(&ctx, 3, LAYOUT_IOV)
+}
+
+// 挂载点: tracepoint:syscalls/sys_enter_sendmsg
+#[tracepoint]
+pub fn masdeepflow_sendmsg_enter(ctx: TracePointContext) -> u32 {
+    msg_enter(&ctx, 2, LAYOUT_MSGHDR)
+}
+
+// 挂载点: tracepoint:syscalls/sys_enter_recvmsg
+#[tracepoint]
+pub fn masdeepflow_recvmsg_enter(ctx: TracePointContext) -> u32 {
+    msg_enter(&ctx, 3, LAYOUT_MSGHDR)
+}
+
+// 挂载点: tracepoint:syscalls/sys_enter_sendmmsg
+#[tracepoint]
+pub fn masdeepflow_sendmmsg_enter(ctx: TracePointContext) -> u32 {
+    msg_enter(&ctx, 2, LAYOUT_MMSGHDR)
+}
+
+// 挂载点: tracepoint:syscalls/sys_enter_recvmmsg
+#[tracepoint]
+pub fn masdeepflow_recvmmsg_enter(ctx: TracePointContext) -> u32 {
+    msg_enter(&ctx, 3, LAYOUT_MMSGHDR)
+}
+
+// 挂载点: 以上 6 个系统调用的 sys_exit_*，返回值 @ offset 16
+// readv/writev/sendmsg/recvmsg 返回传输的字节数，sendmmsg/recvmmsg 返回处理的消息数
+#[tracepoint]
+pub fn masdeepflow_msg_exit(ctx: TracePointContext) -> u32 {
+    let pid_tgid = bpf_get_current_pid_tgid();
+    let pid = (pid_tgid >> 32) as u32;
+
+    let args = match unsafe { MSG_ARGS.get(&pid_tgid) } {
+        Some(args) => *args,
+        None => return 0,
+    };
+    let _ = MSG_ARGS.remove(&pid_tgid);
+
+    let ret: i64 = unsafe { ctx.read_at::<i64>(16).unwrap_or(0) };
+    if ret <= 0 {
+        return 0;
+    }
+
+    match args.layout {
+        LAYOUT_IOV => emit_iov(&ctx, pid, &args, args.vec_ptr, args.vlen as u64, ret as u64),
+        LAYOUT_MSGHDR => {
+            let iov_ptr: u64 = read_user(args.vec_ptr + MSGHDR_IOV_OFF, 0);
+            let iovcnt: u64 = read_user(args.vec_ptr + MSGHDR_IOVLEN_OFF, 0);
+            emit_iov(&ctx, pid, &args, iov_ptr, iovcnt, ret as u64);
+        }
+        _ => {
+            // 每条消息一个事件，字节数取内核回填的 msg_len
+            for i in 0..MAX_MMSG {
+                if i as i64 >= ret {
+                    break;
+                }
+                let hdr = args.vec_ptr + i as u64 * MMSGHDR_SIZE;
+                let iov_ptr: u64 = read_user(hdr + MSGHDR_IOV_OFF, 0);
+                let iovcnt: u64 = read_user(hdr + MSGHDR_IOVLEN_OFF, 0);
+                let msg_len: u32 = read_user(hdr + MMSGHDR_LEN_OFF, 0);
+                if msg_len > 0 {
+                    emit_iov(&ctx, pid, &args, iov_ptr, iovcnt, msg_len as u64);
+                }
+            }
+        }
+    }
     0
 }
 
@@ -726,9 +1235,9 @@ pub fn handle_sock_ops(ctx: SockOpsContext) -> u32 {
         return 0;
     }
 
-    // AF_INET = 2 (仅处理 IPv4)
+    // AF_INET = 2 / AF_INET6 = 10
     let family = unsafe { (*ops).family };
-    if family != 2 {
+    if family != AF_INET as u32 && family != AF_INET6 as u32 {
         return 0;
     }
 
@@ -737,8 +1246,20 @@ pub fn handle_sock_ops(ctx: SockOpsContext) -> u32 {
     // 但 (*ops).local_port 在某些内核版本/上下文中可能是 Host Endian。
     // 这里我们假设 local_port 是 Host Endian (aya/kernel 惯例对于 sock_ops 字段)，
     // 而 remote_port 是 Network Endian。
-    let remote_ip4 = unsafe { (*ops).remote_ip4 };
-    let local_ip4 = unsafe { (*ops).local_ip4 };
+    // IPv4 转为 IPv4-mapped，使 IPv4 与 IPv6 连接共用同一种 Key
+    let (local_ip, remote_ip) = unsafe {
+        if family == AF_INET6 as u32 {
+            (
+                ipv6_from_words((*ops).local_ip6),
+                ipv6_from_words((*ops).remote_ip6),
+            )
+        } else {
+            (
+                ipv4_mapped((*ops).local_ip4),
+                ipv4_mapped((*ops).remote_ip4),
+            )
+        }
+    };
     let local_port = unsafe { (*ops).local_port };
     let remote_port = unsafe { (*ops).remote_port };
 
@@ -750,8 +1271,8 @@ pub fn handle_sock_ops(ctx: SockOpsContext) -> u32 {
     let remote_port_host = u32::from_be(remote_port);
 
     let key = SockKey {
-        sip: local_ip4,
-        dip: remote_ip4,
+        sip: local_ip,
+        dip: remote_ip,
         sport: local_port,
         dport: remote_port_host,
     };
@@ -776,14 +1297,26 @@ pub fn handle_sock_ops(ctx: SockOpsContext) -> u32 {
 pub fn redirect_traffic(ctx: SkMsgContext) -> u32 {
     let msg = ctx.msg;
 
-    // AF_INET = 2
+    // AF_INET = 2 / AF_INET6 = 10
     let family = unsafe { (*msg).family };
-    if family != 2 {
+    if family != AF_INET as u32 && family != AF_INET6 as u32 {
         return 1; // SK_PASS = 1 (放行，走标准协议栈)
     }
 
-    let remote_ip4 = unsafe { (*msg).remote_ip4 };
-    let local_ip4 = unsafe { (*msg).local_ip4 };
+    // 与 handle_sock_ops 相同的地址归一化
+    let (local_ip, remote_ip) = unsafe {
+        if family == AF_INET6 as u32 {
+            (
+                ipv6_from_words((*msg).local_ip6),
+                ipv6_from_words((*msg).remote_ip6),
+            )
+        } else {
+            (
+                ipv4_mapped((*msg).local_ip4),
+                ipv4_mapped((*msg).remote_ip4),
+            )
+        }
+    };
     let local_port = unsafe { (*msg).local_port };
     let remote_port = unsafe { (*msg).remote_port };
 
@@ -799,8 +1332,8 @@ pub fn redirect_traffic(ctx: SkMsgContext) -> u32 {
     // 所以，我们要查找的 Key 应该是 (Remote, Local, RemP, LocP)。
 
     let key = SockKey {
-        sip: remote_ip4,         // 对应 B 的 SIP
-        dip: local_ip4,          // 对应 B 的 DIP
+        sip: remote_ip,          // 对应 B 的 SIP
+        dip: local_ip,           // 对应 B 的 DIP
         sport: remote_port_host, // 对应 B 的 SPort
         dport: local_port,       // 对应 B 的 DPort
     };
@@ -1,3 +1,5 @@
+use std::{env, fs, path::PathBuf};
+
 use anyhow::{Context as _, anyhow};
 use aya_build::Toolchain;
 
@@ -25,17 +27,39 @@ fn main() -> anyhow::Result<()> {
 
     // 4. 构建 aya_build 所需的 Package 对象
     // 告诉 aya：eBPF 代码在这个目录下，请帮我处理
-    let ebpf_package = aya_build::Package {
-        name: name.as_str(),
-        root_dir: manifest_path
-            .parent()
-            .ok_or_else(|| anyhow!("no parent for {manifest_path}"))?
-            .as_str(),
-        ..Default::default()
-    };
+    let root_dir = manifest_path
+        .parent()
+        .ok_or_else(|| anyhow!("no parent for {manifest_path}"))?
+        .as_str();
 
     // 5. 【核心步骤】执行 eBPF 编译
     // 这行代码会调用 Rust Nightly + bpf-linker 去编译内核代码
     // 并把生成的 .o (ELF) 文件自动放到 OUT_DIR 目录下供 main.rs 加载
-    aya_build::build_ebpf([ebpf_package], Toolchain::default())
+    //
+    // [Transport] 编译两份: 先编译 perf 降级版本并改名为 masdeepflow-perf，
+    // 再编译默认的 RingBuf 版本 (两次产物同名，后一次会覆盖前一次)
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").context("OUT_DIR not set")?);
+    aya_build::build_ebpf(
+        [aya_build::Package {
+            name: name.as_str(),
+            root_dir,
+            features: &["perf"],
+            ..Default::default()
+        }],
+        Toolchain::default(),
+    )?;
+    // 产物以 eBPF crate 的 [[bin]] 名命名
+    fs::rename(
+        out_dir.join("masdeepflow"),
+        out_dir.join("masdeepflow-perf"),
+    )
+    .context("failed to rename perf eBPF object")?;
+    aya_build::build_ebpf(
+        [aya_build::Package {
+            name: name.as_str(),
+            root_dir,
+            ..Default::default()
+        }],
+        Toolchain::default(),
+    )
 }
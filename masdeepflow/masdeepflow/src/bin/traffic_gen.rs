@@ -17,7 +17,8 @@ fn main() -> std::io::Result<()> {
         for stream in listener.incoming() {
             match stream {
                 Ok(mut stream) => {
-                    println!("New client connected!");
+                    // 打印对端地址，便于与 Agent 输出的入向五元组对照
+                    println!("New client connected from {}!", peer(&stream));
                     let mut buffer = [0u8; 1024];
                     // 1. Read Request
                     let n = stream.read(&mut buffer)?;
@@ -75,24 +76,112 @@ fn main() -> std::io::Result<()> {
             }
         }
     } else if mode == "benchmark-client" {
-        println!("Starting Benchmark Client -> 127.0.0.1:8080 (10s test)...");
+        // --duration N (秒，默认 10) --size BYTES (每次 write 的大小，默认 65536)
+        // 小 size 意味着更高的系统调用 (即 eBPF 事件) 速率，用于对比 Agent 不同事件通道的开销
+        let duration = flag_value(&args, "--duration").unwrap_or(10);
+        let size = flag_value(&args, "--size").unwrap_or(65536) as usize;
+        println!(
+            "Starting Benchmark Client -> 127.0.0.1:8080 ({}s test, {} bytes per write)...",
+            duration, size
+        );
         let mut stream = TcpStream::connect("127.0.0.1:8080")?;
-        let buf = [1u8; 65536];
+        let buf = vec![1u8; size.max(1)];
         let mut total = 0usize;
+        let mut writes = 0u64;
         let start = std::time::Instant::now();
-        while start.elapsed().as_secs() < 10 {
+        while start.elapsed().as_secs() < duration {
             use std::io::Write;
             stream.write_all(&buf)?;
             total += buf.len();
+            writes += 1;
         }
         let dur = start.elapsed();
         let info = format!(
-            "Sent {} bytes in {:?}. Speed: {:.2} MB/s",
+            "Sent {} bytes in {:?}. Speed: {:.2} MB/s, {:.0} writes/s",
             total,
             dur,
-            (total as f64 / 1024.0 / 1024.0) / dur.as_secs_f64()
+            (total as f64 / 1024.0 / 1024.0) / dur.as_secs_f64(),
+            writes as f64 / dur.as_secs_f64()
         );
         println!("{}", info);
+    } else if mode == "threaded-server" {
+        // 多线程 HTTP 服务端: 每个连接一个线程，所有线程同时阻塞在 read 上。
+        // 用于验证 Agent 按线程关联 read 的 enter/exit: 服务端记录中每个连接 (sport) 的
+        // 请求路径都应当来自同一个客户端线程 (/thread/<i>/...)，响应头 X-Thread 回显该编号
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+        println!("Starting Threaded HTTP Server on 0.0.0.0:8090...");
+        let listener = TcpListener::bind("0.0.0.0:8090")?;
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            thread::spawn(move || {
+                println!("Client connected from {}!", peer(&stream));
+                let mut buf = [0u8; 1024];
+                loop {
+                    let n = match stream.read(&mut buf) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => n,
+                    };
+                    // "GET /thread/3/req/17 HTTP/1.1" -> "3"
+                    let request = String::from_utf8_lossy(&buf[..n]);
+                    let thread_id = request.split('/').nth(2).unwrap_or("?").to_string();
+                    thread::sleep(Duration::from_millis(1));
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nX-Thread: {}\r\nContent-Length: 0\r\n\r\n",
+                        thread_id
+                    );
+                    if stream.write_all(response.as_bytes()).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    } else if mode == "threaded-client" {
+        // --threads N (默认 8) --requests M (每个线程的请求数，默认 100)
+        // 每个线程一个连接，并发发送 keep-alive 请求并校验响应中回显的线程编号
+        use std::io::{Read, Write};
+        let threads = flag_value(&args, "--threads").unwrap_or(8);
+        let requests = flag_value(&args, "--requests").unwrap_or(100);
+        println!(
+            "Starting Threaded Client -> 127.0.0.1:8090 ({} threads x {} requests)...",
+            threads, requests
+        );
+        let handles: Vec<_> = (0..threads)
+            .map(|i| {
+                thread::spawn(move || -> std::io::Result<u64> {
+                    let mut stream = TcpStream::connect("127.0.0.1:8090")?;
+                    println!("Thread {}: {}", i, peer(&stream));
+                    let expected = format!("X-Thread: {}\r\n", i);
+                    let mut mismatches = 0;
+                    let mut buf = [0u8; 1024];
+                    for j in 0..requests {
+                        let request = format!(
+                            "GET /thread/{}/req/{} HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n",
+                            i, j
+                        );
+                        stream.write_all(request.as_bytes())?;
+                        let n = stream.read(&mut buf)?;
+                        if !String::from_utf8_lossy(&buf[..n]).contains(&expected) {
+                            mismatches += 1;
+                        }
+                    }
+                    Ok(mismatches)
+                })
+            })
+            .collect();
+        let mut mismatches = 0;
+        for handle in handles {
+            match handle.join() {
+                Ok(Ok(n)) => mismatches += n,
+                Ok(Err(e)) => eprintln!("Thread failed: {}", e),
+                Err(_) => eprintln!("Thread panicked"),
+            }
+        }
+        println!(
+            "Done: {} requests, {} responses with the wrong thread id.",
+            threads * requests,
+            mismatches
+        );
     } else if mode == "mysql-client" {
         println!("Mode: MySQL Client (Simulated)");
         println!("Connecting to 127.0.0.1:3306...");
@@ -162,8 +251,10 @@ fn main() -> std::io::Result<()> {
         let listener = TcpListener::bind("0.0.0.0:6379")?;
         for stream in listener.incoming() {
             if let Ok(mut stream) = stream {
-                println!("Redis Client connected!");
+                println!("Redis Client connected from {}!", peer(&stream));
                 let mut buf = [0u8; 1024];
+                let mut store = std::collections::HashMap::new();
+                let mut queued: Option<Vec<Vec<String>>> = None; // MULTI 之后排队的命令
                 loop {
                     let n = stream.read(&mut buf)?;
                     if n == 0 {
@@ -173,8 +264,14 @@ fn main() -> std::io::Result<()> {
                         "Received Redis Command: {:?}",
                         String::from_utf8_lossy(&buf[..n])
                     );
-                    // Respond with +OK\r\n (Simple String)
-                    stream.write_all(b"+OK\r\n")?;
+                    // Simulate delay
+                    thread::sleep(Duration::from_millis(10));
+                    // 一次 read 中可能有多个 pipelining 的命令，逐个回复
+                    let mut resp = Vec::new();
+                    for cmd in redis_commands(&buf[..n]) {
+                        resp.extend(redis_reply(&cmd, &mut store, &mut queued));
+                    }
+                    stream.write_all(&resp)?;
                 }
             }
         }
@@ -192,6 +289,110 @@ fn main() -> std::io::Result<()> {
             "Received Redis Response: {:?}",
             String::from_utf8_lossy(&buf[..n])
         );
+    } else if mode == "redis-pipeline-client" {
+        use std::io::{Read, Write};
+        println!("Connecting to Redis 127.0.0.1:6379 (pipelining)...");
+        let mut stream = TcpStream::connect("127.0.0.1:6379")?;
+        let batches: [&[&[&str]]; 3] = [
+            // 一次 write 发出 6 个命令: Simple String / Bulk / Null / Integer / WRONGTYPE / MOVED
+            &[
+                &["SET", "foo", "bar"],
+                &["GET", "foo"],
+                &["GET", "missing"],
+                &["INCR", "counter"],
+                &["LPUSH", "foo", "x"],
+                &["GET", "{user1000}.profile"],
+            ],
+            // 事务
+            &[&["MULTI"], &["INCR", "counter"], &["GET", "foo"], &["EXEC"]],
+            // 订阅确认之后服务端推送一条消息
+            &[&["SUBSCRIBE", "news"]],
+        ];
+        let mut buf = [0u8; 4096];
+        for batch in batches {
+            let mut cmd = Vec::new();
+            for args in batch {
+                cmd.extend(format!("*{}\r\n", args.len()).into_bytes());
+                for arg in *args {
+                    cmd.extend(format!("${}\r\n{}\r\n", arg.len(), arg).into_bytes());
+                }
+            }
+            stream.write_all(&cmd)?;
+            println!("Sent {} pipelined Redis commands", batch.len());
+            let n = stream.read(&mut buf)?;
+            println!(
+                "Received Redis Response: {:?}",
+                String::from_utf8_lossy(&buf[..n])
+            );
+        }
+    } else if mode == "vectored-client" {
+        // 与 redis-server 配合: 请求分散在多个 iovec 中，分别走 writev + recvmsg 和 sendmsg + readv，
+        // 模拟 Go / tokio / nginx 等不使用 write/read 的运行时
+        println!("Connecting to Redis 127.0.0.1:6379 (vectored I/O)...");
+        let stream = TcpStream::connect("127.0.0.1:6379")?;
+        let fd = stream.as_raw_fd();
+        let mut buf = [0u8; 1024];
+        let iov = |parts: &[&[u8]]| -> Vec<libc::iovec> {
+            parts
+                .iter()
+                .map(|p| libc::iovec {
+                    iov_base: p.as_ptr() as *mut libc::c_void,
+                    iov_len: p.len(),
+                })
+                .collect()
+        };
+
+        // 1. writev: "*2\r\n" | "$3\r\nGET\r\n" | "$3\r\nfoo\r\n"
+        let parts: [&[u8]; 3] = [b"*2\r\n", b"$3\r\nGET\r\n", b"$3\r\nfoo\r\n"];
+        let vec = iov(&parts);
+        let ret = unsafe { libc::writev(fd, vec.as_ptr(), vec.len() as i32) };
+        println!("Sent GET foo via writev ({} bytes).", ret);
+
+        // 2. recvmsg
+        let mut read_vec = [libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        }];
+        let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg.msg_iov = read_vec.as_mut_ptr();
+        msg.msg_iovlen = 1;
+        let n = unsafe { libc::recvmsg(fd, &mut msg, 0) };
+        println!(
+            "Received via recvmsg: {:?}",
+            String::from_utf8_lossy(&buf[..n.max(0) as usize])
+        );
+
+        // 3. sendmsg: "*3\r\n" | "$3\r\nSET\r\n" | "$3\r\nfoo\r\n" | "$3\r\nbar\r\n"
+        let parts: [&[u8]; 4] = [
+            b"*3\r\n",
+            b"$3\r\nSET\r\n",
+            b"$3\r\nfoo\r\n",
+            b"$3\r\nbar\r\n",
+        ];
+        let mut vec = iov(&parts);
+        let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg.msg_iov = vec.as_mut_ptr();
+        msg.msg_iovlen = vec.len();
+        let ret = unsafe { libc::sendmsg(fd, &msg, 0) };
+        println!("Sent SET foo bar via sendmsg ({} bytes).", ret);
+
+        // 4. readv: 响应分两段读取
+        let (head, tail) = buf.split_at_mut(2);
+        let read_vec = [
+            libc::iovec {
+                iov_base: head.as_mut_ptr() as *mut libc::c_void,
+                iov_len: head.len(),
+            },
+            libc::iovec {
+                iov_base: tail.as_mut_ptr() as *mut libc::c_void,
+                iov_len: tail.len(),
+            },
+        ];
+        let n = unsafe { libc::readv(fd, read_vec.as_ptr(), read_vec.len() as i32) };
+        println!(
+            "Received via readv: {:?}",
+            String::from_utf8_lossy(&buf[..n.max(0) as usize])
+        );
     } else if mode == "pg-server" {
         use std::io::{Read, Write};
         use std::net::TcpListener;
@@ -199,7 +400,7 @@ fn main() -> std::io::Result<()> {
         let listener = TcpListener::bind("0.0.0.0:5432")?;
         for stream in listener.incoming() {
             if let Ok(mut stream) = stream {
-                println!("PG Client connected!");
+                println!("PG Client connected from {}!", peer(&stream));
                 let mut buf = [0u8; 1024];
                 // 1. Read Startup Message (Length + Protocol)
                 let _ = stream.read(&mut buf)?;
@@ -226,6 +427,13 @@ fn main() -> std::io::Result<()> {
                         stream.write_all(&resp)?;
                         // Send ReadyForQuery (Z)
                         stream.write_all(&[b'Z', 0, 0, 0, 5, b'I'])?;
+                    } else {
+                        // 扩展查询: Parse / Bind / Describe / Execute / Sync 批量到达
+                        let resp = pg_extended_reply(&buf[..n]);
+                        if !resp.is_empty() {
+                            thread::sleep(Duration::from_millis(20));
+                            stream.write_all(&resp)?;
+                        }
                     }
                 }
             }
@@ -260,6 +468,324 @@ fn main() -> std::io::Result<()> {
             "Received PG Response: {:?}",
             String::from_utf8_lossy(&buf[..n])
         );
+    } else if mode == "pg-extended-client" {
+        use std::io::Write;
+        println!("Connecting to Postgres 127.0.0.1:5432 (extended query protocol)...");
+        let mut stream = TcpStream::connect("127.0.0.1:5432")?;
+
+        // StartupMessage: Len(4) + 3.0 + user / database 参数
+        let mut startup = vec![0, 3, 0, 0];
+        startup.extend_from_slice(b"user\0app\0database\0shop\0\0");
+        let mut msg = ((startup.len() + 4) as u32).to_be_bytes().to_vec();
+        msg.extend(startup);
+        stream.write_all(&msg)?;
+        read_pg_until_ready(&mut stream)?;
+
+        // 1. 命名语句执行两次，2. 查询不存在的表 (ErrorResponse)，3. 未命名语句的 INSERT
+        let batches: [(&str, &str, usize); 3] = [
+            ("s1", "SELECT name FROM users WHERE id = $1", 2),
+            ("", "SELECT * FROM missing", 1),
+            ("", "INSERT INTO orders VALUES ($1)", 1),
+        ];
+        for (name, sql, executions) in batches {
+            let mut batch = pg_message(
+                b'P',
+                &[name.as_bytes(), b"\0", sql.as_bytes(), b"\0\0\0"].concat(),
+            );
+            for _ in 0..executions {
+                batch.extend(pg_message(
+                    b'B',
+                    &[b"\0", name.as_bytes(), b"\0\0\0\0\0\0\0"].concat(),
+                ));
+                batch.extend(pg_message(b'D', b"P\0"));
+                batch.extend(pg_message(b'E', b"\0\0\0\0\0"));
+            }
+            batch.extend(pg_message(b'S', b""));
+            stream.write_all(&batch)?;
+            println!("Sent PG batch: {} x{}", sql, executions);
+            read_pg_until_ready(&mut stream)?;
+        }
+    } else if mode == "kafka-broker" {
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+        // 支持 ApiVersions v0 / Produce v3 / Fetch v4，Topic "missing" 返回 UNKNOWN_TOPIC_OR_PARTITION
+        println!("Starting Mock Kafka Broker on 0.0.0.0:9092...");
+        let listener = TcpListener::bind("0.0.0.0:9092")?;
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else {
+                continue;
+            };
+            println!("Kafka client connected from {}!", peer(&stream));
+            let mut received = Vec::new();
+            let mut buf = [0u8; 4096];
+            loop {
+                let n = stream.read(&mut buf)?;
+                if n == 0 {
+                    break;
+                }
+                received.extend_from_slice(&buf[..n]);
+                // 一次 read 中可能有多个在途请求，逐个回复，最后一次性写回
+                let mut resp = Vec::new();
+                while received.len() >= 4 {
+                    let size =
+                        u32::from_be_bytes([received[0], received[1], received[2], received[3]])
+                            as usize;
+                    if received.len() < 4 + size {
+                        break;
+                    }
+                    let request: Vec<u8> = received.drain(..4 + size).skip(4).collect();
+                    resp.extend(kafka_reply(&request));
+                }
+                if !resp.is_empty() {
+                    thread::sleep(Duration::from_millis(20));
+                    stream.write_all(&resp)?;
+                }
+            }
+            println!("Kafka client disconnected.");
+        }
+    } else if mode == "kafka-client" {
+        use std::io::{Read, Write};
+        println!("Mode: Kafka Client -> 127.0.0.1:9092");
+        let mut stream = TcpStream::connect("127.0.0.1:9092")?;
+        let mut buf = [0u8; 4096];
+
+        // 1. ApiVersions v0
+        stream.write_all(&kafka_request(18, 0, 1, &[]))?;
+        let n = stream.read(&mut buf)?;
+        println!("ApiVersions response: {} bytes", n);
+
+        // 2. 一次 write 发出三个在途请求: Produce orders / Produce missing / Fetch orders
+        let produce = |topic: &str| {
+            // transactional_id (null) + acks(1) + timeout_ms + topic_data[name + partition_data[index + records]]
+            let mut body = vec![0xff, 0xff, 0, 1, 0, 0, 0x75, 0x30, 0, 0, 0, 1];
+            body.extend(kafka_string(topic));
+            body.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 5]);
+            body.extend_from_slice(b"hello");
+            body
+        };
+        // replica_id(-1) + max_wait_ms + min_bytes + max_bytes + isolation_level
+        // + topics[topic + partitions[partition + fetch_offset + partition_max_bytes]]
+        let mut fetch = vec![
+            0xff, 0xff, 0xff, 0xff, 0, 0, 1, 0xf4, 0, 0, 0, 1, 0, 0x10, 0, 0, 0, 0, 0, 0, 1,
+        ];
+        fetch.extend(kafka_string("orders"));
+        fetch.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 0]);
+        fetch.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0x10, 0, 0]);
+        let mut batch = kafka_request(0, 3, 2, &produce("orders"));
+        batch.extend(kafka_request(0, 3, 3, &produce("missing")));
+        batch.extend(kafka_request(1, 4, 4, &fetch));
+        stream.write_all(&batch)?;
+        println!("Sent Produce orders / Produce missing / Fetch orders");
+        let n = stream.read(&mut buf)?;
+        println!("Received Kafka responses: {} bytes", n);
+    } else if mode == "otlp-collector" {
+        // Mock OTLP collector (HTTP/protobuf)，用于验证 Agent 的 Span 导出
+        // 用法: traffic_gen otlp-collector [前 N 次请求返回 503，用于验证重试]
+        use opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceRequest;
+        use prost::Message;
+        use std::io::{BufRead, BufReader, Read, Write};
+        use std::net::TcpListener;
+
+        let mut fail_first: usize = args.get(2).and_then(|n| n.parse().ok()).unwrap_or(0);
+        println!("Starting Mock OTLP Collector on 0.0.0.0:4318 (POST /v1/traces)...");
+        let listener = TcpListener::bind("0.0.0.0:4318")?;
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { continue };
+            let mut reader = BufReader::new(stream);
+
+            // 1. Request line + headers
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line)?;
+            let mut content_length = 0usize;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line)? == 0 || line == "\r\n" {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':')
+                    && name.eq_ignore_ascii_case("content-length")
+                {
+                    content_length = value.trim().parse().unwrap_or(0);
+                }
+            }
+
+            // 2. Body (protobuf ExportTraceServiceRequest)
+            let mut body = vec![0u8; content_length];
+            reader.read_exact(&mut body)?;
+            let mut stream = reader.into_inner();
+
+            if fail_first > 0 {
+                fail_first -= 1;
+                println!("Rejecting export with 503 ({} more to reject)", fail_first);
+                stream.write_all(
+                    b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                )?;
+                continue;
+            }
+
+            match ExportTraceServiceRequest::decode(body.as_slice()) {
+                Ok(request) => {
+                    let spans = request
+                        .resource_spans
+                        .iter()
+                        .flat_map(|rs| &rs.scope_spans)
+                        .flat_map(|ss| &ss.spans);
+                    for span in spans {
+                        let attrs: Vec<_> = span
+                            .attributes
+                            .iter()
+                            .map(|kv| {
+                                format!(
+                                    "{}={:?}",
+                                    kv.key,
+                                    kv.value.as_ref().and_then(|v| v.value.as_ref())
+                                )
+                            })
+                            .collect();
+                        println!(
+                            "Span: {} ({:.3}ms) {}",
+                            span.name,
+                            span.end_time_unix_nano
+                                .saturating_sub(span.start_time_unix_nano)
+                                as f64
+                                / 1_000_000.0,
+                            attrs.join(", ")
+                        );
+                    }
+                    // 空的 ExportTraceServiceResponse 编码后就是 0 字节
+                    stream.write_all(
+                        b"HTTP/1.1 200 OK\r\nContent-Type: application/x-protobuf\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    )?;
+                }
+                Err(e) => {
+                    println!("Invalid OTLP payload: {} ({})", e, request_line.trim());
+                    stream.write_all(
+                        b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    )?;
+                }
+            }
+        }
+    } else if mode == "mysql-session-server" {
+        use std::io::Write;
+        use std::net::TcpListener;
+        // 完整的命令周期: 握手 -> 登录 -> 查询 (结果集) / 预编译语句 / USE / 错误
+        println!("Starting Mock MySQL Session Server on 0.0.0.0:3307...");
+        let listener = TcpListener::bind("0.0.0.0:3307")?;
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else {
+                continue;
+            };
+            println!("MySQL client connected from {}!", peer(&stream));
+
+            // HandshakeV10: 协议版本 + 版本号 + 连接 ID + auth 数据 + 能力位等
+            let mut handshake = vec![0x0a];
+            handshake.extend_from_slice(b"8.0.36-mock\0");
+            handshake.extend_from_slice(&42u32.to_le_bytes());
+            handshake.extend_from_slice(b"abcdefgh\0");
+            handshake.extend_from_slice(&[0xff, 0xf7, 0x21, 0x02, 0x00, 0xff, 0x81, 21]);
+            handshake.extend_from_slice(&[0; 10]);
+            handshake.extend_from_slice(b"ijklmnopqrst\0mysql_native_password\0");
+            stream.write_all(&mysql_packet(0, &handshake))?;
+
+            let mut next_statement = 1u32;
+            while let Some((seq, body)) = read_mysql_packet(&mut stream)? {
+                if seq == 1 {
+                    println!("Login received, sending OK.");
+                    stream.write_all(&mysql_packet(2, &MYSQL_OK))?;
+                    continue;
+                }
+                thread::sleep(Duration::from_millis(10));
+                match body.first() {
+                    Some(0x03) => {
+                        let sql = String::from_utf8_lossy(&body[1..]).into_owned();
+                        println!("COM_QUERY: {}", sql);
+                        if sql.contains("missing") {
+                            let mut err = vec![0xff];
+                            err.extend_from_slice(&1146u16.to_le_bytes());
+                            err.extend_from_slice(b"#42S02Table 'shop.missing' doesn't exist");
+                            stream.write_all(&mysql_packet(1, &err))?;
+                        } else if sql.starts_with("SELECT") {
+                            // 列定义和行分两次 write，验证跨系统调用的结果集
+                            let (head, rows) = mysql_result_set(&["1", "2", "3"]);
+                            stream.write_all(&head)?;
+                            thread::sleep(Duration::from_millis(5));
+                            stream.write_all(&rows)?;
+                        } else {
+                            stream.write_all(&mysql_packet(
+                                1,
+                                &[0x00, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00],
+                            ))?;
+                        }
+                    }
+                    Some(0x16) => {
+                        // COM_STMT_PREPARE_OK: 1 列 1 参数，之后是参数定义、列定义，各带一个 EOF
+                        println!("COM_STMT_PREPARE: {}", String::from_utf8_lossy(&body[1..]));
+                        let mut ok = vec![0x00];
+                        ok.extend_from_slice(&next_statement.to_le_bytes());
+                        ok.extend_from_slice(&[0x01, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00]);
+                        next_statement += 1;
+                        let mut response = mysql_packet(1, &ok);
+                        response.extend(mysql_packet(2, &mysql_column("?")));
+                        response.extend(mysql_packet(3, &MYSQL_EOF));
+                        response.extend(mysql_packet(4, &mysql_column("name")));
+                        response.extend(mysql_packet(5, &MYSQL_EOF));
+                        stream.write_all(&response)?;
+                    }
+                    Some(0x17) => {
+                        println!(
+                            "COM_STMT_EXECUTE: stmt {}",
+                            u32::from_le_bytes([body[1], body[2], body[3], body[4]])
+                        );
+                        let (head, rows) = mysql_result_set(&["alice", "bob"]);
+                        stream.write_all(&[head, rows].concat())?;
+                    }
+                    Some(0x01) => break,
+                    Some(0x19) => {} // COM_STMT_CLOSE 没有响应
+                    _ => stream.write_all(&mysql_packet(1, &MYSQL_OK))?,
+                }
+            }
+            println!("MySQL client disconnected.");
+        }
+    } else if mode == "mysql-session-client" {
+        use std::io::Write;
+        println!("Mode: MySQL Session Client -> 127.0.0.1:3307");
+        let mut stream = TcpStream::connect("127.0.0.1:3307")?;
+
+        let Some((_, handshake)) = read_mysql_packet(&mut stream)? else {
+            return Ok(());
+        };
+        println!("Server handshake: {} bytes", handshake.len());
+
+        // HandshakeResponse41: PROTOCOL_41 | SECURE_CONNECTION | CONNECT_WITH_DB | PLUGIN_AUTH
+        let capabilities: u32 = 0x0000_0200 | 0x0000_8000 | 0x0000_0008 | 0x0008_0000;
+        let mut login = Vec::new();
+        login.extend_from_slice(&capabilities.to_le_bytes());
+        login.extend_from_slice(&(16u32 << 20).to_le_bytes());
+        login.push(0x21);
+        login.extend_from_slice(&[0; 23]);
+        login.extend_from_slice(b"app\0");
+        login.push(20);
+        login.extend_from_slice(&[0x5a; 20]);
+        login.extend_from_slice(b"shop\0mysql_native_password\0");
+        stream.write_all(&mysql_packet(1, &login))?;
+        read_mysql_response(&mut stream)?;
+
+        let commands: [(u8, &[u8]); 6] = [
+            (0x03, b"SELECT id FROM orders"),
+            (0x16, b"SELECT name FROM users WHERE id = ?"),
+            (0x17, &[1, 0, 0, 0, 0x00, 1, 0, 0, 0]),
+            (0x02, b"inventory"),
+            (0x03, b"SELECT * FROM missing"),
+            (0x03, b"UPDATE stock SET qty = qty - 1"),
+        ];
+        for (command, arg) in commands {
+            let mut body = vec![command];
+            body.extend_from_slice(arg);
+            stream.write_all(&mysql_packet(0, &body))?;
+            let packets = read_mysql_response(&mut stream)?;
+            println!("Command 0x{:02x}: {} response packets", command, packets);
+        }
+        stream.write_all(&mysql_packet(0, &[0x01]))?;
     } else {
         println!("Mode: HTTP (Default)");
         println!("Connecting to 1.1.1.1:80...");
@@ -288,3 +814,339 @@ fn main() -> std::io::Result<()> {
 
     Ok(())
 }
+
+// "127.0.0.1:54321 -> 0.0.0.0:3306"
+fn peer(stream: &TcpStream) -> String {
+    match (stream.peer_addr(), stream.local_addr()) {
+        (Ok(peer), Ok(local)) => format!("{} -> {}", peer, local),
+        _ => "<unknown>".to_string(),
+    }
+}
+
+// "--name VALUE" 形式的数字参数
+fn flag_value(args: &[String], name: &str) -> Option<u64> {
+    let pos = args.iter().position(|a| a == name)?;
+    args.get(pos + 1)?.parse().ok()
+}
+
+// MySQL OK 包: 0x00 + affected_rows(0) + last_insert_id(0) + status(2) + warnings(2)
+const MYSQL_OK: [u8; 7] = [0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00];
+// 经典 EOF 包: 0xfe + warnings(2) + status(2)
+const MYSQL_EOF: [u8; 5] = [0xfe, 0x00, 0x00, 0x02, 0x00];
+
+fn mysql_packet(seq: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = (body.len() as u32).to_le_bytes()[..3].to_vec();
+    packet.push(seq);
+    packet.extend_from_slice(body);
+    packet
+}
+
+// 读一个完整的包，连接关闭时返回 None
+fn read_mysql_packet(stream: &mut TcpStream) -> std::io::Result<Option<(u8, Vec<u8>)>> {
+    use std::io::Read;
+    let mut header = [0u8; 4];
+    if let Err(e) = stream.read_exact(&mut header) {
+        return match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => Ok(None),
+            _ => Err(e),
+        };
+    }
+    let len = u32::from_le_bytes([header[0], header[1], header[2], 0]) as usize;
+    let mut body = vec![0u8; len];
+    stream.read_exact(&mut body)?;
+    Ok(Some((header[3], body)))
+}
+
+// 读完一个命令的全部响应包 (OK / ERR / 结果集 / COM_STMT_PREPARE_OK)，返回包数
+fn read_mysql_response(stream: &mut TcpStream) -> std::io::Result<usize> {
+    let mut packets = 0;
+    let mut eofs_left = 0;
+    while let Some((_, body)) = read_mysql_packet(stream)? {
+        packets += 1;
+        match body.first() {
+            // PREPARE_OK 之后 (有参数 / 列时) 各跟一个 EOF
+            Some(0x00) if packets == 1 && body.len() == 12 => {
+                let columns = u16::from_le_bytes([body[5], body[6]]);
+                let params = u16::from_le_bytes([body[7], body[8]]);
+                eofs_left = usize::from(columns > 0) + usize::from(params > 0);
+                if eofs_left == 0 {
+                    break;
+                }
+            }
+            Some(0x00 | 0xff) if packets == 1 => break,
+            Some(0xfe) if body.len() < 9 => {
+                // 结果集: 列定义后一个 EOF，行结束后一个 EOF
+                eofs_left = eofs_left.saturating_sub(1);
+                if eofs_left == 0 {
+                    break;
+                }
+            }
+            _ if packets == 1 => eofs_left = 2,
+            _ => {}
+        }
+    }
+    Ok(packets)
+}
+
+// 最简的列定义 (Protocol::ColumnDefinition41)
+fn mysql_column(name: &str) -> Vec<u8> {
+    let mut column = Vec::new();
+    for field in ["def", "shop", "t", "t", name, name] {
+        column.push(field.len() as u8);
+        column.extend_from_slice(field.as_bytes());
+    }
+    column.extend_from_slice(&[
+        0x0c, 0x21, 0x00, 0xff, 0x00, 0x00, 0x00, 0xfd, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ]);
+    column
+}
+
+// 单列结果集，返回 (列数 + 列定义 + EOF, 行 + EOF)
+fn mysql_result_set(rows: &[&str]) -> (Vec<u8>, Vec<u8>) {
+    let mut head = mysql_packet(1, &[0x01]);
+    head.extend(mysql_packet(2, &mysql_column("v")));
+    head.extend(mysql_packet(3, &MYSQL_EOF));
+    let mut seq = 4;
+    let mut tail = Vec::new();
+    for row in rows {
+        let mut body = vec![row.len() as u8];
+        body.extend_from_slice(row.as_bytes());
+        tail.extend(mysql_packet(seq, &body));
+        seq += 1;
+    }
+    tail.extend(mysql_packet(seq, &MYSQL_EOF));
+    (head, tail)
+}
+
+fn pg_message(tag: u8, body: &[u8]) -> Vec<u8> {
+    let mut msg = vec![tag];
+    msg.extend_from_slice(&((body.len() + 4) as u32).to_be_bytes());
+    msg.extend_from_slice(body);
+    msg
+}
+
+// 按扩展查询的语义逐条回复: 1 ParseComplete / 2 BindComplete / T RowDescription / D+C 或 E / Z ReadyForQuery。
+// 查询 "missing" 的语句返回 ErrorResponse，之后的消息被跳过直到 Sync
+fn pg_extended_reply(data: &[u8]) -> Vec<u8> {
+    let mut resp = Vec::new();
+    let mut sql = String::new();
+    let mut failed = false;
+    let mut pos = 0;
+    while pos + 5 <= data.len() {
+        let tag = data[pos];
+        let len = u32::from_be_bytes([data[pos + 1], data[pos + 2], data[pos + 3], data[pos + 4]])
+            as usize;
+        let body = &data[(pos + 5).min(data.len())..(pos + 1 + len).min(data.len())];
+        pos += 1 + len;
+        if failed && tag != b'S' {
+            continue;
+        }
+        match tag {
+            b'P' => {
+                // 语句名 \0 SQL \0
+                let mut parts = body.split(|&b| b == 0);
+                parts.next();
+                sql = String::from_utf8_lossy(parts.next().unwrap_or_default()).into_owned();
+                resp.extend(pg_message(b'1', b""));
+            }
+            b'B' => resp.extend(pg_message(b'2', b"")),
+            b'D' if sql.starts_with("SELECT") => resp.extend(pg_message(b'T', &[0, 0])),
+            b'D' => resp.extend(pg_message(b'n', b"")),
+            b'E' if sql.contains("missing") => {
+                resp.extend(pg_message(
+                    b'E',
+                    b"SERROR\0VERROR\0C42P01\0Mrelation \"missing\" does not exist\0\0",
+                ));
+                failed = true;
+            }
+            b'E' if sql.starts_with("SELECT") => {
+                resp.extend(pg_message(
+                    b'D',
+                    &[0, 1, 0, 0, 0, 5, b'a', b'l', b'i', b'c', b'e'],
+                ));
+                resp.extend(pg_message(b'C', b"SELECT 1\0"));
+            }
+            b'E' => resp.extend(pg_message(b'C', b"INSERT 0 1\0")),
+            b'S' => {
+                resp.extend(pg_message(b'Z', if failed { b"E" } else { b"I" }));
+                failed = false;
+            }
+            _ => {}
+        }
+    }
+    resp
+}
+
+// 读到 ReadyForQuery (Z) 为止
+fn read_pg_until_ready(stream: &mut TcpStream) -> std::io::Result<()> {
+    use std::io::Read;
+    let mut received = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        received.extend_from_slice(&buf[..n]);
+        if received.len() >= 6 && received[received.len() - 6] == b'Z' {
+            println!("Received PG Response: {} bytes", received.len());
+            return Ok(());
+        }
+    }
+}
+
+// 按 RESP Array of Bulk String 切分一次 read 中的命令 (Mock 只处理完整的命令)
+fn redis_commands(data: &[u8]) -> Vec<Vec<String>> {
+    let text = String::from_utf8_lossy(data);
+    let mut lines = text.split("\r\n");
+    let mut commands = Vec::new();
+    while let Some(header) = lines.next() {
+        let Some(count) = header
+            .strip_prefix('*')
+            .and_then(|n| n.parse::<usize>().ok())
+        else {
+            continue;
+        };
+        let args: Vec<String> = (0..count)
+            .filter_map(|_| {
+                lines.next()?;
+                lines.next().map(str::to_string)
+            })
+            .collect();
+        commands.push(args);
+    }
+    commands
+}
+
+fn redis_reply(
+    cmd: &[String],
+    store: &mut std::collections::HashMap<String, String>,
+    queued: &mut Option<Vec<Vec<String>>>,
+) -> Vec<u8> {
+    let name = cmd
+        .first()
+        .map(|c| c.to_ascii_uppercase())
+        .unwrap_or_default();
+    if let Some(commands) = queued {
+        if name != "EXEC" {
+            commands.push(cmd.to_vec());
+            return b"+QUEUED\r\n".to_vec();
+        }
+        let commands = queued.take().unwrap_or_default();
+        let mut resp = format!("*{}\r\n", commands.len()).into_bytes();
+        for cmd in commands {
+            resp.extend(redis_reply(&cmd, store, queued));
+        }
+        return resp;
+    }
+    let key = cmd.get(1).cloned().unwrap_or_default();
+    match name.as_str() {
+        "MULTI" => {
+            *queued = Some(Vec::new());
+            b"+OK\r\n".to_vec()
+        }
+        // 带 hash tag 的 Key 模拟落在其他集群节点上
+        _ if key.starts_with('{') => b"-MOVED 3999 127.0.0.1:6381\r\n".to_vec(),
+        "SET" => {
+            store.insert(key, cmd.get(2).cloned().unwrap_or_default());
+            b"+OK\r\n".to_vec()
+        }
+        "GET" => match store.get(&key) {
+            Some(value) => format!("${}\r\n{}\r\n", value.len(), value).into_bytes(),
+            None => b"$-1\r\n".to_vec(),
+        },
+        "INCR" => {
+            let value = store.get(&key).and_then(|v| v.parse::<i64>().ok()).unwrap_or(0) + 1;
+            store.insert(key, value.to_string());
+            format!(":{}\r\n", value).into_bytes()
+        }
+        "LPUSH" if store.contains_key(&key) => {
+            b"-WRONGTYPE Operation against a key holding the wrong kind of value\r\n".to_vec()
+        }
+        "LPUSH" => b":1\r\n".to_vec(),
+        "SUBSCRIBE" => format!(
+            "*3\r\n$9\r\nsubscribe\r\n${}\r\n{}\r\n:1\r\n*3\r\n$7\r\nmessage\r\n${}\r\n{}\r\n$5\r\nhello\r\n",
+            key.len(),
+            key,
+            key.len(),
+            key
+        )
+        .into_bytes(),
+        // 其他命令一律回复 +OK
+        _ => b"+OK\r\n".to_vec(),
+    }
+}
+
+fn kafka_string(s: &str) -> Vec<u8> {
+    let mut out = (s.len() as i16).to_be_bytes().to_vec();
+    out.extend_from_slice(s.as_bytes());
+    out
+}
+
+// Size + api_key + api_version + correlation_id + client_id + body
+fn kafka_request(key: i16, version: i16, correlation_id: i32, body: &[u8]) -> Vec<u8> {
+    let mut msg = Vec::new();
+    msg.extend_from_slice(&key.to_be_bytes());
+    msg.extend_from_slice(&version.to_be_bytes());
+    msg.extend_from_slice(&correlation_id.to_be_bytes());
+    msg.extend(kafka_string("traffic-gen"));
+    msg.extend_from_slice(body);
+    let mut framed = (msg.len() as u32).to_be_bytes().to_vec();
+    framed.extend(msg);
+    framed
+}
+
+fn kafka_reply(request: &[u8]) -> Vec<u8> {
+    let key = i16::from_be_bytes([request[0], request[1]]);
+    let correlation_id = &request[4..8];
+    let client_len = i16::from_be_bytes([request[8], request[9]]).max(0) as usize;
+    let body = &request[10 + client_len..];
+    // 第一个 Topic 名: Produce v3 前面是 transactional_id(-1) + acks + timeout + 数组长度，Fetch v4 是 17 + 4 字节
+    let topic_at = match key {
+        0 => 12,
+        1 => 21,
+        _ => 0,
+    };
+    let topic = body.get(topic_at..).and_then(|b| {
+        let len = i16::from_be_bytes([*b.first()?, *b.get(1)?]) as usize;
+        b.get(2..2 + len)
+    });
+    let error: i16 = if topic == Some(b"missing") { 3 } else { 0 };
+    println!(
+        "Kafka request api_key={} topic={:?}",
+        key,
+        topic.map(String::from_utf8_lossy)
+    );
+
+    let mut resp = correlation_id.to_vec();
+    match key {
+        // error_code + api_keys[]
+        18 => resp.extend_from_slice(&[0, 0, 0, 0, 0, 0]),
+        // responses[name + partition_responses[index + error_code + base_offset + log_append_time]] + throttle_time_ms
+        0 => {
+            resp.extend_from_slice(&[0, 0, 0, 1]);
+            resp.extend_from_slice(&body[topic_at..topic_at + 2 + topic.map_or(0, <[u8]>::len)]);
+            resp.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 0]);
+            resp.extend_from_slice(&error.to_be_bytes());
+            resp.extend_from_slice(&42i64.to_be_bytes());
+            resp.extend_from_slice(&(-1i64).to_be_bytes());
+            resp.extend_from_slice(&[0, 0, 0, 0]);
+        }
+        // throttle_time_ms + responses[topic + partitions[index + error_code + high_watermark
+        //                    + last_stable_offset + aborted_transactions + records]]
+        1 => {
+            resp.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+            resp.extend_from_slice(&body[topic_at..topic_at + 2 + topic.map_or(0, <[u8]>::len)]);
+            resp.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 0]);
+            resp.extend_from_slice(&error.to_be_bytes());
+            resp.extend_from_slice(&43i64.to_be_bytes());
+            resp.extend_from_slice(&43i64.to_be_bytes());
+            resp.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0]);
+        }
+        // UNSUPPORTED_VERSION
+        _ => resp.extend_from_slice(&35i16.to_be_bytes()),
+    }
+    let mut framed = (resp.len() as u32).to_be_bytes().to_vec();
+    framed.extend(resp);
+    framed
+}
@@ -0,0 +1,482 @@
+// [BTF] struct sock 字段偏移量
+//
+// kprobe/tcp_connect 和 kretprobe/inet_csk_accept 直接从 struct sock 中读取五元组
+// (skc_family / skc_daddr / skc_rcv_saddr / skc_v6_* / skc_dport / skc_num)，这些字段的偏移量随内核版本和编译选项变化。
+// 启动时解析 /sys/kernel/btf/vmlinux 计算真实偏移量，在加载 eBPF 程序之前写入其全局常量 (.rodata)。
+// 没有 BTF 的内核 (CONFIG_DEBUG_INFO_BTF=n) 无法保证偏移量正确，直接拒绝启动。
+//
+// 这里只实现了读取结构体成员偏移量所需的最小 BTF 解析，格式参见内核文档 Documentation/bpf/btf.rst。
+
+use std::{borrow::Cow, fs, path::Path};
+
+use anyhow::{Context as _, bail};
+
+pub const VMLINUX_BTF: &str = "/sys/kernel/btf/vmlinux";
+
+const BTF_MAGIC: u16 = 0xEB9F;
+
+// BTF_KIND_*
+const KIND_INT: u8 = 1;
+const KIND_ARRAY: u8 = 3;
+const KIND_STRUCT: u8 = 4;
+const KIND_UNION: u8 = 5;
+const KIND_ENUM: u8 = 6;
+const KIND_TYPEDEF: u8 = 8;
+const KIND_VOLATILE: u8 = 9;
+const KIND_CONST: u8 = 10;
+const KIND_RESTRICT: u8 = 11;
+const KIND_FUNC_PROTO: u8 = 13;
+const KIND_VAR: u8 = 14;
+const KIND_DATASEC: u8 = 15;
+const KIND_DECL_TAG: u8 = 17;
+const KIND_TYPE_TAG: u8 = 18;
+const KIND_ENUM64: u8 = 19;
+
+struct Member {
+    name_off: u32,
+    type_id: u32,
+    bit_offset: u32,
+}
+
+struct Type {
+    name_off: u32,
+    kind: u8,
+    type_ref: u32,        // typedef / const / volatile 等修饰类型指向的类型
+    members: Vec<Member>, // 仅 struct / union
+}
+
+pub struct Btf {
+    types: Vec<Type>, // 下标即 type id，0 为 void
+    strings: Vec<u8>,
+}
+
+impl Btf {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let data = fs::read(path).with_context(|| {
+            format!(
+                "Failed to read kernel BTF {}. The kernel must be built with CONFIG_DEBUG_INFO_BTF=y",
+                path.display()
+            )
+        })?;
+        Self::parse(&data).with_context(|| format!("Failed to parse BTF {}", path.display()))
+    }
+
+    pub fn parse(data: &[u8]) -> anyhow::Result<Self> {
+        let u16_at = |off: usize| -> anyhow::Result<u16> {
+            let bytes = data.get(off..off + 2).context("truncated BTF")?;
+            Ok(u16::from_ne_bytes([bytes[0], bytes[1]]))
+        };
+        let u32_at = |off: usize| -> anyhow::Result<u32> {
+            let bytes = data.get(off..off + 4).context("truncated BTF")?;
+            Ok(u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        };
+
+        // struct btf_header { magic u16, version u8, flags u8, hdr_len, type_off, type_len, str_off, str_len }
+        if u16_at(0)? != BTF_MAGIC {
+            bail!("bad BTF magic (foreign endianness is not supported)");
+        }
+        let hdr_len = u32_at(4)? as usize;
+        let type_off = hdr_len + u32_at(8)? as usize;
+        let type_end = type_off + u32_at(12)? as usize;
+        let str_off = hdr_len + u32_at(16)? as usize;
+        let str_end = str_off + u32_at(20)? as usize;
+        let strings = data
+            .get(str_off..str_end)
+            .context("BTF string section out of range")?
+            .to_vec();
+
+        let mut types = vec![Type {
+            name_off: 0,
+            kind: 0,
+            type_ref: 0,
+            members: Vec::new(),
+        }];
+        let mut off = type_off;
+        while off < type_end {
+            // struct btf_type { name_off, info, size/type }
+            let name_off = u32_at(off)?;
+            let info = u32_at(off + 4)?;
+            let type_ref = u32_at(off + 8)?;
+            off += 12;
+
+            let vlen = (info & 0xffff) as usize;
+            let kind = ((info >> 24) & 0x1f) as u8;
+            let kind_flag = info >> 31 == 1;
+            let mut members = Vec::new();
+            match kind {
+                KIND_INT | KIND_VAR | KIND_DECL_TAG => off += 4,
+                KIND_ARRAY => off += 12,
+                KIND_STRUCT | KIND_UNION => {
+                    for i in 0..vlen {
+                        let m = off + i * 12;
+                        let raw = u32_at(m + 8)?;
+                        members.push(Member {
+                            name_off: u32_at(m)?,
+                            type_id: u32_at(m + 4)?,
+                            // kind_flag 置位时高 8 位是位域宽度
+                            bit_offset: if kind_flag { raw & 0x00ff_ffff } else { raw },
+                        });
+                    }
+                    off += vlen * 12;
+                }
+                KIND_ENUM | KIND_FUNC_PROTO => off += vlen * 8,
+                KIND_DATASEC | KIND_ENUM64 => off += vlen * 12,
+                0..=19 => {}
+                _ => bail!("unknown BTF kind {} at offset {}", kind, off - 12),
+            }
+            types.push(Type {
+                name_off,
+                kind,
+                type_ref,
+                members,
+            });
+        }
+        Ok(Btf { types, strings })
+    }
+
+    fn name(&self, off: u32) -> Cow<'_, str> {
+        let rest = self.strings.get(off as usize..).unwrap_or_default();
+        let end = rest.iter().position(|b| *b == 0).unwrap_or(rest.len());
+        String::from_utf8_lossy(&rest[..end])
+    }
+
+    // 按名字查找有成员定义的 struct (跳过前向声明)
+    fn struct_id(&self, name: &str) -> Option<u32> {
+        self.types
+            .iter()
+            .position(|t| {
+                t.kind == KIND_STRUCT && !t.members.is_empty() && self.name(t.name_off) == name
+            })
+            .map(|id| id as u32)
+    }
+
+    // 去掉 typedef / const / volatile 等修饰
+    fn resolve(&self, mut id: u32) -> u32 {
+        while let Some(t) = self.types.get(id as usize) {
+            match t.kind {
+                KIND_TYPEDEF | KIND_VOLATILE | KIND_CONST | KIND_RESTRICT | KIND_TYPE_TAG => {
+                    id = t.type_ref
+                }
+                _ => break,
+            }
+        }
+        id
+    }
+
+    // 成员相对于结构体起始处的位偏移，会递归进入匿名 struct / union
+    fn member_bits(&self, id: u32, field: &str) -> Option<u32> {
+        let t = self.types.get(self.resolve(id) as usize)?;
+        for m in &t.members {
+            if m.name_off == 0 {
+                if let Some(bits) = self.member_bits(m.type_id, field) {
+                    return Some(m.bit_offset + bits);
+                }
+            } else if self.name(m.name_off) == field {
+                return Some(m.bit_offset);
+            }
+        }
+        None
+    }
+
+    // 成员的字节偏移量，路径如 ["__sk_common", "skc_daddr"]
+    pub fn member_offset(&self, struct_name: &str, path: &[&str]) -> anyhow::Result<u32> {
+        let mut id = self
+            .struct_id(struct_name)
+            .with_context(|| format!("struct {} not found in BTF", struct_name))?;
+        let mut bits = 0;
+        for field in path {
+            bits += self.member_bits(id, field).with_context(|| {
+                format!("struct {} has no member {}", struct_name, path.join("."))
+            })?;
+            id = self.member_type(id, field).unwrap_or(0);
+        }
+        if bits % 8 != 0 {
+            bail!("{}.{} is a bitfield", struct_name, path.join("."));
+        }
+        Ok(bits / 8)
+    }
+
+    // 成员自身的类型 id (用于多级路径)
+    fn member_type(&self, id: u32, field: &str) -> Option<u32> {
+        let t = self.types.get(self.resolve(id) as usize)?;
+        for m in &t.members {
+            if m.name_off == 0 {
+                if let Some(ty) = self.member_type(m.type_id, field) {
+                    return Some(ty);
+                }
+            } else if self.name(m.name_off) == field {
+                return Some(m.type_id);
+            }
+        }
+        None
+    }
+}
+
+// 写入 eBPF 全局常量的 struct sock 偏移量 (字节)
+#[derive(Debug, Clone, Copy)]
+pub struct SockOffsets {
+    pub daddr: u32,     // skc_daddr     (对端地址，大端序)
+    pub rcv_saddr: u32, // skc_rcv_saddr (本端地址，大端序)
+    pub dport: u32,     // skc_dport     (对端端口，大端序)
+    pub num: u32,       // skc_num       (本端端口，主机序)
+    pub family: u32,    // skc_family
+    // skc_v6_daddr / skc_v6_rcv_saddr (IPv6 地址)，CONFIG_IPV6=n 的内核没有这两个字段
+    pub v6_daddr: Option<u32>,
+    pub v6_rcv_saddr: Option<u32>,
+}
+
+impl SockOffsets {
+    pub fn from_btf(btf: &Btf) -> anyhow::Result<Self> {
+        let field = |name| btf.member_offset("sock", &["__sk_common", name]);
+        // IPv6 地址要成对读取，只找到其中一个同样按不支持处理
+        let (v6_daddr, v6_rcv_saddr) =
+            match (field("skc_v6_daddr").ok(), field("skc_v6_rcv_saddr").ok()) {
+                (Some(daddr), Some(rcv_saddr)) => (Some(daddr), Some(rcv_saddr)),
+                _ => (None, None),
+            };
+        Ok(SockOffsets {
+            daddr: field("skc_daddr")?,
+            rcv_saddr: field("skc_rcv_saddr")?,
+            dport: field("skc_dport")?,
+            num: field("skc_num")?,
+            family: field("skc_family")?,
+            v6_daddr,
+            v6_rcv_saddr,
+        })
+    }
+
+    pub fn has_ipv6(&self) -> bool {
+        self.v6_daddr.is_some() && self.v6_rcv_saddr.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 按 Documentation/bpf/btf.rst 的布局手工拼装 BTF，type id 从 1 开始按添加顺序分配
+    struct BtfBuilder {
+        types: Vec<u8>,
+        strings: Vec<u8>,
+        next_id: u32,
+    }
+
+    impl BtfBuilder {
+        fn new() -> Self {
+            BtfBuilder {
+                types: Vec::new(),
+                strings: vec![0],
+                next_id: 1,
+            }
+        }
+
+        fn name(&mut self, name: &str) -> u32 {
+            if name.is_empty() {
+                return 0;
+            }
+            let off = self.strings.len() as u32;
+            self.strings.extend_from_slice(name.as_bytes());
+            self.strings.push(0);
+            off
+        }
+
+        fn push(&mut self, values: &[u32]) {
+            for v in values {
+                self.types.extend_from_slice(&v.to_ne_bytes());
+            }
+        }
+
+        fn header(
+            &mut self,
+            name: &str,
+            kind: u8,
+            kind_flag: bool,
+            vlen: usize,
+            size_or_type: u32,
+        ) -> u32 {
+            let name_off = self.name(name);
+            let info = (kind_flag as u32) << 31 | (kind as u32) << 24 | vlen as u32;
+            self.push(&[name_off, info, size_or_type]);
+            self.next_id += 1;
+            self.next_id - 1
+        }
+
+        fn int(&mut self, name: &str, size: u32) -> u32 {
+            let id = self.header(name, KIND_INT, false, 0, size);
+            self.push(&[size * 8]);
+            id
+        }
+
+        fn enumeration(&mut self, name: &str, values: &[&str]) -> u32 {
+            let id = self.header(name, KIND_ENUM, false, values.len(), 4);
+            for (i, value) in values.iter().enumerate() {
+                let name_off = self.name(value);
+                self.push(&[name_off, i as u32]);
+            }
+            id
+        }
+
+        fn modifier(&mut self, kind: u8, name: &str, target: u32) -> u32 {
+            self.header(name, kind, false, 0, target)
+        }
+
+        // members: (名字, 类型, 原始 offset 字段)，kind_flag 置位时 offset 高 8 位是位域宽度
+        fn composite(
+            &mut self,
+            kind: u8,
+            name: &str,
+            size: u32,
+            kind_flag: bool,
+            members: &[(&str, u32, u32)],
+        ) -> u32 {
+            let id = self.header(name, kind, kind_flag, members.len(), size);
+            for (member, type_id, offset) in members {
+                let name_off = self.name(member);
+                self.push(&[name_off, *type_id, *offset]);
+            }
+            id
+        }
+
+        fn build(self) -> Vec<u8> {
+            let mut data = Vec::new();
+            data.extend_from_slice(&BTF_MAGIC.to_ne_bytes());
+            data.extend_from_slice(&[1, 0]); // version, flags
+            for v in [
+                24,
+                0,
+                self.types.len() as u32,
+                self.types.len() as u32,
+                self.strings.len() as u32,
+            ] {
+                data.extend_from_slice(&v.to_ne_bytes());
+            }
+            data.extend(self.types);
+            data.extend(self.strings);
+            data
+        }
+    }
+
+    // 仿照内核的 struct sock:
+    //   struct sock { sock_common_t __sk_common; ... }
+    //   typedef const struct sock_common sock_common_t;   (typedef -> const -> struct 链)
+    //   struct sock_common {
+    //       union { u32 skc_addrpair; struct { __be32 skc_daddr; __be32 skc_rcv_saddr; }; };
+    //       __be16 skc_dport; u16 skc_num; u16 skc_family; unsigned skc_reuse:4; ...
+    //   }
+    fn sock_btf(ipv6: bool) -> Btf {
+        let mut b = BtfBuilder::new();
+        let uint = b.int("unsigned int", 4);
+        let ushort = b.int("unsigned short", 2);
+        b.enumeration("sk_state", &["TCP_ESTABLISHED", "TCP_SYN_SENT"]);
+        let be32 = b.modifier(KIND_TYPEDEF, "__be32", uint);
+        let be16 = b.modifier(KIND_TYPEDEF, "__be16", ushort);
+        let addrs = b.composite(
+            KIND_STRUCT,
+            "",
+            8,
+            false,
+            &[("skc_daddr", be32, 0), ("skc_rcv_saddr", be32, 32)],
+        );
+        let addrpair = b.composite(
+            KIND_UNION,
+            "",
+            8,
+            false,
+            &[("skc_addrpair", uint, 0), ("", addrs, 0)],
+        );
+        // 前向声明，查找时应被跳过
+        b.composite(KIND_STRUCT, "sock", 0, false, &[]);
+        let mut members = vec![
+            ("", addrpair, 0),
+            ("skc_dport", be16, 64),
+            ("skc_num", ushort, 80),
+            ("skc_family", ushort, 96),
+            ("skc_reuse", ushort, 4 << 24 | 116),
+        ];
+        if ipv6 {
+            members.extend([("skc_v6_daddr", uint, 128), ("skc_v6_rcv_saddr", uint, 256)]);
+        }
+        let common = b.composite(KIND_STRUCT, "sock_common", 48, true, &members);
+        let common_const = b.modifier(KIND_CONST, "", common);
+        let common_t = b.modifier(KIND_TYPEDEF, "sock_common_t", common_const);
+        b.composite(
+            KIND_STRUCT,
+            "sock",
+            56,
+            false,
+            &[("__sk_common", common_t, 0), ("sk_mark", uint, 384)],
+        );
+        Btf::parse(&b.build()).unwrap()
+    }
+
+    #[test]
+    fn member_offsets_through_anonymous_union_and_typedefs() {
+        let btf = sock_btf(true);
+        let offset = |path: &[&str]| btf.member_offset("sock", path).unwrap();
+        assert_eq!(offset(&["__sk_common", "skc_daddr"]), 0);
+        assert_eq!(offset(&["__sk_common", "skc_rcv_saddr"]), 4);
+        assert_eq!(offset(&["__sk_common", "skc_addrpair"]), 0);
+        assert_eq!(offset(&["__sk_common", "skc_dport"]), 8);
+        assert_eq!(offset(&["__sk_common", "skc_num"]), 10);
+        assert_eq!(offset(&["sk_mark"]), 48);
+        assert_eq!(
+            btf.member_offset("sock_common", &["skc_family"]).unwrap(),
+            12
+        );
+    }
+
+    #[test]
+    fn bitfield_and_missing_members_are_errors() {
+        let btf = sock_btf(true);
+        let err = btf
+            .member_offset("sock", &["__sk_common", "skc_reuse"])
+            .unwrap_err();
+        assert_eq!(err.to_string(), "sock.__sk_common.skc_reuse is a bitfield");
+        let err = btf
+            .member_offset("sock", &["__sk_common", "skc_bound_dev_if"])
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "struct sock has no member __sk_common.skc_bound_dev_if"
+        );
+        let err = btf.member_offset("tcp_sock", &["inet_conn"]).unwrap_err();
+        assert_eq!(err.to_string(), "struct tcp_sock not found in BTF");
+    }
+
+    #[test]
+    fn sock_offsets_with_and_without_ipv6() {
+        let sock = SockOffsets::from_btf(&sock_btf(true)).unwrap();
+        assert_eq!(
+            (
+                sock.daddr,
+                sock.rcv_saddr,
+                sock.dport,
+                sock.num,
+                sock.family
+            ),
+            (0, 4, 8, 10, 12)
+        );
+        assert_eq!((sock.v6_daddr, sock.v6_rcv_saddr), (Some(16), Some(32)));
+        assert!(sock.has_ipv6());
+
+        // CONFIG_IPV6=n: 其余字段照常可用
+        let sock = SockOffsets::from_btf(&sock_btf(false)).unwrap();
+        assert_eq!(sock.daddr, 0);
+        assert_eq!((sock.v6_daddr, sock.v6_rcv_saddr), (None, None));
+        assert!(!sock.has_ipv6());
+    }
+
+    #[test]
+    fn rejects_bad_magic_and_truncated_data() {
+        let mut data = BtfBuilder::new().build();
+        data[0] ^= 0xff;
+        assert!(Btf::parse(&data).is_err());
+
+        let mut b = BtfBuilder::new();
+        b.int("int", 4);
+        let mut data = b.build();
+        // 声明的类型段比实际数据长
+        data[12] += 4;
+        assert!(Btf::parse(&data).is_err());
+    }
+}
@@ -0,0 +1,465 @@
+// [K8s Context] Cgroup ID -> Pod / Container 解析器
+//
+// 内核侧 `bpf_get_current_cgroup_id()` 返回的是 cgroup v2 目录的 inode 号。
+// 我们在用户态扫描 cgroupfs (默认 /sys/fs/cgroup)，建立 inode -> 路径 的索引，
+// 再从路径中按 kubepods / docker / containerd 的命名约定拆出 Pod UID、容器 ID 和 QoS。
+// 索引通过 inotify 监听目录的创建/删除来保持最新。
+//
+// 根目录可以任意指定，因此可以直接在临时目录中伪造一棵 cgroup 树来验证解析逻辑。
+
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    fs, io,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    thread,
+};
+
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+use log::{debug, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QosClass {
+    Guaranteed,
+    Burstable,
+    BestEffort,
+}
+
+impl QosClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QosClass::Guaranteed => "guaranteed",
+            QosClass::Burstable => "burstable",
+            QosClass::BestEffort => "besteffort",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CgroupInfo {
+    pub path: String, // 相对 cgroup 根的路径，如 /kubepods/burstable/pod<uid>/<cid>
+    pub pod_uid: Option<String>, // Pod UID (统一为带 '-' 的标准格式)
+    pub container_id: Option<String>, // 64 位十六进制容器 ID
+    pub qos: Option<QosClass>, // 仅 kubepods 下的 cgroup 才有
+}
+
+impl CgroupInfo {
+    // 按路径约定解析，支持以下几种常见布局:
+    // cgroupfs 驱动:  /kubepods/burstable/pod<uid>/<cid>
+    //                 /kubepods/pod<uid>/<cid>                        (Guaranteed)
+    // systemd 驱动:   /kubepods.slice/kubepods-besteffort.slice/
+    //                   kubepods-besteffort-pod<uid_下划线>.slice/cri-containerd-<cid>.scope
+    // 纯 Docker:      /docker/<cid> 或 /system.slice/docker-<cid>.scope
+    pub fn parse(path: &str) -> Self {
+        let mut info = CgroupInfo {
+            path: path.to_string(),
+            ..Default::default()
+        };
+        let mut in_kubepods = false;
+
+        for seg in path.split('/').filter(|s| !s.is_empty()) {
+            if seg == "kubepods" || seg.starts_with("kubepods.") || seg.starts_with("kubepods-") {
+                in_kubepods = true;
+            }
+
+            // QoS 层级
+            if seg == "burstable" || seg.starts_with("kubepods-burstable") {
+                info.qos = Some(QosClass::Burstable);
+            } else if seg == "besteffort" || seg.starts_with("kubepods-besteffort") {
+                info.qos = Some(QosClass::BestEffort);
+            }
+
+            // Pod 层级
+            if let Some(uid) = parse_pod_segment(seg) {
+                info.pod_uid = Some(uid);
+                continue;
+            }
+
+            // 容器层级
+            if let Some(cid) = parse_container_segment(seg) {
+                info.container_id = Some(cid);
+            }
+        }
+
+        // kubepods 下没有 burstable/besteffort 层级的 Pod 即为 Guaranteed
+        if in_kubepods && info.pod_uid.is_some() && info.qos.is_none() {
+            info.qos = Some(QosClass::Guaranteed);
+        }
+        if !in_kubepods {
+            info.qos = None;
+        }
+        info
+    }
+
+    // 日志里展示的名称 (在没有 K8s API 元数据时使用)
+    pub fn display_name(&self) -> String {
+        let short_cid = self.container_id.as_deref().map(|c| &c[..12]);
+        match (&self.pod_uid, short_cid) {
+            (Some(uid), Some(cid)) => format!("pod-{}/{}", uid, cid),
+            (Some(uid), None) => format!("pod-{}", uid),
+            (None, Some(cid)) => format!("container-{}", cid),
+            (None, None) if self.path == "/" => "host".to_string(),
+            (None, None) => self.path.clone(),
+        }
+    }
+}
+
+// "pod<uid>" (cgroupfs) 或 "kubepods-<qos>-pod<uid_with_underscores>.slice" (systemd)
+fn parse_pod_segment(seg: &str) -> Option<String> {
+    let raw = if let Some(slice) = seg.strip_suffix(".slice") {
+        let idx = slice.rfind("-pod")?;
+        &slice[idx + 4..]
+    } else {
+        seg.strip_prefix("pod")?
+    };
+    let uid = raw.replace('_', "-");
+    let valid = uid.len() == 36 && uid.chars().all(|c| c.is_ascii_hexdigit() || c == '-');
+    valid.then_some(uid)
+}
+
+// "<cid>" (cgroupfs / docker) 或 "<runtime>-<cid>.scope" (systemd)
+fn parse_container_segment(seg: &str) -> Option<String> {
+    let raw = match seg.strip_suffix(".scope") {
+        Some(scope) => ["cri-containerd-", "docker-", "crio-", "containerd-"]
+            .iter()
+            .find_map(|prefix| scope.strip_prefix(prefix))?,
+        None => seg,
+    };
+    let valid = raw.len() == 64 && raw.chars().all(|c| c.is_ascii_hexdigit());
+    valid.then(|| raw.to_string())
+}
+
+#[derive(Clone)]
+pub struct CgroupResolver {
+    root: PathBuf,
+    index: Arc<RwLock<HashMap<u64, CgroupInfo>>>,
+}
+
+impl CgroupResolver {
+    // 创建解析器并完成首次全量扫描
+    pub fn new(root: impl Into<PathBuf>) -> io::Result<Self> {
+        let resolver = CgroupResolver {
+            root: root.into(),
+            index: Arc::new(RwLock::new(HashMap::new())),
+        };
+        resolver.rescan()?;
+        Ok(resolver)
+    }
+
+    pub fn resolve(&self, cgroup_id: u64) -> Option<CgroupInfo> {
+        self.index.read().ok()?.get(&cgroup_id).cloned()
+    }
+
+    // 替代原来的 resolve_pod: 找不到时返回 "unknown"
+    pub fn pod_name(&self, cgroup_id: u64) -> String {
+        self.resolve(cgroup_id)
+            .map(|info| info.display_name())
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.read().map(|m| m.len()).unwrap_or(0)
+    }
+
+    // 全量重建索引
+    pub fn rescan(&self) -> io::Result<()> {
+        let mut fresh = HashMap::new();
+        scan_tree(&self.root, &self.root, &mut fresh, &mut |_| {})?;
+        if let Ok(mut index) = self.index.write() {
+            *index = fresh;
+        }
+        Ok(())
+    }
+
+    // 启动 inotify 监听线程: 新建 cgroup 目录时加入索引，删除时移除。
+    // inotify 不支持递归监听，所以每个目录都需要单独 add watch。
+    pub fn watch(&self) -> io::Result<thread::JoinHandle<()>> {
+        let mut inotify = Inotify::init()?;
+        let mut watches = HashMap::<WatchDescriptor, PathBuf>::new();
+
+        // 先挂 watch 再扫描，避免漏掉两步之间创建的目录
+        let mut pending = vec![self.root.clone()];
+        while let Some(dir) = pending.pop() {
+            if let Ok(wd) = inotify.watches().add(&dir, watch_mask()) {
+                watches.insert(wd, dir.clone());
+            }
+            if let Ok(entries) = fs::read_dir(&dir) {
+                pending.extend(
+                    entries
+                        .flatten()
+                        .filter(|e| e.file_type().map(|t| t.is_dir()).unwrap_or(false))
+                        .map(|e| e.path()),
+                );
+            }
+        }
+        self.rescan()?;
+
+        let resolver = self.clone();
+        thread::Builder::new()
+            .name("cgroup-watch".to_string())
+            .spawn(move || {
+                let mut buffer = [0u8; 4096];
+                loop {
+                    let events = match inotify.read_events_blocking(&mut buffer) {
+                        Ok(events) => events,
+                        Err(e) => {
+                            warn!("cgroup inotify read failed: {}", e);
+                            return;
+                        }
+                    };
+
+                    let mut overflow = false;
+                    let mut created = Vec::new();
+                    let mut removed = Vec::new();
+                    for event in events {
+                        if event.mask.contains(EventMask::Q_OVERFLOW) {
+                            overflow = true;
+                            continue;
+                        }
+                        let (Some(parent), Some(name)) = (watches.get(&event.wd), event.name)
+                        else {
+                            continue;
+                        };
+                        let path = parent.join(name);
+                        if event.mask.contains(EventMask::CREATE) {
+                            created.push(path);
+                        } else if event.mask.contains(EventMask::DELETE) {
+                            removed.push(path);
+                        }
+                    }
+
+                    for path in created {
+                        resolver.add_subtree(&path, &mut |dir| {
+                            if let Ok(wd) = inotify.watches().add(dir, watch_mask()) {
+                                watches.insert(wd, dir.to_path_buf());
+                            }
+                        });
+                    }
+                    for path in removed {
+                        resolver.remove_subtree(&path);
+                        watches.retain(|_, dir| !dir.starts_with(&path));
+                    }
+                    if overflow {
+                        debug!("cgroup inotify queue overflow, rescanning");
+                        if let Err(e) = resolver.rescan() {
+                            warn!("cgroup rescan failed: {}", e);
+                        }
+                    }
+                }
+            })
+    }
+
+    fn add_subtree(&self, dir: &Path, on_dir: &mut dyn FnMut(&Path)) {
+        let mut found = HashMap::new();
+        if let Err(e) = scan_tree(&self.root, dir, &mut found, on_dir) {
+            debug!("cgroup scan of {} failed: {}", dir.display(), e);
+        }
+        if let Ok(mut index) = self.index.write() {
+            index.extend(found);
+        }
+    }
+
+    fn remove_subtree(&self, dir: &Path) {
+        let prefix = relative_path(&self.root, dir);
+        if let Ok(mut index) = self.index.write() {
+            index.retain(|_, info| {
+                info.path != prefix && !info.path.starts_with(&format!("{}/", prefix))
+            });
+        }
+    }
+}
+
+fn watch_mask() -> WatchMask {
+    WatchMask::CREATE | WatchMask::DELETE | WatchMask::ONLYDIR
+}
+
+// 递归扫描 dir，把每个目录的 inode 号 (即 cgroup id) 记入 index
+fn scan_tree(
+    root: &Path,
+    dir: &Path,
+    index: &mut HashMap<u64, CgroupInfo>,
+    on_dir: &mut dyn FnMut(&Path),
+) -> io::Result<()> {
+    let meta = fs::metadata(dir)?;
+    on_dir(dir);
+    index.insert(meta.ino(), CgroupInfo::parse(&relative_path(root, dir)));
+
+    for entry in fs::read_dir(dir)?.flatten() {
+        // cgroup 目录在扫描过程中随时可能被删除，单个子目录失败不影响整体
+        if entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+            let _ = scan_tree(root, &entry.path(), index, on_dir);
+        }
+    }
+    Ok(())
+}
+
+fn relative_path(root: &Path, dir: &Path) -> String {
+    let rel = dir.strip_prefix(root).unwrap_or(dir);
+    let parts: Vec<&str> = rel.iter().filter_map(OsStr::to_str).collect();
+    format!("/{}", parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    const POD_UID: &str = "0f3c6a2e-5b1d-4c8e-9a7f-1e2d3c4b5a69";
+    const CID_A: &str = "3b8f0c2d1e4a5b6c7d8e9f0a1b2c3d4e5f6a7b8c9d0e1f2a3b4c5d6e7f8a9b0c";
+    const CID_B: &str = "a1b2c3d4e5f6a7b8c9d0e1f2a3b4c5d6e7f8a9b0c1d2e3f4a5b6c7d8e9f0a1b2";
+
+    fn mkdir(root: &Path, rel: &str) -> u64 {
+        let dir = root.join(rel.trim_start_matches('/'));
+        fs::create_dir_all(&dir).unwrap();
+        fs::metadata(&dir).unwrap().ino()
+    }
+
+    // inotify 线程异步更新索引，轮询等待条件成立
+    fn wait_for(mut cond: impl FnMut() -> bool) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            if cond() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        false
+    }
+
+    #[test]
+    fn parse_cgroupfs_kubepods() {
+        let info = CgroupInfo::parse(&format!("/kubepods/burstable/pod{}/{}", POD_UID, CID_A));
+        assert_eq!(info.pod_uid.as_deref(), Some(POD_UID));
+        assert_eq!(info.container_id.as_deref(), Some(CID_A));
+        assert_eq!(info.qos, Some(QosClass::Burstable));
+
+        // 没有 QoS 层级的 Pod 是 Guaranteed
+        let info = CgroupInfo::parse(&format!("/kubepods/pod{}/{}", POD_UID, CID_A));
+        assert_eq!(info.qos, Some(QosClass::Guaranteed));
+    }
+
+    #[test]
+    fn parse_systemd_cri_containerd() {
+        let path = format!(
+            "/kubepods.slice/kubepods-besteffort.slice/kubepods-besteffort-pod{}.slice/cri-containerd-{}.scope",
+            POD_UID.replace('-', "_"),
+            CID_A
+        );
+        let info = CgroupInfo::parse(&path);
+        assert_eq!(info.pod_uid.as_deref(), Some(POD_UID));
+        assert_eq!(info.container_id.as_deref(), Some(CID_A));
+        assert_eq!(info.qos, Some(QosClass::BestEffort));
+        assert_eq!(
+            info.display_name(),
+            format!("pod-{}/{}", POD_UID, &CID_A[..12])
+        );
+    }
+
+    #[test]
+    fn parse_plain_docker() {
+        for path in [
+            format!("/docker/{}", CID_B),
+            format!("/system.slice/docker-{}.scope", CID_B),
+        ] {
+            let info = CgroupInfo::parse(&path);
+            assert_eq!(info.pod_uid, None);
+            assert_eq!(info.container_id.as_deref(), Some(CID_B));
+            assert_eq!(info.qos, None);
+        }
+
+        // 长度不对的段不是容器 ID
+        let info = CgroupInfo::parse("/system.slice/docker-abc.scope");
+        assert_eq!(info.container_id, None);
+        assert_eq!(CgroupInfo::parse("/").display_name(), "host");
+    }
+
+    #[test]
+    fn resolve_fake_tree() {
+        let root = tempfile::tempdir().unwrap();
+        let pod = mkdir(root.path(), &format!("/kubepods/burstable/pod{}", POD_UID));
+        let container = mkdir(
+            root.path(),
+            &format!("/kubepods/burstable/pod{}/{}", POD_UID, CID_A),
+        );
+        let systemd = mkdir(
+            root.path(),
+            &format!(
+                "/kubepods.slice/kubepods-pod{}.slice/cri-containerd-{}.scope",
+                POD_UID.replace('-', "_"),
+                CID_B
+            ),
+        );
+        let docker = mkdir(
+            root.path(),
+            &format!("/system.slice/docker-{}.scope", CID_B),
+        );
+
+        let resolver = CgroupResolver::new(root.path()).unwrap();
+        let root_id = fs::metadata(root.path()).unwrap().ino();
+        assert_eq!(resolver.resolve(root_id).unwrap().path, "/");
+
+        let info = resolver.resolve(pod).unwrap();
+        assert_eq!(info.pod_uid.as_deref(), Some(POD_UID));
+        assert_eq!(info.container_id, None);
+
+        let info = resolver.resolve(container).unwrap();
+        assert_eq!(
+            info.path,
+            format!("/kubepods/burstable/pod{}/{}", POD_UID, CID_A)
+        );
+        assert_eq!(info.container_id.as_deref(), Some(CID_A));
+        assert_eq!(info.qos, Some(QosClass::Burstable));
+
+        let info = resolver.resolve(systemd).unwrap();
+        assert_eq!(info.pod_uid.as_deref(), Some(POD_UID));
+        assert_eq!(info.container_id.as_deref(), Some(CID_B));
+        assert_eq!(info.qos, Some(QosClass::Guaranteed));
+
+        assert_eq!(
+            resolver.pod_name(docker),
+            format!("container-{}", &CID_B[..12])
+        );
+        assert_eq!(resolver.pod_name(u64::MAX), "unknown");
+    }
+
+    #[test]
+    fn watch_tracks_create_and_remove() {
+        let root = tempfile::tempdir().unwrap();
+        mkdir(root.path(), "/kubepods/besteffort");
+        let resolver = CgroupResolver::new(root.path()).unwrap();
+        resolver.watch().unwrap();
+        let before = resolver.len();
+
+        // 一次性创建 Pod 和容器两层目录: 容器目录可能在 Pod 目录的 watch 挂上之前就已存在，
+        // 依赖 add_subtree 的递归扫描补齐
+        let pod_rel = format!("/kubepods/besteffort/pod{}", POD_UID);
+        let container = mkdir(root.path(), &format!("{}/{}", pod_rel, CID_A));
+        assert!(wait_for(|| resolver.resolve(container).is_some()));
+        let info = resolver.resolve(container).unwrap();
+        assert_eq!(info.pod_uid.as_deref(), Some(POD_UID));
+        assert_eq!(info.qos, Some(QosClass::BestEffort));
+        assert_eq!(resolver.len(), before + 2);
+
+        // 新目录上也挂了 watch: 之后在其下创建的容器同样能被发现
+        let second = mkdir(root.path(), &format!("{}/{}", pod_rel, CID_B));
+        assert!(wait_for(|| resolver.resolve(second).is_some()));
+
+        // 删除容器目录后只移除该容器
+        fs::remove_dir(
+            root.path()
+                .join(format!("kubepods/besteffort/pod{}/{}", POD_UID, CID_A)),
+        )
+        .unwrap();
+        assert!(wait_for(|| resolver.resolve(container).is_none()));
+        assert!(resolver.resolve(second).is_some());
+
+        // 删除整个 Pod 目录后，Pod 及其下所有容器都被移除
+        let pod_dir = root.path().join(pod_rel.trim_start_matches('/'));
+        fs::remove_dir(pod_dir.join(CID_B)).unwrap();
+        fs::remove_dir(&pod_dir).unwrap();
+        assert!(wait_for(|| resolver.len() == before));
+    }
+}
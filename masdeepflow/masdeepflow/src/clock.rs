@@ -0,0 +1,38 @@
+// [Time] 内核事件时间戳
+//
+// bpf_ktime_get_ns 与用户态的 CLOCK_MONOTONIC 是同一个时钟 (系统启动以来，不含休眠)。
+// 耗时直接用两个内核时间戳相减；输出记录需要 Unix 时间，启动时测量一次
+// CLOCK_REALTIME 与 CLOCK_MONOTONIC 的差值，之后按固定偏移换算。
+// (运行期间 NTP 对墙上时钟的跳变不会反映到换算结果中，只影响记录的绝对时间，不影响耗时)
+
+pub fn monotonic_ns() -> u64 {
+    clock_ns(libc::CLOCK_MONOTONIC)
+}
+
+fn clock_ns(clock: libc::clockid_t) -> u64 {
+    let mut ts: libc::timespec = unsafe { std::mem::zeroed() };
+    unsafe { libc::clock_gettime(clock, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct WallClock {
+    offset_ns: u64, // CLOCK_REALTIME - CLOCK_MONOTONIC
+}
+
+impl WallClock {
+    pub fn calibrate() -> Self {
+        // 取前后两次单调时钟的中点，抵消读取墙上时钟本身的耗时
+        let before = monotonic_ns();
+        let realtime = clock_ns(libc::CLOCK_REALTIME);
+        let after = monotonic_ns();
+        WallClock {
+            offset_ns: realtime.saturating_sub(before + (after - before) / 2),
+        }
+    }
+
+    // 内核时间戳 -> Unix 时间戳 (纳秒)
+    pub fn to_unix_ns(self, ktime_ns: u64) -> u64 {
+        ktime_ns + self.offset_ns
+    }
+}
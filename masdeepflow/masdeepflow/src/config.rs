@@ -0,0 +1,457 @@
+// [Config] Agent 配置
+//
+// 配置来源 (优先级从低到高): 内置默认值 -> TOML 配置文件 (--config) -> 命令行参数。
+// 所有字段都有默认值，配置文件只需要写想修改的部分，例如:
+//
+//   cgroup_path = "/sys/fs/cgroup"
+//
+//   [probes]
+//   sock_accel = false
+//
+//   [protocol]
+//   port_hints = ["6380=redis", "6432=postgres"]
+//   session_idle_secs = 300
+//
+//   [output]
+//   format = "json"
+//   file = "/var/log/masdeepflow/l7.jsonl"
+//
+//   [filter]
+//   exclude_comm = ["sshd"]
+//
+//   [metrics]
+//   listen = "0.0.0.0:9435"
+//   max_series = 2000
+//
+//   [otlp]
+//   endpoint = "http://otel-collector:4318"
+//   protocol = "http"   # 或 "grpc"
+//
+//   [capture]
+//   payload_len = 4096  # 每次读写最多拷贝的载荷字节数
+//
+//   [transport]
+//   kind = "auto"       # 或 "ringbuf" / "perf"
+//   ringbuf_size_kb = 8192
+//   reorder_window_ms = 10
+//
+// --check-config 只做加载和校验，不触碰 eBPF，可以在没有 root 权限的 CI 中运行。
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context as _, bail};
+use clap::ValueEnum;
+use masdeepflow_common::MAX_PAYLOAD_LEN;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    metrics::MetricsConfig,
+    otlp::OtlpConfig,
+    protocol::{DecoderRegistry, PortHint},
+    record::OutputFormat,
+    transport::TransportConfig,
+};
+
+// drop_noise 丢弃的基础设施进程 (Agent 自身和 docker/dockerd 已在内核中按进程名过滤)
+const NOISE_COMMS: &[&str] = &["containerd", "containerd-shim", "runc", "kubelet"];
+// Docker Engine API 的 TCP 端口 (明文 / TLS)
+const NOISE_PORTS: &[u16] = &[2375, 2376];
+
+// 内核 task->comm 最多 15 个字符 (TASK_COMM_LEN = 16，含结尾 \0)
+const MAX_COMM_LEN: usize = 15;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub cgroup_path: PathBuf, // cgroup v2 挂载点: handle_sock_ops 挂载在这里，cgroup 索引也从这里扫描
+    pub probes: ProbeConfig,
+    pub k8s: K8sConfig,
+    pub output: OutputConfig,
+    pub protocol: ProtocolConfig,
+    pub filter: FilterConfig,
+    pub metrics: MetricsConfig,
+    pub otlp: OtlpConfig,
+    pub capture: CaptureConfig,
+    pub transport: TransportConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            cgroup_path: PathBuf::from("/sys/fs/cgroup"),
+            probes: ProbeConfig::default(),
+            k8s: K8sConfig::default(),
+            output: OutputConfig::default(),
+            protocol: ProtocolConfig::default(),
+            filter: FilterConfig::default(),
+            metrics: MetricsConfig::default(),
+            otlp: OtlpConfig::default(),
+            capture: CaptureConfig::default(),
+            transport: TransportConfig::default(),
+        }
+    }
+}
+
+// 探针分组，命令行通过 --enable / --disable 切换
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ProbeGroup {
+    Process,        // sched_process_exec
+    Connect,        // sys_enter/exit_connect + tcp_connect
+    Accept,         // inet_csk_accept + sys_exit_accept/accept4
+    ReadWrite,      // sys_enter_write + sys_enter/exit_read
+    SendtoRecvfrom, // sys_enter_sendto + sys_enter/exit_recvfrom
+    Vectored,       // readv/writev + sendmsg/recvmsg + sendmmsg/recvmmsg (enter/exit)
+    Close, // 连接关闭时的流量汇总 (FlowEvent) + sock:inet_sock_set_state；关闭后 sys_enter_close 仍用于清理连接表
+    SockAccel, // handle_sock_ops + redirect_traffic (Socket Acceleration)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProbeConfig {
+    pub process: bool,
+    pub connect: bool,
+    pub accept: bool,
+    pub read_write: bool,
+    pub sendto_recvfrom: bool,
+    pub vectored: bool,
+    pub close: bool,
+    pub sock_accel: bool,
+}
+
+impl Default for ProbeConfig {
+    fn default() -> Self {
+        ProbeConfig {
+            process: true,
+            connect: true,
+            accept: true,
+            read_write: true,
+            sendto_recvfrom: true,
+            vectored: true,
+            close: true,
+            sock_accel: true,
+        }
+    }
+}
+
+impl ProbeConfig {
+    pub fn set(&mut self, group: ProbeGroup, enabled: bool) {
+        let flag = match group {
+            ProbeGroup::Process => &mut self.process,
+            ProbeGroup::Connect => &mut self.connect,
+            ProbeGroup::Accept => &mut self.accept,
+            ProbeGroup::ReadWrite => &mut self.read_write,
+            ProbeGroup::SendtoRecvfrom => &mut self.sendto_recvfrom,
+            ProbeGroup::Vectored => &mut self.vectored,
+            ProbeGroup::Close => &mut self.close,
+            ProbeGroup::SockAccel => &mut self.sock_accel,
+        };
+        *flag = enabled;
+    }
+
+    // 是否有探针读写内核连接表 CONNECTIONS
+    pub fn uses_connections(&self) -> bool {
+        self.connect
+            || self.accept
+            || self.read_write
+            || self.sendto_recvfrom
+            || self.vectored
+            || self.close
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct K8sConfig {
+    pub enabled: bool,
+    pub kubeconfig: Option<PathBuf>, // 指定后隐含 enabled = true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    pub format: OutputFormat,
+    pub file: Option<PathBuf>, // 仅 json 格式使用，默认 stdout
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        OutputConfig {
+            format: OutputFormat::Log,
+            file: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProtocolConfig {
+    pub port_hints: Vec<PortHint>, // "PORT=PROTOCOL"，优先于内容推断
+    pub session_idle_secs: u64,    // 连接空闲超过该时长即回收其用户态状态 (关闭事件丢失时的兜底)
+}
+
+impl Default for ProtocolConfig {
+    fn default() -> Self {
+        ProtocolConfig {
+            port_hints: Vec::new(),
+            session_idle_secs: 300,
+        }
+    }
+}
+
+// [Capture] 载荷拷贝长度越大，能看到的 SQL / URL / Header 越完整，但每个事件的拷贝和传输开销也越大
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CaptureConfig {
+    pub payload_len: u32, // 每次读写最多拷贝的字节数，1..=MAX_PAYLOAD_LEN
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        CaptureConfig { payload_len: 1024 }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilterConfig {
+    pub comm: Vec<String>,         // 非空时只保留这些进程名
+    pub exclude_comm: Vec<String>, // 丢弃这些进程名
+    pub ports: Vec<u16>,           // 非空时只保留本端或对端端口在列表中的连接
+    pub drop_noise: bool,          // 按进程名 / 端口丢弃容器运行时、kubelet 等基础设施流量
+}
+
+impl FilterConfig {
+    pub fn allows_comm(&self, comm: &str) -> bool {
+        (self.comm.is_empty() || self.comm.iter().any(|c| c == comm))
+            && !self.exclude_comm.iter().any(|c| c == comm)
+    }
+
+    // 端口为 0 说明连接信息未知 (如未观测到 connect)，此时无法判断，只要配置了端口过滤就丢弃
+    // (即使 ports 中写了 0 也不匹配)
+    pub fn allows_ports(&self, sport: u16, dport: u16) -> bool {
+        self.ports.is_empty()
+            || [sport, dport]
+                .iter()
+                .any(|port| *port != 0 && self.ports.contains(port))
+    }
+
+    pub fn is_noise(&self, comm: &str, sport: u16, dport: u16) -> bool {
+        self.drop_noise
+            && (NOISE_COMMS.contains(&comm)
+                || NOISE_PORTS.contains(&sport)
+                || NOISE_PORTS.contains(&dport))
+    }
+}
+
+impl Config {
+    // path 为空时使用默认配置
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        let Some(path) = path else {
+            return Ok(Config::default());
+        };
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        toml::from_str(&text)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))
+    }
+
+    pub fn k8s_enabled(&self) -> bool {
+        self.k8s.enabled || self.k8s.kubeconfig.is_some()
+    }
+
+    // 按配置构建解码器注册表 (内置协议 + 端口提示)
+    pub fn registry(&self) -> anyhow::Result<DecoderRegistry> {
+        let mut registry = DecoderRegistry::with_builtin();
+        for hint in &self.protocol.port_hints {
+            if !registry.add_port_hint(*hint) {
+                bail!("no decoder registered for port hint '{}'", hint);
+            }
+        }
+        Ok(registry)
+    }
+
+    // 校验只依赖文件系统，不需要加载 eBPF
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !self.cgroup_path.is_dir() {
+            bail!(
+                "cgroup_path {} is not a directory. Ensure Cgroup V2 is mounted there",
+                self.cgroup_path.display()
+            );
+        }
+        if let Some(kubeconfig) = &self.k8s.kubeconfig
+            && !kubeconfig.is_file()
+        {
+            bail!("k8s.kubeconfig {} does not exist", kubeconfig.display());
+        }
+        if let Some(file) = &self.output.file {
+            if self.output.format != OutputFormat::Json {
+                bail!("output.file is only used with output.format = \"json\"");
+            }
+            let dir = file.parent().filter(|d| !d.as_os_str().is_empty());
+            if let Some(dir) = dir
+                && !dir.is_dir()
+            {
+                bail!("output.file directory {} does not exist", dir.display());
+            }
+        }
+        self.registry()?;
+        if self.protocol.session_idle_secs == 0 {
+            bail!("protocol.session_idle_secs must be greater than 0");
+        }
+        for comm in self.filter.comm.iter().chain(&self.filter.exclude_comm) {
+            if comm.is_empty() || comm.len() > MAX_COMM_LEN {
+                bail!(
+                    "filter comm '{}' must be 1..={} bytes (kernel truncates task names)",
+                    comm,
+                    MAX_COMM_LEN
+                );
+            }
+        }
+        if self.metrics.max_series == 0 {
+            bail!("metrics.max_series must be greater than 0");
+        }
+        if self
+            .metrics
+            .buckets_ms
+            .iter()
+            .any(|ms| !ms.is_finite() || *ms <= 0.0)
+        {
+            bail!("metrics.buckets_ms must be positive numbers");
+        }
+        if let Some(endpoint) = &self.otlp.endpoint
+            && !(endpoint.starts_with("http://") || endpoint.starts_with("https://"))
+        {
+            bail!(
+                "otlp.endpoint '{}' must start with http:// or https://",
+                endpoint
+            );
+        }
+        if self.otlp.batch_size == 0 || self.otlp.queue_size == 0 {
+            bail!("otlp.batch_size and otlp.queue_size must be greater than 0");
+        }
+        if self.capture.payload_len == 0 || self.capture.payload_len as usize > MAX_PAYLOAD_LEN {
+            bail!("capture.payload_len must be 1..={} bytes", MAX_PAYLOAD_LEN);
+        }
+        if self.transport.ringbuf_size_kb == 0 {
+            bail!("transport.ringbuf_size_kb must be greater than 0");
+        }
+        let probes = &self.probes;
+        if !(probes.process
+            || probes.connect
+            || probes.accept
+            || probes.read_write
+            || probes.sendto_recvfrom
+            || probes.vectored
+            || probes.close
+            || probes.sock_accel)
+        {
+            bail!("all probe groups are disabled, nothing to do");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write as _;
+
+    use super::*;
+
+    // 写入临时 TOML 文件并加载
+    fn load_str(text: &str) -> anyhow::Result<Config> {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+        Config::load(Some(file.path()))
+    }
+
+    // cgroup_path 指向临时目录，使 validate 不依赖本机的 cgroup 挂载
+    fn valid_config(cgroup: &tempfile::TempDir) -> Config {
+        Config {
+            cgroup_path: cgroup.path().to_path_buf(),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn minimal_file_merges_over_defaults() {
+        let config = load_str(
+            r#"
+            [probes]
+            sock_accel = false
+
+            [capture]
+            payload_len = 4096
+            "#,
+        )
+        .unwrap();
+        assert!(!config.probes.sock_accel);
+        assert_eq!(config.capture.payload_len, 4096);
+        // 未写的字段 (包括同一节中的其他字段) 保持默认值
+        assert!(config.probes.process && config.probes.connect);
+        assert_eq!(config.cgroup_path, PathBuf::from("/sys/fs/cgroup"));
+        assert_eq!(config.protocol.session_idle_secs, 300);
+        assert_eq!(config.transport.ringbuf_size_kb, 8192);
+        assert!(!config.filter.drop_noise);
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        // 顶层、节内的拼写错误都要报错，而不是被静默忽略
+        assert!(load_str("cgroup_pth = \"/sys/fs/cgroup\"").is_err());
+        assert!(load_str("[probes]\nsock_acel = false").is_err());
+        assert!(load_str("[capture]\npayload = 64").is_err());
+    }
+
+    #[test]
+    fn payload_len_must_be_in_range() {
+        let cgroup = tempfile::tempdir().unwrap();
+        let mut config = valid_config(&cgroup);
+        config.validate().unwrap();
+
+        config.capture.payload_len = 0;
+        assert!(config.validate().is_err());
+        config.capture.payload_len = MAX_PAYLOAD_LEN as u32 + 1;
+        assert!(config.validate().is_err());
+        config.capture.payload_len = MAX_PAYLOAD_LEN as u32;
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn unknown_port_hint_is_rejected() {
+        assert!(load_str("[protocol]\nport_hints = [\"6380=smtp\"]").is_err());
+        assert!(load_str("[protocol]\nport_hints = [\"redis\"]").is_err());
+
+        let cgroup = tempfile::tempdir().unwrap();
+        let mut config = valid_config(&cgroup);
+        config.protocol.port_hints = vec!["6380=redis".parse().unwrap()];
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn disabling_every_probe_group_is_rejected() {
+        let cgroup = tempfile::tempdir().unwrap();
+        let mut config = valid_config(&cgroup);
+        for group in ProbeGroup::value_variants() {
+            config.probes.set(*group, false);
+        }
+        assert!(config.validate().is_err());
+        config.probes.set(ProbeGroup::Process, true);
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn port_filter_never_matches_unknown_ports() {
+        let filter = FilterConfig {
+            ports: vec![0, 6379],
+            ..FilterConfig::default()
+        };
+        assert!(filter.allows_ports(40000, 6379));
+        assert!(!filter.allows_ports(0, 0));
+        assert!(!filter.allows_ports(40000, 0));
+        // 未配置端口过滤时全部放行
+        assert!(FilterConfig::default().allows_ports(0, 0));
+    }
+}
@@ -0,0 +1,258 @@
+// [Conntrack] 预填充内核连接表 CONNECTIONS
+//
+// 内核侧只能在 connect/accept 时把 (tgid, fd) 和五元组关联起来，
+// Agent 启动前就已经建立的长连接 (连接池、数据库连接等) 永远不会再触发这两个事件。
+// 启动时扫描一遍 /proc 补齐:
+//   /proc/<pid>/fd/<fd>      -> "socket:[<inode>]"
+//   /proc/<pid>/net/tcp(6)   -> inode -> 本端/对端地址 (按进程所在的 network namespace 读取)
+// 同一个 netns 的 /proc/<pid>/net/tcp 和 tcp6 只解析一次。
+// 这些连接的真实建立时间无从得知，start_ns 记为扫描时刻，关闭时的 duration 是下限。
+
+use std::{collections::HashMap, fs, io, os::unix::fs::MetadataExt, path::Path};
+
+use aya::maps::{HashMap as BpfHashMap, MapData};
+use log::debug;
+use masdeepflow_common::{AF_INET, AF_INET6, ConnInfo, ConnKey, ipv4_mapped};
+
+// /proc/net/tcp 中的 TCP_LISTEN，监听 socket 上不会有数据事件
+const TCP_LISTEN: u8 = 0x0A;
+
+// 返回写入的连接数
+pub fn seed(
+    proc_root: &Path,
+    connections: &mut BpfHashMap<&mut MapData, ConnKey, ConnInfo>,
+) -> io::Result<usize> {
+    // netns inode -> (socket inode -> 五元组)
+    let mut tables: HashMap<u64, HashMap<u64, ConnInfo>> = HashMap::new();
+    let mut seeded = 0;
+    let now = monotonic_ns();
+
+    for entry in fs::read_dir(proc_root)? {
+        let Ok(entry) = entry else { continue };
+        let Some(tgid) = entry
+            .file_name()
+            .to_str()
+            .and_then(|s| s.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        let dir = entry.path();
+        // 进程可能在扫描过程中退出，任何错误都只跳过这个进程
+        let Ok(netns) = fs::metadata(dir.join("ns/net")).map(|m| m.ino()) else {
+            continue;
+        };
+        let table = tables.entry(netns).or_insert_with(|| {
+            let mut table = HashMap::new();
+            for (file, family) in [("net/tcp", AF_INET), ("net/tcp6", AF_INET6)] {
+                // 内核未开启 IPv6 时没有 tcp6
+                if let Ok(text) = fs::read_to_string(dir.join(file)) {
+                    table.extend(parse_tcp_table(&text, family, now));
+                }
+            }
+            table
+        });
+        if table.is_empty() {
+            continue;
+        }
+
+        let Ok(fds) = fs::read_dir(dir.join("fd")) else {
+            continue;
+        };
+        for fd in fds.flatten() {
+            let Some(fd_num) = fd.file_name().to_str().and_then(|s| s.parse::<u32>().ok()) else {
+                continue;
+            };
+            let Some(inode) = fs::read_link(fd.path())
+                .ok()
+                .and_then(|target| socket_inode(&target.to_string_lossy()))
+            else {
+                continue;
+            };
+            if let Some(info) = table.get(&inode) {
+                let key = ConnKey { tgid, fd: fd_num };
+                match connections.insert(key, *info, 0) {
+                    Ok(()) => seeded += 1,
+                    Err(e) => debug!("[Conntrack] insert {}/{} failed: {}", tgid, fd_num, e),
+                }
+            }
+        }
+    }
+    Ok(seeded)
+}
+
+// "socket:[12345]" -> 12345
+fn socket_inode(link: &str) -> Option<u64> {
+    link.strip_prefix("socket:[")?
+        .strip_suffix(']')?
+        .parse()
+        .ok()
+}
+
+// /proc/net/tcp 每行:
+//   sl  local_address rem_address   st tx_queue:rx_queue tr:tm->when retrnsmt uid timeout inode
+//   0: 0100007F:0CEA 0100007F:A1B2 01 00000000:00000000 00:00000000 00000000 1000 0 12345 ...
+// 地址是把网络序的 __be32 按本机 u32 打印的十六进制，原样解析回 u32 即得到与内核事件一致的值；
+// tcp6 的地址是 4 个这样的 u32 依次拼接。端口是主机序，需要转回大端序。
+fn parse_tcp_table(text: &str, family: u16, start_ns: u64) -> HashMap<u64, ConnInfo> {
+    let mut table = HashMap::new();
+    for line in text.lines().skip(1) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 10 {
+            continue;
+        }
+        let (Some((saddr, sport)), Some((daddr, dport))) =
+            (parse_endpoint(fields[1]), parse_endpoint(fields[2]))
+        else {
+            continue;
+        };
+        let Ok(state) = u8::from_str_radix(fields[3], 16) else {
+            continue;
+        };
+        let Ok(inode) = fields[9].parse::<u64>() else {
+            continue;
+        };
+        if state == TCP_LISTEN || inode == 0 {
+            continue;
+        }
+        table.insert(
+            inode,
+            ConnInfo {
+                saddr,
+                daddr,
+                sport: sport.to_be(),
+                dport: dport.to_be(),
+                family,
+                _pad: 0,
+                start_ns,
+                bytes_sent: 0,
+                bytes_recv: 0,
+                packets_sent: 0,
+                packets_recv: 0,
+            },
+        );
+    }
+    table
+}
+
+// "0100007F:0CEA" -> (地址, 主机序端口)，IPv4 转为 IPv4-mapped 与内核事件一致
+fn parse_endpoint(field: &str) -> Option<([u8; 16], u16)> {
+    let (addr, port) = field.split_once(':')?;
+    let addr = match addr.len() {
+        8 => ipv4_mapped(u32::from_str_radix(addr, 16).ok()?),
+        32 => {
+            let mut bytes = [0u8; 16];
+            for (i, chunk) in bytes.chunks_exact_mut(4).enumerate() {
+                let word = u32::from_str_radix(addr.get(i * 8..i * 8 + 8)?, 16).ok()?;
+                chunk.copy_from_slice(&word.to_ne_bytes());
+            }
+            bytes
+        }
+        _ => return None,
+    };
+    Some((addr, u16::from_str_radix(port, 16).ok()?))
+}
+
+// 与内核 bpf_ktime_get_ns() 同一时钟 (CLOCK_MONOTONIC)
+fn monotonic_ns() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+// 夹具取自 x86_64 主机上的真实 /proc/net/tcp(6) 输出，地址字段的十六进制依赖本机字节序
+#[cfg(all(test, target_endian = "little"))]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use super::*;
+
+    const TCP: &str = "\
+  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 0100007F:0CEA 00000000:0000 0A 00000000:00000000 00:00000000 00000000   999        0 23001 1 0000000000000000 100 0 0 10 0
+   1: 0500000A:C822 0900000A:0CEA 01 00000000:00000000 02:000A7B2C 00000000  1000        0 23456 2 0000000000000000 20 4 30 10 -1
+   2: 0500000A:C824 0900000A:0CEA 06 00000000:00000000 03:00001770 00000000     0        0 0 3 0000000000000000
+   3: 0100007F:1F90 0100007F:E3A6 08 00000000:00000001 00:00000000 00000000  1000        0 23999 1 0000000000000000 20 4 0 10 -1
+";
+
+    const TCP6: &str = "\
+  sl  local_address                         remote_address                        st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 00000000000000000000000000000000:0050 00000000000000000000000000000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 31001 1 0000000000000000 100 0 0 10 0
+   1: B80D0120000000000000000001000000:D431 B80D0120000000000000000002000000:01BB 01 00000000:00000000 02:00000E4B 00000000  1000        0 31002 2 0000000000000000 20 4 28 10 -1
+   2: 0000000000000000FFFF00000100007F:1F90 0000000000000000FFFF00000100007F:E3A8 01 00000000:00000000 00:00000000 00000000  1000        0 31003 1 0000000000000000 20 4 0 10 -1
+";
+
+    fn ip(addr: [u8; 16]) -> IpAddr {
+        Ipv6Addr::from(addr).to_canonical()
+    }
+
+    #[test]
+    fn endpoint_byte_order() {
+        // 10.0.0.5:51234，地址保持内核 __be32 的内存布局
+        let (addr, port) = parse_endpoint("0500000A:C822").unwrap();
+        assert_eq!(ip(addr), IpAddr::V4(Ipv4Addr::new(10, 0, 0, 5)));
+        assert_eq!(addr[12..], [10, 0, 0, 5]);
+        assert_eq!(port, 51234);
+
+        let (addr, port) = parse_endpoint("B80D0120000000000000000001000000:01BB").unwrap();
+        assert_eq!(ip(addr), "2001:db8::1".parse::<IpAddr>().unwrap());
+        assert_eq!(port, 443);
+
+        assert!(parse_endpoint("0100007F").is_none());
+        assert!(parse_endpoint("7F:0050").is_none());
+        assert!(parse_endpoint("0100007G:0050").is_none());
+    }
+
+    #[test]
+    fn tcp_table_skips_listen_and_orphan_sockets() {
+        let table = parse_tcp_table(TCP, AF_INET, 7);
+        // LISTEN 和 inode 为 0 (TIME_WAIT 等已脱离进程) 的行被跳过
+        let mut inodes: Vec<_> = table.keys().copied().collect();
+        inodes.sort();
+        assert_eq!(inodes, [23456, 23999]);
+
+        let info = table[&23456];
+        assert_eq!(ip(info.saddr), IpAddr::V4(Ipv4Addr::new(10, 0, 0, 5)));
+        assert_eq!(ip(info.daddr), IpAddr::V4(Ipv4Addr::new(10, 0, 0, 9)));
+        // 端口与内核事件一样是网络序
+        assert_eq!(info.sport.to_ne_bytes(), 51234u16.to_be_bytes());
+        assert_eq!(u16::from_be(info.dport), 3306);
+        assert_eq!(info.family, AF_INET);
+        assert_eq!(info.start_ns, 7);
+        assert_eq!(info.bytes_sent, 0);
+    }
+
+    #[test]
+    fn tcp6_table() {
+        let table = parse_tcp_table(TCP6, AF_INET6, 0);
+        assert_eq!(table.len(), 2);
+
+        let info = table[&31002];
+        assert_eq!(ip(info.saddr), "2001:db8::1".parse::<IpAddr>().unwrap());
+        assert_eq!(ip(info.daddr), "2001:db8::2".parse::<IpAddr>().unwrap());
+        assert_eq!(u16::from_be(info.sport), 54321);
+        assert_eq!(u16::from_be(info.dport), 443);
+        assert_eq!(info.family, AF_INET6);
+
+        // 双栈 socket 上的 IPv4 连接以 IPv4-mapped 地址出现在 tcp6 中
+        let info = table[&31003];
+        assert_eq!(info.saddr, ipv4_mapped(u32::from_ne_bytes([127, 0, 0, 1])));
+        assert_eq!(u16::from_be(info.sport), 8080);
+        assert_eq!(u16::from_be(info.dport), 58280);
+    }
+
+    #[test]
+    fn malformed_lines_are_ignored() {
+        let text = "header\n   0: 0500000A:C822 0900000A:0CEA 01\n   1: zz:C822 0900000A:0CEA 01 0:0 0:0 0 0 0 1\n";
+        assert!(parse_tcp_table(text, AF_INET, 0).is_empty());
+    }
+
+    #[test]
+    fn socket_link_target() {
+        assert_eq!(socket_inode("socket:[23456]"), Some(23456));
+        assert_eq!(socket_inode("pipe:[23456]"), None);
+        assert_eq!(socket_inode("/dev/null"), None);
+    }
+}
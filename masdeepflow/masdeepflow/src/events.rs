@@ -0,0 +1,417 @@
+// [Events] 内核事件处理
+//
+// 所有事件经同一个通道 (RingBuf 或 perf 降级模式下的 PerfEventArray) 到达用户态，
+// 每条记录以 kind 开头，这里按 kind 分发到进程 / TCP / 连接关闭三类处理逻辑。
+// 记录是变长的: TcpEvent 头部之后紧跟 captured_len 字节的载荷。
+
+use std::{
+    collections::HashMap,
+    mem::{MaybeUninit, size_of},
+    net::Ipv6Addr,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use log::{debug, info};
+use masdeepflow_common::{
+    EVENT_FLOW, EVENT_PROCESS, EVENT_TCP, FlowEvent, MAX_PAYLOAD_LEN, ProcessEvent, TcpEvent,
+};
+
+use crate::{
+    SessionKey,
+    clock::WallClock,
+    config::FilterConfig,
+    k8s::WorkloadResolver,
+    metrics::Metrics,
+    otlp::OtlpExporter,
+    protocol::{Direction, L7Tracker, Payload},
+    record::{CloseReason, FlowRecord, L7Record, RecordSink, Role},
+};
+
+// 单条记录的最大长度，perf 降级模式按此分配读取缓冲区
+pub const MAX_RECORD_LEN: usize = max(
+    size_of::<TcpEvent>() + MAX_PAYLOAD_LEN,
+    max(size_of::<ProcessEvent>(), size_of::<FlowEvent>()),
+);
+
+const fn max(a: usize, b: usize) -> usize {
+    if a > b { a } else { b }
+}
+
+// 两次空闲回收之间至少间隔的内核时间
+const SWEEP_INTERVAL_NS: u64 = 10_000_000_000;
+
+// [Conntrack] 用户态的连接附加信息，连接关闭时与内核统计合并为 FlowRecord
+// 内核在软中断中检测到的关闭没有可靠的进程上下文，comm / cgroup 以这里记录的为准
+struct FlowMeta {
+    comm: String,
+    cgroup_id: u64,
+    role: Option<Role>,
+    l7_requests: u64,
+    last_ns: u64, // 最近一次事件的内核时间戳
+}
+
+pub struct EventHandler {
+    // 每个连接推断出的协议、解码状态 + 尚未收到响应的请求，用于配对并计算 Latency (耗时)
+    tracker: Mutex<L7Tracker>,
+    // 每个已知连接的 comm / 角色 / L7 请求数，关闭时移除
+    flows: Mutex<HashMap<SessionKey, FlowMeta>>,
+    pods: WorkloadResolver,
+    sink: RecordSink,
+    metrics: Option<Arc<Metrics>>,
+    otlp: Option<OtlpExporter>,
+    filter: FilterConfig,
+    clock: WallClock, // 内核时间戳 -> Unix 时间
+    // [Idle] 关闭事件可能丢失 (缓冲区溢出、close 探针被禁用)，空闲超过 idle_ns 的连接状态由事件驱动的回收兜底
+    idle_ns: u64,
+    next_sweep_ns: AtomicU64,
+}
+
+impl EventHandler {
+    pub fn new(
+        tracker: L7Tracker,
+        pods: WorkloadResolver,
+        sink: RecordSink,
+        metrics: Option<Arc<Metrics>>,
+        otlp: Option<OtlpExporter>,
+        filter: FilterConfig,
+        idle_timeout: Duration,
+    ) -> Self {
+        EventHandler {
+            tracker: Mutex::new(tracker),
+            flows: Mutex::new(HashMap::new()),
+            pods,
+            sink,
+            metrics,
+            otlp,
+            filter,
+            clock: WallClock::calibrate(),
+            idle_ns: idle_timeout.as_nanos().try_into().unwrap_or(u64::MAX),
+            next_sweep_ns: AtomicU64::new(0),
+        }
+    }
+
+    // 处理一条原始记录
+    pub fn handle(&self, data: &[u8]) {
+        let Some(kind) = data
+            .get(..4)
+            .map(|b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+        else {
+            return;
+        };
+        match kind {
+            EVENT_PROCESS => {
+                if let Some(event) = read_record::<ProcessEvent>(data, size_of::<ProcessEvent>()) {
+                    self.on_process(&event);
+                }
+            }
+            EVENT_TCP => {
+                if let Some(event) = read_record::<TcpEvent>(data, size_of::<TcpEvent>()) {
+                    let payload = &data[size_of::<TcpEvent>()..];
+                    let captured = payload.len().min(event.captured_len as usize);
+                    self.on_tcp(&event, &payload[..captured]);
+                    self.maybe_evict_idle(event.timestamp_ns);
+                }
+            }
+            EVENT_FLOW => {
+                if let Some(event) = read_record::<FlowEvent>(data, size_of::<FlowEvent>()) {
+                    self.on_flow(&event);
+                }
+            }
+            _ => debug!(
+                "[Events] unknown record kind {} ({} bytes)",
+                kind,
+                data.len()
+            ),
+        }
+    }
+
+    // --- [模块一] 进程事件 (Process Monitoring) ---
+    fn on_process(&self, event: &ProcessEvent) {
+        let comm = std::str::from_utf8(&event.comm)
+            .unwrap_or("<unknown>")
+            .trim_matches('\0');
+        if !self.filter.allows_comm(comm) {
+            return;
+        }
+
+        // [业务增强] 解析 Cgroup ID 对应的 Pod 名称 (K8s Context)
+        let pod_name = self.pods.local_name(event.cgroup_id);
+        let qos = self
+            .pods
+            .cgroups()
+            .resolve(event.cgroup_id)
+            .and_then(|c| c.qos)
+            .map(|q| q.as_str())
+            .unwrap_or("-");
+        let workload = self.pods.pod(event.cgroup_id);
+        info!(
+            "[PROCESS] PID: {}, Pod: {}, QoS: {}, Workload: {}, Comm: {}",
+            event.pid,
+            pod_name,
+            qos,
+            workload
+                .as_ref()
+                .and_then(|p| p.workload.as_deref())
+                .unwrap_or("-"),
+            comm
+        );
+        if let Some(pod) = workload {
+            debug!(
+                "[PROCESS] PID: {}, Services: {:?}, Labels: {:?}",
+                event.pid, pod.services, pod.labels
+            );
+        }
+    }
+
+    // --- [模块二] 网络/TCP 事件 (TCP Events) ---
+    // 这里的逻辑最为复杂，负责将碎片化的内核事件拼接成完整的调用链
+    fn on_tcp(&self, event: &TcpEvent, payload: &[u8]) {
+        let pod_name = self.pods.local_name(event.cgroup_id);
+        // [Conntrack] 五元组已由内核连接表补全 (查不到的 FD 为 0)
+        // [IPv6] 内核统一使用 16 字节地址，IPv4-mapped 还原为 IPv4 显示
+        let saddr = Ipv6Addr::from(event.saddr).to_canonical();
+        let daddr = Ipv6Addr::from(event.daddr).to_canonical();
+        let sport = u16::from_be(event.sport);
+        let dport = u16::from_be(event.dport);
+
+        let direction_code = event.direction;
+
+        let key = SessionKey {
+            pid: event.pid,
+            fd: event.fd,
+        };
+
+        let comm = std::str::from_utf8(&event.comm)
+            .unwrap_or("<unknown>")
+            .trim_matches('\0');
+
+        // [Conntrack] 文件、管道等非 socket FD 已在内核中过滤 (只有连接表中的 FD 会产生数据事件)；
+        // 这里再按五元组兜底一次: 没有地址信息的事件既不记录连接信息，也不做 L7 解析，避免状态无限增长
+        let tracked = event.daddr != [0; 16] || event.dport != 0;
+        if tracked && let Ok(mut flows) = self.flows.lock() {
+            let meta = flows.entry(key).or_insert_with(|| FlowMeta {
+                comm: comm.to_string(),
+                cgroup_id: event.cgroup_id,
+                role: None,
+                l7_requests: 0,
+                last_ns: 0,
+            });
+            meta.last_ns = meta.last_ns.max(event.timestamp_ns);
+            match direction_code {
+                0 => meta.role = Some(Role::Client),
+                1 => meta.role = Some(Role::Server),
+                _ => {}
+            }
+        }
+
+        // [ANTI-NOISE FILTER] 按进程名 / 端口丢弃基础设施流量 (Agent 自身和 dockerd 已在内核中过滤)
+        if !self.filter.allows_comm(comm)
+            || !self.filter.allows_ports(sport, dport)
+            || self.filter.is_noise(comm, sport, dport)
+        {
+            return;
+        }
+
+        // === L7 应用层解析逻辑 ===
+        // 协议识别、请求/响应配对都由 L7Tracker 通过 DecoderRegistry 分发完成
+        if let Some(data_dir) = Direction::from_code(direction_code)
+            && event.data_len > 0
+            && tracked
+        {
+            let payload = Payload::new(payload, event.data_len as u64);
+
+            let exchanges = match self.tracker.lock() {
+                Ok(mut tracker) => {
+                    tracker.on_data(key, data_dir, sport, dport, payload, event.timestamp_ns)
+                }
+                Err(_) => Vec::new(),
+            };
+            if !exchanges.is_empty()
+                && let Ok(mut flows) = self.flows.lock()
+                && let Some(meta) = flows.get_mut(&key)
+            {
+                meta.l7_requests += exchanges.len() as u64;
+            }
+
+            // 每一对 请求/响应 输出一条完整记录
+            for exchange in exchanges {
+                let record = L7Record {
+                    timestamp_ns: self.clock.to_unix_ns(exchange.start_ns),
+                    pid: event.pid,
+                    comm: comm.to_string(),
+                    pod: pod_name.clone(),
+                    workload: self.pods.pod(event.cgroup_id).and_then(|p| p.workload),
+                    peer: self.pods.peer(daddr).map(|p| p.display_name()),
+                    saddr,
+                    sport,
+                    daddr,
+                    dport,
+                    protocol: exchange.protocol,
+                    role: exchange.role,
+                    request: exchange.request.summary,
+                    response: Some(exchange.response.status),
+                    latency_us: Some(exchange.end_ns.saturating_sub(exchange.start_ns) / 1000),
+                    req_bytes: exchange.req_bytes,
+                    resp_bytes: exchange.resp_bytes,
+                    attributes: exchange
+                        .request
+                        .attributes
+                        .into_iter()
+                        .chain(exchange.response.attributes)
+                        .collect(),
+                };
+                if let Some(metrics) = &self.metrics {
+                    metrics.observe(&record);
+                }
+                if let Some(otlp) = &self.otlp {
+                    otlp.export(&record);
+                }
+                self.sink.emit(&record);
+            }
+        }
+
+        // [LOGGING STRATEGY] 日志策略
+        // 握手事件直接打印；L7 数据统一通过 RecordSink 输出
+        if direction_code == 0 || direction_code == 1 {
+            let direction = if direction_code == 0 {
+                "CONNECT"
+            } else {
+                "ACCEPT"
+            };
+            // [K8s Context] 对端解析: daddr 可能是 Pod IP 或 Service ClusterIP
+            let peer = self
+                .pods
+                .peer(daddr)
+                .map(|p| format!(" ({})", p.display_name()))
+                .unwrap_or_default();
+            info!(
+                "[TCP] Type: {}, Pod: {}, {} -> {}:{}{}",
+                direction, pod_name, saddr, daddr, dport, peer
+            );
+        }
+    }
+
+    // 按事件时间戳驱动空闲回收，不需要额外的定时器；没有数据事件时也不会有新状态产生
+    fn maybe_evict_idle(&self, now_ns: u64) {
+        let next = self.next_sweep_ns.load(Ordering::Relaxed);
+        if now_ns < next
+            || self
+                .next_sweep_ns
+                .compare_exchange(
+                    next,
+                    now_ns.saturating_add(SWEEP_INTERVAL_NS),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                )
+                .is_err()
+        {
+            return;
+        }
+        let idle_before_ns = now_ns.saturating_sub(self.idle_ns);
+        let sessions = match self.tracker.lock() {
+            Ok(mut tracker) => tracker.evict_idle(idle_before_ns),
+            Err(_) => 0,
+        };
+        let flows = match self.flows.lock() {
+            Ok(mut flows) => {
+                let before = flows.len();
+                flows.retain(|_, meta| meta.last_ns >= idle_before_ns);
+                before - flows.len()
+            }
+            Err(_) => 0,
+        };
+        if sessions > 0 || flows > 0 {
+            debug!(
+                "[Events] evicted {} idle L7 sessions and {} idle flows",
+                sessions, flows
+            );
+        }
+    }
+
+    // --- [模块三] 连接关闭事件 (Flow Summary) ---
+    // 输出流量汇总，并回收该连接在用户态的全部状态 (协议绑定、未配对请求、连接信息)
+    fn on_flow(&self, event: &FlowEvent) {
+        let key = SessionKey {
+            pid: event.pid,
+            fd: event.fd,
+        };
+        if let Ok(mut tracker) = self.tracker.lock() {
+            tracker.close(&key);
+        }
+        let meta = self
+            .flows
+            .lock()
+            .ok()
+            .and_then(|mut flows| flows.remove(&key));
+
+        let reason = if event.reason == 1 {
+            CloseReason::Close
+        } else {
+            CloseReason::TcpState
+        };
+        // 没有用户态记录时 (Agent 启动前建立且一直空闲的连接)，
+        // 只有 close() 系统调用的进程上下文是可信的
+        let (comm, cgroup_id, role, l7_requests) = match meta {
+            Some(meta) => (meta.comm, meta.cgroup_id, meta.role, meta.l7_requests),
+            None if reason == CloseReason::Close => (
+                std::str::from_utf8(&event.comm)
+                    .unwrap_or("<unknown>")
+                    .trim_matches('\0')
+                    .to_string(),
+                event.cgroup_id,
+                None,
+                0,
+            ),
+            None => ("-".to_string(), 0, None, 0),
+        };
+
+        let conn = event.conn;
+        let saddr = Ipv6Addr::from(conn.saddr).to_canonical();
+        let daddr = Ipv6Addr::from(conn.daddr).to_canonical();
+        let sport = u16::from_be(conn.sport);
+        let dport = u16::from_be(conn.dport);
+        if !self.filter.allows_comm(&comm) || !self.filter.allows_ports(sport, dport) {
+            return;
+        }
+
+        let record = FlowRecord {
+            timestamp_ns: self.clock.to_unix_ns(event.timestamp_ns),
+            pid: event.pid,
+            comm,
+            pod: self.pods.local_name(cgroup_id),
+            workload: self.pods.pod(cgroup_id).and_then(|p| p.workload),
+            peer: self.pods.peer(daddr).map(|p| p.display_name()),
+            saddr,
+            sport,
+            daddr,
+            dport,
+            role,
+            duration_us: event.duration_ns / 1000,
+            bytes_sent: conn.bytes_sent,
+            bytes_recv: conn.bytes_recv,
+            packets_sent: conn.packets_sent as u64,
+            packets_recv: conn.packets_recv as u64,
+            l7_requests,
+            reason,
+        };
+        self.sink.emit_flow(&record);
+    }
+}
+
+// 记录 -> 定长结构体: 至少要有 min_len 字节，不足 size_of::<T>() 的尾部填 0
+fn read_record<T: aya::Pod>(data: &[u8], min_len: usize) -> Option<T> {
+    if data.len() < min_len {
+        return None;
+    }
+    let len = data.len().min(size_of::<T>());
+    let mut event = MaybeUninit::<T>::zeroed();
+    // Pod 类型的任意字节组合都是合法值
+    unsafe {
+        std::ptr::copy_nonoverlapping(data.as_ptr(), event.as_mut_ptr().cast::<u8>(), len);
+        Some(event.assume_init())
+    }
+}
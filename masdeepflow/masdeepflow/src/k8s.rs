@@ -0,0 +1,575 @@
+// [K8s Context] Kubernetes 元数据增强
+//
+// 通过 list-watch 从 API Server 同步 Pod / Service / EndpointSlice，
+// 在内存中维护两类索引:
+// 1. 容器 ID -> Pod   (本端: cgroup_id -> 容器 ID -> Pod)
+// 2. IP      -> Pod / Service (对端: TcpEvent.daddr 可能是 Pod IP，也可能是 ClusterIP)
+//
+// 该子系统是可选的: 只有指定 --k8s 或 --kubeconfig 时才会启动。
+// kubeconfig 可以指向本地的 mock API Server，方便脱离真实集群验证。
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Debug,
+    net::IpAddr,
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::Context as _;
+use futures::{StreamExt, TryStreamExt};
+use k8s_openapi::api::{
+    core::v1::{Pod, Service},
+    discovery::v1::EndpointSlice,
+};
+use kube::{
+    Api, Client, Config, Resource, ResourceExt,
+    config::{KubeConfigOptions, Kubeconfig},
+    runtime::{WatchStreamExt, watcher},
+};
+use log::{info, warn};
+use serde::de::DeserializeOwned;
+
+use crate::cgroup::CgroupResolver;
+
+#[derive(Debug, Clone, Default)]
+pub struct PodMeta {
+    pub uid: String,
+    pub namespace: String,
+    pub name: String,
+    pub labels: BTreeMap<String, String>,
+    pub workload: Option<String>, // 所属工作负载，如 "Deployment/web"
+    pub services: Vec<String>,    // 选中该 Pod 的 Service，如 "default/web"
+    ips: Vec<IpAddr>,
+    container_ids: Vec<String>,
+    host_network: bool, // hostNetwork Pod 的 IP 就是节点 IP
+}
+
+impl PodMeta {
+    pub fn full_name(&self) -> String {
+        format!("{}/{}", self.namespace, self.name)
+    }
+
+    fn from_pod(pod: &Pod) -> Self {
+        let status = pod.status.as_ref();
+        let ips = status
+            .and_then(|s| s.pod_ips.as_ref())
+            .map(|ips| ips.iter().filter_map(|ip| ip.ip.parse().ok()).collect())
+            .unwrap_or_default();
+
+        // containerID 形如 "containerd://<64位十六进制>"，只保留 ID 部分，与 cgroup 路径中的一致
+        let container_ids = status
+            .into_iter()
+            .flat_map(|s| {
+                [
+                    s.container_statuses.as_ref(),
+                    s.init_container_statuses.as_ref(),
+                    s.ephemeral_container_statuses.as_ref(),
+                ]
+            })
+            .flatten()
+            .flatten()
+            .filter_map(|c| c.container_id.as_deref())
+            .map(|id| id.rsplit("://").next().unwrap_or(id).to_string())
+            .collect();
+
+        PodMeta {
+            uid: pod.uid().unwrap_or_default(),
+            namespace: pod.namespace().unwrap_or_default(),
+            name: pod.name_any(),
+            labels: pod.labels().clone(),
+            workload: owner_workload(pod),
+            services: Vec::new(),
+            ips,
+            container_ids,
+            host_network: pod
+                .spec
+                .as_ref()
+                .and_then(|s| s.host_network)
+                .unwrap_or(false),
+        }
+    }
+}
+
+// 解析 ownerReferences 中的 controller。
+// ReplicaSet 名称是 "<deployment>-<pod-template-hash>"，这里还原成 Deployment。
+fn owner_workload(pod: &Pod) -> Option<String> {
+    let owner = pod
+        .owner_references()
+        .iter()
+        .find(|o| o.controller == Some(true))?;
+
+    if owner.kind == "ReplicaSet"
+        && let Some(hash) = pod.labels().get("pod-template-hash")
+        && let Some(deploy) = owner.name.strip_suffix(&format!("-{}", hash))
+    {
+        return Some(format!("Deployment/{}", deploy));
+    }
+    Some(format!("{}/{}", owner.kind, owner.name))
+}
+
+#[derive(Debug, Clone)]
+pub enum Peer {
+    Pod(PodMeta),
+    Service(String),
+}
+
+impl Peer {
+    pub fn display_name(&self) -> String {
+        match self {
+            Peer::Pod(pod) => pod.full_name(),
+            Peer::Service(svc) => format!("svc/{}", svc),
+        }
+    }
+}
+
+// 增量变更: 对应 watcher 的 Apply / Delete 以及 Init..InitDone 的整体替换
+enum Change<K> {
+    Upsert(K),
+    Delete(K),
+    Reset(Vec<K>),
+}
+
+#[derive(Default)]
+struct Cache {
+    pods: HashMap<String, PodMeta>,                 // uid -> Pod
+    by_container: HashMap<String, String>,          // 容器 ID -> uid
+    by_pod_ip: HashMap<IpAddr, String>,             // Pod IP -> uid
+    by_cluster_ip: HashMap<IpAddr, String>,         // ClusterIP -> "ns/svc"
+    service_ips: HashMap<String, Vec<IpAddr>>,      // "ns/svc" -> ClusterIPs
+    slices: HashMap<String, (String, Vec<IpAddr>)>, // "ns/slice" -> ("ns/svc", 端点 IP)
+    ip_services: HashMap<IpAddr, BTreeSet<String>>, // 端点 IP -> "ns/svc" (由 slices 派生)
+}
+
+impl Cache {
+    fn on_pod(&mut self, change: Change<Pod>) {
+        match change {
+            Change::Upsert(pod) => self.insert_pod(PodMeta::from_pod(&pod)),
+            Change::Delete(pod) => self.remove_pod(&pod.uid().unwrap_or_default()),
+            Change::Reset(pods) => {
+                self.pods.clear();
+                self.by_container.clear();
+                self.by_pod_ip.clear();
+                for pod in pods {
+                    self.insert_pod(PodMeta::from_pod(&pod));
+                }
+            }
+        }
+    }
+
+    fn insert_pod(&mut self, meta: PodMeta) {
+        self.remove_pod(&meta.uid);
+        for cid in &meta.container_ids {
+            self.by_container.insert(cid.clone(), meta.uid.clone());
+        }
+        // hostNetwork Pod 与节点 (以及同节点上的其他 hostNetwork Pod) 共享 IP，
+        // 不能用来反查对端，否则节点 IP 会随最后一次更新漂移到任意一个 hostNetwork Pod 上
+        if !meta.host_network {
+            for ip in &meta.ips {
+                self.by_pod_ip.insert(*ip, meta.uid.clone());
+            }
+        }
+        self.pods.insert(meta.uid.clone(), meta);
+    }
+
+    fn remove_pod(&mut self, uid: &str) {
+        if let Some(old) = self.pods.remove(uid) {
+            for cid in &old.container_ids {
+                self.by_container.remove(cid);
+            }
+            for ip in &old.ips {
+                // Pod IP 可能已被新 Pod 复用 (旧 Pod 的删除事件晚于新 Pod 的创建)，只删除仍然指向自己的条目
+                if self.by_pod_ip.get(ip).map(String::as_str) == Some(uid) {
+                    self.by_pod_ip.remove(ip);
+                }
+            }
+        }
+    }
+
+    fn on_service(&mut self, change: Change<Service>) {
+        match change {
+            Change::Upsert(svc) => self.insert_service(&svc),
+            Change::Delete(svc) => self.remove_service(&object_key(&svc)),
+            Change::Reset(svcs) => {
+                self.service_ips.clear();
+                self.by_cluster_ip.clear();
+                for svc in svcs {
+                    self.insert_service(&svc);
+                }
+            }
+        }
+    }
+
+    fn insert_service(&mut self, svc: &Service) {
+        let key = object_key(svc);
+        self.remove_service(&key);
+        // Headless Service 的 clusterIP 为 "None"，parse 失败自然被过滤
+        let ips: Vec<IpAddr> = svc
+            .spec
+            .as_ref()
+            .and_then(|s| s.cluster_ips.as_ref())
+            .map(|ips| ips.iter().filter_map(|ip| ip.parse().ok()).collect())
+            .unwrap_or_default();
+        for ip in &ips {
+            self.by_cluster_ip.insert(*ip, key.clone());
+        }
+        self.service_ips.insert(key, ips);
+    }
+
+    fn remove_service(&mut self, key: &str) {
+        if let Some(ips) = self.service_ips.remove(key) {
+            for ip in ips {
+                self.by_cluster_ip.remove(&ip);
+            }
+        }
+    }
+
+    fn on_slice(&mut self, change: Change<EndpointSlice>) {
+        match change {
+            Change::Upsert(slice) => {
+                let (key, entry) = slice_entry(&slice);
+                self.slices.insert(key, entry);
+            }
+            Change::Delete(slice) => {
+                self.slices.remove(&object_key(&slice));
+            }
+            Change::Reset(slices) => {
+                self.slices = slices.iter().map(slice_entry).collect();
+            }
+        }
+
+        self.ip_services.clear();
+        for (service, ips) in self.slices.values() {
+            for ip in ips {
+                self.ip_services
+                    .entry(*ip)
+                    .or_default()
+                    .insert(service.clone());
+            }
+        }
+    }
+
+    fn pod(&self, uid: &str) -> Option<PodMeta> {
+        let mut meta = self.pods.get(uid)?.clone();
+        let services: BTreeSet<&String> = meta
+            .ips
+            .iter()
+            .filter_map(|ip| self.ip_services.get(ip))
+            .flatten()
+            .collect();
+        meta.services = services.into_iter().cloned().collect();
+        Some(meta)
+    }
+}
+
+fn object_key<K: Resource>(obj: &K) -> String {
+    format!(
+        "{}/{}",
+        obj.meta().namespace.as_deref().unwrap_or_default(),
+        obj.meta().name.as_deref().unwrap_or_default()
+    )
+}
+
+fn slice_entry(slice: &EndpointSlice) -> (String, (String, Vec<IpAddr>)) {
+    let service = format!(
+        "{}/{}",
+        slice.namespace().unwrap_or_default(),
+        slice
+            .labels()
+            .get("kubernetes.io/service-name")
+            .map(String::as_str)
+            .unwrap_or_default()
+    );
+    let ips = slice
+        .endpoints
+        .iter()
+        .flat_map(|ep| ep.addresses.iter())
+        .filter_map(|addr| addr.parse().ok())
+        .collect();
+    (object_key(slice), (service, ips))
+}
+
+#[derive(Clone)]
+pub struct K8sMetadata {
+    cache: Arc<RwLock<Cache>>,
+}
+
+impl K8sMetadata {
+    // 连接 API Server 并启动三个 watcher。
+    // kubeconfig 为空时按 in-cluster / $KUBECONFIG / ~/.kube/config 的顺序推断。
+    pub async fn start(kubeconfig: Option<&Path>) -> anyhow::Result<Self> {
+        let config = match kubeconfig {
+            Some(path) => {
+                let kc = Kubeconfig::read_from(path)
+                    .with_context(|| format!("Failed to read kubeconfig {}", path.display()))?;
+                Config::from_custom_kubeconfig(kc, &KubeConfigOptions::default()).await?
+            }
+            None => Config::infer().await?,
+        };
+        info!(
+            "Connecting to Kubernetes API Server at {}",
+            config.cluster_url
+        );
+        let client = Client::try_from(config)?;
+
+        let metadata = K8sMetadata {
+            cache: Arc::new(RwLock::new(Cache::default())),
+        };
+        metadata.spawn_watcher(Api::<Pod>::all(client.clone()), Cache::on_pod);
+        metadata.spawn_watcher(Api::<Service>::all(client.clone()), Cache::on_service);
+        metadata.spawn_watcher(Api::<EndpointSlice>::all(client), Cache::on_slice);
+        Ok(metadata)
+    }
+
+    fn spawn_watcher<K>(&self, api: Api<K>, apply: fn(&mut Cache, Change<K>))
+    where
+        K: Resource + Clone + DeserializeOwned + Debug + Send + Sync + 'static,
+        K::DynamicType: Default,
+    {
+        let cache = self.cache.clone();
+        let kind = K::kind(&K::DynamicType::default()).to_string();
+        tokio::spawn(async move {
+            let mut stream = watcher(api, watcher::Config::default())
+                .default_backoff()
+                .boxed();
+            // Init ~ InitDone 之间的对象先缓存起来，结束后整体替换，避免中途出现空窗
+            let mut initial = Vec::new();
+            loop {
+                let event = match stream.try_next().await {
+                    Ok(Some(event)) => event,
+                    Ok(None) => break,
+                    Err(e) => {
+                        warn!("K8s {} watch error: {}", kind, e);
+                        continue;
+                    }
+                };
+                let change = match event {
+                    watcher::Event::Init => {
+                        initial.clear();
+                        continue;
+                    }
+                    watcher::Event::InitApply(obj) => {
+                        initial.push(obj);
+                        continue;
+                    }
+                    watcher::Event::InitDone => {
+                        info!("K8s {} cache synced ({} objects)", kind, initial.len());
+                        Change::Reset(std::mem::take(&mut initial))
+                    }
+                    watcher::Event::Apply(obj) => Change::Upsert(obj),
+                    watcher::Event::Delete(obj) => Change::Delete(obj),
+                };
+                if let Ok(mut cache) = cache.write() {
+                    apply(&mut cache, change);
+                }
+            }
+        });
+    }
+
+    pub fn pod_by_container(&self, container_id: &str) -> Option<PodMeta> {
+        let cache = self.cache.read().ok()?;
+        let uid = cache.by_container.get(container_id)?;
+        cache.pod(uid)
+    }
+
+    pub fn pod_by_uid(&self, uid: &str) -> Option<PodMeta> {
+        self.cache.read().ok()?.pod(uid)
+    }
+
+    // 对端解析: 优先 Pod IP，其次 Service ClusterIP
+    pub fn peer_by_ip(&self, ip: IpAddr) -> Option<Peer> {
+        let cache = self.cache.read().ok()?;
+        if let Some(uid) = cache.by_pod_ip.get(&ip) {
+            return cache.pod(uid).map(Peer::Pod);
+        }
+        cache.by_cluster_ip.get(&ip).cloned().map(Peer::Service)
+    }
+}
+
+// 把 cgroup 解析与 K8s 元数据组合在一起，供事件处理循环使用。
+// 没有启用 K8s 子系统时，退化为 cgroup 路径解析出的名称。
+#[derive(Clone)]
+pub struct WorkloadResolver {
+    cgroups: CgroupResolver,
+    k8s: Option<K8sMetadata>,
+}
+
+impl WorkloadResolver {
+    pub fn new(cgroups: CgroupResolver, k8s: Option<K8sMetadata>) -> Self {
+        WorkloadResolver { cgroups, k8s }
+    }
+
+    pub fn cgroups(&self) -> &CgroupResolver {
+        &self.cgroups
+    }
+
+    // 本端: cgroup_id -> 容器 ID (或 Pod UID) -> Pod
+    pub fn pod(&self, cgroup_id: u64) -> Option<PodMeta> {
+        let k8s = self.k8s.as_ref()?;
+        let cgroup = self.cgroups.resolve(cgroup_id)?;
+        cgroup
+            .container_id
+            .as_deref()
+            .and_then(|cid| k8s.pod_by_container(cid))
+            .or_else(|| {
+                cgroup
+                    .pod_uid
+                    .as_deref()
+                    .and_then(|uid| k8s.pod_by_uid(uid))
+            })
+    }
+
+    pub fn local_name(&self, cgroup_id: u64) -> String {
+        match self.pod(cgroup_id) {
+            Some(pod) => pod.full_name(),
+            None => self.cgroups.pod_name(cgroup_id),
+        }
+    }
+
+    // 对端: daddr -> Pod / Service
+    pub fn peer(&self, ip: IpAddr) -> Option<Peer> {
+        self.k8s.as_ref()?.peer_by_ip(ip)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn pod(uid: &str, name: &str, ip: &str, host_network: bool) -> Pod {
+        serde_json::from_value(json!({
+            "metadata": {
+                "uid": uid,
+                "namespace": "shop",
+                "name": name,
+                "labels": { "app": "web", "pod-template-hash": "7d9f8c6b5" },
+                "ownerReferences": [{
+                    "apiVersion": "apps/v1",
+                    "kind": "ReplicaSet",
+                    "name": "web-7d9f8c6b5",
+                    "uid": "rs-uid",
+                    "controller": true
+                }]
+            },
+            "spec": { "containers": [], "hostNetwork": host_network },
+            "status": {
+                "podIPs": [{ "ip": ip }],
+                "containerStatuses": [{
+                    "name": "app",
+                    "image": "web:1",
+                    "imageID": "",
+                    "ready": true,
+                    "restartCount": 0,
+                    "containerID": format!("containerd://{}-cid", uid)
+                }]
+            }
+        }))
+        .unwrap()
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn owner_workload_resolves_deployment() {
+        let web = pod("u1", "web-7d9f8c6b5-x2x9q", "10.1.0.5", false);
+        assert_eq!(owner_workload(&web).as_deref(), Some("Deployment/web"));
+
+        // pod-template-hash 与 ReplicaSet 名称不匹配时保留 ReplicaSet 本身
+        let mut bare = web.clone();
+        bare.metadata
+            .labels
+            .as_mut()
+            .unwrap()
+            .remove("pod-template-hash");
+        assert_eq!(
+            owner_workload(&bare).as_deref(),
+            Some("ReplicaSet/web-7d9f8c6b5")
+        );
+
+        let mut sts = web.clone();
+        let owner = &mut sts.metadata.owner_references.as_mut().unwrap()[0];
+        owner.kind = "StatefulSet".to_string();
+        owner.name = "db".to_string();
+        assert_eq!(owner_workload(&sts).as_deref(), Some("StatefulSet/db"));
+
+        let mut orphan = web;
+        orphan.metadata.owner_references = None;
+        assert_eq!(owner_workload(&orphan), None);
+    }
+
+    #[test]
+    fn upsert_indexes_containers_and_ips() {
+        let mut cache = Cache::default();
+        cache.on_pod(Change::Upsert(pod("u1", "web-a", "10.1.0.5", false)));
+        assert_eq!(cache.by_container["u1-cid"], "u1");
+        assert_eq!(cache.by_pod_ip[&ip("10.1.0.5")], "u1");
+        let meta = cache.pod("u1").unwrap();
+        assert_eq!(meta.full_name(), "shop/web-a");
+        assert_eq!(meta.workload.as_deref(), Some("Deployment/web"));
+
+        // IP 变化后旧 IP 不再指向该 Pod
+        cache.on_pod(Change::Upsert(pod("u1", "web-a", "10.1.0.6", false)));
+        assert!(!cache.by_pod_ip.contains_key(&ip("10.1.0.5")));
+        assert_eq!(cache.by_pod_ip[&ip("10.1.0.6")], "u1");
+    }
+
+    #[test]
+    fn host_network_pods_are_not_indexed_by_ip() {
+        let mut cache = Cache::default();
+        cache.on_pod(Change::Upsert(pod("u1", "web-a", "10.1.0.5", false)));
+        cache.on_pod(Change::Upsert(pod(
+            "n1",
+            "node-exporter",
+            "192.168.1.10",
+            true,
+        )));
+        cache.on_pod(Change::Upsert(pod(
+            "n2",
+            "kube-proxy",
+            "192.168.1.10",
+            true,
+        )));
+        assert!(!cache.by_pod_ip.contains_key(&ip("192.168.1.10")));
+        // 本端解析 (容器 ID) 不受影响
+        assert_eq!(cache.by_container["n2-cid"], "n2");
+        assert_eq!(cache.by_pod_ip.len(), 1);
+    }
+
+    #[test]
+    fn remove_keeps_ip_reused_by_newer_pod() {
+        let mut cache = Cache::default();
+        cache.on_pod(Change::Upsert(pod("old", "web-a", "10.1.0.5", false)));
+        // 新 Pod 复用了同一个 IP，旧 Pod 的删除事件随后才到达
+        cache.on_pod(Change::Upsert(pod("new", "web-b", "10.1.0.5", false)));
+        cache.on_pod(Change::Delete(pod("old", "web-a", "10.1.0.5", false)));
+        assert_eq!(cache.by_pod_ip[&ip("10.1.0.5")], "new");
+        assert!(!cache.by_container.contains_key("old-cid"));
+        assert!(cache.pod("old").is_none());
+
+        cache.on_pod(Change::Delete(pod("new", "web-b", "10.1.0.5", false)));
+        assert!(cache.by_pod_ip.is_empty());
+        assert!(cache.by_container.is_empty());
+    }
+
+    #[test]
+    fn reset_replaces_all_pods() {
+        let mut cache = Cache::default();
+        cache.on_pod(Change::Upsert(pod("u1", "web-a", "10.1.0.5", false)));
+        cache.on_pod(Change::Upsert(pod("u2", "web-b", "10.1.0.6", false)));
+        cache.on_pod(Change::Reset(vec![pod("u3", "web-c", "10.1.0.7", false)]));
+        assert_eq!(cache.pods.len(), 1);
+        assert!(cache.pod("u1").is_none());
+        assert_eq!(
+            cache.by_container.keys().collect::<Vec<_>>(),
+            [&"u3-cid".to_string()]
+        );
+        assert_eq!(
+            cache.by_pod_ip.keys().copied().collect::<Vec<_>>(),
+            [ip("10.1.0.7")]
+        );
+    }
+}
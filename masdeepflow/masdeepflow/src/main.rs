@@ -1,38 +1,204 @@
+mod btf;
+mod cgroup;
+mod clock;
+mod config;
+mod conntrack;
+mod events;
+mod k8s;
+mod metrics;
+mod otlp;
+mod protocol;
+mod record;
+mod reorder;
+mod transport;
+
 use anyhow::Context;
 use aya::{
-    Ebpf as Bpf, include_bytes_aligned,
-    maps::{SockHash, perf::AsyncPerfEventArray},
+    EbpfLoader, include_bytes_aligned,
+    maps::{HashMap as BpfHashMap, SockHash},
     programs::{KProbe, SkMsg, SockOps, TracePoint, links::CgroupAttachMode},
-    util::online_cpus,
 };
 use aya_log::EbpfLogger;
-use bytes::BytesMut;
 use clap::Parser;
 use log::{debug, info, warn};
-use masdeepflow_common::{ProcessEvent, TcpEvent};
-use std::net::Ipv4Addr;
-use tokio::{signal, task};
+use masdeepflow_common::{ConnInfo, ConnKey};
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::signal;
+
+use crate::{
+    btf::{Btf, SockOffsets},
+    cgroup::CgroupResolver,
+    config::{Config, ProbeGroup},
+    events::EventHandler,
+    k8s::{K8sMetadata, WorkloadResolver},
+    metrics::Metrics,
+    otlp::{OtlpExporter, OtlpProtocol},
+    protocol::{L7Tracker, PortHint},
+    record::{OutputFormat, RecordSink},
+    transport::TransportKind,
+};
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
-struct Args {}
+struct Args {
+    /// TOML 配置文件，命令行参数会覆盖其中的同名配置
+    #[clap(long, short = 'c', value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// 只加载并校验配置 (不加载 eBPF)，打印生效配置后退出
+    #[clap(long)]
+    check_config: bool,
+
+    /// cgroup v2 挂载点，handle_sock_ops 挂载于此 [默认: /sys/fs/cgroup]
+    #[clap(long, value_name = "PATH")]
+    cgroup_path: Option<PathBuf>,
+
+    /// 启用探针分组，可重复
+    #[clap(long, value_enum, value_name = "PROBE")]
+    enable: Vec<ProbeGroup>,
+
+    /// 禁用探针分组，可重复，如 --disable sock-accel
+    #[clap(long, value_enum, value_name = "PROBE")]
+    disable: Vec<ProbeGroup>,
+
+    /// 启用 Kubernetes 元数据增强 (in-cluster 或 $KUBECONFIG)
+    #[clap(long)]
+    k8s: bool,
+
+    /// 指定 kubeconfig 文件 (隐含 --k8s)，可指向本地 mock API Server
+    #[clap(long, value_name = "PATH")]
+    kubeconfig: Option<PathBuf>,
+
+    /// L7 记录输出格式: log (info! 日志) 或 json (每行一个 JSON 对象) [默认: log]
+    #[clap(long, value_enum)]
+    output: Option<OutputFormat>,
+
+    /// JSON 记录写入的文件 (默认 stdout)
+    #[clap(long, value_name = "PATH")]
+    output_file: Option<PathBuf>,
+
+    /// 端口协议提示，优先于内容推断，可重复: --port-hint 6380=redis --port-hint 6432=postgres
+    #[clap(long = "port-hint", value_name = "PORT=PROTOCOL")]
+    port_hints: Vec<PortHint>,
+
+    /// 只保留这些进程名的事件，可重复
+    #[clap(long = "comm", value_name = "COMM")]
+    comms: Vec<String>,
+
+    /// 丢弃这些进程名的事件，可重复
+    #[clap(long = "exclude-comm", value_name = "COMM")]
+    exclude_comms: Vec<String>,
+
+    /// 只保留本端或对端端口在列表中的连接，可重复
+    #[clap(long = "port", value_name = "PORT")]
+    ports: Vec<u16>,
+
+    /// 丢弃 containerd / kubelet / Docker API 端口等基础设施流量 (默认保留)
+    #[clap(long)]
+    drop_noise: bool,
+
+    /// 在该地址提供 Prometheus /metrics，如 0.0.0.0:9435
+    #[clap(long, value_name = "ADDR")]
+    metrics_addr: Option<SocketAddr>,
+
+    /// 指标标签组合上限，超出部分合并到 endpoint="__overflow__"
+    #[clap(long, value_name = "N")]
+    metrics_max_series: Option<usize>,
+
+    /// 将 L7 调用以 OTLP Span 导出到该 collector，如 http://otel-collector:4318
+    #[clap(long, value_name = "URL")]
+    otlp_endpoint: Option<String>,
+
+    /// OTLP 传输协议 [默认: http]
+    #[clap(long, value_enum)]
+    otlp_protocol: Option<OtlpProtocol>,
+
+    /// 每次读写最多拷贝的载荷字节数 (最大 8192) [默认: 1024]
+    #[clap(long, value_name = "BYTES")]
+    payload_len: Option<u32>,
+
+    /// 内核事件通道: auto / ringbuf (5.8+) / perf [默认: auto]
+    #[clap(long, value_enum)]
+    transport: Option<TransportKind>,
+
+    /// 每隔 N 秒打印事件速率、丢失数和 Agent CPU 占用
+    #[clap(long, value_name = "SECS")]
+    transport_stats: Option<u64>,
+}
+
+impl Args {
+    // 命令行参数叠加到配置文件之上: 标量覆盖，列表追加
+    fn apply(self, config: &mut Config) {
+        if let Some(path) = self.cgroup_path {
+            config.cgroup_path = path;
+        }
+        for group in self.enable {
+            config.probes.set(group, true);
+        }
+        for group in self.disable {
+            config.probes.set(group, false);
+        }
+        config.k8s.enabled |= self.k8s;
+        if self.kubeconfig.is_some() {
+            config.k8s.kubeconfig = self.kubeconfig;
+        }
+        if let Some(format) = self.output {
+            config.output.format = format;
+        }
+        if self.output_file.is_some() {
+            config.output.file = self.output_file;
+        }
+        config.protocol.port_hints.extend(self.port_hints);
+        config.filter.comm.extend(self.comms);
+        config.filter.exclude_comm.extend(self.exclude_comms);
+        config.filter.ports.extend(self.ports);
+        if self.drop_noise {
+            config.filter.drop_noise = true;
+        }
+        if self.metrics_addr.is_some() {
+            config.metrics.listen = self.metrics_addr;
+        }
+        if let Some(max_series) = self.metrics_max_series {
+            config.metrics.max_series = max_series;
+        }
+        if self.otlp_endpoint.is_some() {
+            config.otlp.endpoint = self.otlp_endpoint;
+        }
+        if let Some(protocol) = self.otlp_protocol {
+            config.otlp.protocol = protocol;
+        }
+        if let Some(len) = self.payload_len {
+            config.capture.payload_len = len;
+        }
+        if let Some(kind) = self.transport {
+            config.transport.kind = kind;
+        }
+        if let Some(secs) = self.transport_stats {
+            config.transport.stats_interval_secs = secs;
+        }
+    }
+}
 
 // 我们定义了与内核态完全一致的结构体 SockKey，并标记为 #[repr(C)]。这是用户态和内核态读写 Map 的“通用语言”。
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
 pub struct SockKey {
-    pub sip: u32,
-    pub dip: u32,
+    pub sip: [u8; 16], // IPv4 以 IPv4-mapped 存放
+    pub dip: [u8; 16],
     pub sport: u32,
     pub dport: u32,
 }
 unsafe impl aya::Pod for SockKey {}
 
 // [Phase 2] Latency Tracking Key (5-tuple equivalent: PID+FD)
-// Since we don't have PID in TcpEvent yet, we use CGroup ID + FD which is unique enough for a Pod.
+// 与内核连接表 CONNECTIONS 的 Key 一致: FD 表属于进程，(tgid, fd) 唯一确定一个连接
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 struct SessionKey {
-    cgroup_id: u64,
+    pid: u32,
     fd: u32,
 }
 
@@ -42,7 +208,16 @@ async fn main() -> anyhow::Result<()> {
         unsafe { std::env::set_var("RUST_LOG", "info") };
     }
     env_logger::init();
-    let _opt = Args::parse();
+    let args = Args::parse();
+    let check_only = args.check_config;
+    let mut config = Config::load(args.config.as_deref())?;
+    args.apply(&mut config);
+    config.validate().context("Invalid configuration")?;
+    if check_only {
+        print!("{}", toml::to_string_pretty(&config)?);
+        info!("Configuration OK");
+        return Ok(());
+    }
 
     // 1. 提升内存锁定限制 (RLIMIT_MEMLOCK)
     let rlim = libc::rlimit {
@@ -55,15 +230,49 @@ async fn main() -> anyhow::Result<()> {
     }
 
     // 2. 加载 eBPF 程序
+    // [BTF] 先计算 struct sock 字段偏移量并写入全局常量，没有 BTF 的内核拒绝启动
+    let btf = Btf::load(Path::new(btf::VMLINUX_BTF))?;
+    let sock = SockOffsets::from_btf(&btf)
+        .context("Unsupported kernel: cannot locate struct sock fields in BTF")?;
+    info!("struct sock offsets from BTF: {:?}", sock);
+    if !sock.has_ipv6() {
+        warn!("Kernel has no IPv6 fields in struct sock, IPv6 connections are not resolved");
+    }
+    let has_ipv6 = sock.has_ipv6() as u32;
+    let v6_daddr = sock.v6_daddr.unwrap_or(0);
+    let v6_rcv_saddr = sock.v6_rcv_saddr.unwrap_or(0);
+    let flow_events = config.probes.close as u32;
+    let mut loader = EbpfLoader::new();
+    loader
+        .set_global("SKC_DADDR_OFF", &sock.daddr, true)
+        .set_global("SKC_RCV_SADDR_OFF", &sock.rcv_saddr, true)
+        .set_global("SKC_DPORT_OFF", &sock.dport, true)
+        .set_global("SKC_NUM_OFF", &sock.num, true)
+        .set_global("SKC_FAMILY_OFF", &sock.family, true)
+        .set_global("SKC_HAS_IPV6", &has_ipv6, true)
+        .set_global("SKC_V6_DADDR_OFF", &v6_daddr, true)
+        .set_global("SKC_V6_RCV_SADDR_OFF", &v6_rcv_saddr, true)
+        .set_global("PAYLOAD_CAPTURE_LEN", &config.capture.payload_len, true)
+        .set_global("FLOW_EVENTS", &flow_events, true);
+
+    // [Transport] 按内核能力选择 RingBuf 或 perf 版本的 eBPF 对象
+    let transport = config.transport.kind.resolve();
+    info!("Event transport: {:?}", transport);
+    let ringbuf_size = config.transport.ringbuf_size_kb.saturating_mul(1024);
+    if transport == TransportKind::Ringbuf {
+        loader.set_max_entries("EVENTS", ringbuf_size);
+    }
+
     #[cfg(debug_assertions)]
-    let mut bpf = Bpf::load(include_bytes_aligned!(
-        "../../target/bpfel-unknown-none/debug/masdeepflow"
-    ))?;
+    let ringbuf_obj = include_bytes_aligned!("../../target/bpfel-unknown-none/debug/masdeepflow");
     #[cfg(not(debug_assertions))]
-    let mut bpf = Bpf::load(include_bytes_aligned!(concat!(
-        env!("OUT_DIR"),
-        "/masdeepflow"
-    )))?;
+    let ringbuf_obj = include_bytes_aligned!(concat!(env!("OUT_DIR"), "/masdeepflow"));
+    // perf 版本只由 build.rs 生成
+    let perf_obj = include_bytes_aligned!(concat!(env!("OUT_DIR"), "/masdeepflow-perf"));
+    let mut bpf = match transport {
+        TransportKind::Perf => loader.load(perf_obj)?,
+        _ => loader.load(ringbuf_obj)?,
+    };
 
     // 初始化 eBPF 日志系统
     if let Err(e) = EbpfLogger::init(&mut bpf) {
@@ -77,492 +286,256 @@ async fn main() -> anyhow::Result<()> {
         aya::maps::HashMap::try_from(bpf.map_mut("FILTER_PID").unwrap())?;
     filter_pid.insert(my_pid, 1, 0)?;
 
-    // 3. 挂载探针 (Probes Attachment)
+    // 3. 挂载探针 (Probes Attachment)，按配置启用各分组
 
     // (A) Process Monitoring
-    let program: &mut TracePoint = bpf.program_mut("masdeepflow_exec").unwrap().try_into()?;
-    program.load()?;
-    program.attach("sched", "sched_process_exec")?;
-
-    // (B) Network Connect
-    let program: &mut TracePoint = bpf
-        .program_mut("masdeepflow_tcp_connect")
-        .unwrap()
-        .try_into()?;
-    program.load()?;
-    program.attach("syscalls", "sys_enter_connect")?;
-
-    // (B-2) Network Connect Source IP Supplement (kprobe)
-    let program: &mut KProbe = bpf
-        .program_mut("masdeepflow_tcp_connect_detailed")
-        .unwrap()
-        .try_into()?;
-    program.load()?;
-    // Attempt attach to tcp_connect. If fails (some kernels), try tcp_v4_connect kretprobe?
-    // Let's stick to tcp_connect (core tcp function)
-    program.attach("tcp_connect", 0)?;
+    if config.probes.process {
+        let program: &mut TracePoint = bpf.program_mut("masdeepflow_exec").unwrap().try_into()?;
+        program.load()?;
+        program.attach("sched", "sched_process_exec")?;
+    }
 
-    // (C) Network Accept
-    let program: &mut KProbe = bpf
-        .program_mut("masdeepflow_tcp_accept")
-        .unwrap()
-        .try_into()?;
-    program.load()?;
-    program.attach("inet_csk_accept", 0)?;
-
-    // (D) L7 Observability (Write)
-    let program: &mut TracePoint = bpf.program_mut("masdeepflow_write").unwrap().try_into()?;
-    program.load()?;
-    program.attach("syscalls", "sys_enter_write")?;
-
-    // (E) Additional Data Capture (Sendto)
-    let program: &mut TracePoint = bpf.program_mut("masdeepflow_sendto").unwrap().try_into()?;
-    program.load()?;
-    program.attach("syscalls", "sys_enter_sendto")?;
-
-    // (F) L7 Observability (Read)
-    let program: &mut TracePoint = bpf
-        .program_mut("masdeepflow_read_enter")
-        .unwrap()
-        .try_into()?;
-    program.load()?;
-    program.attach("syscalls", "sys_enter_read")?;
-
-    let program: &mut TracePoint = bpf
-        .program_mut("masdeepflow_read_exit")
-        .unwrap()
-        .try_into()?;
-    program.load()?;
-    program.attach("syscalls", "sys_exit_read")?;
-
-    // (G) L7 Observability (Recvfrom)
-    let program: &mut TracePoint = bpf
-        .program_mut("masdeepflow_recvfrom_enter")
-        .unwrap()
-        .try_into()?;
-    program.load()?;
-    program.attach("syscalls", "sys_enter_recvfrom")?;
-
-    let program: &mut TracePoint = bpf
-        .program_mut("masdeepflow_recvfrom_exit")
-        .unwrap()
-        .try_into()?;
-    program.load()?;
-    program.attach("syscalls", "sys_exit_recvfrom")?;
+    if config.probes.connect {
+        // (B) Network Connect
+        let program: &mut TracePoint = bpf
+            .program_mut("masdeepflow_tcp_connect")
+            .unwrap()
+            .try_into()?;
+        program.load()?;
+        program.attach("syscalls", "sys_enter_connect")?;
+
+        let program: &mut TracePoint = bpf
+            .program_mut("masdeepflow_connect_exit")
+            .unwrap()
+            .try_into()?;
+        program.load()?;
+        program.attach("syscalls", "sys_exit_connect")?;
+
+        // (B-2) Network Connect Source IP Supplement (kprobe)
+        let program: &mut KProbe = bpf
+            .program_mut("masdeepflow_tcp_connect_detailed")
+            .unwrap()
+            .try_into()?;
+        program.load()?;
+        // Attempt attach to tcp_connect. If fails (some kernels), try tcp_v4_connect kretprobe?
+        // Let's stick to tcp_connect (core tcp function)
+        program.attach("tcp_connect", 0)?;
+    }
 
-    // (H) Socket Acceleration (Phase 8)
-    info!("Loading Socket Acceleration programs...");
+    // (C) Network Accept
+    if config.probes.accept {
+        let program: &mut KProbe = bpf
+            .program_mut("masdeepflow_tcp_accept")
+            .unwrap()
+            .try_into()?;
+        program.load()?;
+        program.attach("inet_csk_accept", 0)?;
+
+        // (C-2) accept 返回的 FD 与新连接绑定
+        let program: &mut TracePoint = bpf
+            .program_mut("masdeepflow_accept_exit")
+            .unwrap()
+            .try_into()?;
+        program.load()?;
+        program.attach("syscalls", "sys_exit_accept")?;
+        program.attach("syscalls", "sys_exit_accept4")?;
+    }
 
-    // 1. Load Map & Extract FD (Scope to release borrow)
-    let map_fd = {
-        let intercept_map: SockHash<_, SockKey> =
-            SockHash::try_from(bpf.map_mut("INTERCEPT_MAP").unwrap())?;
-        intercept_map.fd().try_clone()?
-    };
+    if config.probes.read_write {
+        // (D) L7 Observability (Write)
+        let program: &mut TracePoint = bpf.program_mut("masdeepflow_write").unwrap().try_into()?;
+        program.load()?;
+        program.attach("syscalls", "sys_enter_write")?;
+
+        // (F) L7 Observability (Read)
+        let program: &mut TracePoint = bpf
+            .program_mut("masdeepflow_read_enter")
+            .unwrap()
+            .try_into()?;
+        program.load()?;
+        program.attach("syscalls", "sys_enter_read")?;
+
+        let program: &mut TracePoint = bpf
+            .program_mut("masdeepflow_read_exit")
+            .unwrap()
+            .try_into()?;
+        program.load()?;
+        program.attach("syscalls", "sys_exit_read")?;
+    }
 
-    // 2. Attach SockOpts to CgroupV2 Root
-    let cgroup_path = "/sys/fs/cgroup";
-    let cgroup_file = std::fs::File::open(cgroup_path)
-        .context("Failed to open cgroup root. Ensure Cgroup V2 is mounted at /sys/fs/cgroup")?;
+    if config.probes.sendto_recvfrom {
+        // (E) Additional Data Capture (Sendto)
+        let program: &mut TracePoint = bpf.program_mut("masdeepflow_sendto").unwrap().try_into()?;
+        program.load()?;
+        program.attach("syscalls", "sys_enter_sendto")?;
+
+        // (G) L7 Observability (Recvfrom)
+        let program: &mut TracePoint = bpf
+            .program_mut("masdeepflow_recvfrom_enter")
+            .unwrap()
+            .try_into()?;
+        program.load()?;
+        program.attach("syscalls", "sys_enter_recvfrom")?;
+
+        let program: &mut TracePoint = bpf
+            .program_mut("masdeepflow_recvfrom_exit")
+            .unwrap()
+            .try_into()?;
+        program.load()?;
+        program.attach("syscalls", "sys_exit_recvfrom")?;
+    }
 
-    let program: &mut SockOps = bpf.program_mut("handle_sock_ops").unwrap().try_into()?;
-    program.load()?;
-    program.attach(cgroup_file, CgroupAttachMode::Single)?;
+    // (J) 向量 I/O: 每个系统调用一个 enter 程序，exit 共用一个
+    if config.probes.vectored {
+        for (name, syscall) in [
+            ("masdeepflow_writev_enter", "writev"),
+            ("masdeepflow_readv_enter", "readv"),
+            ("masdeepflow_sendmsg_enter", "sendmsg"),
+            ("masdeepflow_recvmsg_enter", "recvmsg"),
+            ("masdeepflow_sendmmsg_enter", "sendmmsg"),
+            ("masdeepflow_recvmmsg_enter", "recvmmsg"),
+        ] {
+            let program: &mut TracePoint = bpf.program_mut(name).unwrap().try_into()?;
+            program.load()?;
+            program.attach("syscalls", &format!("sys_enter_{}", syscall))?;
+        }
+
+        let program: &mut TracePoint = bpf
+            .program_mut("masdeepflow_msg_exit")
+            .unwrap()
+            .try_into()?;
+        program.load()?;
+        for syscall in [
+            "writev", "readv", "sendmsg", "recvmsg", "sendmmsg", "recvmmsg",
+        ] {
+            program.attach("syscalls", &format!("sys_exit_{}", syscall))?;
+        }
+    }
 
-    // 3. Attach SkMsg to Map
-    let program: &mut SkMsg = bpf.program_mut("redirect_traffic").unwrap().try_into()?;
-    program.load()?;
-    program.attach(&map_fd)?;
+    // (I) Connection Lifecycle (Close)
+    // sys_enter_close 负责清理连接表，只要有探针会用到连接表就挂载；close 组只决定是否输出 FlowEvent
+    if config.probes.uses_connections() {
+        let program: &mut TracePoint = bpf.program_mut("masdeepflow_close").unwrap().try_into()?;
+        program.load()?;
+        program.attach("syscalls", "sys_enter_close")?;
+    }
+    if config.probes.close {
+        let program: &mut TracePoint = bpf
+            .program_mut("masdeepflow_sock_state")
+            .unwrap()
+            .try_into()?;
+        program.load()?;
+        program.attach("sock", "inet_sock_set_state")?;
+    }
 
-    info!("Socket Acceleration Enabled.");
+    // (H) Socket Acceleration (Phase 8)
+    let cgroup_path = config.cgroup_path.as_path();
+    if config.probes.sock_accel {
+        info!("Loading Socket Acceleration programs...");
+
+        // 1. Load Map & Extract FD (Scope to release borrow)
+        let map_fd = {
+            let intercept_map: SockHash<_, SockKey> =
+                SockHash::try_from(bpf.map_mut("INTERCEPT_MAP").unwrap())?;
+            intercept_map.fd().try_clone()?
+        };
+
+        // 2. Attach SockOpts to CgroupV2 Root
+        let cgroup_file = std::fs::File::open(cgroup_path).with_context(|| {
+            format!(
+                "Failed to open cgroup root. Ensure Cgroup V2 is mounted at {}",
+                cgroup_path.display()
+            )
+        })?;
+
+        let program: &mut SockOps = bpf.program_mut("handle_sock_ops").unwrap().try_into()?;
+        program.load()?;
+        program.attach(cgroup_file, CgroupAttachMode::Single)?;
+
+        // 3. Attach SkMsg to Map
+        let program: &mut SkMsg = bpf.program_mut("redirect_traffic").unwrap().try_into()?;
+        program.load()?;
+        program.attach(&map_fd)?;
+
+        info!("Socket Acceleration Enabled.");
+    }
 
     info!("Probes attached. Monitoring...");
 
-    // 4. 用户态轮询 (Polling) & 处理
-    // [1. 初始化] 获取在线 CPU 数量
-    // eBPF PerfEventArray 是 Per-CPU 的，所以我们需要为每个 CPU 创建一个读取器
-    let cpus =
-        online_cpus().map_err(|(_, e)| anyhow::anyhow!("Failed to get online cpus: {}", e))?;
-    let num_cpus = cpus.len();
-    let mut buffers = (0..num_cpus)
-        .map(|_| BytesMut::with_capacity(10240))
-        .collect::<Vec<_>>();
-    // 进程事件
-    // [2. 事件通道] 从 eBPF map 中接管两个核心通道
-    // PROCESS_EVENTS: 进程启停事件 (exec)
-    // TCP_EVENTS:     所有网络相关事件 (connect, accept, write, read)
-    let mut process_events: AsyncPerfEventArray<_> =
-        bpf.take_map("PROCESS_EVENTS").unwrap().try_into()?;
-    let mut tcp_events: AsyncPerfEventArray<_> = bpf.take_map("TCP_EVENTS").unwrap().try_into()?;
-
-    // --- [模块一] 处理进程事件 (Process Monitoring) ---
-    // 为每个 CPU 启动一个异步任务来读取进程事件
-    for cpu_id in cpus.clone() {
-        let mut buf = process_events.open(cpu_id, None)?;
-        task::spawn(async move {
-            let mut buffers = (0..10)
-                .map(|_| BytesMut::with_capacity(10240))
-                .collect::<Vec<_>>();
-            loop {
-                let events = buf.read_events(&mut buffers).await.unwrap();
-                for i in 0..events.read {
-                    let mut const_buf = buffers[i].as_ref();
-                    // [反序列化] 直接将字节流转换为 ProcessEvent 结构体
-                    let event =
-                        unsafe { const_buf.as_ptr().cast::<ProcessEvent>().read_unaligned() };
-
-                    let comm = std::str::from_utf8(&event.comm)
-                        .unwrap_or("<unknown>")
-                        .trim_matches('\0');
-
-                    // [业务增强] 解析 Cgroup ID 对应的 Pod 名称 (K8s Context)
-                    let pod_name = resolve_pod(event.cgroup_id);
-                    info!(
-                        "[PROCESS] PID: {}, Pod: {}, Comm: {}",
-                        event.pid, pod_name, comm
-                    );
-                }
-            }
-        });
+    // [Conntrack] 探针挂载后再扫描 /proc，补齐 Agent 启动前已建立的连接
+    {
+        let mut connections: BpfHashMap<_, ConnKey, ConnInfo> =
+            BpfHashMap::try_from(bpf.map_mut("CONNECTIONS").unwrap())?;
+        let seeded = conntrack::seed(Path::new("/proc"), &mut connections)
+            .context("Failed to scan /proc for existing connections")?;
+        info!("Seeded {} existing connections", seeded);
     }
 
-    // [3. 状态管理] 用户态核心状态机
-    // sessions: 记录 HTTP 请求的开始时间，用于计算 Latency (耗时)
-    type SessionTable = std::collections::HashMap<SessionKey, std::time::Instant>;
-    let sessions: std::sync::Arc<std::sync::Mutex<SessionTable>> =
-        std::sync::Arc::new(std::sync::Mutex::new(std::collections::HashMap::new()));
+    // [K8s Context] 建立 cgroup id -> Pod/容器 的索引，并通过 inotify 持续更新
+    let pods = CgroupResolver::new(cgroup_path)
+        .with_context(|| format!("Failed to index cgroups under {}", cgroup_path.display()))?;
+    pods.watch().context("Failed to watch cgroup tree")?;
+    info!(
+        "Indexed {} cgroups under {}",
+        pods.len(),
+        cgroup_path.display()
+    );
+
+    // [K8s Context] 可选: 从 API Server 同步 Pod/Service/EndpointSlice
+    let k8s = if config.k8s_enabled() {
+        let metadata = K8sMetadata::start(config.k8s.kubeconfig.as_deref())
+            .await
+            .context("Failed to start Kubernetes metadata watcher")?;
+        Some(metadata)
+    } else {
+        None
+    };
+    let pods = WorkloadResolver::new(pods, k8s);
+
+    // [Output] L7 记录输出 (log / json)
+    let sink = RecordSink::open(config.output.format, config.output.file.as_deref())
+        .context("Failed to open L7 record output")?;
+
+    // [Metrics] 可选: Prometheus RED 指标
+    let metrics = match config.metrics.listen {
+        Some(addr) => {
+            let metrics = std::sync::Arc::new(Metrics::new(config.metrics.clone()));
+            metrics
+                .clone()
+                .serve(addr)
+                .await
+                .with_context(|| format!("Failed to serve metrics on {}", addr))?;
+            Some(metrics)
+        }
+        None => None,
+    };
+
+    // [OTLP] 可选: 每一对请求/响应导出为一个 Span
+    let otlp = match config.otlp.endpoint {
+        Some(_) => Some(
+            OtlpExporter::start(config.otlp.clone()).context("Failed to start OTLP exporter")?,
+        ),
+        None => None,
+    };
 
+    // 4. 用户态轮询 (Polling) & 处理
+    // [状态管理] L7Tracker: 每个连接推断出的协议、解码状态 + 尚未收到响应的请求，用于配对并计算 Latency (耗时)
+    let handler = std::sync::Arc::new(EventHandler::new(
+        L7Tracker::new(config.registry()?),
+        pods,
+        sink,
+        metrics,
+        otlp,
+        config.filter.clone(),
+        Duration::from_secs(config.protocol.session_idle_secs),
+    ));
     info!("Latency Tracking Enabled (Userspace)");
 
-    // [Correlation] 关联表：存储连接的五元组信息
-    // 为什么需要这个？因为 write/read 系统调用里没有 IP 信息，只有 FD。
-    struct ConnectionInfo {
-        saddr: Ipv4Addr,
-        daddr: Ipv4Addr,
-        sport: u16,
-        dport: u16,
-    }
-    use std::collections::HashMap;
-    use std::sync::{Arc, Mutex};
-
-    // connections:  Map<Key(Cgroup, FD), ConnectionInfo> -> 长期存储连接详情
-    let connections = Arc::new(Mutex::new(HashMap::<SessionKey, ConnectionInfo>::new()));
-
-    // pending_connects: Map<PID, Key> -> 临时存储，用于关联 connect 和 kprobe
-    // 作用：打通 tracepoint (有FD) 和 kprobe (有SourceIP) 的桥梁
-    let pending_connects = Arc::new(Mutex::new(HashMap::<u32, SessionKey>::new()));
-
-    // --- [模块二] 处理网络/TCP 事件 (TCP Events) ---
-    // 这里的逻辑最为复杂，负责将碎片化的内核事件拼接成完整的调用链
-    for cpu_id in cpus {
-        let mut buf = tcp_events.open(cpu_id, None)?;
-        let sessions = sessions.clone();
-        let connections = connections.clone();
-        let pending_connects = pending_connects.clone();
-
-        task::spawn(async move {
-            let mut buffers = (0..10)
-                .map(|_| BytesMut::with_capacity(10240))
-                .collect::<Vec<_>>();
-            loop {
-                let events = buf.read_events(&mut buffers).await.unwrap();
-                for i in 0..events.read {
-                    let mut const_buf = buffers[i].as_ref();
-                    // [反序列化]
-                    let event = unsafe { const_buf.as_ptr().cast::<TcpEvent>().read_unaligned() };
-
-                    let pod_name = resolve_pod(event.cgroup_id);
-                    // 初始 IP 解析 (注意: 对于 TX/RX 事件，这里的 IP 可能是 0)
-                    let mut saddr = Ipv4Addr::from(u32::from_be(event.saddr));
-                    let mut daddr = Ipv4Addr::from(u32::from_be(event.daddr));
-                    let mut sport = u16::from_be(event.sport as u16);
-                    let mut dport = u16::from_be(event.dport as u16);
-
-                    let direction_code = event.direction;
-
-                    // [Correlation Logic] 关联拼接逻辑
-                    let key = SessionKey {
-                        cgroup_id: event.cgroup_id,
-                        fd: event.fd,
-                    };
-
-                    // === 事件分发逻辑 ===
-                    if direction_code == 0 {
-                        // [阶段 A] CONNECT (tracepoint)
-                        // 此时我们有了 FD 和 目标IP，但还没有 源IP。
-                        // 动作：
-                        // 1. 在 connections 表中占位 (存入目标 IP)
-                        // 2. 在 pending_connects 挂号，等待 kprobe 来补全源 IP
-                        let info = ConnectionInfo {
-                            saddr, // 0.0.0.0
-                            daddr,
-                            sport,
-                            dport,
-                        };
-                        if let Ok(mut map) = connections.lock() {
-                            map.insert(key, info);
-                        }
-                        if let Ok(mut map) = pending_connects.lock() {
-                            map.insert(event.pid, key);
-                        }
-                    } else if direction_code == 4 {
-                        // [阶段 B] IP_INFO (kprobe)
-                        // 内核已经分配了 源IP。
-                        // 动作：
-                        // 1. 用 PID 查 pending_connects 找到刚才那个 FD 是谁
-                        // 2. 更新 connections 表，把 源IP 填进去
-                        if let Ok(pending) = pending_connects.lock() {
-                            if let Some(session_key) = pending.get(&event.pid) {
-                                if let Ok(mut conn_map) = connections.lock() {
-                                    if let Some(info) = conn_map.get_mut(session_key) {
-                                        info.saddr = saddr; // 填入真实的 Source IP
-                                        info.sport = sport; // 填入真实的 Source Port
-                                        // 更新本地变量以便立即打印
-                                        // saddr/sport already set from event
-                                    }
-                                }
-                            }
-                        }
-                    } else if direction_code == 2 || direction_code == 3 {
-                        // [阶段 C] TX (2) 或 RX (3)
-                        // 只有 FD，没有 IP。
-                        // 动作：去 connections 表里查这个 FD 对应的 IP 是什么。
-                        if let Ok(map) = connections.lock() {
-                            if let Some(info) = map.get(&key) {
-                                saddr = info.saddr;
-                                daddr = info.daddr;
-                                sport = info.sport;
-                                dport = info.dport;
-                            }
-                        }
-                    }
-
-                    if let Ok(mut map) = sessions.lock() {
-                        // Clean up old sessions logic ...
-                    }
-
-                    let direction = match direction_code {
-                        0 => "CONNECT",
-                        1 => "ACCEPT",
-                        2 => "TX", // Outgoing (发送)
-                        _ => "RX", // Incoming (接收)
-                    };
-
-                    // === L7 应用层解析逻辑 ===
-                    let mut l7_info = String::new();
-                    let mut latency_ms: Option<u128> = None;
-                    let mut payload_clean = "";
-
-                    if event.data_len > 0 {
-                        let payload_len =
-                            std::cmp::min(event.data_len as usize, event.payload.len());
-                        let payload_bytes = &event.payload[..payload_len];
-
-                        // === Protocol 1: MySQL (Binary) ===
-                        if dport == 3306 || sport == 3306 {
-                            if payload_bytes.len() > 4 {
-                                let seq = payload_bytes[3];
-
-                                // Determine Direction based on Port AND Direction Code
-                                // direction_code: 2 = TX (Write), 3 = RX (Read)
-
-                                let is_request = if dport == 3306 {
-                                    // We are Client (connecting to MySQL) OR Server (receiving from Client?)
-                                    // If we are Client, TX (2) is Request. RX (3) is Response.
-                                    direction_code == 2
-                                } else {
-                                    // sport == 3306. We are Server (replying to Client) OR Client (receiving from Server?)
-                                    // If we are Server, RX (3) is Request. TX (2) is Response.
-                                    direction_code == 3
-                                };
-
-                                if is_request {
-                                    // [MySQL Request]
-                                    // Header(4) + Command(1) + SQL(...)
-                                    // COM_QUERY = 0x03
-                                    if seq == 0
-                                        && payload_bytes.len() > 5
-                                        && payload_bytes[4] == 0x03
-                                    {
-                                        if let Ok(mut map) = sessions.lock() {
-                                            map.insert(key, std::time::Instant::now());
-                                        }
-                                        let sql_slice = &payload_bytes[5..];
-                                        let sql = String::from_utf8_lossy(sql_slice);
-                                        l7_info = format!("MySQL Query: {}", sql);
-                                    }
-                                } else {
-                                    // [MySQL Response]
-                                    // OK Packet: 0x00, ERR Packet: 0xFF
-                                    let packet_type = payload_bytes[4];
-                                    if packet_type == 0x00 || packet_type == 0xFF {
-                                        if let Ok(mut map) = sessions.lock() {
-                                            if let Some(start_time) = map.remove(&key) {
-                                                latency_ms = Some(start_time.elapsed().as_millis());
-                                            }
-                                        }
-                                        if packet_type == 0x00 {
-                                            l7_info = "MySQL Response: OK".to_string();
-                                        } else {
-                                            l7_info = "MySQL Response: ERR".to_string();
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        // === Protocol 2: Redis (RESP) ===
-                        if dport == 6379 || sport == 6379 {
-                            let is_request = if dport == 6379 {
-                                direction_code == 2
-                            } else {
-                                direction_code == 3
-                            };
-                            if is_request {
-                                // Request: Array "*"
-                                if payload_bytes.len() > 0 && payload_bytes[0] == b'*' {
-                                    if let Ok(mut map) = sessions.lock() {
-                                        map.insert(key, std::time::Instant::now());
-                                    }
-                                    // Parse RESP Array: *2\r\n$3\r\nGET\r\n...
-                                    // Simplified: Just convert to string and take first line or "Redis Command"
-                                    if let Ok(s) = std::str::from_utf8(payload_bytes) {
-                                        let cmd_line = s.lines().nth(2).unwrap_or("UNKNOWN");
-                                        l7_info = format!("Redis Command: {}", cmd_line);
-                                    }
-                                }
-                            } else {
-                                // Response: Simple String "+"
-                                if payload_bytes.len() > 0 && payload_bytes[0] == b'+' {
-                                    if let Ok(mut map) = sessions.lock() {
-                                        if let Some(start_time) = map.remove(&key) {
-                                            latency_ms = Some(start_time.elapsed().as_millis());
-                                        }
-                                    }
-                                    l7_info = "Redis Response: OK".to_string();
-                                }
-                            }
-                        }
-
-                        // === Protocol 3: PostgreSQL ===
-                        if dport == 5432 || sport == 5432 {
-                            let is_request = if dport == 5432 {
-                                direction_code == 2
-                            } else {
-                                direction_code == 3
-                            };
-                            if is_request {
-                                // Simple Query: 'Q'
-                                if payload_bytes.len() > 5 && payload_bytes[0] == b'Q' {
-                                    if let Ok(mut map) = sessions.lock() {
-                                        map.insert(key, std::time::Instant::now());
-                                    }
-                                    // Format: Q | Len(4) | SQLString | \0
-                                    let sql_slice = &payload_bytes[5..];
-                                    let sql = String::from_utf8_lossy(sql_slice);
-                                    l7_info = format!("PG Query: {}", sql.trim_matches('\0'));
-                                }
-                            } else {
-                                // CommandComplete: 'C'
-                                if payload_bytes.len() > 0 && payload_bytes[0] == b'C' {
-                                    if let Ok(mut map) = sessions.lock() {
-                                        if let Some(start_time) = map.remove(&key) {
-                                            latency_ms = Some(start_time.elapsed().as_millis());
-                                        }
-                                    }
-                                    l7_info = "PG Response: CommandComplete".to_string();
-                                }
-                            }
-                        }
-
-                        // === Protocol 2: HTTP (Text) ===
-                        // Fallback logic if L7 info is still empty
-                        if l7_info.is_empty() {
-                            if let Ok(payload_str) = std::str::from_utf8(payload_bytes) {
-                                payload_clean = payload_str.trim_matches('\0');
-
-                                // 1. [开始] 识别 HTTP 请求头
-                                // 如果是 GET/POST... 则认为是请求开始，记录时间
-                                if payload_clean.starts_with("GET ")
-                                    || payload_clean.starts_with("POST ")
-                                    || payload_clean.starts_with("PUT ")
-                                    || payload_clean.starts_with("DELETE ")
-                                    || payload_clean.starts_with("HEAD ")
-                                {
-                                    if let Ok(mut map) = sessions.lock() {
-                                        map.insert(key, std::time::Instant::now());
-                                    }
-                                    if let Some(line) = payload_clean.lines().next() {
-                                        l7_info = format!("HTTP Request: {}", line);
-                                    }
-                                }
-                                // 2. [结束] 识别 HTTP 响应头
-                                // 如果是 HTTP/1.1... 则认为是响应结束，计算耗时
-                                else if payload_clean.starts_with("HTTP/") {
-                                    if let Ok(mut map) = sessions.lock() {
-                                        if let Some(start_time) = map.remove(&key) {
-                                            latency_ms = Some(start_time.elapsed().as_millis());
-                                        }
-                                    }
-                                    if let Some(line) = payload_clean.lines().next() {
-                                        l7_info = format!("HTTP Response: {}", line);
-                                    }
-                                }
-                            }
-                        }
-                    }
-
-                    // [ANTI-NOISE FILTER] 降噪过滤器
-                    // 过滤掉 Agent 自身通信、Docker 内部通信等产生的干扰流量
-                    if payload_clean.contains("{\"log\":")
-                        || payload_clean.contains("masdeepflow")
-                        || payload_clean.contains("SandboxID")
-                        || payload_clean.contains("Bridge")
-                        || payload_clean.contains("/containers/")
-                        || payload_clean.contains("GET /v1.")
-                    {
-                        continue;
-                    }
-
-                    // [LOGGING STRATEGY] 日志策略
-                    // 只打印关键信息：握手(Handshake) 和 有效的 HTTP 数据
-                    let is_handshake = direction == "CONNECT" || direction == "ACCEPT";
-                    let is_http = !l7_info.is_empty();
-
-                    if is_handshake || is_http {
-                        info!(
-                            "[TCP] Type: {}, Pod: {}, {} -> {}:{}, {}, {}",
-                            direction,
-                            pod_name,
-                            saddr,
-                            daddr,
-                            dport,
-                            if !l7_info.is_empty() {
-                                format!("{}, ", l7_info)
-                            } else {
-                                "".to_string()
-                            },
-                            if let Some(ms) = latency_ms {
-                                format!("Latency: {}ms", ms)
-                            } else {
-                                "".to_string()
-                            }
-                        );
-                    }
-                }
-            }
-        });
-    }
+    // [Transport] 接管 EVENTS 通道，进程 / TCP / 连接关闭事件都从这里进入 EventHandler
+    transport::spawn(&mut bpf, transport, &config.transport, handler)?;
 
     info!("Waiting for events... (Ctrl-C to exit)");
     signal::ctrl_c().await?;
     info!("Exiting...");
     Ok(())
 }
-
-fn resolve_pod(cgroup_id: u64) -> &'static str {
-    match cgroup_id % 3 {
-        0 => "frontend-pod-1",
-        1 => "backend-service-2",
-        _ => "unknown-pod",
-    }
-}
@@ -0,0 +1,484 @@
+// [Metrics] Prometheus RED 指标
+//
+// 每条 L7Record 按 (源 Pod, 目的端, 协议, 归一化 endpoint) 聚合为:
+//   masdeepflow_l7_requests_total            请求数 (Rate)
+//   masdeepflow_l7_errors_total              错误数 (Errors)
+//   masdeepflow_l7_request_duration_seconds  延迟直方图 (Duration)
+//
+// endpoint 会先归一化 (HTTP 方法 + 去掉 ID 的路径、gRPC 方法、SQL 动词、Redis 命令)，
+// 再加上 max_series 的硬上限: 超出上限的新标签组合统一记到 endpoint="__overflow__" 的一组序列中，
+// 这样无论流量如何变化，/metrics 的大小都是有界的。
+//
+// /metrics 由一个内嵌的极简 HTTP/1.1 服务提供，只处理 GET 请求。
+
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::record::{L7Record, Protocol};
+
+const OVERFLOW: &str = "__overflow__";
+const MAX_ENDPOINT_LEN: usize = 64;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    pub listen: Option<SocketAddr>, // 为空时不启用 /metrics
+    pub max_series: usize,          // 标签组合上限
+    pub endpoint_label: bool,       // 关闭后 endpoint 恒为 ""，只按 源/目的/协议 聚合
+    pub buckets_ms: Vec<f64>,       // 延迟直方图桶 (毫秒)
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        MetricsConfig {
+            listen: None,
+            max_series: 2000,
+            endpoint_label: true,
+            buckets_ms: vec![
+                1.0, 2.5, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0,
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct SeriesKey {
+    src_pod: String,
+    destination: String,
+    protocol: Protocol,
+    endpoint: String,
+}
+
+struct Series {
+    requests: u64,
+    errors: u64,
+    buckets: Vec<u64>, // 与 Metrics::buckets 一一对应 (非累计)，+Inf 由 requests 表示
+    sum_seconds: f64,
+}
+
+pub struct Metrics {
+    config: MetricsConfig,
+    buckets: Vec<f64>, // 秒，升序
+    series: Mutex<HashMap<SeriesKey, Series>>,
+}
+
+impl Metrics {
+    pub fn new(config: MetricsConfig) -> Self {
+        let mut buckets: Vec<f64> = config.buckets_ms.iter().map(|ms| ms / 1000.0).collect();
+        buckets.sort_by(f64::total_cmp);
+        buckets.dedup();
+        Metrics {
+            config,
+            buckets,
+            series: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn observe(&self, record: &L7Record) {
+        let endpoint = if self.config.endpoint_label {
+            normalize_endpoint(record.protocol, &record.request)
+        } else {
+            String::new()
+        };
+        let mut key = SeriesKey {
+            src_pod: record.pod.clone(),
+            destination: record
+                .peer
+                .clone()
+                .unwrap_or_else(|| format!("{}:{}", record.daddr, record.dport)),
+            protocol: record.protocol,
+            endpoint,
+        };
+        let error = record
+            .response
+            .as_deref()
+            .is_some_and(|status| is_error(record.protocol, status));
+
+        let Ok(mut series) = self.series.lock() else {
+            return;
+        };
+        if !series.contains_key(&key) && series.len() >= self.config.max_series {
+            debug!("[Metrics] series limit reached, folding {:?}", key);
+            key = SeriesKey {
+                src_pod: OVERFLOW.to_string(),
+                destination: OVERFLOW.to_string(),
+                protocol: key.protocol,
+                endpoint: OVERFLOW.to_string(),
+            };
+        }
+        let entry = series.entry(key).or_insert_with(|| Series {
+            requests: 0,
+            errors: 0,
+            buckets: vec![0; self.buckets.len()],
+            sum_seconds: 0.0,
+        });
+        entry.requests += 1;
+        if error {
+            entry.errors += 1;
+        }
+        if let Some(us) = record.latency_us {
+            let seconds = us as f64 / 1_000_000.0;
+            entry.sum_seconds += seconds;
+            if let Some(idx) = self.buckets.iter().position(|le| seconds <= *le) {
+                entry.buckets[idx] += 1;
+            }
+        }
+    }
+
+    // Prometheus text exposition format 0.0.4
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let Ok(series) = self.series.lock() else {
+            return out;
+        };
+        let mut keys: Vec<_> = series.keys().collect();
+        keys.sort_by(|a, b| {
+            (&a.src_pod, &a.destination, a.protocol.name(), &a.endpoint).cmp(&(
+                &b.src_pod,
+                &b.destination,
+                b.protocol.name(),
+                &b.endpoint,
+            ))
+        });
+
+        out.push_str("# HELP masdeepflow_l7_requests_total Matched L7 request/response pairs.\n");
+        out.push_str("# TYPE masdeepflow_l7_requests_total counter\n");
+        for key in &keys {
+            let _ = writeln!(
+                out,
+                "masdeepflow_l7_requests_total{{{}}} {}",
+                labels(key),
+                series[*key].requests
+            );
+        }
+
+        out.push_str("# HELP masdeepflow_l7_errors_total L7 responses classified as errors.\n");
+        out.push_str("# TYPE masdeepflow_l7_errors_total counter\n");
+        for key in &keys {
+            let _ = writeln!(
+                out,
+                "masdeepflow_l7_errors_total{{{}}} {}",
+                labels(key),
+                series[*key].errors
+            );
+        }
+
+        out.push_str(
+            "# HELP masdeepflow_l7_request_duration_seconds L7 request latency in seconds.\n",
+        );
+        out.push_str("# TYPE masdeepflow_l7_request_duration_seconds histogram\n");
+        for key in &keys {
+            let s = &series[*key];
+            let labels = labels(key);
+            let mut cumulative = 0;
+            for (le, count) in self.buckets.iter().zip(&s.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "masdeepflow_l7_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, le, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "masdeepflow_l7_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, s.requests
+            );
+            let _ = writeln!(
+                out,
+                "masdeepflow_l7_request_duration_seconds_sum{{{}}} {}",
+                labels, s.sum_seconds
+            );
+            let _ = writeln!(
+                out,
+                "masdeepflow_l7_request_duration_seconds_count{{{}}} {}",
+                labels, s.requests
+            );
+        }
+
+        out.push_str("# HELP masdeepflow_l7_series Active label combinations.\n");
+        out.push_str("# TYPE masdeepflow_l7_series gauge\n");
+        let _ = writeln!(out, "masdeepflow_l7_series {}", series.len());
+        out
+    }
+
+    // 在后台启动 /metrics 服务；只有绑定端口失败才返回错误
+    pub async fn serve(self: Arc<Self>, addr: SocketAddr) -> std::io::Result<()> {
+        let listener = TcpListener::bind(addr).await?;
+        info!(
+            "[Metrics] Serving Prometheus metrics on http://{}/metrics",
+            addr
+        );
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        let metrics = self.clone();
+                        tokio::spawn(async move {
+                            if let Err(e) = metrics.handle(stream).await {
+                                debug!("[Metrics] connection error: {}", e);
+                            }
+                        });
+                    }
+                    Err(e) => warn!("[Metrics] accept failed: {}", e),
+                }
+            }
+        });
+        Ok(())
+    }
+
+    async fn handle(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        // 只需要请求行，读一次即可 (Prometheus 的抓取请求远小于 4K)
+        let mut buf = [0u8; 4096];
+        let n = stream.read(&mut buf).await?;
+        let request = String::from_utf8_lossy(&buf[..n]);
+        let mut parts = request.lines().next().unwrap_or("").split_whitespace();
+        let (status, body) = match (parts.next(), parts.next()) {
+            (Some("GET"), Some("/metrics")) => ("200 OK", self.render()),
+            (Some("GET"), Some(_)) => ("404 Not Found", "Not Found\n".to_string()),
+            _ => ("405 Method Not Allowed", "Method Not Allowed\n".to_string()),
+        };
+        let header = format!(
+            "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            status,
+            body.len()
+        );
+        stream.write_all(header.as_bytes()).await?;
+        stream.write_all(body.as_bytes()).await?;
+        stream.shutdown().await
+    }
+}
+
+fn labels(key: &SeriesKey) -> String {
+    format!(
+        "src_pod=\"{}\",destination=\"{}\",protocol=\"{}\",endpoint=\"{}\"",
+        escape(&key.src_pod),
+        escape(&key.destination),
+        key.protocol.name(),
+        escape(&key.endpoint)
+    )
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// 按协议把请求摘要归一化为低基数的 endpoint:
+// HTTP  : "GET /users/42?x=1 HTTP/1.1" -> "GET /users/{id}"
+// SQL   : "select * from t where ..."  -> "SELECT"
+// Redis : "get"                        -> "GET"
+pub fn normalize_endpoint(protocol: Protocol, request: &str) -> String {
+    let mut endpoint = match protocol {
+        Protocol::Http | Protocol::Http2 => {
+            let mut parts = request.split_whitespace();
+            let method = parts.next().unwrap_or("");
+            let path = parts.next().unwrap_or("");
+            let path = path.split(['?', '#']).next().unwrap_or("");
+            let path = path
+                .split('/')
+                .map(|seg| if is_id_segment(seg) { "{id}" } else { seg })
+                .collect::<Vec<_>>()
+                .join("/");
+            format!("{} {}", method, path)
+        }
+        // "/package.Service/Method" 本身就是有界的
+        Protocol::Grpc => request.to_string(),
+        // "Produce orders" / "Fetch orders": API 名 + Topic，按 Topic 区分延迟
+        Protocol::Kafka => request.to_string(),
+        Protocol::Mysql | Protocol::Postgres | Protocol::Redis => request
+            .split_whitespace()
+            .next()
+            .unwrap_or("")
+            .trim_matches(|c: char| !c.is_ascii_alphanumeric())
+            .to_ascii_uppercase(),
+    };
+    if endpoint.len() > MAX_ENDPOINT_LEN {
+        let mut end = MAX_ENDPOINT_LEN;
+        while !endpoint.is_char_boundary(end) {
+            end -= 1;
+        }
+        endpoint.truncate(end);
+    }
+    endpoint
+}
+
+// 纯数字、UUID、长十六进制串 (如对象 ID / 哈希) 视为 ID
+fn is_id_segment(seg: &str) -> bool {
+    if seg.is_empty() {
+        return false;
+    }
+    let hex_or_dash = seg.chars().all(|c| c.is_ascii_hexdigit() || c == '-');
+    seg.chars().all(|c| c.is_ascii_digit()) || (hex_or_dash && seg.len() >= 16)
+}
+
+// HTTP 5xx / 数据库 ERR (PG 为 ERROR / FATAL) / gRPC 服务端错误码 / Kafka 非 0 错误码视为错误；4xx、NOT_FOUND 等属于调用方问题，不计入服务端错误
+pub fn is_error(protocol: Protocol, status: &str) -> bool {
+    match protocol {
+        Protocol::Http | Protocol::Http2 => status.starts_with('5'),
+        Protocol::Grpc => matches!(
+            status,
+            "UNKNOWN"
+                | "DEADLINE_EXCEEDED"
+                | "UNIMPLEMENTED"
+                | "INTERNAL"
+                | "UNAVAILABLE"
+                | "DATA_LOSS"
+        ),
+        Protocol::Mysql | Protocol::Redis => status.starts_with("ERR"),
+        // ErrorResponse 的严重级别 + SQLSTATE，如 "ERROR 42P01"、"FATAL 28P01"
+        Protocol::Postgres => matches!(status.split(' ').next(), Some("ERROR" | "FATAL" | "PANIC")),
+        // 错误码名称 (如 NOT_LEADER_OR_FOLLOWER)，成功为 OK
+        Protocol::Kafka => status != "OK",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        net::{IpAddr, Ipv4Addr},
+    };
+
+    use super::*;
+    use crate::record::Role;
+
+    fn record(protocol: Protocol, request: &str, status: &str, latency_us: u64) -> L7Record {
+        L7Record {
+            timestamp_ns: 0,
+            pid: 42,
+            comm: "api".to_string(),
+            pod: "shop/api".to_string(),
+            workload: None,
+            peer: Some("shop/db".to_string()),
+            saddr: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 5)),
+            sport: 51234,
+            daddr: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 9)),
+            dport: 80,
+            protocol,
+            role: Role::Client,
+            request: request.to_string(),
+            response: Some(status.to_string()),
+            latency_us: Some(latency_us),
+            req_bytes: 0,
+            resp_bytes: 0,
+            attributes: BTreeMap::new(),
+        }
+    }
+
+    fn sample(text: &str, name: &str) -> Vec<String> {
+        text.lines()
+            .filter(|line| line.starts_with(name))
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn normalizes_ids_and_verbs() {
+        let http = |req| normalize_endpoint(Protocol::Http, req);
+        assert_eq!(
+            http("GET /api/v1/users/42/orders?page=3 HTTP/1.1"),
+            "GET /api/v1/users/{id}/orders"
+        );
+        assert_eq!(
+            http("DELETE /objects/3fa85f64-5717-4562-b3fc-2c963f66afa6 HTTP/1.1"),
+            "DELETE /objects/{id}"
+        );
+        assert_eq!(
+            http("GET /blobs/9f86d081884c7d659a2feaa0c55ad015#x HTTP/1.1"),
+            "GET /blobs/{id}"
+        );
+        // 短的十六进制单词 (如 "cafe"、"v1") 不是 ID
+        assert_eq!(http("GET /cafe/v1 HTTP/1.1"), "GET /cafe/v1");
+
+        assert_eq!(
+            normalize_endpoint(Protocol::Mysql, "select * from orders where id = 7"),
+            "SELECT"
+        );
+        assert_eq!(
+            normalize_endpoint(Protocol::Postgres, "(UPDATE accounts SET ...)"),
+            "UPDATE"
+        );
+        assert_eq!(normalize_endpoint(Protocol::Redis, "get session:9"), "GET");
+
+        let long = format!("GET /{} HTTP/1.1", "z".repeat(100));
+        assert_eq!(http(&long).len(), MAX_ENDPOINT_LEN);
+    }
+
+    #[test]
+    fn folds_new_series_into_overflow_after_limit() {
+        let metrics = Metrics::new(MetricsConfig {
+            max_series: 2,
+            ..MetricsConfig::default()
+        });
+        metrics.observe(&record(Protocol::Http, "GET /a HTTP/1.1", "200 OK", 100));
+        metrics.observe(&record(Protocol::Http, "GET /b HTTP/1.1", "200 OK", 100));
+        // 已存在的序列不受上限影响
+        metrics.observe(&record(Protocol::Http, "GET /a HTTP/1.1", "500 Error", 100));
+        for path in ["/c", "/d", "/e"] {
+            let req = format!("GET {} HTTP/1.1", path);
+            metrics.observe(&record(Protocol::Http, &req, "200 OK", 100));
+        }
+
+        let text = metrics.render();
+        let requests = sample(&text, "masdeepflow_l7_requests_total{");
+        assert_eq!(
+            requests,
+            [
+                "masdeepflow_l7_requests_total{src_pod=\"__overflow__\",destination=\"__overflow__\",protocol=\"http\",endpoint=\"__overflow__\"} 3",
+                "masdeepflow_l7_requests_total{src_pod=\"shop/api\",destination=\"shop/db\",protocol=\"http\",endpoint=\"GET /a\"} 2",
+                "masdeepflow_l7_requests_total{src_pod=\"shop/api\",destination=\"shop/db\",protocol=\"http\",endpoint=\"GET /b\"} 1",
+            ]
+        );
+        assert!(text.contains("endpoint=\"GET /a\"} 1\n"));
+        // 溢出序列本身计入一组，此后不再增长
+        assert!(text.ends_with("masdeepflow_l7_series 3\n"));
+    }
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let metrics = Metrics::new(MetricsConfig {
+            buckets_ms: vec![10.0, 1.0, 100.0],
+            ..MetricsConfig::default()
+        });
+        // 0.5ms, 5ms, 5ms, 50ms, 2s (超出所有桶)
+        for us in [500, 5_000, 5_000, 50_000, 2_000_000] {
+            metrics.observe(&record(Protocol::Redis, "GET k", "OK", us));
+        }
+        let text = metrics.render();
+        let buckets: Vec<String> = sample(&text, "masdeepflow_l7_request_duration_seconds_bucket")
+            .iter()
+            .map(|line| line.split_once(",le=").unwrap().1.to_string())
+            .collect();
+        assert_eq!(
+            buckets,
+            ["\"0.001\"} 1", "\"0.01\"} 3", "\"0.1\"} 4", "\"+Inf\"} 5"]
+        );
+        assert_eq!(
+            sample(&text, "masdeepflow_l7_request_duration_seconds_count")[0]
+                .rsplit(' ')
+                .next(),
+            Some("5")
+        );
+        let sum: f64 = sample(&text, "masdeepflow_l7_request_duration_seconds_sum")[0]
+            .rsplit(' ')
+            .next()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((sum - 2.0605).abs() < 1e-9);
+    }
+}
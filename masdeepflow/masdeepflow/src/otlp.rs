@@ -0,0 +1,650 @@
+// [Export] OpenTelemetry OTLP 导出
+//
+// 每条 L7Record 转换为一个 OTLP Span，携带语义约定 (semantic conventions) 属性:
+//   HTTP : http.request.method / url.path / http.response.status_code
+//   gRPC : rpc.system / rpc.service / rpc.method / rpc.grpc.status_code
+//   DB   : db.system / db.statement / db.operation.name
+//   Kafka: messaging.system / messaging.operation.name / messaging.destination.name / messaging.client.id
+//   通用 : server.address / server.port / network.peer.* / k8s.pod.name / process.pid
+//
+// 发送流程: export() 非阻塞地放入有界队列 (满了直接丢弃，绝不反压事件循环)
+//        -> 后台任务按 batch_size / flush_interval_ms 攒批
+//        -> HTTP/protobuf (POST /v1/traces) 或 gRPC (TraceService/Export) 发送，可重试错误按指数退避重试。
+//
+// 本地验证: `traffic_gen otlp-collector` 启动一个 mock collector (HTTP/protobuf, :4318)。
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use anyhow::Context as _;
+use clap::ValueEnum;
+use log::{debug, info, warn};
+use opentelemetry_proto::tonic::{
+    collector::trace::v1::{ExportTraceServiceRequest, trace_service_client::TraceServiceClient},
+    common::v1::{AnyValue, InstrumentationScope, KeyValue, any_value},
+    resource::v1::Resource,
+    trace::v1::{ResourceSpans, ScopeSpans, Span, Status, span::SpanKind, status::StatusCode},
+};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use tokio::{sync::mpsc, time};
+use tonic::{Code, transport::Channel};
+
+use crate::{
+    metrics::{is_error, normalize_endpoint},
+    protocol::AttrValue,
+    record::{L7Record, Protocol, Role},
+};
+
+// 重试退避: 200ms 起步，每次翻倍，最多 5s
+const INITIAL_BACKOFF: Duration = Duration::from_millis(200);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OtlpProtocol {
+    Http, // HTTP/protobuf，默认端口 4318
+    Grpc, // gRPC，默认端口 4317
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OtlpConfig {
+    pub endpoint: Option<String>, // 为空时不启用，如 "http://otel-collector:4318"
+    pub protocol: OtlpProtocol,
+    pub service_name: String, // Resource 上的 service.name
+    pub batch_size: usize,
+    pub flush_interval_ms: u64,
+    pub queue_size: usize, // 待发送 Span 的队列上限
+    pub max_retries: u32,
+    pub timeout_ms: u64, // 单次导出请求的超时
+}
+
+impl Default for OtlpConfig {
+    fn default() -> Self {
+        OtlpConfig {
+            endpoint: None,
+            protocol: OtlpProtocol::Http,
+            service_name: "masdeepflow".to_string(),
+            batch_size: 512,
+            flush_interval_ms: 2000,
+            queue_size: 4096,
+            max_retries: 5,
+            timeout_ms: 10_000,
+        }
+    }
+}
+
+enum Transport {
+    Http {
+        client: reqwest::Client,
+        url: String,
+    },
+    Grpc(TraceServiceClient<Channel>),
+}
+
+enum SendError {
+    Retryable(String),
+    Fatal(String),
+}
+
+impl Transport {
+    fn new(config: &OtlpConfig, endpoint: &str) -> anyhow::Result<Self> {
+        let timeout = Duration::from_millis(config.timeout_ms);
+        match config.protocol {
+            OtlpProtocol::Http => {
+                // 与 OTEL_EXPORTER_OTLP_ENDPOINT 的约定一致: 只给了基础地址时补上 /v1/traces
+                let base = endpoint.trim_end_matches('/');
+                let url = if base.ends_with("/v1/traces") {
+                    base.to_string()
+                } else {
+                    format!("{}/v1/traces", base)
+                };
+                let client = reqwest::Client::builder()
+                    .timeout(timeout)
+                    .build()
+                    .context("Failed to build OTLP HTTP client")?;
+                Ok(Transport::Http { client, url })
+            }
+            OtlpProtocol::Grpc => {
+                // 延迟连接: collector 暂时不可用不影响 Agent 启动，由重试兜底
+                let channel = Channel::from_shared(endpoint.to_string())
+                    .with_context(|| format!("Invalid OTLP gRPC endpoint '{}'", endpoint))?
+                    .timeout(timeout)
+                    .connect_lazy();
+                Ok(Transport::Grpc(TraceServiceClient::new(channel)))
+            }
+        }
+    }
+
+    async fn send(&mut self, request: &ExportTraceServiceRequest) -> Result<(), SendError> {
+        match self {
+            Transport::Http { client, url } => {
+                let response = client
+                    .post(url.as_str())
+                    .header("Content-Type", "application/x-protobuf")
+                    .body(request.encode_to_vec())
+                    .send()
+                    .await
+                    .map_err(|e| SendError::Retryable(e.to_string()))?;
+                let status = response.status();
+                if status.is_success() {
+                    return Ok(());
+                }
+                // OTLP/HTTP 规范: 429/502/503/504 可重试，其余视为永久失败
+                let message = format!("collector responded {}", status);
+                match status.as_u16() {
+                    429 | 502 | 503 | 504 => Err(SendError::Retryable(message)),
+                    _ => Err(SendError::Fatal(message)),
+                }
+            }
+            Transport::Grpc(client) => match client.export(request.clone()).await {
+                Ok(_) => Ok(()),
+                Err(status) => {
+                    let message = format!("{:?}: {}", status.code(), status.message());
+                    match status.code() {
+                        Code::Cancelled
+                        | Code::DeadlineExceeded
+                        | Code::ResourceExhausted
+                        | Code::Aborted
+                        | Code::OutOfRange
+                        | Code::Unavailable
+                        | Code::DataLoss => Err(SendError::Retryable(message)),
+                        _ => Err(SendError::Fatal(message)),
+                    }
+                }
+            },
+        }
+    }
+}
+
+pub struct OtlpExporter {
+    tx: mpsc::Sender<Span>,
+    dropped: AtomicU64,
+}
+
+impl OtlpExporter {
+    // 启动后台发送任务；必须在 tokio runtime 中调用
+    pub fn start(config: OtlpConfig) -> anyhow::Result<Self> {
+        let endpoint = config
+            .endpoint
+            .clone()
+            .context("OTLP endpoint is not configured")?;
+        let transport = Transport::new(&config, &endpoint)?;
+        let (tx, rx) = mpsc::channel(config.queue_size.max(1));
+        info!(
+            "[OTLP] Exporting spans to {} ({:?})",
+            endpoint, config.protocol
+        );
+        tokio::spawn(run(config, transport, rx));
+        Ok(OtlpExporter {
+            tx,
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    pub fn export(&self, record: &L7Record) {
+        if self.tx.try_send(to_span(record)).is_err() {
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            // 避免刷屏: 只在 1, 2, 4, 8... 时提示
+            if dropped.is_power_of_two() {
+                warn!("[OTLP] export queue full, {} spans dropped so far", dropped);
+            }
+        }
+    }
+}
+
+async fn run(config: OtlpConfig, mut transport: Transport, mut rx: mpsc::Receiver<Span>) {
+    let batch_size = config.batch_size.max(1);
+    let mut batch = Vec::with_capacity(batch_size);
+    // interval 默认第一次 tick 立即触发，会把刚收到的第一个 Span 单独发出去，这里推迟一个周期
+    let period = Duration::from_millis(config.flush_interval_ms.max(1));
+    let mut ticker = time::interval_at(time::Instant::now() + period, period);
+    loop {
+        tokio::select! {
+            span = rx.recv() => match span {
+                Some(span) => {
+                    batch.push(span);
+                    if batch.len() >= batch_size {
+                        flush(&config, &mut transport, &mut batch).await;
+                    }
+                }
+                None => {
+                    flush(&config, &mut transport, &mut batch).await;
+                    return;
+                }
+            },
+            _ = ticker.tick() => flush(&config, &mut transport, &mut batch).await,
+        }
+    }
+}
+
+async fn flush(config: &OtlpConfig, transport: &mut Transport, batch: &mut Vec<Span>) {
+    if batch.is_empty() {
+        return;
+    }
+    let count = batch.len();
+    let request = ExportTraceServiceRequest {
+        resource_spans: vec![ResourceSpans {
+            resource: Some(Resource {
+                attributes: vec![string_attr("service.name", &config.service_name)],
+                ..Default::default()
+            }),
+            scope_spans: vec![ScopeSpans {
+                scope: Some(InstrumentationScope {
+                    name: "masdeepflow".to_string(),
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                    ..Default::default()
+                }),
+                spans: std::mem::take(batch),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }],
+    };
+
+    let mut backoff = INITIAL_BACKOFF;
+    for attempt in 0..=config.max_retries {
+        match transport.send(&request).await {
+            Ok(()) => {
+                debug!("[OTLP] exported {} spans", count);
+                return;
+            }
+            Err(SendError::Fatal(e)) => {
+                warn!("[OTLP] dropping {} spans: {}", count, e);
+                return;
+            }
+            Err(SendError::Retryable(e)) if attempt < config.max_retries => {
+                debug!(
+                    "[OTLP] export failed (attempt {}): {}, retrying in {:?}",
+                    attempt + 1,
+                    e,
+                    backoff
+                );
+                time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+            Err(SendError::Retryable(e)) => {
+                warn!(
+                    "[OTLP] dropping {} spans after {} retries: {}",
+                    count, config.max_retries, e
+                );
+            }
+        }
+    }
+}
+
+fn to_span(record: &L7Record) -> Span {
+    let endpoint = normalize_endpoint(record.protocol, &record.request);
+    let mut attributes = Vec::new();
+
+    match record.protocol {
+        // method / url.path / status_code、rpc.service / rpc.method 等由解码器放在 attributes 中
+        Protocol::Http | Protocol::Http2 | Protocol::Grpc => {}
+        Protocol::Mysql | Protocol::Postgres | Protocol::Redis => {
+            let system = match record.protocol {
+                Protocol::Postgres => "postgresql",
+                other => other.name(),
+            };
+            attributes.push(string_attr("db.system", system));
+            attributes.push(string_attr("db.statement", &record.request));
+            if !endpoint.is_empty() {
+                attributes.push(string_attr("db.operation.name", &endpoint));
+            }
+        }
+        // Topic / client.id / 错误码由解码器放在 attributes 中
+        Protocol::Kafka => {
+            attributes.push(string_attr("messaging.system", "kafka"));
+            if let Some(operation) = record.request.split(' ').next() {
+                attributes.push(string_attr("messaging.operation.name", operation));
+            }
+        }
+    }
+
+    for (key, value) in &record.attributes {
+        attributes.push(match value {
+            AttrValue::Str(s) => string_attr(key, s),
+            AttrValue::Int(n) => int_attr(key, *n),
+        });
+    }
+
+    // client 视角下对端是服务端；server 视角下本端是服务端
+    let (server_addr, server_port) = match record.role {
+        Role::Client => (
+            record
+                .peer
+                .clone()
+                .unwrap_or_else(|| record.daddr.to_string()),
+            record.dport,
+        ),
+        Role::Server => (record.saddr.to_string(), record.sport),
+    };
+    attributes.push(string_attr("server.address", &server_addr));
+    attributes.push(int_attr("server.port", server_port.into()));
+    attributes.push(string_attr(
+        "network.peer.address",
+        &record.daddr.to_string(),
+    ));
+    attributes.push(int_attr("network.peer.port", record.dport.into()));
+    // 本端 Pod 名为 "namespace/name" (K8s 元数据可用时)，否则是 cgroup 推断出的名字
+    match record.pod.split_once('/') {
+        Some((namespace, name)) => {
+            attributes.push(string_attr("k8s.namespace.name", namespace));
+            attributes.push(string_attr("k8s.pod.name", name));
+        }
+        None => attributes.push(string_attr("k8s.pod.name", &record.pod)),
+    }
+    if let Some(workload) = &record.workload {
+        attributes.push(string_attr("k8s.workload.name", workload));
+    }
+    attributes.push(int_attr("process.pid", record.pid.into()));
+    attributes.push(string_attr("process.executable.name", &record.comm));
+
+    let status = match record.response.as_deref() {
+        Some(status) if is_error(record.protocol, status) => Status {
+            code: StatusCode::Error as i32,
+            message: status.to_string(),
+        },
+        _ => Status::default(), // Unset
+    };
+
+    let end_ns = record.timestamp_ns + record.latency_us.unwrap_or(0) * 1000;
+    let trace_id = [random_u64().to_be_bytes(), random_u64().to_be_bytes()].concat();
+    Span {
+        trace_id,
+        span_id: random_u64().to_be_bytes().to_vec(),
+        name: if endpoint.is_empty() {
+            record.protocol.name().to_string()
+        } else {
+            endpoint
+        },
+        kind: match record.role {
+            Role::Client => SpanKind::Client,
+            Role::Server => SpanKind::Server,
+        } as i32,
+        start_time_unix_nano: record.timestamp_ns,
+        end_time_unix_nano: end_ns,
+        attributes,
+        status: Some(status),
+        ..Default::default()
+    }
+}
+
+fn string_attr(key: &str, value: &str) -> KeyValue {
+    KeyValue {
+        key: key.to_string(),
+        value: Some(AnyValue {
+            value: Some(any_value::Value::StringValue(value.to_string())),
+        }),
+    }
+}
+
+fn int_attr(key: &str, value: i64) -> KeyValue {
+    KeyValue {
+        key: key.to_string(),
+        value: Some(AnyValue {
+            value: Some(any_value::Value::IntValue(value)),
+        }),
+    }
+}
+
+// trace/span ID 只需要唯一，不需要密码学强度: 用随机种子的 SipHash 打散递增计数器
+fn random_u64() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.finish().max(1) // 全 0 ID 无效
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{BTreeMap, VecDeque},
+        net::{IpAddr, Ipv4Addr},
+        sync::{Arc, Mutex},
+    };
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::*;
+
+    fn record(protocol: Protocol, request: &str, response: &str) -> L7Record {
+        L7Record {
+            timestamp_ns: 1_700_000_000_000_000_000,
+            pid: 42,
+            comm: "api".to_string(),
+            pod: "shop/api-7d9f".to_string(),
+            workload: Some("api".to_string()),
+            peer: Some("shop/mysql".to_string()),
+            saddr: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 5)),
+            sport: 51234,
+            daddr: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 9)),
+            dport: 3306,
+            protocol,
+            role: Role::Client,
+            request: request.to_string(),
+            response: Some(response.to_string()),
+            latency_us: Some(1500),
+            req_bytes: 20,
+            resp_bytes: 11,
+            attributes: BTreeMap::new(),
+        }
+    }
+
+    fn attr<'a>(span: &'a Span, key: &str) -> Option<&'a any_value::Value> {
+        span.attributes
+            .iter()
+            .find(|kv| kv.key == key)
+            .and_then(|kv| kv.value.as_ref()?.value.as_ref())
+    }
+
+    fn string(value: &str) -> Option<any_value::Value> {
+        Some(any_value::Value::StringValue(value.to_string()))
+    }
+
+    #[test]
+    fn db_span_attributes() {
+        let span = to_span(&record(
+            Protocol::Mysql,
+            "SELECT * FROM orders",
+            "ERR 1146 (42S02)",
+        ));
+        assert_eq!(span.name, "SELECT");
+        assert_eq!(span.kind, SpanKind::Client as i32);
+        assert_eq!(span.trace_id.len(), 16);
+        assert_eq!(span.span_id.len(), 8);
+        assert_eq!(
+            span.end_time_unix_nano - span.start_time_unix_nano,
+            1_500_000
+        );
+        assert_eq!(attr(&span, "db.system").cloned(), string("mysql"));
+        assert_eq!(
+            attr(&span, "db.statement").cloned(),
+            string("SELECT * FROM orders")
+        );
+        assert_eq!(attr(&span, "server.address").cloned(), string("shop/mysql"));
+        assert_eq!(
+            attr(&span, "server.port").cloned(),
+            Some(any_value::Value::IntValue(3306))
+        );
+        assert_eq!(attr(&span, "k8s.namespace.name").cloned(), string("shop"));
+        assert_eq!(attr(&span, "k8s.pod.name").cloned(), string("api-7d9f"));
+        let status = span.status.unwrap();
+        assert_eq!(status.code, StatusCode::Error as i32);
+        assert_eq!(status.message, "ERR 1146 (42S02)");
+    }
+
+    #[test]
+    fn http_span_keeps_decoder_attributes() {
+        let mut rec = record(Protocol::Http, "GET /api/v1/users/42", "200 OK");
+        rec.role = Role::Server;
+        rec.peer = None;
+        rec.attributes
+            .insert("http.request.method", AttrValue::from("GET"));
+        rec.attributes
+            .insert("http.response.status_code", AttrValue::Int(200));
+        let span = to_span(&rec);
+        assert_eq!(span.kind, SpanKind::Server as i32);
+        assert_eq!(attr(&span, "http.request.method").cloned(), string("GET"));
+        assert_eq!(
+            attr(&span, "http.response.status_code").cloned(),
+            Some(any_value::Value::IntValue(200))
+        );
+        // 服务端视角下 server.address 是本端地址
+        assert_eq!(attr(&span, "server.address").cloned(), string("10.0.0.5"));
+        assert!(attr(&span, "db.system").is_none());
+        assert_eq!(span.status.unwrap().code, StatusCode::Unset as i32);
+    }
+
+    // 进程内的 OTLP/HTTP mock collector: 按 replies 依次返回状态码 (用完后返回 200)，记录每次收到的请求
+    struct MockCollector {
+        url: String,
+        received: Arc<Mutex<Vec<(u16, ExportTraceServiceRequest)>>>,
+    }
+
+    async fn mock_collector(replies: &[u16]) -> MockCollector {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let replies = Arc::new(Mutex::new(replies.iter().copied().collect::<VecDeque<_>>()));
+        let state = (received.clone(), replies);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, state.clone()));
+            }
+        });
+        MockCollector { url, received }
+    }
+
+    type MockState = (
+        Arc<Mutex<Vec<(u16, ExportTraceServiceRequest)>>>,
+        Arc<Mutex<VecDeque<u16>>>,
+    );
+
+    async fn serve(mut stream: TcpStream, (received, replies): MockState) {
+        let mut buf = Vec::new();
+        loop {
+            let head_end = loop {
+                if let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                    break end + 4;
+                }
+                let mut chunk = [0u8; 4096];
+                match stream.read(&mut chunk).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => buf.extend_from_slice(&chunk[..n]),
+                }
+            };
+            let head = String::from_utf8_lossy(&buf[..head_end]).to_ascii_lowercase();
+            assert!(head.starts_with("post /v1/traces "));
+            assert!(head.contains("content-type: application/x-protobuf"));
+            let len: usize = head
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length:"))
+                .and_then(|len| len.trim().parse().ok())
+                .unwrap();
+            while buf.len() < head_end + len {
+                let mut chunk = [0u8; 4096];
+                match stream.read(&mut chunk).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(n) => buf.extend_from_slice(&chunk[..n]),
+                }
+            }
+            let body: Vec<u8> = buf.drain(..head_end + len).skip(head_end).collect();
+            let request = ExportTraceServiceRequest::decode(body.as_slice()).unwrap();
+            let code = replies.lock().unwrap().pop_front().unwrap_or(200);
+            received.lock().unwrap().push((code, request));
+            let reply = format!("HTTP/1.1 {} Mock\r\nContent-Length: 0\r\n\r\n", code);
+            if stream.write_all(reply.as_bytes()).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    fn config(endpoint: &str, batch_size: usize, max_retries: u32) -> OtlpConfig {
+        OtlpConfig {
+            endpoint: Some(endpoint.to_string()),
+            batch_size,
+            flush_interval_ms: 60_000,
+            max_retries,
+            timeout_ms: 2_000,
+            ..OtlpConfig::default()
+        }
+    }
+
+    // 直接驱动后台任务: 先把 spans 放入队列并关闭，再运行到最后一批发送完成
+    async fn export_all(config: OtlpConfig, spans: usize) {
+        let endpoint = config.endpoint.clone().unwrap();
+        let transport = Transport::new(&config, &endpoint).unwrap();
+        let (tx, rx) = mpsc::channel(spans.max(1));
+        for i in 0..spans {
+            let rec = record(Protocol::Redis, &format!("GET key{}", i), "OK");
+            tx.send(to_span(&rec)).await.unwrap();
+        }
+        drop(tx);
+        run(config, transport, rx).await;
+    }
+
+    fn span_counts(collector: &MockCollector) -> Vec<(u16, usize)> {
+        collector
+            .received
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(code, request)| {
+                let spans = request.resource_spans[0].scope_spans[0].spans.len();
+                (*code, spans)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn batches_spans_by_size() {
+        let collector = mock_collector(&[]).await;
+        export_all(config(&collector.url, 2, 0), 5).await;
+        // 两个满批次，关闭时再刷出剩下的一个
+        assert_eq!(span_counts(&collector), [(200, 2), (200, 2), (200, 1)]);
+
+        let received = collector.received.lock().unwrap();
+        let resource = received[0].1.resource_spans[0].resource.as_ref().unwrap();
+        assert_eq!(resource.attributes[0].key, "service.name");
+        let span = &received[0].1.resource_spans[0].scope_spans[0].spans[0];
+        assert_eq!(span.name, "GET");
+    }
+
+    #[tokio::test]
+    async fn retries_retryable_status_with_same_batch() {
+        let collector = mock_collector(&[503, 429]).await;
+        export_all(config(&collector.url, 3, 5), 3).await;
+        assert_eq!(span_counts(&collector), [(503, 3), (429, 3), (200, 3)]);
+
+        // 重试发送的是同一批 Span
+        let received = collector.received.lock().unwrap();
+        let ids = |i: usize| -> Vec<Vec<u8>> {
+            received[i].1.resource_spans[0].scope_spans[0]
+                .spans
+                .iter()
+                .map(|span| span.span_id.clone())
+                .collect()
+        };
+        assert_eq!(ids(0), ids(2));
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_retries_and_on_fatal_status() {
+        let collector = mock_collector(&[503, 503, 503]).await;
+        export_all(config(&collector.url, 1, 1), 1).await;
+        assert_eq!(span_counts(&collector), [(503, 1), (503, 1)]);
+
+        // 400 不可重试，这一批直接丢弃
+        let collector = mock_collector(&[400]).await;
+        export_all(config(&collector.url, 1, 5), 2).await;
+        assert_eq!(span_counts(&collector), [(400, 1), (200, 1)]);
+    }
+}
@@ -0,0 +1,414 @@
+// HPACK (RFC 7541) 头部解压
+//
+// HTTP/2 的 Header 块依赖发送方维护的动态表，同一连接上每个方向各有一份。
+// 只要观测到了该方向的全部 Header 块，本地就能重建出相同的表；一旦有 Header 块没看到
+// (载荷截断、从连接中途开始观测)，之前的表项就不可信了，此时清空表并继续解码:
+// HPACK 索引是相对于最新表项的，之后新插入的表项位置仍然正确，引用不到的旧表项按未知处理。
+
+use std::{collections::VecDeque, sync::LazyLock};
+
+// Appendix A 静态表 (下标 1..=61)
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+// 动态表初始大小 (SETTINGS_HEADER_TABLE_SIZE 默认值)，发送方可通过 Header 块内的大小更新指令调整
+const DEFAULT_TABLE_SIZE: usize = 4096;
+
+// 表项大小 = name + value + 32 字节开销 (4.1)
+const ENTRY_OVERHEAD: usize = 32;
+
+// Appendix B Huffman 码长，下标为符号 (256 为 EOS)。
+// 该编码是规范 Huffman 码: 码长相同的符号按符号值递增依次分配码字，因此只需码长即可还原码表。
+const CODE_LENGTHS: [u8; 257] = [
+    13, 23, 28, 28, 28, 28, 28, 28, 28, 24, 30, 28, 28, 30, 28, 28, 28, 28, 28, 28, 28, 28, 30, 28,
+    28, 28, 28, 28, 28, 28, 28, 28, 6, 10, 10, 12, 13, 6, 8, 11, 10, 10, 8, 11, 8, 6, 6, 6, 5, 5,
+    5, 6, 6, 6, 6, 6, 6, 6, 7, 8, 15, 6, 12, 10, 13, 6, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7, 7,
+    7, 7, 7, 7, 7, 7, 7, 7, 8, 7, 8, 13, 19, 13, 14, 6, 15, 5, 6, 5, 6, 5, 6, 6, 6, 5, 7, 7, 6, 6,
+    6, 5, 6, 7, 6, 5, 5, 6, 7, 7, 7, 7, 7, 15, 11, 14, 13, 28, 20, 22, 20, 20, 22, 22, 22, 23, 22,
+    23, 23, 23, 23, 23, 24, 23, 24, 24, 22, 23, 24, 23, 23, 23, 23, 21, 22, 23, 22, 23, 23, 24, 22,
+    21, 20, 22, 22, 23, 23, 21, 23, 22, 22, 24, 21, 22, 23, 23, 21, 21, 22, 21, 23, 22, 23, 23, 20,
+    22, 22, 22, 23, 22, 22, 23, 26, 26, 20, 19, 22, 23, 22, 25, 26, 26, 26, 27, 27, 26, 24, 25, 19,
+    21, 26, 27, 27, 26, 27, 24, 21, 21, 26, 26, 28, 27, 27, 27, 20, 24, 20, 21, 22, 21, 21, 23, 22,
+    22, 25, 25, 24, 24, 26, 23, 26, 27, 26, 26, 27, 27, 27, 27, 27, 28, 27, 27, 27, 27, 27, 26, 30,
+];
+
+const MAX_CODE_LEN: usize = 30;
+const EOS: u16 = 256;
+
+struct HuffmanTable {
+    first_code: [u32; MAX_CODE_LEN + 1], // 每个码长的第一个码字
+    count: [u32; MAX_CODE_LEN + 1],      // 每个码长的符号数
+    offset: [u32; MAX_CODE_LEN + 1],     // 每个码长的第一个符号在 symbols 中的位置
+    symbols: Vec<u16>,                   // 按 (码长, 符号) 排序
+}
+
+static HUFFMAN: LazyLock<HuffmanTable> = LazyLock::new(|| {
+    let mut symbols: Vec<u16> = (0..=EOS).collect();
+    symbols.sort_by_key(|&sym| (CODE_LENGTHS[sym as usize], sym));
+    let mut count = [0u32; MAX_CODE_LEN + 1];
+    for &len in &CODE_LENGTHS {
+        count[len as usize] += 1;
+    }
+    let mut first_code = [0u32; MAX_CODE_LEN + 1];
+    let mut offset = [0u32; MAX_CODE_LEN + 1];
+    let (mut code, mut index) = (0u32, 0u32);
+    for len in 1..=MAX_CODE_LEN {
+        first_code[len] = code;
+        offset[len] = index;
+        code = (code + count[len]) << 1;
+        index += count[len];
+    }
+    HuffmanTable {
+        first_code,
+        count,
+        offset,
+        symbols,
+    }
+});
+
+fn huffman_decode(data: &[u8]) -> Option<String> {
+    let table = &*HUFFMAN;
+    let mut out = Vec::with_capacity(data.len() * 8 / 5);
+    let (mut code, mut len) = (0u32, 0usize);
+    for byte in data {
+        for shift in (0..8).rev() {
+            code = (code << 1) | u32::from((byte >> shift) & 1);
+            len += 1;
+            if len > MAX_CODE_LEN {
+                return None;
+            }
+            let idx = code.wrapping_sub(table.first_code[len]);
+            if idx < table.count[len] {
+                match table.symbols[(table.offset[len] + idx) as usize] {
+                    EOS => return None, // 5.2: 出现 EOS 是解码错误
+                    sym => out.push(sym as u8),
+                }
+                code = 0;
+                len = 0;
+            }
+        }
+    }
+    // 末尾的填充是 EOS 的前缀 (全 1)，不超过 7 位
+    if len > 7 || code != (1 << len) - 1 {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&out).into_owned())
+}
+
+// 按位读取 Header 块
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.pos).copied()
+    }
+
+    // 5.1 整数表示: 首字节低 prefix 位，全 1 时后续字节按 7 位一组续接
+    fn integer(&mut self, prefix: u8) -> Option<usize> {
+        let mask = (1u16 << prefix) as u8 - 1;
+        let first = self.peek()? & mask;
+        self.pos += 1;
+        if first < mask {
+            return Some(first as usize);
+        }
+        let mut value = mask as usize;
+        for shift in (0..35).step_by(7) {
+            let byte = self.peek()?;
+            self.pos += 1;
+            value += ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    // 5.2 字符串: H 位 + 7 位前缀长度
+    fn string(&mut self) -> Option<String> {
+        let huffman = self.peek()? & 0x80 != 0;
+        let len = self.integer(7)?;
+        let raw = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        if huffman {
+            huffman_decode(raw)
+        } else {
+            Some(String::from_utf8_lossy(raw).into_owned())
+        }
+    }
+}
+
+// 一个方向上的解码器 (对应发送方的编码器)
+pub struct Decoder {
+    table: VecDeque<(String, String)>, // 最新的表项在前；名字未知的表项 name 为空
+    size: usize,
+    max_size: usize,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Decoder {
+            table: VecDeque::new(),
+            size: 0,
+            max_size: DEFAULT_TABLE_SIZE,
+        }
+    }
+}
+
+impl Decoder {
+    // 有 Header 块没有观测到，已有的表项不再可信
+    pub fn desync(&mut self) {
+        self.table.clear();
+        self.size = 0;
+    }
+
+    // 解码一个完整的 Header 块 (HEADERS + CONTINUATION)。
+    // complete 为 false 表示块被截断: 解码已有的部分后视为失去同步。
+    // 引用了未知表项的字段会被跳过，调用方拿到的只是能确定的那部分 Header。
+    pub fn decode(&mut self, block: &[u8], complete: bool) -> Vec<(String, String)> {
+        let mut headers = Vec::new();
+        let mut reader = Reader {
+            data: block,
+            pos: 0,
+        };
+        while let Some(byte) = reader.peek() {
+            let ok = if byte & 0x80 != 0 {
+                // 6.1 Indexed Header Field
+                reader.integer(7).map(|idx| {
+                    if let Some((name, value)) = self.get(idx) {
+                        headers.push((name, value));
+                    }
+                })
+            } else if byte & 0xc0 == 0x40 {
+                // 6.2.1 Literal with Incremental Indexing
+                self.literal(&mut reader, 6).map(|(name, value)| {
+                    self.insert(name.clone(), value.clone());
+                    if !name.is_empty() {
+                        headers.push((name, value));
+                    }
+                })
+            } else if byte & 0xe0 == 0x20 {
+                // 6.3 Dynamic Table Size Update
+                reader.integer(5).map(|size| {
+                    self.max_size = size;
+                    self.evict();
+                })
+            } else {
+                // 6.2.2 / 6.2.3 Literal without Indexing / Never Indexed
+                self.literal(&mut reader, 4).map(|(name, value)| {
+                    if !name.is_empty() {
+                        headers.push((name, value));
+                    }
+                })
+            };
+            if ok.is_none() {
+                self.desync();
+                return headers;
+            }
+        }
+        if !complete {
+            self.desync();
+        }
+        headers
+    }
+
+    // 名字为已索引或字面量，值总是字面量；名字引用了未知表项时返回空名字
+    fn literal(&self, reader: &mut Reader, prefix: u8) -> Option<(String, String)> {
+        let name = match reader.integer(prefix)? {
+            0 => reader.string()?,
+            idx => self.get(idx).map(|(name, _)| name).unwrap_or_default(),
+        };
+        let value = reader.string()?;
+        Some((name, value))
+    }
+
+    fn get(&self, idx: usize) -> Option<(String, String)> {
+        match idx {
+            0 => None,
+            1..=61 => {
+                let (name, value) = STATIC_TABLE[idx - 1];
+                Some((name.to_string(), value.to_string()))
+            }
+            _ => self
+                .table
+                .get(idx - 62)
+                .filter(|(name, _)| !name.is_empty())
+                .cloned(),
+        }
+    }
+
+    fn insert(&mut self, name: String, value: String) {
+        self.size += name.len() + value.len() + ENTRY_OVERHEAD;
+        self.table.push_front((name, value));
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        while self.size > self.max_size {
+            let Some((name, value)) = self.table.pop_back() else {
+                self.size = 0;
+                break;
+            };
+            self.size -= name.len() + value.len() + ENTRY_OVERHEAD;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        let s: String = s.split_whitespace().collect();
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn pairs(headers: &[(&str, &str)]) -> Vec<(String, String)> {
+        headers
+            .iter()
+            .map(|(n, v)| (n.to_string(), v.to_string()))
+            .collect()
+    }
+
+    // RFC 7541 C.3: 同一连接上连续三个请求，不使用 Huffman 编码
+    #[test]
+    fn rfc7541_requests_without_huffman() {
+        let mut decoder = Decoder::default();
+        let first = decoder.decode(
+            &hex("8286 8441 0f77 7777 2e65 7861 6d70 6c65 2e63 6f6d"),
+            true,
+        );
+        assert_eq!(
+            first,
+            pairs(&[
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":authority", "www.example.com")
+            ])
+        );
+        assert_eq!(decoder.size, 57);
+
+        let second = decoder.decode(&hex("8286 84be 5808 6e6f 2d63 6163 6865"), true);
+        assert_eq!(
+            second[3],
+            (":authority".to_string(), "www.example.com".to_string())
+        );
+        assert_eq!(
+            second[4],
+            ("cache-control".to_string(), "no-cache".to_string())
+        );
+        assert_eq!(decoder.size, 110);
+
+        let third = decoder.decode(
+            &hex("8287 85bf 400a 6375 7374 6f6d 2d6b 6579 0c63 7573 746f 6d2d 7661 6c75 65"),
+            true,
+        );
+        assert_eq!(
+            third,
+            pairs(&[
+                (":method", "GET"),
+                (":scheme", "https"),
+                (":path", "/index.html"),
+                (":authority", "www.example.com"),
+                ("custom-key", "custom-value")
+            ])
+        );
+        assert_eq!(decoder.size, 164);
+    }
+
+    // RFC 7541 C.4.1: 同样的请求，字符串使用 Huffman 编码
+    #[test]
+    fn rfc7541_huffman_strings() {
+        let mut decoder = Decoder::default();
+        let headers = decoder.decode(&hex("8286 8441 8cf1 e3c2 e5f2 3a6b a0ab 90f4 ff"), true);
+        assert_eq!(
+            headers[3],
+            (":authority".to_string(), "www.example.com".to_string())
+        );
+    }
+
+    #[test]
+    fn truncated_block_desyncs_dynamic_table() {
+        let mut decoder = Decoder::default();
+        decoder.decode(
+            &hex("8286 8441 0f77 7777 2e65 7861 6d70 6c65 2e63 6f6d"),
+            true,
+        );
+        // 块被截断: 已解码的部分照常输出，之后动态表不再可信
+        let block = hex("8286 84be 5808 6e6f 2d63 6163 6865");
+        let headers = decoder.decode(&block[..6], false);
+        assert_eq!(headers.len(), 4);
+        assert!(decoder.table.is_empty());
+
+        // 引用未知动态表项的字段被跳过，静态表项不受影响
+        let headers = decoder.decode(&hex("82be"), true);
+        assert_eq!(headers, pairs(&[(":method", "GET")]));
+    }
+}
@@ -0,0 +1,554 @@
+// HTTP/1.x (Text) 协议
+// 没有固定端口，按首行内容识别: "GET /path HTTP/1.1" 或 "HTTP/1.1 200 OK"
+//
+// 每个方向各自维护消息边界 (Body): 解析完首行和 Header 后，按 Content-Length / chunked 跳过消息体，
+// 因此一次 write 中的多个请求 (pipelining)、跨多次系统调用的大 Body 都能正确切分。
+// 请求和响应按 FIFO 配对；HEAD 请求的响应没有 Body，CONNECT / 101 之后不再是 HTTP，
+// 所以状态中还记录了待响应请求的方法。
+
+use std::collections::VecDeque;
+
+use super::{Attributes, MAX_PENDING, MessageKind, Payload, ProtocolDecoder, Request, Response};
+use crate::record::Protocol;
+
+const METHODS: [&str; 9] = [
+    "GET", "POST", "PUT", "DELETE", "HEAD", "PATCH", "OPTIONS", "CONNECT", "TRACE",
+];
+
+// 单条消息最多解析的 Header 行数
+const MAX_HEADERS: usize = 64;
+
+// 当前方向上，下一个字节属于什么
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Body {
+    #[default]
+    None, // 下一条消息的首行
+    Length(u64), // Content-Length 剩余字节
+    Chunk(u64),  // chunked: 当前分块剩余字节 (含结尾 CRLF)，0 表示下一行是分块大小
+    UntilClose,  // 没有长度信息的响应，Body 持续到连接关闭
+    Upgraded,    // CONNECT 隧道 / 101 Switching Protocols 之后不再是 HTTP
+}
+
+#[derive(Default)]
+pub struct HttpState {
+    request: Body,
+    response: Body,
+    methods: VecDeque<&'static str>, // 待响应请求的方法，与 L7Tracker 的待响应队列一一对应
+}
+
+pub struct HttpDecoder;
+
+// 首行 + Header
+struct Head<'a> {
+    start_line: &'a str,
+    headers: Vec<(&'a str, &'a str)>,
+    len: usize,     // 含结尾空行的字节数
+    complete: bool, // 已拷贝的数据中是否包含完整的 Header (截断或跨系统调用时为 false)
+}
+
+impl Head<'_> {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| *value)
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        self.header("Content-Length")?.parse().ok()
+    }
+
+    fn chunked(&self) -> bool {
+        self.header("Transfer-Encoding")
+            .is_some_and(|te| te.to_ascii_lowercase().contains("chunked"))
+    }
+}
+
+fn find(data: &[u8], needle: &[u8]) -> Option<usize> {
+    data.windows(needle.len()).position(|w| w == needle)
+}
+
+fn parse_head(data: &[u8]) -> Option<Head<'_>> {
+    let (head, complete) = match find(data, b"\r\n\r\n") {
+        Some(end) => (&data[..end + 4], true),
+        None => (data, false),
+    };
+    let text = match std::str::from_utf8(head) {
+        Ok(text) => text,
+        Err(e) => std::str::from_utf8(&head[..e.valid_up_to()]).ok()?,
+    };
+    let mut lines: Vec<&str> = text.split("\r\n").collect();
+    if !complete {
+        // 最后一行可能被截断 (如 "Content-Le")，不可信
+        lines.pop();
+    }
+    let (&start_line, rest) = lines.split_first()?;
+    let headers = rest
+        .iter()
+        .take_while(|line| !line.is_empty())
+        .take(MAX_HEADERS)
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim(), value.trim()))
+        .collect();
+    Some(Head {
+        start_line,
+        headers,
+        len: head.len(),
+        complete,
+    })
+}
+
+// "GET /path?q=1 HTTP/1.1"
+struct RequestLine<'a> {
+    method: &'static str,
+    target: &'a str,
+    version: &'a str, // "1.0" / "1.1"
+}
+
+impl<'a> RequestLine<'a> {
+    fn parse(line: &'a str) -> Option<Self> {
+        let mut parts = line.splitn(3, ' ');
+        let method = parts.next()?;
+        let method = METHODS.iter().find(|m| **m == method)?;
+        let target = parts.next().filter(|t| !t.is_empty())?;
+        let version = http_version(parts.next()?)?;
+        Some(RequestLine {
+            method,
+            target,
+            version,
+        })
+    }
+}
+
+// 请求目标 -> (path, query)；代理请求的 absolute-form ("http://host/path") 去掉 scheme 和 authority
+pub(super) fn split_target(target: &str) -> (&str, Option<&str>) {
+    let target = target.split('#').next().unwrap_or(target);
+    let target = match target.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("/", |idx| &rest[idx..]),
+        None => target,
+    };
+    match target.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (target, None),
+    }
+}
+
+// "HTTP/1.1 200 OK"
+struct StatusLine<'a> {
+    version: &'a str,
+    code: u16,
+    reason: &'a str,
+}
+
+impl<'a> StatusLine<'a> {
+    fn parse(line: &'a str) -> Option<Self> {
+        let (version, rest) = line.split_once(' ')?;
+        let version = http_version(version)?;
+        let (code, reason) = rest.split_once(' ').unwrap_or((rest, ""));
+        if code.len() != 3 {
+            return None;
+        }
+        let code = code.parse().ok().filter(|c| (100..600).contains(c))?;
+        Some(StatusLine {
+            version,
+            code,
+            reason: reason.trim(),
+        })
+    }
+}
+
+fn http_version(s: &str) -> Option<&str> {
+    s.strip_prefix("HTTP/")
+        .filter(|v| *v == "1.1" || *v == "1.0")
+}
+
+// 在一个方向的数据中按消息边界切分，每遇到一个首行就调用 on_head，由它返回该消息的 Body 类型。
+// on_head 返回 None 表示首行无效 (从消息中间开始观测、或前面丢失了同步)，丢弃本次剩余数据，
+// 下一次系统调用从头重新识别。
+fn split_messages<'a>(
+    body: &mut Body,
+    payload: &Payload<'a>,
+    mut on_head: impl FnMut(&Head<'a>) -> Option<Body>,
+) {
+    let data: &'a [u8] = payload.data;
+    // 截断时已拷贝的只是前缀，Body 长度按系统调用实际传输的字节数跳过
+    let total = payload.total_len().max(data.len() as u64);
+    let mut pos: u64 = 0;
+    while pos < total {
+        match *body {
+            Body::UntilClose | Body::Upgraded => return,
+            Body::Length(remaining) | Body::Chunk(remaining) if remaining > 0 => {
+                let n = remaining.min(total - pos);
+                pos += n;
+                *body = match (*body, remaining - n) {
+                    (Body::Length(_), 0) => Body::None,
+                    (Body::Length(_), left) => Body::Length(left),
+                    (_, left) => Body::Chunk(left),
+                };
+            }
+            Body::Length(_) => *body = Body::None,
+            Body::Chunk(_) => {
+                // 分块大小行 "1a;ext=1\r\n" 必须在已拷贝的范围内，否则失去同步
+                *body = Body::None;
+                let Some(rest) = data.get(pos as usize..) else {
+                    return;
+                };
+                let Some(line_end) = find(rest, b"\r\n") else {
+                    return;
+                };
+                let size = std::str::from_utf8(&rest[..line_end])
+                    .ok()
+                    .and_then(|line| line.split(';').next())
+                    .and_then(|hex| u64::from_str_radix(hex.trim(), 16).ok());
+                let Some(size) = size else {
+                    return;
+                };
+                pos += line_end as u64 + 2;
+                if size > 0 {
+                    *body = Body::Chunk(size + 2);
+                    continue;
+                }
+                // 最后一块之后是可选的 trailer，以空行结束
+                let trailer = &rest[line_end + 2..];
+                if trailer.starts_with(b"\r\n") {
+                    pos += 2;
+                } else if let Some(end) = find(trailer, b"\r\n\r\n") {
+                    pos += end as u64 + 4;
+                } else {
+                    return;
+                }
+            }
+            Body::None => {
+                let Some(rest) = data.get(pos as usize..).filter(|rest| !rest.is_empty()) else {
+                    return;
+                };
+                let Some(head) = parse_head(rest) else {
+                    return;
+                };
+                let Some(next) = on_head(&head) else {
+                    return;
+                };
+                if !head.complete {
+                    // Header 不完整时无法确定 Body 从哪里开始
+                    if next == Body::Upgraded {
+                        *body = next;
+                    }
+                    return;
+                }
+                *body = next;
+                pos += head.len as u64;
+            }
+        }
+    }
+}
+
+fn push_header(attributes: &mut Attributes, head: &Head, name: &str, key: &'static str) {
+    if let Some(value) = head.header(name) {
+        attributes.push((key, value.into()));
+    }
+}
+
+fn push_body_size(attributes: &mut Attributes, head: &Head, key: &'static str) {
+    if let Some(len) = head.content_length() {
+        attributes.push((key, (len as i64).into()));
+    }
+}
+
+fn request_attributes(line: &RequestLine, head: &Head) -> Attributes {
+    let (path, query) = split_target(line.target);
+    let mut attributes: Attributes = vec![
+        ("http.request.method", line.method.into()),
+        ("url.path", path.into()),
+    ];
+    if let Some(query) = query {
+        attributes.push(("url.query", query.into()));
+    }
+    attributes.push(("network.protocol.version", line.version.into()));
+    push_header(&mut attributes, head, "Host", "http.request.header.host");
+    push_header(&mut attributes, head, "User-Agent", "user_agent.original");
+    push_header(
+        &mut attributes,
+        head,
+        "Content-Type",
+        "http.request.header.content-type",
+    );
+    push_body_size(&mut attributes, head, "http.request.body.size");
+    push_header(
+        &mut attributes,
+        head,
+        "X-Request-Id",
+        "http.request.header.x-request-id",
+    );
+    push_header(
+        &mut attributes,
+        head,
+        "traceparent",
+        "http.request.header.traceparent",
+    );
+    attributes
+}
+
+fn response_attributes(line: &StatusLine, head: &Head) -> Attributes {
+    let mut attributes: Attributes = vec![
+        ("http.response.status_code", i64::from(line.code).into()),
+        ("network.protocol.version", line.version.into()),
+    ];
+    push_header(
+        &mut attributes,
+        head,
+        "Content-Type",
+        "http.response.header.content-type",
+    );
+    push_body_size(&mut attributes, head, "http.response.body.size");
+    push_header(
+        &mut attributes,
+        head,
+        "X-Request-Id",
+        "http.response.header.x-request-id",
+    );
+    attributes
+}
+
+// 请求只有声明了长度才有 Body
+fn request_body(head: &Head) -> Body {
+    if head.chunked() {
+        Body::Chunk(0)
+    } else {
+        match head.content_length() {
+            Some(len) if len > 0 => Body::Length(len),
+            _ => Body::None,
+        }
+    }
+}
+
+// RFC 9112 6.3: HEAD 的响应、1xx/204/304 没有 Body；既没有 chunked 也没有 Content-Length 时读到连接关闭
+fn response_body(head: &Head, code: u16, method: &str) -> Body {
+    if method == "HEAD" || code < 200 || code == 204 || code == 304 {
+        Body::None
+    } else if head.chunked() {
+        Body::Chunk(0)
+    } else if let Some(len) = head.content_length() {
+        Body::Length(len)
+    } else {
+        Body::UntilClose
+    }
+}
+
+impl ProtocolDecoder for HttpDecoder {
+    type State = HttpState;
+
+    fn protocol(&self) -> Protocol {
+        Protocol::Http
+    }
+
+    // keep-alive 连接上可以连续发送多个请求，响应按顺序返回
+    fn pipelined(&self) -> bool {
+        true
+    }
+
+    fn detect(&self, payload: &Payload) -> Option<MessageKind> {
+        let head = parse_head(payload)?;
+        if RequestLine::parse(head.start_line).is_some() {
+            Some(MessageKind::Request)
+        } else if StatusLine::parse(head.start_line).is_some() {
+            Some(MessageKind::Response)
+        } else {
+            None
+        }
+    }
+
+    fn parse_request(&self, state: &mut HttpState, payload: &Payload) -> Vec<Request> {
+        let mut requests = Vec::new();
+        let methods = &mut state.methods;
+        split_messages(&mut state.request, payload, |head| {
+            let line = RequestLine::parse(head.start_line)?;
+            if methods.len() >= MAX_PENDING {
+                methods.pop_front();
+            }
+            methods.push_back(line.method);
+            requests.push(Request {
+                attributes: request_attributes(&line, head),
+                ..Request::new(format!("{} {}", line.method, line.target))
+            });
+            Some(request_body(head))
+        });
+        requests
+    }
+
+    fn parse_response(&self, state: &mut HttpState, payload: &Payload) -> Vec<Response> {
+        let mut responses = Vec::new();
+        let methods = &mut state.methods;
+        let request = &mut state.request;
+        split_messages(&mut state.response, payload, |head| {
+            let line = StatusLine::parse(head.start_line)?;
+            // 100 Continue / 103 Early Hints 是中间响应，真正的响应随后到达
+            if (100..200).contains(&line.code) && line.code != 101 {
+                return Some(Body::None);
+            }
+            let method = methods.pop_front().unwrap_or("GET");
+            let status = if line.reason.is_empty() {
+                line.code.to_string()
+            } else {
+                format!("{} {}", line.code, line.reason)
+            };
+            responses.push(Response {
+                attributes: response_attributes(&line, head),
+                ..Response::new(status)
+            });
+            // 协议切换 (WebSocket 等) 或 CONNECT 隧道建立后，两个方向都不再是 HTTP
+            if line.code == 101 || (method == "CONNECT" && (200..300).contains(&line.code)) {
+                *request = Body::Upgraded;
+                return Some(Body::Upgraded);
+            }
+            Some(response_body(head, line.code, method))
+        });
+        responses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::AttrValue, *};
+
+    fn requests(state: &mut HttpState, data: &[u8]) -> Vec<Request> {
+        HttpDecoder.parse_request(state, &Payload::new(data, data.len() as u64))
+    }
+
+    fn responses(state: &mut HttpState, data: &[u8]) -> Vec<Response> {
+        HttpDecoder.parse_response(state, &Payload::new(data, data.len() as u64))
+    }
+
+    fn statuses(responses: &[Response]) -> Vec<&str> {
+        responses.iter().map(|r| r.status.as_str()).collect()
+    }
+
+    #[test]
+    fn detect_request_and_response() {
+        let req = b"GET /index.html HTTP/1.1\r\nHost: a\r\n\r\n";
+        assert_eq!(
+            HttpDecoder.detect(&Payload::new(req, req.len() as u64)),
+            Some(MessageKind::Request)
+        );
+        let resp = b"HTTP/1.1 404 Not Found\r\n\r\n";
+        assert_eq!(
+            HttpDecoder.detect(&Payload::new(resp, resp.len() as u64)),
+            Some(MessageKind::Response)
+        );
+        assert_eq!(HttpDecoder.detect(&Payload::new(b"+OK\r\n", 5)), None);
+    }
+
+    #[test]
+    fn request_attributes_from_headers() {
+        let mut state = HttpState::default();
+        let reqs = requests(
+            &mut state,
+            b"POST http://api.local/v1/orders?id=7 HTTP/1.1\r\nHost: api.local\r\nContent-Length: 2\r\n\r\n{}",
+        );
+        assert_eq!(reqs[0].summary, "POST http://api.local/v1/orders?id=7");
+        let attrs = &reqs[0].attributes;
+        assert!(attrs.contains(&("url.path", AttrValue::from("/v1/orders"))));
+        assert!(attrs.contains(&("url.query", AttrValue::from("id=7"))));
+        assert!(attrs.contains(&("http.request.body.size", AttrValue::Int(2))));
+    }
+
+    #[test]
+    fn pipelined_requests_in_one_write() {
+        let mut state = HttpState::default();
+        let reqs = requests(
+            &mut state,
+            b"GET /a HTTP/1.1\r\n\r\nPOST /b HTTP/1.1\r\nContent-Length: 5\r\n\r\nhelloHEAD /c HTTP/1.1\r\n\r\n",
+        );
+        let summaries: Vec<_> = reqs.iter().map(|r| r.summary.as_str()).collect();
+        assert_eq!(summaries, ["GET /a", "POST /b", "HEAD /c"]);
+
+        // HEAD 的响应虽然带 Content-Length，但没有 Body
+        let resps = responses(
+            &mut state,
+            b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\nabcHTTP/1.1 201 Created\r\nContent-Length: 0\r\n\r\nHTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\n",
+        );
+        assert_eq!(statuses(&resps), ["200 OK", "201 Created", "200 OK"]);
+        assert!(state.methods.is_empty());
+    }
+
+    #[test]
+    fn body_split_across_reads() {
+        let mut state = HttpState::default();
+        requests(
+            &mut state,
+            b"GET /a HTTP/1.1\r\n\r\nGET /b HTTP/1.1\r\n\r\n",
+        );
+
+        // Content-Length 的 Body 跨两次 read，第二次 read 里紧接着下一个响应
+        let resps = responses(
+            &mut state,
+            b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n0123",
+        );
+        assert_eq!(statuses(&resps), ["200 OK"]);
+        let resps = responses(
+            &mut state,
+            b"456789HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\n\r\n",
+        );
+        assert_eq!(statuses(&resps), ["500 Internal Server Error"]);
+
+        // chunked: 分块在两次 read 之间被切开
+        requests(
+            &mut state,
+            b"GET /c HTTP/1.1\r\n\r\nGET /d HTTP/1.1\r\n\r\n",
+        );
+        let resps = responses(
+            &mut state,
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhel",
+        );
+        assert_eq!(resps.len(), 1);
+        let resps = responses(
+            &mut state,
+            b"lo\r\n0\r\n\r\nHTTP/1.1 204 No Content\r\n\r\n",
+        );
+        assert_eq!(statuses(&resps), ["204 No Content"]);
+    }
+
+    #[test]
+    fn truncated_upload_body_is_skipped() {
+        let mut state = HttpState::default();
+        // 10MB 的上传只拷贝到 Header，Body 分两次 write，按 Content-Length 跳过
+        let head = b"PUT /upload HTTP/1.1\r\nContent-Length: 10485760\r\n\r\n";
+        let first_total = head.len() as u64 + 4 * 1024 * 1024;
+        let reqs = HttpDecoder.parse_request(&mut state, &Payload::new(head, first_total));
+        assert_eq!(reqs[0].summary, "PUT /upload");
+        assert_eq!(state.request, Body::Length(6 * 1024 * 1024));
+
+        let next = b"GET /done HTTP/1.1\r\n\r\n";
+        let total = 6 * 1024 * 1024 + next.len() as u64;
+        // 拷贝到的前缀全是 Body，下一个请求在未拷贝的部分
+        let reqs = HttpDecoder.parse_request(&mut state, &Payload::new(&[0u8; 64], total));
+        assert!(reqs.is_empty());
+        assert_eq!(state.request, Body::None);
+        assert_eq!(requests(&mut state, next)[0].summary, "GET /done");
+    }
+
+    #[test]
+    fn resyncs_after_losing_boundary() {
+        let mut state = HttpState::default();
+        requests(&mut state, b"GET /a HTTP/1.1\r\n\r\n");
+        // 从响应中间开始观测 (如 Agent 启动时连接上正在传输): 无法识别的数据被丢弃
+        assert!(responses(&mut state, b"tail of an earlier body").is_empty());
+        // 下一次系统调用从首行重新识别
+        let resps = responses(&mut state, b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n");
+        assert_eq!(statuses(&resps), ["200 OK"]);
+    }
+
+    #[test]
+    fn upgrade_stops_parsing() {
+        let mut state = HttpState::default();
+        requests(
+            &mut state,
+            b"GET /ws HTTP/1.1\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\r\n",
+        );
+        let resps = responses(
+            &mut state,
+            b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\r\n\x81\x05hello",
+        );
+        assert_eq!(statuses(&resps), ["101 Switching Protocols"]);
+        assert!(requests(&mut state, b"GET /not-http HTTP/1.1\r\n\r\n").is_empty());
+    }
+}
@@ -0,0 +1,783 @@
+// HTTP/2 (RFC 9113) 与 gRPC
+// 明文 HTTP/2 (h2c / prior knowledge): 客户端以连接前言 "PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n" 开头，
+// 服务端的第一个帧是 SETTINGS。
+//
+// 每个方向维护帧边界和一份 HPACK 解码器 (对应对端的编码器)，请求方向的 HEADERS 产生请求，
+// 响应方向带 END_STREAM 的帧 (HEADERS / DATA / trailers) 结束一次响应，按 stream ID 配对，
+// 因此同一连接上并发的多个流互不干扰。content-type 为 application/grpc* 的流按 gRPC 输出:
+// 请求摘要为 :path ("/package.Service/Method")，状态取 trailers 中的 grpc-status。
+
+use std::{borrow::Cow, collections::HashMap};
+
+use super::{
+    Attributes, MessageKind, Payload, ProtocolDecoder, Request, Response, hpack, http::split_target,
+};
+use crate::record::Protocol;
+
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+const FRAME_HEADER_LEN: usize = 9;
+// 跨系统调用缓存的帧负载上限 (SETTINGS_MAX_FRAME_SIZE 默认值)，更大的 Header 帧按截断处理
+const MAX_FRAME_LEN: usize = 16384;
+// 单个连接上同时跟踪的流数上限
+const MAX_STREAMS: usize = 256;
+
+const DATA: u8 = 0x0;
+const HEADERS: u8 = 0x1;
+const PRIORITY_FRAME: u8 = 0x2;
+const RST_STREAM: u8 = 0x3;
+const SETTINGS: u8 = 0x4;
+const PUSH_PROMISE: u8 = 0x5;
+const PING: u8 = 0x6;
+const GOAWAY: u8 = 0x7;
+const CONTINUATION: u8 = 0x9;
+
+const END_STREAM: u8 = 0x1;
+const END_HEADERS: u8 = 0x4;
+const PADDED: u8 = 0x8;
+const PRIORITY: u8 = 0x20;
+
+// gRPC 状态码名称，下标即状态码
+const GRPC_STATUS: [&str; 17] = [
+    "OK",
+    "CANCELLED",
+    "UNKNOWN",
+    "INVALID_ARGUMENT",
+    "DEADLINE_EXCEEDED",
+    "NOT_FOUND",
+    "ALREADY_EXISTS",
+    "PERMISSION_DENIED",
+    "RESOURCE_EXHAUSTED",
+    "FAILED_PRECONDITION",
+    "ABORTED",
+    "OUT_OF_RANGE",
+    "UNIMPLEMENTED",
+    "INTERNAL",
+    "UNAVAILABLE",
+    "DATA_LOSS",
+    "UNAUTHENTICATED",
+];
+
+// RST_STREAM 错误码名称 (7. Error Codes)
+const ERROR_CODES: [&str; 14] = [
+    "NO_ERROR",
+    "PROTOCOL_ERROR",
+    "INTERNAL_ERROR",
+    "FLOW_CONTROL_ERROR",
+    "SETTINGS_TIMEOUT",
+    "STREAM_CLOSED",
+    "FRAME_SIZE_ERROR",
+    "REFUSED_STREAM",
+    "CANCEL",
+    "COMPRESSION_ERROR",
+    "CONNECT_ERROR",
+    "ENHANCE_YOUR_CALM",
+    "INADEQUATE_SECURITY",
+    "HTTP_1_1_REQUIRED",
+];
+
+#[derive(Debug, Clone, Copy)]
+struct FrameHeader {
+    len: usize,
+    kind: u8,
+    flags: u8,
+    stream: u32,
+}
+
+impl FrameHeader {
+    fn parse(data: &[u8]) -> Option<Self> {
+        let bytes: &[u8; FRAME_HEADER_LEN] = data.get(..FRAME_HEADER_LEN)?.try_into().ok()?;
+        Some(FrameHeader {
+            len: u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]) as usize,
+            kind: bytes[3],
+            flags: bytes[4],
+            stream: u32::from_be_bytes([bytes[5], bytes[6], bytes[7], bytes[8]]) & 0x7fff_ffff,
+        })
+    }
+
+    // 失去同步后，用来判断一段数据的开头是否像一个帧头
+    fn plausible(&self) -> bool {
+        let needs_stream = matches!(
+            self.kind,
+            DATA | HEADERS | PRIORITY_FRAME | RST_STREAM | PUSH_PROMISE | CONTINUATION
+        );
+        let connection_only = matches!(self.kind, SETTINGS | PING | GOAWAY);
+        self.kind <= CONTINUATION
+            && self.len <= MAX_FRAME_LEN
+            && (!needs_stream || self.stream != 0)
+            && (!connection_only || self.stream == 0)
+    }
+
+    // 需要完整负载才能处理的帧 (Header 块必须完整解码，否则 HPACK 状态会错乱)
+    fn needs_payload(&self) -> bool {
+        matches!(
+            self.kind,
+            HEADERS | PUSH_PROMISE | CONTINUATION | RST_STREAM
+        )
+    }
+}
+
+enum FrameEvent {
+    Headers {
+        stream: u32,
+        headers: Vec<(String, String)>,
+        end_stream: bool,
+    },
+    // 带 END_STREAM 的 DATA 帧
+    EndStream {
+        stream: u32,
+    },
+    Reset {
+        stream: u32,
+        code: u32,
+    },
+}
+
+// 一个方向的帧解析状态
+#[derive(Default)]
+struct FrameReader {
+    partial: Vec<u8>, // 跨系统调用的不完整帧 (帧头，或需要完整负载的帧)
+    skip: u64,        // 当前帧尚未到达的字节数 (不需要解析的负载)
+    lost: bool,       // 帧边界丢失，等待下一次系统调用开头出现合理的帧头
+    // 尚未结束 (END_HEADERS) 的 Header 块
+    block: Vec<u8>,
+    block_stream: u32,
+    block_end_stream: bool,
+    block_promise: bool, // PUSH_PROMISE 的 Header 块只用于维护 HPACK 状态
+    block_truncated: bool,
+    hpack: hpack::Decoder,
+}
+
+impl FrameReader {
+    fn feed(&mut self, payload: &Payload) -> Vec<FrameEvent> {
+        let mut events = Vec::new();
+        let mut data: &[u8] = payload;
+        // 截断时已拷贝的只是前缀，帧长度按系统调用实际传输的字节数计算
+        let mut total = payload.total_len().max(data.len() as u64);
+
+        if self.skip > 0 {
+            let n = self.skip.min(total);
+            self.skip -= n;
+            total -= n;
+            data = data.get(n as usize..).unwrap_or_default();
+            if total == 0 {
+                return events;
+            }
+        }
+        if self.lost {
+            // 只在系统调用边界尝试重新同步
+            if !FrameHeader::parse(data).is_some_and(|header| header.plausible()) {
+                return events;
+            }
+            self.lost = false;
+        }
+
+        let input: Cow<[u8]> = if self.partial.is_empty() {
+            Cow::Borrowed(data)
+        } else {
+            let mut buf = std::mem::take(&mut self.partial);
+            buf.extend_from_slice(data);
+            Cow::Owned(buf)
+        };
+        let visible = input.len() as u64;
+        // 本次可解析数据的逻辑终点 (含未拷贝的部分)
+        let end = visible - data.len() as u64 + total;
+        let mut pos = 0usize;
+        while (pos as u64) < end {
+            let Some(header) = FrameHeader::parse(&input[pos..]) else {
+                if visible == end {
+                    // 帧头跨了系统调用
+                    self.partial = input[pos..].to_vec();
+                } else {
+                    // 帧头落在未拷贝的部分，之后的帧边界无从得知
+                    self.lose();
+                }
+                break;
+            };
+            let body = pos + FRAME_HEADER_LEN;
+            let frame_end = (body + header.len) as u64;
+            if frame_end <= visible {
+                self.on_frame(&header, &input[body..frame_end as usize], true, &mut events);
+                pos = frame_end as usize;
+                continue;
+            }
+            // 负载延续到了已拷贝的范围之外
+            if visible == end && header.needs_payload() && header.len <= MAX_FRAME_LEN {
+                self.partial = input[pos..].to_vec();
+                break;
+            }
+            self.on_frame(&header, &input[body.min(input.len())..], false, &mut events);
+            if frame_end >= end {
+                self.skip = frame_end - end;
+            } else {
+                self.lose();
+            }
+            break;
+        }
+        events
+    }
+
+    fn lose(&mut self) {
+        self.lost = true;
+        self.partial.clear();
+        self.block.clear();
+        self.hpack.desync();
+    }
+
+    // complete 为 false 时 payload 只是负载的前缀
+    fn on_frame(
+        &mut self,
+        header: &FrameHeader,
+        payload: &[u8],
+        complete: bool,
+        events: &mut Vec<FrameEvent>,
+    ) {
+        match header.kind {
+            DATA if header.flags & END_STREAM != 0 => events.push(FrameEvent::EndStream {
+                stream: header.stream,
+            }),
+            HEADERS | PUSH_PROMISE => {
+                // Pad Length(1)? + [HEADERS: 依赖信息(5)? | PUSH_PROMISE: Promised Stream ID(4)] + 块片段 + Padding
+                let padded = header.flags & PADDED != 0;
+                let pad = if padded {
+                    payload.first().copied().unwrap_or(0) as usize
+                } else {
+                    0
+                };
+                let mut start = usize::from(padded);
+                if header.kind == PUSH_PROMISE {
+                    start += 4;
+                } else if header.flags & PRIORITY != 0 {
+                    start += 5;
+                }
+                let stop = header.len.saturating_sub(pad).min(payload.len());
+                self.block.clear();
+                self.block
+                    .extend_from_slice(payload.get(start..stop).unwrap_or_default());
+                self.block_stream = header.stream;
+                self.block_end_stream = header.flags & END_STREAM != 0;
+                self.block_promise = header.kind == PUSH_PROMISE;
+                self.block_truncated = !complete;
+                if header.flags & END_HEADERS != 0 || !complete {
+                    self.finish_block(events);
+                }
+            }
+            CONTINUATION if header.stream == self.block_stream => {
+                self.block.extend_from_slice(payload);
+                self.block_truncated |= !complete;
+                if header.flags & END_HEADERS != 0 || !complete {
+                    self.finish_block(events);
+                }
+            }
+            RST_STREAM => events.push(FrameEvent::Reset {
+                stream: header.stream,
+                code: payload
+                    .get(..4)
+                    .map_or(0, |b| u32::from_be_bytes([b[0], b[1], b[2], b[3]])),
+            }),
+            _ => {}
+        }
+    }
+
+    fn finish_block(&mut self, events: &mut Vec<FrameEvent>) {
+        let headers = self.hpack.decode(&self.block, !self.block_truncated);
+        self.block.clear();
+        if !self.block_promise {
+            events.push(FrameEvent::Headers {
+                stream: self.block_stream,
+                headers,
+                end_stream: self.block_end_stream,
+            });
+        }
+    }
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+fn push_header(
+    attributes: &mut Attributes,
+    headers: &[(String, String)],
+    name: &str,
+    key: &'static str,
+) {
+    if let Some(value) = header(headers, name) {
+        attributes.push((key, value.into()));
+    }
+}
+
+// 一个已发出请求、尚未结束响应的流
+struct Stream {
+    grpc: bool,
+    status: Option<u16>, // :status
+    grpc_status: Option<i64>,
+    attributes: Attributes, // 响应 Header 中提取的字段
+}
+
+impl Stream {
+    fn on_headers(&mut self, headers: &[(String, String)]) {
+        // 1xx 是中间响应，真正的响应 Header 随后到达
+        if let Some(status) = header(headers, ":status").and_then(|s| s.parse::<u16>().ok())
+            && status >= 200
+            && self.status.is_none()
+        {
+            self.status = Some(status);
+            self.attributes
+                .push(("http.response.status_code", i64::from(status).into()));
+            push_header(
+                &mut self.attributes,
+                headers,
+                "content-type",
+                "http.response.header.content-type",
+            );
+            if let Some(len) = header(headers, "content-length").and_then(|s| s.parse::<i64>().ok())
+            {
+                self.attributes
+                    .push(("http.response.body.size", len.into()));
+            }
+        }
+        // grpc-status 通常在 trailers 中；出错时可能直接放在唯一的响应 Header 中 (Trailers-Only)
+        if let Some(code) = header(headers, "grpc-status").and_then(|s| s.parse().ok()) {
+            self.grpc_status = Some(code);
+        }
+    }
+
+    fn finish(mut self, id: u32) -> Response {
+        let status = if self.grpc {
+            let code = self.grpc_status.unwrap_or(2); // 没有 grpc-status 视为 UNKNOWN
+            self.attributes.push(("rpc.grpc.status_code", code.into()));
+            GRPC_STATUS
+                .get(code as usize)
+                .map_or_else(|| code.to_string(), |name| name.to_string())
+        } else {
+            self.status
+                .map_or_else(|| "-".to_string(), |s| s.to_string())
+        };
+        Response {
+            attributes: self.attributes,
+            id: Some(u64::from(id)),
+            ..Response::new(status)
+        }
+    }
+
+    fn reset(self, id: u32, code: u32) -> Response {
+        let name = ERROR_CODES
+            .get(code as usize)
+            .map_or_else(|| code.to_string(), |name| name.to_string());
+        Response {
+            attributes: self.attributes,
+            id: Some(u64::from(id)),
+            ..Response::new(format!("RST_STREAM {}", name))
+        }
+    }
+}
+
+fn build_request(stream: u32, headers: &[(String, String)]) -> Option<Request> {
+    let method = header(headers, ":method")?;
+    let target = header(headers, ":path").unwrap_or("/");
+    let grpc = header(headers, "content-type").is_some_and(|ct| ct.starts_with("application/grpc"));
+    let (path, query) = split_target(target);
+
+    let mut attributes: Attributes = vec![
+        ("http.request.method", method.into()),
+        ("url.path", path.into()),
+    ];
+    if let Some(query) = query {
+        attributes.push(("url.query", query.into()));
+    }
+    attributes.push(("network.protocol.version", "2".into()));
+    push_header(
+        &mut attributes,
+        headers,
+        ":authority",
+        "http.request.header.host",
+    );
+    push_header(
+        &mut attributes,
+        headers,
+        "user-agent",
+        "user_agent.original",
+    );
+    push_header(
+        &mut attributes,
+        headers,
+        "content-type",
+        "http.request.header.content-type",
+    );
+    push_header(
+        &mut attributes,
+        headers,
+        "x-request-id",
+        "http.request.header.x-request-id",
+    );
+    push_header(
+        &mut attributes,
+        headers,
+        "traceparent",
+        "http.request.header.traceparent",
+    );
+
+    let (summary, protocol) = if grpc {
+        // "/package.Service/Method"
+        attributes.push(("rpc.system", "grpc".into()));
+        if let Some((service, method)) = path.trim_start_matches('/').split_once('/') {
+            attributes.push(("rpc.service", service.into()));
+            attributes.push(("rpc.method", method.into()));
+        }
+        (path.to_string(), Some(Protocol::Grpc))
+    } else {
+        (format!("{} {}", method, target), None)
+    };
+    Some(Request {
+        attributes,
+        id: Some(u64::from(stream)),
+        protocol,
+        ..Request::new(summary)
+    })
+}
+
+#[derive(Default)]
+pub struct Http2State {
+    requests: FrameReader,  // 请求方向 (客户端 -> 服务端)
+    responses: FrameReader, // 响应方向
+    streams: HashMap<u32, Stream>,
+}
+
+pub struct Http2Decoder;
+
+impl ProtocolDecoder for Http2Decoder {
+    type State = Http2State;
+
+    fn protocol(&self) -> Protocol {
+        Protocol::Http2
+    }
+
+    // 多路复用: 同一连接上有多个在途的流，响应按 stream ID 配对
+    fn pipelined(&self) -> bool {
+        true
+    }
+
+    // 每个并发流一个待响应请求
+    fn max_pending(&self) -> usize {
+        MAX_STREAMS
+    }
+
+    fn detect(&self, payload: &Payload) -> Option<MessageKind> {
+        if payload.starts_with(PREFACE) {
+            return Some(MessageKind::Request);
+        }
+        // 3.4: 服务端连接前言是一个 SETTINGS 帧 (每个参数 6 字节)
+        let header = FrameHeader::parse(payload)?;
+        (header.kind == SETTINGS
+            && header.flags == 0
+            && header.stream == 0
+            && header.len % 6 == 0
+            && header.len <= 6 * 16)
+            .then_some(MessageKind::Response)
+    }
+
+    fn parse_request(&self, state: &mut Http2State, payload: &Payload) -> Vec<Request> {
+        let events = if payload.starts_with(PREFACE) {
+            let rest = Payload::new(
+                &payload[PREFACE.len()..],
+                payload.total_len() - PREFACE.len() as u64,
+            );
+            state.requests.feed(&rest)
+        } else {
+            state.requests.feed(payload)
+        };
+
+        let mut requests = Vec::new();
+        for event in events {
+            match event {
+                FrameEvent::Headers {
+                    stream, headers, ..
+                } => {
+                    // 已有的流上再次出现 HEADERS 是请求 trailers
+                    if state.streams.contains_key(&stream) {
+                        continue;
+                    }
+                    let Some(request) = build_request(stream, &headers) else {
+                        continue;
+                    };
+                    if state.streams.len() >= MAX_STREAMS {
+                        state.streams.clear();
+                    }
+                    state.streams.insert(
+                        stream,
+                        Stream {
+                            grpc: request.protocol == Some(Protocol::Grpc),
+                            status: None,
+                            grpc_status: None,
+                            attributes: Vec::new(),
+                        },
+                    );
+                    requests.push(request);
+                }
+                // 客户端取消了这个流，不会再有响应
+                FrameEvent::Reset { stream, .. } => {
+                    state.streams.remove(&stream);
+                }
+                FrameEvent::EndStream { .. } => {}
+            }
+        }
+        requests
+    }
+
+    fn parse_response(&self, state: &mut Http2State, payload: &Payload) -> Vec<Response> {
+        let mut responses = Vec::new();
+        for event in state.responses.feed(payload) {
+            let (id, end_stream) = match event {
+                FrameEvent::Headers {
+                    stream,
+                    headers,
+                    end_stream,
+                } => {
+                    if let Some(s) = state.streams.get_mut(&stream) {
+                        s.on_headers(&headers);
+                    }
+                    (stream, end_stream)
+                }
+                FrameEvent::EndStream { stream } => (stream, true),
+                FrameEvent::Reset { stream, code } => {
+                    if let Some(s) = state.streams.remove(&stream) {
+                        responses.push(s.reset(stream, code));
+                    }
+                    continue;
+                }
+            };
+            if end_stream && let Some(s) = state.streams.remove(&id) {
+                responses.push(s.finish(id));
+            }
+        }
+        responses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::AttrValue, *};
+
+    fn frame(kind: u8, flags: u8, stream: u32, payload: &[u8]) -> Vec<u8> {
+        let mut out = (payload.len() as u32).to_be_bytes()[1..].to_vec();
+        out.push(kind);
+        out.push(flags);
+        out.extend_from_slice(&stream.to_be_bytes());
+        out.extend_from_slice(payload);
+        out
+    }
+
+    // HPACK Literal without Indexing，名字和值都是字面量
+    fn literal(name: &str, value: &str) -> Vec<u8> {
+        let mut out = vec![0, name.len() as u8];
+        out.extend_from_slice(name.as_bytes());
+        out.push(value.len() as u8);
+        out.extend_from_slice(value.as_bytes());
+        out
+    }
+
+    fn get(path: &str) -> Vec<u8> {
+        // :method GET (静态表 2) + :scheme http (6) + :path
+        [vec![0x82, 0x86], literal(":path", path)].concat()
+    }
+
+    fn grpc_call(path: &str) -> Vec<u8> {
+        // :method POST (3) + :scheme http (6) + :path + content-type
+        [
+            vec![0x83, 0x86],
+            literal(":path", path),
+            literal("content-type", "application/grpc"),
+        ]
+        .concat()
+    }
+
+    const STATUS_200: u8 = 0x88;
+
+    fn requests(state: &mut Http2State, data: &[u8]) -> Vec<Request> {
+        Http2Decoder.parse_request(state, &Payload::new(data, data.len() as u64))
+    }
+
+    fn responses(state: &mut Http2State, data: &[u8]) -> Vec<Response> {
+        Http2Decoder.parse_response(state, &Payload::new(data, data.len() as u64))
+    }
+
+    fn statuses(responses: &[Response]) -> Vec<(&str, Option<u64>)> {
+        responses
+            .iter()
+            .map(|r| (r.status.as_str(), r.id))
+            .collect()
+    }
+
+    #[test]
+    fn detect_preface_and_server_settings() {
+        let client = [PREFACE, &frame(SETTINGS, 0, 0, &[0; 12])].concat();
+        assert_eq!(
+            Http2Decoder.detect(&Payload::new(&client, client.len() as u64)),
+            Some(MessageKind::Request)
+        );
+        let server = frame(SETTINGS, 0, 0, &[0; 18]);
+        assert_eq!(
+            Http2Decoder.detect(&Payload::new(&server, server.len() as u64)),
+            Some(MessageKind::Response)
+        );
+        let http1 = b"GET / HTTP/1.1\r\n\r\n";
+        assert_eq!(
+            Http2Decoder.detect(&Payload::new(http1, http1.len() as u64)),
+            None
+        );
+    }
+
+    #[test]
+    fn concurrent_streams_in_one_write() {
+        let mut state = Http2State::default();
+        let write = [
+            PREFACE,
+            &frame(SETTINGS, 0, 0, &[]),
+            &frame(HEADERS, END_HEADERS | END_STREAM, 1, &get("/a?x=1")),
+            &frame(
+                HEADERS,
+                END_HEADERS,
+                3,
+                &grpc_call("/helloworld.Greeter/SayHello"),
+            ),
+            &frame(DATA, END_STREAM, 3, &[0, 0, 0, 0, 2, 8, 1]),
+        ]
+        .concat();
+        let reqs = requests(&mut state, &write);
+        let summaries: Vec<_> = reqs.iter().map(|r| (r.summary.as_str(), r.id)).collect();
+        assert_eq!(
+            summaries,
+            [
+                ("GET /a?x=1", Some(1)),
+                ("/helloworld.Greeter/SayHello", Some(3))
+            ]
+        );
+        assert_eq!(reqs[1].protocol, Some(Protocol::Grpc));
+
+        // 两个流的响应交错到达: gRPC 流先到 Header，HTTP 流先结束，gRPC 状态在 trailers 中
+        let read = [
+            frame(SETTINGS, 0, 0, &[]),
+            frame(
+                HEADERS,
+                END_HEADERS,
+                3,
+                &[
+                    vec![STATUS_200],
+                    literal("content-type", "application/grpc"),
+                ]
+                .concat(),
+            ),
+            frame(HEADERS, END_HEADERS, 1, &[STATUS_200]),
+            frame(DATA, END_STREAM, 1, b"ok"),
+            frame(DATA, 0, 3, &[0, 0, 0, 0, 0]),
+            frame(
+                HEADERS,
+                END_HEADERS | END_STREAM,
+                3,
+                &literal("grpc-status", "5"),
+            ),
+        ]
+        .concat();
+        let resps = responses(&mut state, &read);
+        assert_eq!(statuses(&resps), [("200", Some(1)), ("NOT_FOUND", Some(3))]);
+        assert!(
+            resps[1]
+                .attributes
+                .contains(&("rpc.grpc.status_code", AttrValue::Int(5)))
+        );
+        assert!(state.streams.is_empty());
+    }
+
+    #[test]
+    fn frames_split_across_reads() {
+        let mut state = Http2State::default();
+        let headers = frame(HEADERS, END_HEADERS | END_STREAM, 1, &get("/split"));
+        // 帧头被切开
+        assert!(requests(&mut state, &headers[..4]).is_empty());
+        // Header 块需要完整负载才能解码，跨系统调用缓存
+        assert!(requests(&mut state, &headers[4..12]).is_empty());
+        assert_eq!(
+            requests(&mut state, &headers[12..])[0].summary,
+            "GET /split"
+        );
+
+        // CONTINUATION 在下一次 read 中
+        let block = get("/cont");
+        let write = [
+            frame(HEADERS, END_STREAM, 3, &block[..4]),
+            frame(CONTINUATION, END_HEADERS, 3, &block[4..]),
+        ]
+        .concat();
+        assert!(requests(&mut state, &write[..13]).is_empty());
+        assert_eq!(requests(&mut state, &write[13..])[0].summary, "GET /cont");
+    }
+
+    #[test]
+    fn truncated_data_frame_is_skipped() {
+        let mut state = Http2State::default();
+        requests(
+            &mut state,
+            &frame(HEADERS, END_HEADERS | END_STREAM, 1, &get("/download")),
+        );
+        // 16KB 的 DATA 帧只拷贝到开头，且只传输了一半，剩余负载在下一次 read 中跳过
+        let data = frame(DATA, 0, 1, &[b'x'; 16000]);
+        let mut first = frame(HEADERS, END_HEADERS, 1, &[STATUS_200]);
+        let first_total = (first.len() + 9 + 8000) as u64;
+        first.extend_from_slice(&data[..40]);
+        assert!(
+            Http2Decoder
+                .parse_response(&mut state, &Payload::new(&first, first_total))
+                .is_empty()
+        );
+
+        let second = [&data[9 + 8000..], &frame(DATA, END_STREAM, 1, &[])].concat();
+        assert_eq!(
+            statuses(&responses(&mut state, &second)),
+            [("200", Some(1))]
+        );
+    }
+
+    #[test]
+    fn lost_boundary_resyncs_on_plausible_frame() {
+        let mut state = Http2State::default();
+        let write = [
+            frame(HEADERS, END_HEADERS | END_STREAM, 1, &get("/a")),
+            frame(HEADERS, END_HEADERS | END_STREAM, 3, &get("/b")),
+        ]
+        .concat();
+        requests(&mut state, &write);
+
+        // 只拷贝到第一个帧的一部分，之后的帧头都在未拷贝的部分
+        let read = [
+            frame(DATA, 0, 1, &[0; 64]),
+            frame(HEADERS, END_HEADERS | END_STREAM, 1, &[STATUS_200]),
+        ]
+        .concat();
+        assert!(
+            Http2Decoder
+                .parse_response(&mut state, &Payload::new(&read[..20], read.len() as u64))
+                .is_empty()
+        );
+        assert!(state.responses.lost);
+
+        // 不像帧头的数据被忽略，直到某次 read 以合理的帧头开始
+        assert!(responses(&mut state, b"\xff\xff\xff\xff garbage").is_empty());
+        let resps = responses(
+            &mut state,
+            &frame(HEADERS, END_HEADERS | END_STREAM, 3, &[STATUS_200]),
+        );
+        assert_eq!(statuses(&resps), [("200", Some(3))]);
+    }
+
+    #[test]
+    fn reset_stream_ends_response() {
+        let mut state = Http2State::default();
+        requests(&mut state, &frame(HEADERS, END_HEADERS, 1, &get("/slow")));
+        let resps = responses(&mut state, &frame(RST_STREAM, 0, 1, &8u32.to_be_bytes()));
+        assert_eq!(statuses(&resps), [("RST_STREAM CANCEL", Some(1))]);
+    }
+}
@@ -0,0 +1,765 @@
+// Kafka 协议
+// 消息格式: Size(4, 不含自身) + Payload，两个方向相同
+// 请求头: api_key(2) + api_version(2) + correlation_id(4) + client_id (可空 STRING)
+//         + [TAG_BUFFER，flexible 版本 (请求头 v2)]
+// 响应头: correlation_id(4) + [TAG_BUFFER，flexible 版本 (响应头 v1)，ApiVersions 例外]
+//
+// 响应体没有 api_key，解析方式取决于请求的 api_key / api_version，所以按 correlation_id 记录在途请求；
+// 客户端默认允许多个在途请求 (max.in.flight.requests.per.connection)，请求/响应也按 correlation_id 配对。
+// flexible 版本的 STRING / ARRAY 为 compact 编码 (UNSIGNED_VARINT 长度 + 1)，并带 tagged fields。
+
+use std::{borrow::Cow, collections::HashMap};
+
+use super::{MessageKind, Payload, ProtocolDecoder, Request, Response};
+use crate::record::Protocol;
+
+const PRODUCE: i16 = 0;
+const FETCH: i16 = 1;
+const FIND_COORDINATOR: i16 = 10;
+const JOIN_GROUP: i16 = 11;
+const HEARTBEAT: i16 = 12;
+const LEAVE_GROUP: i16 = 13;
+const SYNC_GROUP: i16 = 14;
+const API_VERSIONS: i16 = 18;
+const INIT_PRODUCER_ID: i16 = 22;
+
+const MAX_API_VERSION: i16 = 20;
+const MAX_MESSAGE_LEN: u32 = 100 * 1024 * 1024;
+// 跨系统调用缓存的单条消息上限，更大的消息 (Produce 的记录、Fetch 的结果) 只看已拷贝的前缀
+const MAX_BUFFERED: usize = 16 * 1024;
+// 单个连接上记住的在途请求数上限
+const MAX_INFLIGHT: usize = 1024;
+
+// api_key -> (名称, 第一个 flexible 版本)
+fn api(key: i16) -> Option<(&'static str, i16)> {
+    Some(match key {
+        0 => ("Produce", 9),
+        1 => ("Fetch", 12),
+        2 => ("ListOffsets", 6),
+        3 => ("Metadata", 9),
+        8 => ("OffsetCommit", 8),
+        9 => ("OffsetFetch", 6),
+        10 => ("FindCoordinator", 3),
+        11 => ("JoinGroup", 6),
+        12 => ("Heartbeat", 4),
+        13 => ("LeaveGroup", 4),
+        14 => ("SyncGroup", 4),
+        15 => ("DescribeGroups", 5),
+        16 => ("ListGroups", 3),
+        17 => ("SaslHandshake", i16::MAX),
+        18 => ("ApiVersions", 3),
+        19 => ("CreateTopics", 5),
+        20 => ("DeleteTopics", 4),
+        22 => ("InitProducerId", 2),
+        24 => ("AddPartitionsToTxn", 3),
+        25 => ("AddOffsetsToTxn", 3),
+        26 => ("EndTxn", 3),
+        28 => ("TxnOffsetCommit", 3),
+        32 => ("DescribeConfigs", 4),
+        33 => ("AlterConfigs", 2),
+        36 => ("SaslAuthenticate", 2),
+        37 => ("CreatePartitions", 2),
+        42 => ("DeleteGroups", 2),
+        60 => ("DescribeCluster", 0),
+        _ => return None,
+    })
+}
+
+// 常见错误码，其余输出数值
+fn error_name(code: i16) -> Option<&'static str> {
+    Some(match code {
+        -1 => "UNKNOWN_SERVER_ERROR",
+        1 => "OFFSET_OUT_OF_RANGE",
+        2 => "CORRUPT_MESSAGE",
+        3 => "UNKNOWN_TOPIC_OR_PARTITION",
+        5 => "LEADER_NOT_AVAILABLE",
+        6 => "NOT_LEADER_OR_FOLLOWER",
+        7 => "REQUEST_TIMED_OUT",
+        10 => "MESSAGE_TOO_LARGE",
+        14 => "COORDINATOR_LOAD_IN_PROGRESS",
+        15 => "COORDINATOR_NOT_AVAILABLE",
+        16 => "NOT_COORDINATOR",
+        17 => "INVALID_TOPIC_EXCEPTION",
+        19 => "NOT_ENOUGH_REPLICAS",
+        20 => "NOT_ENOUGH_REPLICAS_AFTER_APPEND",
+        22 => "ILLEGAL_GENERATION",
+        25 => "UNKNOWN_MEMBER_ID",
+        27 => "REBALANCE_IN_PROGRESS",
+        29 => "TOPIC_AUTHORIZATION_FAILED",
+        30 => "GROUP_AUTHORIZATION_FAILED",
+        31 => "CLUSTER_AUTHORIZATION_FAILED",
+        35 => "UNSUPPORTED_VERSION",
+        36 => "TOPIC_ALREADY_EXISTS",
+        41 => "NOT_CONTROLLER",
+        47 => "INVALID_PRODUCER_EPOCH",
+        58 => "SASL_AUTHENTICATION_FAILED",
+        _ => return None,
+    })
+}
+
+// 按 Kafka 协议的基本类型顺序读取，越界时返回 None
+struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Cursor { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(n)?)?;
+        self.pos += n;
+        Some(bytes)
+    }
+
+    fn skip(&mut self, n: usize) -> Option<()> {
+        self.bytes(n).map(drop)
+    }
+
+    fn i16(&mut self) -> Option<i16> {
+        Some(i16::from_be_bytes(self.bytes(2)?.try_into().ok()?))
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        Some(i32::from_be_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    fn uvarint(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self.bytes(1)?.first()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    // 数组元素个数: INT32 或 compact (UNSIGNED_VARINT N + 1)，null 数组视为 0
+    fn array_len(&mut self, flexible: bool) -> Option<usize> {
+        if flexible {
+            Some(self.uvarint()?.saturating_sub(1) as usize)
+        } else {
+            Some(self.i32()?.max(0) as usize)
+        }
+    }
+
+    // 可空字符串: INT16 长度 (-1 为 null) 或 compact (UNSIGNED_VARINT N + 1，0 为 null)
+    fn string(&mut self, flexible: bool) -> Option<Option<String>> {
+        let len = if flexible {
+            self.uvarint()?.checked_sub(1)
+        } else {
+            u64::try_from(self.i16()?).ok()
+        };
+        match len {
+            Some(len) => {
+                let bytes = self.bytes(usize::try_from(len).ok()?)?;
+                Some(Some(String::from_utf8_lossy(bytes).into_owned()))
+            }
+            None => Some(None),
+        }
+    }
+
+    // TAG_BUFFER: 个数 + (tag + 长度 + 内容)*
+    fn tagged_fields(&mut self) -> Option<()> {
+        for _ in 0..self.uvarint()? {
+            self.uvarint()?;
+            let len = self.uvarint()?;
+            self.skip(usize::try_from(len).ok()?)?;
+        }
+        Some(())
+    }
+}
+
+// 一个方向的消息切分状态
+#[derive(Default)]
+struct MessageReader {
+    partial: Vec<u8>, // 跨系统调用的不完整消息
+    skip: u64,        // 当前消息尚未到达的字节数
+    lost: bool,       // Size 落在未拷贝的部分，消息边界丢失
+}
+
+impl MessageReader {
+    // on_message 收到已拷贝的消息体 (截断的消息只有前缀)
+    fn feed(&mut self, payload: &Payload, mut on_message: impl FnMut(&[u8])) {
+        if self.lost {
+            return;
+        }
+        let mut data: &[u8] = payload;
+        let mut total = payload.total_len().max(data.len() as u64);
+        if self.skip > 0 {
+            let n = self.skip.min(total);
+            self.skip -= n;
+            total -= n;
+            data = data.get(n as usize..).unwrap_or_default();
+        }
+
+        let input: Cow<[u8]> = if self.partial.is_empty() {
+            Cow::Borrowed(data)
+        } else {
+            let mut buf = std::mem::take(&mut self.partial);
+            buf.extend_from_slice(data);
+            Cow::Owned(buf)
+        };
+        let visible = input.len() as u64;
+        let end = visible - data.len() as u64 + total;
+        let mut pos = 0usize;
+        while (pos as u64) < end {
+            let Some(size) = input.get(pos..pos + 4) else {
+                if visible == end {
+                    self.partial = input[pos..].to_vec();
+                } else {
+                    self.lost = true;
+                }
+                return;
+            };
+            let size = u32::from_be_bytes([size[0], size[1], size[2], size[3]]);
+            if size > MAX_MESSAGE_LEN {
+                self.lost = true;
+                return;
+            }
+            let body = pos + 4;
+            let message_end = (body + size as usize) as u64;
+            if message_end <= visible {
+                on_message(&input[body..message_end as usize]);
+                pos = message_end as usize;
+                continue;
+            }
+            if visible == end && (size as usize) <= MAX_BUFFERED {
+                self.partial = input[pos..].to_vec();
+                return;
+            }
+            on_message(&input[body.min(input.len())..]);
+            if message_end >= end {
+                self.skip = message_end - end;
+            } else {
+                self.lost = true;
+            }
+            return;
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = MessageReader::default();
+    }
+}
+
+// Produce / Fetch 请求中的第一个 Topic (v13+ 只有 topic_id，没有名字)
+fn first_topic(key: i16, version: i16, flexible: bool, r: &mut Cursor) -> Option<String> {
+    match key {
+        PRODUCE => {
+            // [transactional_id (v3+)] + acks(2) + timeout_ms(4) + topic_data[name + partition_data]
+            if version >= 3 {
+                r.string(flexible)?;
+            }
+            r.skip(6)?;
+        }
+        FETCH => {
+            // [replica_id (v0-14)] + max_wait_ms + min_bytes + [max_bytes (v3+)] + [isolation_level (v4+)]
+            // + [session_id + session_epoch (v7+)] + topics[topic + partitions]
+            let mut skip = 8;
+            if version < 15 {
+                skip += 4;
+            }
+            if version >= 3 {
+                skip += 4;
+            }
+            if version >= 4 {
+                skip += 1;
+            }
+            if version >= 7 {
+                skip += 8;
+            }
+            r.skip(skip)?;
+        }
+        _ => return None,
+    }
+    if r.array_len(flexible)? == 0 || version >= 13 {
+        return None;
+    }
+    r.string(flexible)?
+}
+
+// Produce 的 acks，0 表示 broker 不回复
+fn produce_acks(version: i16, flexible: bool, r: &mut Cursor) -> Option<i16> {
+    if version >= 3 {
+        r.string(flexible)?;
+    }
+    r.i16()
+}
+
+// 响应体中的错误码: 顶层 error_code，或第一个出错的分区 (Produce) / 第一个分区 (Fetch)
+fn response_error(key: i16, version: i16, flexible: bool, r: &mut Cursor) -> Option<i16> {
+    match key {
+        PRODUCE => {
+            // responses[name + partition_responses[index + error_code + base_offset + ...]] + throttle_time_ms
+            for _ in 0..r.array_len(flexible)? {
+                if version >= 13 {
+                    r.skip(16)?;
+                } else {
+                    r.string(flexible)?;
+                }
+                for _ in 0..r.array_len(flexible)? {
+                    r.skip(4)?;
+                    let error = r.i16()?;
+                    if error != 0 {
+                        return Some(error);
+                    }
+                    // base_offset + [log_append_time_ms (v2+)] + [log_start_offset (v5+)]
+                    r.skip(
+                        8 + if version >= 2 { 8 } else { 0 } + if version >= 5 { 8 } else { 0 },
+                    )?;
+                    if version >= 8 {
+                        // record_errors[batch_index + batch_index_error_message] + error_message
+                        for _ in 0..r.array_len(flexible)? {
+                            r.skip(4)?;
+                            r.string(flexible)?;
+                            if flexible {
+                                r.tagged_fields()?;
+                            }
+                        }
+                        r.string(flexible)?;
+                    }
+                    if flexible {
+                        r.tagged_fields()?;
+                    }
+                }
+                if flexible {
+                    r.tagged_fields()?;
+                }
+            }
+            Some(0)
+        }
+        FETCH => {
+            // [throttle_time_ms (v1+)] + [error_code + session_id (v7+)] + responses[topic + partitions[index + error_code + ...]]
+            if version >= 1 {
+                r.skip(4)?;
+            }
+            if version >= 7 {
+                let error = r.i16()?;
+                if error != 0 {
+                    return Some(error);
+                }
+                r.skip(4)?;
+            }
+            if r.array_len(flexible)? == 0 {
+                return Some(0);
+            }
+            if version >= 13 {
+                r.skip(16)?;
+            } else {
+                r.string(flexible)?;
+            }
+            if r.array_len(flexible)? == 0 {
+                return Some(0);
+            }
+            r.skip(4)?;
+            r.i16()
+        }
+        // error_code 在 throttle_time_ms 之前
+        API_VERSIONS => r.i16(),
+        // [throttle_time_ms] + error_code；FindCoordinator v4+ 的错误码在 coordinators 数组中
+        FIND_COORDINATOR if version < 4 => {
+            if version >= 1 {
+                r.skip(4)?;
+            }
+            r.i16()
+        }
+        JOIN_GROUP | HEARTBEAT | LEAVE_GROUP | SYNC_GROUP | INIT_PRODUCER_ID => {
+            let throttled = match key {
+                JOIN_GROUP => version >= 2,
+                INIT_PRODUCER_ID => true,
+                _ => version >= 1,
+            };
+            if throttled {
+                r.skip(4)?;
+            }
+            r.i16()
+        }
+        _ => None,
+    }
+}
+
+#[derive(Default)]
+struct Connection {
+    inflight: HashMap<i32, (i16, i16)>, // correlation_id -> (api_key, api_version)
+}
+
+impl Connection {
+    fn on_request(&mut self, body: &[u8]) -> Option<Request> {
+        let mut r = Cursor::new(body);
+        let key = r.i16()?;
+        let version = r.i16()?;
+        let correlation_id = r.i32()?;
+        let client_id = r.string(false)?;
+        let flexible = api(key).is_some_and(|(_, first)| version >= first);
+        if flexible {
+            r.tagged_fields()?;
+        }
+        let body_start = r.pos;
+        if key == PRODUCE && produce_acks(version, flexible, &mut r) == Some(0) {
+            return None;
+        }
+        r.pos = body_start;
+        let topic = first_topic(key, version, flexible, &mut r);
+
+        let name = api(key).map_or_else(|| format!("ApiKey{}", key), |(name, _)| name.to_string());
+        let mut request = Request::new(match &topic {
+            Some(topic) => format!("{} {}", name, topic),
+            None => name,
+        });
+        if let Some(topic) = topic {
+            request
+                .attributes
+                .push(("messaging.destination.name", topic.into()));
+        }
+        if let Some(client_id) = client_id {
+            request
+                .attributes
+                .push(("messaging.client.id", client_id.into()));
+        }
+        request
+            .attributes
+            .push(("messaging.kafka.api_version", i64::from(version).into()));
+        request.attributes.push((
+            "messaging.kafka.correlation_id",
+            i64::from(correlation_id).into(),
+        ));
+        request.id = Some(correlation_id as u32 as u64);
+
+        if self.inflight.len() >= MAX_INFLIGHT {
+            self.inflight.clear();
+        }
+        self.inflight.insert(correlation_id, (key, version));
+        Some(request)
+    }
+
+    fn on_response(&mut self, body: &[u8]) -> Option<Response> {
+        let mut r = Cursor::new(body);
+        let correlation_id = r.i32()?;
+        let (key, version) = self.inflight.remove(&correlation_id)?;
+        let flexible = api(key).is_some_and(|(_, first)| version >= first);
+        // ApiVersions 的响应头固定为 v0，客户端据此才能处理 broker 不支持的版本
+        if flexible && key != API_VERSIONS {
+            r.tagged_fields();
+        }
+        let error = response_error(key, version, flexible, &mut r).unwrap_or(0);
+        let mut response = Response::new(match error {
+            0 => "OK".to_string(),
+            code => error_name(code).map_or_else(|| format!("ERROR {}", code), str::to_string),
+        });
+        if error != 0 {
+            response
+                .attributes
+                .push(("messaging.kafka.error_code", i64::from(error).into()));
+        }
+        response.id = Some(correlation_id as u32 as u64);
+        Some(response)
+    }
+}
+
+#[derive(Default)]
+pub struct KafkaState {
+    requests: MessageReader,
+    responses: MessageReader,
+    conn: Connection,
+}
+
+pub struct KafkaDecoder;
+
+impl ProtocolDecoder for KafkaDecoder {
+    type State = KafkaState;
+
+    fn protocol(&self) -> Protocol {
+        Protocol::Kafka
+    }
+
+    fn default_ports(&self) -> &'static [u16] {
+        &[9092]
+    }
+
+    fn pipelined(&self) -> bool {
+        true
+    }
+
+    fn max_pending(&self) -> usize {
+        MAX_INFLIGHT
+    }
+
+    fn detect(&self, payload: &Payload) -> Option<MessageKind> {
+        // Size(4) + 已知的 api_key + 合理的 api_version + correlation_id(4) + client_id
+        // 一次 write 可能带多个在途请求，Size 只校验范围；只识别请求，响应没有可校验的特征
+        let mut r = Cursor::new(payload);
+        let size = r.i32()?;
+        let key = r.i16()?;
+        let version = r.i16()?;
+        r.i32()?;
+        let client_id = r.i16()?;
+        let valid = (10..=MAX_MESSAGE_LEN as i32).contains(&size)
+            && api(key).is_some()
+            && (0..=MAX_API_VERSION).contains(&version)
+            && (client_id == -1 || (0..=size as i64 - 10).contains(&i64::from(client_id)));
+        valid.then_some(MessageKind::Request)
+    }
+
+    fn parse_request(&self, state: &mut KafkaState, payload: &Payload) -> Vec<Request> {
+        // 请求方向每次系统调用都从消息边界开始
+        if state.requests.lost {
+            state.requests.reset();
+        }
+        if state.responses.lost {
+            state.responses.reset();
+        }
+        let mut requests = Vec::new();
+        let conn = &mut state.conn;
+        state.requests.feed(payload, |body| {
+            requests.extend(conn.on_request(body));
+        });
+        requests
+    }
+
+    fn parse_response(&self, state: &mut KafkaState, payload: &Payload) -> Vec<Response> {
+        let mut responses = Vec::new();
+        let conn = &mut state.conn;
+        state.responses.feed(payload, |body| {
+            responses.extend(conn.on_response(body));
+        });
+        responses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::AttrValue, *};
+
+    fn string(s: &str) -> Vec<u8> {
+        [(s.len() as i16).to_be_bytes().as_slice(), s.as_bytes()].concat()
+    }
+
+    fn frame(body: &[u8]) -> Vec<u8> {
+        [(body.len() as i32).to_be_bytes().as_slice(), body].concat()
+    }
+
+    // 非 flexible 版本的请求: 请求头 v1 + 请求体
+    fn request(key: i16, version: i16, correlation_id: i32, body: &[u8]) -> Vec<u8> {
+        let mut out = key.to_be_bytes().to_vec();
+        out.extend_from_slice(&version.to_be_bytes());
+        out.extend_from_slice(&correlation_id.to_be_bytes());
+        out.extend(string("producer-1"));
+        out.extend_from_slice(body);
+        frame(&out)
+    }
+
+    // Produce v3: transactional_id(null) + acks + timeout_ms + [topic + [partition + records]]
+    fn produce(correlation_id: i32, topic: &str, acks: i16, records: usize) -> Vec<u8> {
+        let mut body = (-1i16).to_be_bytes().to_vec();
+        body.extend_from_slice(&acks.to_be_bytes());
+        body.extend_from_slice(&30000i32.to_be_bytes());
+        body.extend_from_slice(&1i32.to_be_bytes());
+        body.extend(string(topic));
+        body.extend_from_slice(&1i32.to_be_bytes());
+        body.extend_from_slice(&0i32.to_be_bytes());
+        body.extend_from_slice(&(records as i32).to_be_bytes());
+        body.extend(vec![0u8; records]);
+        request(PRODUCE, 3, correlation_id, &body)
+    }
+
+    // Fetch v4: replica_id + max_wait_ms + min_bytes + max_bytes + isolation_level + [topic + ...]
+    fn fetch(correlation_id: i32, topic: &str) -> Vec<u8> {
+        let mut body = (-1i32).to_be_bytes().to_vec();
+        body.extend_from_slice(&500i32.to_be_bytes());
+        body.extend_from_slice(&1i32.to_be_bytes());
+        body.extend_from_slice(&(1i32 << 20).to_be_bytes());
+        body.push(0);
+        body.extend_from_slice(&1i32.to_be_bytes());
+        body.extend(string(topic));
+        body.extend_from_slice(&0i32.to_be_bytes());
+        request(FETCH, 4, correlation_id, &body)
+    }
+
+    // Produce v3 响应: [topic + [partition + error_code + base_offset + log_append_time]] + throttle_time_ms
+    fn produce_reply(correlation_id: i32, topic: &str, error: i16) -> Vec<u8> {
+        let mut body = correlation_id.to_be_bytes().to_vec();
+        body.extend_from_slice(&1i32.to_be_bytes());
+        body.extend(string(topic));
+        body.extend_from_slice(&1i32.to_be_bytes());
+        body.extend_from_slice(&0i32.to_be_bytes());
+        body.extend_from_slice(&error.to_be_bytes());
+        body.extend_from_slice(&[0; 16]);
+        body.extend_from_slice(&0i32.to_be_bytes());
+        frame(&body)
+    }
+
+    // Fetch v4 响应: throttle_time_ms + [topic + [partition + error_code + ...]]
+    fn fetch_reply(correlation_id: i32, topic: &str, records: usize) -> Vec<u8> {
+        let mut body = correlation_id.to_be_bytes().to_vec();
+        body.extend_from_slice(&0i32.to_be_bytes());
+        body.extend_from_slice(&1i32.to_be_bytes());
+        body.extend(string(topic));
+        body.extend_from_slice(&1i32.to_be_bytes());
+        body.extend_from_slice(&0i32.to_be_bytes());
+        body.extend_from_slice(&0i16.to_be_bytes());
+        body.extend_from_slice(&[0; 20]);
+        body.extend_from_slice(&(records as i32).to_be_bytes());
+        body.extend(vec![0u8; records]);
+        frame(&body)
+    }
+
+    fn requests(state: &mut KafkaState, data: &[u8]) -> Vec<Request> {
+        KafkaDecoder.parse_request(state, &Payload::new(data, data.len() as u64))
+    }
+
+    fn responses(state: &mut KafkaState, data: &[u8]) -> Vec<Response> {
+        KafkaDecoder.parse_response(state, &Payload::new(data, data.len() as u64))
+    }
+
+    fn statuses(responses: &[Response]) -> Vec<(&str, Option<u64>)> {
+        responses
+            .iter()
+            .map(|r| (r.status.as_str(), r.id))
+            .collect()
+    }
+
+    #[test]
+    fn detect_request_header() {
+        let versions = request(API_VERSIONS, 0, 1, &[]);
+        assert_eq!(
+            KafkaDecoder.detect(&Payload::new(&versions, versions.len() as u64)),
+            Some(MessageKind::Request)
+        );
+        // 截断的大 Produce 同样能识别
+        let big = produce(2, "orders", 1, 1 << 20);
+        assert_eq!(
+            KafkaDecoder.detect(&Payload::new(&big[..64], big.len() as u64)),
+            Some(MessageKind::Request)
+        );
+        let http = b"GET / HTTP/1.1\r\nHost: x\r\n\r\n";
+        assert_eq!(
+            KafkaDecoder.detect(&Payload::new(http, http.len() as u64)),
+            None
+        );
+    }
+
+    #[test]
+    fn pipelined_requests_match_by_correlation_id() {
+        let mut state = KafkaState::default();
+        let write = [
+            produce(7, "orders", 1, 16),
+            produce(8, "missing", 1, 16),
+            fetch(9, "orders"),
+        ]
+        .concat();
+        let reqs = requests(&mut state, &write);
+        let summaries: Vec<_> = reqs.iter().map(|r| (r.summary.as_str(), r.id)).collect();
+        assert_eq!(
+            summaries,
+            [
+                ("Produce orders", Some(7)),
+                ("Produce missing", Some(8)),
+                ("Fetch orders", Some(9))
+            ]
+        );
+        assert!(
+            reqs[0]
+                .attributes
+                .contains(&("messaging.client.id", AttrValue::from("producer-1")))
+        );
+
+        // 响应顺序与请求不同也能按 correlation_id 找到请求的 api_key
+        let read = [
+            fetch_reply(9, "orders", 8),
+            produce_reply(8, "missing", 3),
+            produce_reply(7, "orders", 0),
+        ]
+        .concat();
+        let resps = responses(&mut state, &read);
+        assert_eq!(
+            statuses(&resps),
+            [
+                ("OK", Some(9)),
+                ("UNKNOWN_TOPIC_OR_PARTITION", Some(8)),
+                ("OK", Some(7))
+            ]
+        );
+        assert!(
+            resps[1]
+                .attributes
+                .contains(&("messaging.kafka.error_code", AttrValue::Int(3)))
+        );
+    }
+
+    #[test]
+    fn acks_zero_produce_has_no_request() {
+        let mut state = KafkaState::default();
+        let write = [produce(1, "logs", 0, 16), fetch(2, "logs")].concat();
+        let reqs = requests(&mut state, &write);
+        assert_eq!(reqs.len(), 1);
+        assert_eq!(reqs[0].summary, "Fetch logs");
+    }
+
+    #[test]
+    fn messages_split_across_reads() {
+        let mut state = KafkaState::default();
+        let req = fetch(5, "orders");
+        assert!(requests(&mut state, &req[..2]).is_empty());
+        assert_eq!(requests(&mut state, &req[2..])[0].summary, "Fetch orders");
+
+        let reply = fetch_reply(5, "orders", 32);
+        assert!(responses(&mut state, &reply[..20]).is_empty());
+        assert_eq!(
+            statuses(&responses(&mut state, &reply[20..])),
+            [("OK", Some(5))]
+        );
+    }
+
+    #[test]
+    fn truncated_produce_is_skipped() {
+        let mut state = KafkaState::default();
+        // 1MB 的 Produce 分两次 write，第一次只拷贝到前 64 字节
+        let big = produce(1, "orders", 1, 1 << 20);
+        let first_total = (big.len() / 2) as u64;
+        let reqs = KafkaDecoder.parse_request(&mut state, &Payload::new(&big[..64], first_total));
+        assert_eq!(reqs[0].summary, "Produce orders");
+
+        // 第二次 write: Produce 的后半部分 (同样截断) + 下一个请求，按 Size 跳过后者之前的部分
+        let rest = &big[big.len() / 2..];
+        let next = fetch(2, "orders");
+        let second = [rest, &next].concat();
+        let reqs = requests(&mut state, &second);
+        assert_eq!(reqs.len(), 1);
+        assert_eq!(
+            (reqs[0].summary.as_str(), reqs[0].id),
+            ("Fetch orders", Some(2))
+        );
+
+        let read = [produce_reply(1, "orders", 0), fetch_reply(2, "orders", 0)].concat();
+        assert_eq!(
+            statuses(&responses(&mut state, &read)),
+            [("OK", Some(1)), ("OK", Some(2))]
+        );
+    }
+
+    #[test]
+    fn lost_boundary_recovers_on_next_request() {
+        let mut state = KafkaState::default();
+        requests(&mut state, &[fetch(1, "a"), fetch(2, "b")].concat());
+
+        // 只拷贝到第一个响应的开头，第二个响应的 Size 落在未拷贝的部分
+        let read = [fetch_reply(1, "a", 4096), fetch_reply(2, "b", 16)].concat();
+        let resps =
+            KafkaDecoder.parse_response(&mut state, &Payload::new(&read[..64], read.len() as u64));
+        assert_eq!(statuses(&resps), [("OK", Some(1))]);
+        assert!(state.responses.lost);
+        assert!(responses(&mut state, &fetch_reply(2, "b", 0)).is_empty());
+
+        // 下一个请求让响应方向从消息边界重新开始
+        requests(&mut state, &fetch(3, "c"));
+        assert_eq!(
+            statuses(&responses(&mut state, &fetch_reply(3, "c", 0))),
+            [("OK", Some(3))]
+        );
+    }
+}
@@ -0,0 +1,825 @@
+// [L7] 协议解析框架
+//
+// 每种协议实现一个 ProtocolDecoder: 识别 (detect)、解析请求、解析响应，
+// 并声明自己的 "每连接状态" 类型。DecoderRegistry 负责为连接推断协议 (端口提示 > 内容 > 约定端口)，
+// L7Tracker 维护每个连接的推断结果、解码状态和待响应请求队列，产出配对完成的 Exchange。
+//
+// 新增协议只需要: 实现 ProtocolDecoder -> 在 DecoderRegistry::with_builtin 中注册 (或外部调用 register)。
+
+mod hpack;
+mod http;
+mod http2;
+mod kafka;
+mod mysql;
+mod postgres;
+mod redis;
+
+use std::{
+    any::Any,
+    collections::{HashMap, VecDeque},
+    fmt,
+    ops::Deref,
+    str::FromStr,
+};
+
+use clap::ValueEnum;
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+pub use self::{
+    http::HttpDecoder, http2::Http2Decoder, kafka::KafkaDecoder, mysql::MysqlDecoder,
+    postgres::PostgresDecoder, redis::RedisDecoder,
+};
+use crate::{
+    SessionKey,
+    record::{Protocol, Role},
+};
+
+// 单个连接上默认最多缓存的待响应请求数，防止只有请求没有响应的连接无限增长。
+// 允许更多在途请求的协议 (HTTP/2 并发流、Redis / Kafka pipelining) 通过 ProtocolDecoder::max_pending 调大
+const MAX_PENDING: usize = 64;
+
+// 一个连接最多尝试推断的载荷次数，超过后认定为未知协议，不再浪费 CPU
+const MAX_INFER_ATTEMPTS: u8 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Tx, // write / sendto
+    Rx, // read / recvfrom
+}
+
+impl Direction {
+    pub fn from_code(direction_code: u8) -> Option<Self> {
+        match direction_code {
+            2 => Some(Direction::Tx),
+            3 => Some(Direction::Rx),
+            _ => None,
+        }
+    }
+
+    fn opposite(self) -> Self {
+        match self {
+            Direction::Tx => Direction::Rx,
+            Direction::Rx => Direction::Tx,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageKind {
+    Request,
+    Response,
+}
+
+// 一次 TX/RX 的载荷。eBPF 只拷贝前 capture.payload_len 字节，total_len 是系统调用实际传输的字节数；
+// 拷贝到的字节少于 total_len 时，解码器看到的只是截断的前缀 (如超长 SQL、大量 Header 的 HTTP 请求)。
+// 解引用为已拷贝的字节。
+#[derive(Debug, Clone, Copy)]
+pub struct Payload<'a> {
+    data: &'a [u8],
+    total_len: u64,
+}
+
+impl<'a> Payload<'a> {
+    pub fn new(data: &'a [u8], total_len: u64) -> Self {
+        Payload { data, total_len }
+    }
+
+    pub fn total_len(&self) -> u64 {
+        self.total_len
+    }
+
+    pub fn is_truncated(&self) -> bool {
+        (self.data.len() as u64) < self.total_len
+    }
+
+    // 按 UTF-8 解码；截断处可能切开一个多字节字符，此时丢弃末尾不完整的部分
+    pub fn text(&self) -> Option<&'a str> {
+        match std::str::from_utf8(self.data) {
+            Ok(text) => Some(text),
+            Err(e) if self.is_truncated() && e.error_len().is_none() => {
+                std::str::from_utf8(&self.data[..e.valid_up_to()]).ok()
+            }
+            Err(_) => None,
+        }
+    }
+}
+
+impl Deref for Payload<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.data
+    }
+}
+
+// 协议相关的结构化字段，键沿用 OpenTelemetry 语义约定 (如 "http.request.method")，
+// 随 L7Record 输出，导出 OTLP 时原样作为 Span 属性
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum AttrValue {
+    Str(String),
+    Int(i64),
+}
+
+impl From<&str> for AttrValue {
+    fn from(value: &str) -> Self {
+        AttrValue::Str(value.to_string())
+    }
+}
+
+impl From<String> for AttrValue {
+    fn from(value: String) -> Self {
+        AttrValue::Str(value)
+    }
+}
+
+impl From<i64> for AttrValue {
+    fn from(value: i64) -> Self {
+        AttrValue::Int(value)
+    }
+}
+
+pub type Attributes = Vec<(&'static str, AttrValue)>;
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub summary: String, // 请求摘要，如 "GET /index.html"、"SELECT 1"
+    pub attributes: Attributes,
+    pub id: Option<u64>, // 多路复用协议的关联 ID (如 HTTP/2 stream ID)，响应按 ID 配对；为空时按 FIFO
+    pub protocol: Option<Protocol>, // 承载在解码器协议之上的子协议 (如 HTTP/2 上的 gRPC)
+}
+
+impl Request {
+    pub fn new(summary: impl Into<String>) -> Self {
+        Request {
+            summary: summary.into(),
+            attributes: Vec::new(),
+            id: None,
+            protocol: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: String, // 响应状态，如 "200 OK"、"OK"、"ERR"
+    pub attributes: Attributes,
+    pub id: Option<u64>, // 对应 Request::id
+}
+
+impl Response {
+    pub fn new(status: impl Into<String>) -> Self {
+        Response {
+            status: status.into(),
+            attributes: Vec::new(),
+            id: None,
+        }
+    }
+}
+
+pub trait ProtocolDecoder: Send + Sync + 'static {
+    // 每个连接一份的解码状态 (如 HPACK 动态表、预编译语句表)
+    type State: Default + Send + 'static;
+
+    fn protocol(&self) -> Protocol;
+
+    // 约定俗成的服务端口，仅在内容无法识别时兜底 (如从连接中途开始观测)
+    fn default_ports(&self) -> &'static [u16] {
+        &[]
+    }
+
+    // 是否允许同一连接上有多个在途请求 (pipelining)。
+    // 不允许时，新请求会覆盖尚未收到响应的旧请求，避免一次漏配导致后续全部错位。
+    fn pipelined(&self) -> bool {
+        false
+    }
+
+    // 单个连接上最多缓存的待响应请求数，应不小于解码器自己跟踪的在途请求上限，
+    // 否则仍会收到响应的请求被提前丢弃
+    fn max_pending(&self) -> usize {
+        MAX_PENDING
+    }
+
+    // 根据载荷内容判断是否属于本协议，以及是请求还是响应。
+    // 会对所有解码器依次调用，实现时应尽量严格 (校验长度字段、命令字等)，避免误判其他协议。
+    // payload 可能是截断的前缀 (见 Payload::is_truncated)，长度字段只能要求不小于已拷贝的字节数。
+    fn detect(&self, payload: &Payload) -> Option<MessageKind>;
+
+    fn parse_request(&self, state: &mut Self::State, payload: &Payload) -> Vec<Request>;
+
+    fn parse_response(&self, state: &mut Self::State, payload: &Payload) -> Vec<Response>;
+}
+
+// ProtocolDecoder 带有关联类型，无法直接做成 trait object，
+// 这里通过 Any 擦除 State 类型，供 Registry 统一存放和分发。
+trait ErasedDecoder: Send + Sync {
+    fn protocol(&self) -> Protocol;
+    fn default_ports(&self) -> &'static [u16];
+    fn pipelined(&self) -> bool;
+    fn max_pending(&self) -> usize;
+    fn detect(&self, payload: &Payload) -> Option<MessageKind>;
+    fn new_state(&self) -> Box<dyn Any + Send>;
+    fn parse_request(&self, state: &mut dyn Any, payload: &Payload) -> Vec<Request>;
+    fn parse_response(&self, state: &mut dyn Any, payload: &Payload) -> Vec<Response>;
+}
+
+impl<D: ProtocolDecoder> ErasedDecoder for D {
+    fn protocol(&self) -> Protocol {
+        ProtocolDecoder::protocol(self)
+    }
+
+    fn default_ports(&self) -> &'static [u16] {
+        ProtocolDecoder::default_ports(self)
+    }
+
+    fn pipelined(&self) -> bool {
+        ProtocolDecoder::pipelined(self)
+    }
+
+    fn max_pending(&self) -> usize {
+        ProtocolDecoder::max_pending(self)
+    }
+
+    fn detect(&self, payload: &Payload) -> Option<MessageKind> {
+        ProtocolDecoder::detect(self, payload)
+    }
+
+    fn new_state(&self) -> Box<dyn Any + Send> {
+        Box::new(D::State::default())
+    }
+
+    fn parse_request(&self, state: &mut dyn Any, payload: &Payload) -> Vec<Request> {
+        match state.downcast_mut::<D::State>() {
+            Some(state) => ProtocolDecoder::parse_request(self, state, payload),
+            None => Vec::new(),
+        }
+    }
+
+    fn parse_response(&self, state: &mut dyn Any, payload: &Payload) -> Vec<Response> {
+        match state.downcast_mut::<D::State>() {
+            Some(state) => ProtocolDecoder::parse_response(self, state, payload),
+            None => Vec::new(),
+        }
+    }
+}
+
+// 显式端口提示，如 "6380=redis"，优先级高于内容推断。
+// 命令行和配置文件使用同一种字符串形式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct PortHint {
+    pub port: u16,
+    pub protocol: Protocol,
+}
+
+impl FromStr for PortHint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (port, protocol) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected PORT=PROTOCOL, got '{}'", s))?;
+        let port = port
+            .trim()
+            .parse()
+            .map_err(|e| format!("invalid port '{}': {}", port, e))?;
+        let protocol = <Protocol as ValueEnum>::from_str(protocol.trim(), true)?;
+        Ok(PortHint { port, protocol })
+    }
+}
+
+impl TryFrom<String> for PortHint {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for PortHint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.port, self.protocol.name())
+    }
+}
+
+impl From<PortHint> for String {
+    fn from(hint: PortHint) -> Self {
+        hint.to_string()
+    }
+}
+
+// 协议是如何推断出来的 (仅用于日志)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InferSource {
+    Hint,
+    Content,
+    Port,
+}
+
+impl fmt::Display for InferSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            InferSource::Hint => "port hint",
+            InferSource::Content => "content",
+            InferSource::Port => "well-known port",
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct DecoderRegistry {
+    decoders: Vec<Box<dyn ErasedDecoder>>,
+    hints: HashMap<u16, usize>, // 端口 -> 解码器下标
+}
+
+impl DecoderRegistry {
+    // 内置协议: 顺序即内容推断的优先级，检测越严格的越靠前，HTTP 放最后
+    pub fn with_builtin() -> Self {
+        let mut registry = DecoderRegistry::default();
+        registry.register(MysqlDecoder);
+        registry.register(RedisDecoder);
+        registry.register(PostgresDecoder);
+        registry.register(KafkaDecoder);
+        registry.register(Http2Decoder);
+        registry.register(HttpDecoder);
+        registry
+    }
+
+    pub fn register<D: ProtocolDecoder>(&mut self, decoder: D) {
+        self.decoders.push(Box::new(decoder));
+    }
+
+    // 添加端口提示；协议没有注册对应解码器时返回 false
+    pub fn add_port_hint(&mut self, hint: PortHint) -> bool {
+        // gRPC 由 HTTP/2 解码器按 content-type 区分
+        let protocol = match hint.protocol {
+            Protocol::Grpc => Protocol::Http2,
+            other => other,
+        };
+        let Some(idx) = self
+            .decoders
+            .iter()
+            .position(|decoder| decoder.protocol() == protocol)
+        else {
+            return false;
+        };
+        self.hints.insert(hint.port, idx);
+        true
+    }
+
+    // 为连接推断协议，同时确定 "请求" 是哪个方向:
+    // 1. 端口提示: dport 命中说明本端是客户端 (TX 为请求)；sport 命中说明本端是服务端 (RX 为请求)
+    // 2. 内容: 依次询问每个解码器，首包是请求则当前方向为请求方向，是响应则相反
+    // 3. 约定端口: 内容无法识别时 (如连接建立早于 Agent 启动) 按 3306/6379/5432 兜底
+    fn infer(
+        &self,
+        sport: u16,
+        dport: u16,
+        direction: Direction,
+        payload: &Payload,
+    ) -> Option<(usize, Direction, InferSource)> {
+        if let Some(&idx) = self.hints.get(&dport) {
+            return Some((idx, Direction::Tx, InferSource::Hint));
+        }
+        if let Some(&idx) = self.hints.get(&sport) {
+            return Some((idx, Direction::Rx, InferSource::Hint));
+        }
+
+        let by_content = self.decoders.iter().enumerate().find_map(|(idx, decoder)| {
+            let request_dir = match decoder.detect(payload)? {
+                MessageKind::Request => direction,
+                MessageKind::Response => direction.opposite(),
+            };
+            Some((idx, request_dir, InferSource::Content))
+        });
+        if by_content.is_some() {
+            return by_content;
+        }
+
+        self.decoders
+            .iter()
+            .enumerate()
+            .find_map(|(idx, decoder)| {
+                if decoder.default_ports().contains(&dport) {
+                    Some((idx, Direction::Tx))
+                } else if decoder.default_ports().contains(&sport) {
+                    Some((idx, Direction::Rx))
+                } else {
+                    None
+                }
+            })
+            .map(|(idx, request_dir)| (idx, request_dir, InferSource::Port))
+    }
+}
+
+// 一次系统调用中解析出 n 条消息 (pipelining) 时，把传输的字节数平摊到每条消息上，
+// 余数计入第一条，保证按消息累计的字节数与实际传输的一致
+fn split_bytes(total: u64, n: usize) -> impl Iterator<Item = u64> {
+    let n = n.max(1) as u64;
+    let share = total / n;
+    std::iter::once(total - share * (n - 1)).chain(std::iter::repeat(share))
+}
+
+// 一次完整的 请求 -> 响应
+pub struct Exchange {
+    pub protocol: Protocol,
+    pub role: Role,
+    pub request: Request,
+    pub response: Response,
+    pub start_ns: u64, // 请求事件的内核时间戳 (bpf_ktime_get_ns)
+    pub end_ns: u64,   // 响应事件的内核时间戳
+    pub req_bytes: u64,
+    pub resp_bytes: u64,
+}
+
+struct PendingRequest {
+    request: Request,
+    start_ns: u64,
+    bytes: u64,
+}
+
+// 推断成功后缓存在连接上的解码器
+struct Binding {
+    decoder: usize,
+    request_dir: Direction,
+    state: Box<dyn Any + Send>,
+}
+
+#[derive(Default)]
+struct Session {
+    binding: Option<Binding>,
+    attempts: u8, // 推断失败的载荷次数
+    pending: VecDeque<PendingRequest>,
+    last_ns: u64, // 最近一次数据事件的内核时间戳，空闲回收用
+}
+
+pub struct L7Tracker {
+    registry: DecoderRegistry,
+    sessions: HashMap<SessionKey, Session>,
+}
+
+impl L7Tracker {
+    pub fn new(registry: DecoderRegistry) -> Self {
+        L7Tracker {
+            registry,
+            sessions: HashMap::new(),
+        }
+    }
+
+    // 处理一个 TX/RX 数据事件，返回本次配对完成的请求/响应
+    // timestamp_ns 为事件的内核时间戳，耗时 = 响应时间戳 - 请求时间戳，与用户态何时处理无关
+    pub fn on_data(
+        &mut self,
+        key: SessionKey,
+        direction: Direction,
+        sport: u16,
+        dport: u16,
+        payload: Payload,
+        timestamp_ns: u64,
+    ) -> Vec<Exchange> {
+        let session = self.sessions.entry(key).or_default();
+        session.last_ns = session.last_ns.max(timestamp_ns);
+        if session.binding.is_none() {
+            // 双方的首批载荷都会参与推断，直到识别成功或次数用尽
+            if session.attempts >= MAX_INFER_ATTEMPTS {
+                return Vec::new();
+            }
+            let Some((decoder, request_dir, source)) =
+                self.registry.infer(sport, dport, direction, &payload)
+            else {
+                session.attempts += 1;
+                return Vec::new();
+            };
+            debug!(
+                "[L7] fd {} inferred as {} (by {})",
+                key.fd,
+                self.registry.decoders[decoder].protocol().as_str(),
+                source
+            );
+            session.binding = Some(Binding {
+                decoder,
+                request_dir,
+                state: self.registry.decoders[decoder].new_state(),
+            });
+        }
+        let Some(binding) = session.binding.as_mut() else {
+            return Vec::new();
+        };
+        let decoder = &self.registry.decoders[binding.decoder];
+        let role = match binding.request_dir {
+            Direction::Tx => Role::Client,
+            Direction::Rx => Role::Server,
+        };
+
+        if direction == binding.request_dir {
+            let requests = decoder.parse_request(binding.state.as_mut(), &payload);
+            let mut bytes = split_bytes(payload.total_len(), requests.len());
+            for request in requests {
+                debug!(
+                    "[L7] {} Request (fd {}): {}",
+                    decoder.protocol().as_str(),
+                    key.fd,
+                    request.summary
+                );
+                if !decoder.pipelined() {
+                    session.pending.clear();
+                } else if session.pending.len() >= decoder.max_pending() {
+                    session.pending.pop_front();
+                }
+                session.pending.push_back(PendingRequest {
+                    request,
+                    start_ns: timestamp_ns,
+                    bytes: bytes.next().unwrap_or(0),
+                });
+            }
+            return Vec::new();
+        }
+
+        let responses = decoder.parse_response(binding.state.as_mut(), &payload);
+        let mut bytes = split_bytes(payload.total_len(), responses.len());
+        responses
+            .into_iter()
+            .filter_map(|response| {
+                let resp_bytes = bytes.next().unwrap_or(0);
+                let pending = match response.id {
+                    Some(id) => {
+                        let idx = session
+                            .pending
+                            .iter()
+                            .position(|pending| pending.request.id == Some(id))?;
+                        session.pending.remove(idx)?
+                    }
+                    None => session.pending.pop_front()?,
+                };
+                Some(Exchange {
+                    protocol: pending.request.protocol.unwrap_or(decoder.protocol()),
+                    role,
+                    request: pending.request,
+                    response,
+                    start_ns: pending.start_ns,
+                    end_ns: timestamp_ns,
+                    req_bytes: pending.bytes,
+                    resp_bytes,
+                })
+            })
+            .collect()
+    }
+
+    // 连接关闭: 丢弃协议绑定和未配对的请求，FD 复用后重新推断
+    pub fn close(&mut self, key: &SessionKey) {
+        self.sessions.remove(key);
+    }
+
+    // 回收 idle_before_ns 之前就再无数据的连接，返回回收数量。
+    // 关闭事件可能丢失 (缓冲区溢出、close 探针被禁用、进程被 kill 时 FD 由内核回收)，
+    // 没有这一步 sessions 会随连接数无限增长
+    pub fn evict_idle(&mut self, idle_before_ns: u64) -> usize {
+        let before = self.sessions.len();
+        self.sessions
+            .retain(|_, session| session.last_ns >= idle_before_ns);
+        before - self.sessions.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: SessionKey = SessionKey { pid: 100, fd: 7 };
+
+    fn data(
+        tracker: &mut L7Tracker,
+        direction: Direction,
+        (sport, dport): (u16, u16),
+        bytes: &[u8],
+        timestamp_ns: u64,
+    ) -> Vec<Exchange> {
+        tracker.on_data(
+            KEY,
+            direction,
+            sport,
+            dport,
+            Payload::new(bytes, bytes.len() as u64),
+            timestamp_ns,
+        )
+    }
+
+    // MySQL COM_QUERY
+    fn mysql_query(sql: &str) -> Vec<u8> {
+        let mut out = ((sql.len() + 1) as u32).to_le_bytes()[..3].to_vec();
+        out.extend_from_slice(&[0, 0x03]);
+        out.extend_from_slice(sql.as_bytes());
+        out
+    }
+
+    const MYSQL_OK: &[u8] = &[7, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0];
+
+    #[test]
+    fn port_hint_parsing() {
+        let hint: PortHint = "6380=redis".parse().unwrap();
+        assert_eq!(hint.port, 6380);
+        assert_eq!(hint.protocol, Protocol::Redis);
+        assert_eq!(hint.to_string(), "6380=redis");
+        assert!("6380".parse::<PortHint>().is_err());
+        assert!("x=redis".parse::<PortHint>().is_err());
+        assert!("6380=smtp".parse::<PortHint>().is_err());
+    }
+
+    #[test]
+    fn infers_protocol_from_content_on_any_port() {
+        let mut tracker = L7Tracker::new(DecoderRegistry::with_builtin());
+        // 非标准端口上的 MySQL 客户端
+        let q = mysql_query("SELECT 1");
+        assert!(data(&mut tracker, Direction::Tx, (40000, 3307), &q, 1_000).is_empty());
+        let exchanges = data(&mut tracker, Direction::Rx, (40000, 3307), MYSQL_OK, 4_000);
+        assert_eq!(exchanges.len(), 1);
+        let exchange = &exchanges[0];
+        assert_eq!(exchange.protocol, Protocol::Mysql);
+        assert_eq!(exchange.role, Role::Client);
+        assert_eq!(exchange.request.summary, "SELECT 1");
+        assert_eq!((exchange.start_ns, exchange.end_ns), (1_000, 4_000));
+    }
+
+    #[test]
+    fn server_side_sees_request_on_rx() {
+        let mut tracker = L7Tracker::new(DecoderRegistry::with_builtin());
+        let req = b"GET /health HTTP/1.1\r\n\r\n";
+        data(&mut tracker, Direction::Rx, (8080, 51000), req, 10);
+        let resp = b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n";
+        let exchanges = data(&mut tracker, Direction::Tx, (8080, 51000), resp, 20);
+        assert_eq!(exchanges[0].role, Role::Server);
+        assert_eq!(exchanges[0].response.status, "200 OK");
+    }
+
+    #[test]
+    fn port_hint_overrides_content_and_default_ports_are_last_resort() {
+        let mut registry = DecoderRegistry::with_builtin();
+        assert!(registry.add_port_hint("8080=redis".parse().unwrap()));
+        let mut tracker = L7Tracker::new(registry);
+        // 内容像 HTTP，但端口提示指定为 Redis (此时按内联命令解析)
+        data(&mut tracker, Direction::Tx, (40000, 8080), b"PING\r\n", 1);
+        let exchanges = data(&mut tracker, Direction::Rx, (40000, 8080), b"+PONG\r\n", 2);
+        assert_eq!(exchanges[0].protocol, Protocol::Redis);
+
+        // 从回复中间开始观测的连接，内容无法识别，按约定端口兜底
+        let mut tracker = L7Tracker::new(DecoderRegistry::with_builtin());
+        let key = SessionKey { pid: 1, fd: 9 };
+        let mid = Payload::new(b"$3\r\nbar\r\n", 9);
+        assert!(
+            tracker
+                .on_data(key, Direction::Rx, 40000, 6379, mid, 1)
+                .is_empty()
+        );
+        let get = b"*2\r\n$3\r\nGET\r\n$3\r\nfoo\r\n";
+        tracker.on_data(key, Direction::Tx, 40000, 6379, Payload::new(get, 22), 2);
+        let exchanges = tracker.on_data(
+            key,
+            Direction::Rx,
+            40000,
+            6379,
+            Payload::new(b"$-1\r\n", 5),
+            3,
+        );
+        assert_eq!(exchanges[0].protocol, Protocol::Redis);
+        assert_eq!(exchanges[0].response.status, "NIL");
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts() {
+        let mut tracker = L7Tracker::new(DecoderRegistry::with_builtin());
+        for _ in 0..MAX_INFER_ATTEMPTS {
+            data(
+                &mut tracker,
+                Direction::Tx,
+                (40000, 12345),
+                b"\x16\x03\x01 tls",
+                1,
+            );
+        }
+        // 之后即使出现可识别的内容也不再推断
+        let req = b"GET / HTTP/1.1\r\n\r\n";
+        data(&mut tracker, Direction::Tx, (40000, 12345), req, 2);
+        assert!(tracker.sessions[&KEY].binding.is_none());
+    }
+
+    #[test]
+    fn pipelined_responses_pair_in_order_by_id() {
+        let mut tracker = L7Tracker::new(DecoderRegistry::with_builtin());
+        let write = b"*2\r\n$3\r\nGET\r\n$1\r\na\r\n*2\r\n$3\r\nGET\r\n$1\r\nb\r\n";
+        data(&mut tracker, Direction::Tx, (40000, 6379), write, 100);
+        let exchanges = data(
+            &mut tracker,
+            Direction::Rx,
+            (40000, 6379),
+            b"$1\r\n1\r\n$-1\r\n",
+            300,
+        );
+        let pairs: Vec<_> = exchanges
+            .iter()
+            .map(|e| (e.request.summary.as_str(), e.response.status.as_str()))
+            .collect();
+        assert_eq!(pairs, [("GET a", "OK"), ("GET b", "NIL")]);
+        // 一次系统调用的字节数平摊到其中的每条消息上，而不是每条都记一遍
+        let req_bytes: Vec<_> = exchanges.iter().map(|e| e.req_bytes).collect();
+        assert_eq!(req_bytes, [20, 20]);
+        let resp_bytes: Vec<_> = exchanges.iter().map(|e| e.resp_bytes).collect();
+        assert_eq!(resp_bytes, [6, 6]);
+    }
+
+    #[test]
+    fn pending_limit_follows_decoder() {
+        // HTTP/2 连接上 100 个并发流，超过默认的 MAX_PENDING，每个流仍能配对
+        let frame = |kind: u8, flags: u8, stream: u32, payload: &[u8]| {
+            let mut out = (payload.len() as u32).to_be_bytes()[1..].to_vec();
+            out.extend_from_slice(&[kind, flags]);
+            out.extend_from_slice(&stream.to_be_bytes());
+            out.extend_from_slice(payload);
+            out
+        };
+        let streams: Vec<u32> = (0..100).map(|i| 2 * i + 1).collect();
+        let mut write = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n".to_vec();
+        let mut read = Vec::new();
+        for &stream in &streams {
+            // HEADERS (END_STREAM | END_HEADERS): :method GET + :scheme http + :path /
+            write.extend(frame(0x1, 0x5, stream, &[0x82, 0x86, 0x84]));
+            // :status 200
+            read.extend(frame(0x1, 0x5, stream, &[0x88]));
+        }
+        let mut tracker = L7Tracker::new(DecoderRegistry::with_builtin());
+        data(&mut tracker, Direction::Tx, (40000, 8080), &write, 1);
+        assert_eq!(tracker.sessions[&KEY].pending.len(), streams.len());
+        let exchanges = data(&mut tracker, Direction::Rx, (40000, 8080), &read, 2);
+        assert_eq!(exchanges.len(), streams.len());
+        assert!(exchanges.iter().all(|e| e.request.id == e.response.id));
+        let total: u64 = exchanges.iter().map(|e| e.req_bytes).sum();
+        assert_eq!(total, write.len() as u64);
+    }
+
+    #[test]
+    fn non_pipelined_request_replaces_unanswered_one() {
+        let mut tracker = L7Tracker::new(DecoderRegistry::with_builtin());
+        data(
+            &mut tracker,
+            Direction::Tx,
+            (40000, 3306),
+            &mysql_query("SELECT 1"),
+            1,
+        );
+        // 漏掉了第一个命令的响应，第二个命令不应与之错位
+        data(
+            &mut tracker,
+            Direction::Tx,
+            (40000, 3306),
+            &mysql_query("SELECT 2"),
+            2,
+        );
+        let exchanges = data(&mut tracker, Direction::Rx, (40000, 3306), MYSQL_OK, 3);
+        assert_eq!(exchanges.len(), 1);
+        assert_eq!(exchanges[0].request.summary, "SELECT 2");
+    }
+
+    #[test]
+    fn close_drops_binding() {
+        let mut tracker = L7Tracker::new(DecoderRegistry::with_builtin());
+        data(
+            &mut tracker,
+            Direction::Tx,
+            (40000, 3306),
+            &mysql_query("SELECT 1"),
+            1,
+        );
+        tracker.close(&KEY);
+        // FD 复用后重新推断
+        let req = b"GET / HTTP/1.1\r\n\r\n";
+        data(&mut tracker, Direction::Tx, (40001, 80), req, 2);
+        let resp = b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n";
+        let exchanges = data(&mut tracker, Direction::Rx, (40001, 80), resp, 3);
+        assert_eq!(exchanges[0].protocol, Protocol::Http);
+    }
+
+    #[test]
+    fn evict_idle_keeps_active_sessions() {
+        let mut tracker = L7Tracker::new(DecoderRegistry::with_builtin());
+        let idle = SessionKey { pid: 100, fd: 8 };
+        let req = b"GET / HTTP/1.1\r\n\r\n";
+        tracker.on_data(
+            idle,
+            Direction::Tx,
+            40002,
+            80,
+            Payload::new(req, req.len() as u64),
+            1_000,
+        );
+        data(&mut tracker, Direction::Tx, (40001, 80), req, 5_000);
+
+        assert_eq!(tracker.evict_idle(5_000), 1);
+        assert!(!tracker.sessions.contains_key(&idle));
+        // 仍活跃的连接保留绑定和待响应请求，响应照常配对
+        let resp = b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n";
+        let exchanges = data(&mut tracker, Direction::Rx, (40001, 80), resp, 6_000);
+        assert_eq!(exchanges.len(), 1);
+        assert_eq!(exchanges[0].start_ns, 5_000);
+        assert_eq!(tracker.evict_idle(5_000), 0);
+    }
+}
@@ -0,0 +1,816 @@
+// MySQL (Binary) 协议
+// 包格式: Header(3 字节长度 + 1 字节序号 seq) + Payload
+//
+// 一个命令周期: 客户端发出 seq=0 的命令包，服务端返回 OK / ERR / 结果集 (多个包，可能跨多次 read)。
+// 两个方向各自按包头切分 (PacketReader)；响应方向再用 Phase 跟踪结果集的 列定义 -> 行 -> 结束包，
+// 结果集结束时才输出响应，所以 SELECT 的耗时覆盖到最后一行。
+// 连接上还记录了握手信息 (服务端版本、连接 ID、登录用户、当前库) 和预编译语句 ID -> SQL 的映射。
+// MySQL 不允许在响应结束前发出下一个命令，所以每个新命令都让响应方向从包边界重新开始。
+
+use std::{borrow::Cow, collections::HashMap};
+
+use super::{Attributes, MessageKind, Payload, ProtocolDecoder, Request, Response};
+use crate::record::Protocol;
+
+const COM_QUIT: u8 = 0x01;
+const COM_INIT_DB: u8 = 0x02;
+const COM_QUERY: u8 = 0x03;
+const COM_PING: u8 = 0x0e;
+const COM_STMT_PREPARE: u8 = 0x16;
+const COM_STMT_EXECUTE: u8 = 0x17;
+const COM_STMT_CLOSE: u8 = 0x19;
+const COM_STMT_RESET: u8 = 0x1a;
+const OK_PACKET: u8 = 0x00;
+const AUTH_MORE_DATA: u8 = 0x01;
+const HANDSHAKE_V10: u8 = 0x0a;
+const LOCAL_INFILE: u8 = 0xfb;
+const EOF_PACKET: u8 = 0xfe; // 也是 CLIENT_DEPRECATE_EOF 下结果集结束的 OK 包、AuthSwitchRequest 的首字节
+const ERR_PACKET: u8 = 0xFF;
+
+// Capability flags
+const CLIENT_CONNECT_WITH_DB: u32 = 0x0000_0008;
+const CLIENT_PROTOCOL_41: u32 = 0x0000_0200;
+const CLIENT_SSL: u32 = 0x0000_0800;
+const CLIENT_SECURE_CONNECTION: u32 = 0x0000_8000;
+const CLIENT_PLUGIN_AUTH_LENENC_CLIENT_DATA: u32 = 0x0020_0000;
+const CLIENT_QUERY_ATTRIBUTES: u32 = 0x0800_0000;
+
+// OK / EOF 包中的状态位: 后面还有结果集 (存储过程、多语句)
+const SERVER_MORE_RESULTS_EXISTS: u16 = 0x0008;
+
+const MAX_PACKET_LEN: usize = 0xff_ffff;
+// 跨系统调用缓存的单个包上限，更大的包 (大字段的行) 只看已拷贝的前缀
+const MAX_BUFFERED: usize = 16 * 1024;
+// 单个连接上记住的预编译语句数上限
+const MAX_STATEMENTS: usize = 1024;
+
+// 包头中的 3 字节小端长度与实际载荷一致 (载荷可能被 eBPF 截断，只要求不小于已拷贝的部分)
+fn header_matches(payload: &[u8]) -> bool {
+    if payload.len() < 5 {
+        return false;
+    }
+    let len = u32::from_le_bytes([payload[0], payload[1], payload[2], 0]) as usize;
+    len > 0 && payload.len() - 4 <= len
+}
+
+// Length-Encoded Integer，返回 (值, 占用字节数)；0xfb (NULL) 和 0xff 不是整数
+fn lenenc(data: &[u8]) -> Option<(u64, usize)> {
+    let le = |n: usize| -> Option<u64> {
+        let bytes = data.get(1..=n)?;
+        Some(
+            bytes
+                .iter()
+                .rev()
+                .fold(0, |acc, b| (acc << 8) | u64::from(*b)),
+        )
+    };
+    match *data.first()? {
+        x @ 0..=0xfa => Some((u64::from(x), 1)),
+        0xfc => Some((le(2)?, 3)),
+        0xfd => Some((le(3)?, 4)),
+        0xfe => Some((le(8)?, 9)),
+        _ => None,
+    }
+}
+
+fn u16_le(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn u32_le(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+// 以 \0 结尾的字符串，返回 (字符串, 含 \0 的长度)
+fn null_str(data: &[u8]) -> Option<(String, usize)> {
+    let end = data.iter().position(|&b| b == 0)?;
+    Some((String::from_utf8_lossy(&data[..end]).into_owned(), end + 1))
+}
+
+// OK 包: header(1) + affected_rows + last_insert_id + status_flags(2) + ...，返回 (affected_rows, status_flags)
+fn ok_fields(body: &[u8]) -> (Option<u64>, u16) {
+    let Some((affected, n)) = body.get(1..).and_then(lenenc) else {
+        return (None, 0);
+    };
+    let status = body
+        .get(1 + n..)
+        .and_then(lenenc)
+        .and_then(|(_, m)| u16_le(body, 1 + n + m));
+    (Some(affected), status.unwrap_or(0))
+}
+
+struct Packet<'a> {
+    seq: u8,
+    len: usize,
+    body: &'a [u8], // 已拷贝的部分
+    complete: bool,
+}
+
+impl Packet<'_> {
+    // 经典 EOF 包: 0xfe + warnings(2) + status(2)，长度小于 OK 包的最小长度 7
+    fn is_eof(&self) -> bool {
+        self.body.first() == Some(&EOF_PACKET) && self.len < 7
+    }
+
+    fn text(&self, from: usize) -> String {
+        let mut text = String::from_utf8_lossy(self.body.get(from..).unwrap_or_default())
+            .trim_matches('\0')
+            .to_string();
+        if !self.complete {
+            text.push_str("...");
+        }
+        text
+    }
+}
+
+// 一个方向的包切分状态
+#[derive(Default)]
+struct PacketReader {
+    partial: Vec<u8>, // 跨系统调用的不完整包
+    skip: u64,        // 当前包尚未到达的字节数
+    lost: bool,       // 包头落在未拷贝的部分，包边界丢失
+}
+
+impl PacketReader {
+    fn feed(&mut self, payload: &Payload, mut on_packet: impl FnMut(Packet)) {
+        if self.lost {
+            return;
+        }
+        let mut data: &[u8] = payload;
+        let mut total = payload.total_len().max(data.len() as u64);
+        if self.skip > 0 {
+            let n = self.skip.min(total);
+            self.skip -= n;
+            total -= n;
+            data = data.get(n as usize..).unwrap_or_default();
+        }
+
+        let input: Cow<[u8]> = if self.partial.is_empty() {
+            Cow::Borrowed(data)
+        } else {
+            let mut buf = std::mem::take(&mut self.partial);
+            buf.extend_from_slice(data);
+            Cow::Owned(buf)
+        };
+        let visible = input.len() as u64;
+        let end = visible - data.len() as u64 + total;
+        let mut pos = 0usize;
+        while (pos as u64) < end {
+            let Some(header) = input.get(pos..pos + 4) else {
+                if visible == end {
+                    self.partial = input[pos..].to_vec();
+                } else {
+                    self.lost = true;
+                }
+                return;
+            };
+            let len = u32::from_le_bytes([header[0], header[1], header[2], 0]) as usize;
+            let seq = header[3];
+            let body = pos + 4;
+            let packet_end = (body + len) as u64;
+            if packet_end <= visible {
+                on_packet(Packet {
+                    seq,
+                    len,
+                    body: &input[body..packet_end as usize],
+                    complete: true,
+                });
+                pos = packet_end as usize;
+                continue;
+            }
+            if visible == end && len <= MAX_BUFFERED {
+                self.partial = input[pos..].to_vec();
+                return;
+            }
+            on_packet(Packet {
+                seq,
+                len,
+                body: &input[body.min(input.len())..],
+                complete: false,
+            });
+            if packet_end >= end {
+                self.skip = packet_end - end;
+            } else {
+                self.lost = true;
+            }
+            return;
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = PacketReader::default();
+    }
+}
+
+// 等待响应的命令
+enum Command {
+    Login,
+    Query,
+    Prepare(String),
+    Execute,
+    InitDb(String),
+    Simple, // PING / STMT_RESET: 只回 OK / ERR
+}
+
+// 响应方向当前处于结果集的哪一段
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    #[default]
+    Idle, // 下一个包是响应的第一个包 (OK / ERR / 列数)
+    Columns(u64), // 剩余的列定义包
+    ColumnsEof,   // 列定义之后的 EOF (未启用 CLIENT_DEPRECATE_EOF 时才有)
+    Rows,         // 行数据，直到 EOF / OK / ERR
+    Defs(u64),    // COM_STMT_PREPARE 响应之后的参数、列定义 (及 EOF)，不需要解析
+}
+
+#[derive(Default)]
+struct Connection {
+    server_version: Option<String>,
+    connection_id: Option<u32>,
+    capabilities: u32, // 客户端登录时声明的能力位
+    database: Option<String>,
+    tls: bool, // SSLRequest 之后是 TLS 密文，不再解析
+    statements: HashMap<u32, String>,
+    command: Option<Command>,
+    phase: Phase,
+    rows: u64,         // 当前命令返回的行数 (多结果集累计)
+    result_sets: bool, // 当前命令是否返回过结果集
+}
+
+impl Connection {
+    fn on_handshake(&mut self, body: &[u8]) {
+        // protocol(1) + server_version (NUL 结尾) + connection_id(4) + ...
+        if let Some((version, n)) = null_str(&body[1..]) {
+            self.connection_id = u32_le(body, 1 + n);
+            self.server_version = Some(version);
+        }
+    }
+
+    fn on_request(&mut self, packet: &Packet) -> Option<Request> {
+        if self.tls {
+            return None;
+        }
+        let body = packet.body;
+        if packet.seq == 1 {
+            return self.on_login(body);
+        }
+        // 其他 seq != 0 的包是认证的后续交互或 LOAD DATA 的文件内容
+        if packet.seq != 0 {
+            return None;
+        }
+        let (command, mut request) = match *body.first()? {
+            COM_QUERY => {
+                // CLIENT_QUERY_ATTRIBUTES: SQL 前有参数个数和参数集个数，没有参数时各占 1 字节
+                let from = if self.capabilities & CLIENT_QUERY_ATTRIBUTES != 0 {
+                    match body.get(1..3) {
+                        Some([0, 1]) => 3,
+                        _ => return None,
+                    }
+                } else {
+                    1
+                };
+                (Command::Query, Request::new(packet.text(from)))
+            }
+            COM_STMT_PREPARE => {
+                let sql = packet.text(1);
+                let request = Request::new(format!("PREPARE {}", sql));
+                (Command::Prepare(sql), request)
+            }
+            COM_STMT_EXECUTE => {
+                let id = u32_le(body, 1)?;
+                let mut request = Request::new(match self.statements.get(&id) {
+                    Some(sql) => sql.clone(),
+                    None => format!("EXECUTE stmt#{}", id),
+                });
+                request
+                    .attributes
+                    .push(("db.mysql.statement_id", i64::from(id).into()));
+                (Command::Execute, request)
+            }
+            COM_INIT_DB => {
+                let db = packet.text(1);
+                let request = Request::new(format!("USE {}", db));
+                (Command::InitDb(db), request)
+            }
+            COM_PING => (Command::Simple, Request::new("PING")),
+            COM_STMT_RESET => {
+                let id = u32_le(body, 1)?;
+                (Command::Simple, Request::new(format!("RESET stmt#{}", id)))
+            }
+            // 没有响应的命令
+            COM_STMT_CLOSE => {
+                self.statements.remove(&u32_le(body, 1)?);
+                return None;
+            }
+            // COM_QUIT 等没有响应或不关心的命令
+            _ => return None,
+        };
+        if let Some(db) = &self.database {
+            request
+                .attributes
+                .push(("db.namespace", db.as_str().into()));
+        }
+        self.start(command);
+        Some(request)
+    }
+
+    // HandshakeResponse41: capability(4) + max_packet(4) + charset(1) + 保留(23) + user (NUL 结尾)
+    //                      + auth_response + [database (NUL 结尾)]
+    fn on_login(&mut self, body: &[u8]) -> Option<Request> {
+        let capabilities = u32_le(body, 0)?;
+        if capabilities & CLIENT_PROTOCOL_41 == 0 || body.get(9..32)?.iter().any(|&b| b != 0) {
+            return None;
+        }
+        // SSLRequest 只有前 32 字节，之后切换到 TLS
+        if body.len() == 32 && capabilities & CLIENT_SSL != 0 {
+            self.tls = true;
+            return None;
+        }
+        self.capabilities = capabilities;
+        let (user, n) = null_str(&body[32..])?;
+        let mut pos = 32 + n;
+        let auth_len = if capabilities & CLIENT_PLUGIN_AUTH_LENENC_CLIENT_DATA != 0 {
+            lenenc(&body[pos..]).map(|(len, n)| len as usize + n)
+        } else if capabilities & CLIENT_SECURE_CONNECTION != 0 {
+            body.get(pos).map(|&len| len as usize + 1)
+        } else {
+            null_str(&body[pos..]).map(|(_, n)| n)
+        };
+        pos += auth_len.unwrap_or(body.len());
+        if capabilities & CLIENT_CONNECT_WITH_DB != 0
+            && let Some((db, _)) = body.get(pos..).and_then(null_str)
+            && !db.is_empty()
+        {
+            self.database = Some(db);
+        }
+
+        let mut request = Request::new(format!("LOGIN {}", user));
+        request.attributes.push(("db.user", user.as_str().into()));
+        if let Some(db) = &self.database {
+            request
+                .attributes
+                .push(("db.namespace", db.as_str().into()));
+        }
+        if let Some(version) = &self.server_version {
+            request
+                .attributes
+                .push(("db.mysql.server_version", version.as_str().into()));
+        }
+        if let Some(id) = self.connection_id {
+            request
+                .attributes
+                .push(("db.mysql.connection_id", i64::from(id).into()));
+        }
+        self.start(Command::Login);
+        Some(request)
+    }
+
+    fn start(&mut self, command: Command) {
+        self.command = Some(command);
+        self.phase = Phase::Idle;
+        self.rows = 0;
+        self.result_sets = false;
+    }
+
+    fn on_response(&mut self, packet: &Packet) -> Option<Response> {
+        if self.tls {
+            return None;
+        }
+        let body = packet.body;
+        let first = *body.first()?;
+        if self.command.is_none() {
+            // 连接建立后服务端先发握手包
+            if packet.seq == 0 && first == HANDSHAKE_V10 && self.server_version.is_none() {
+                self.on_handshake(body);
+            }
+            return None;
+        }
+
+        match self.phase {
+            Phase::Columns(n) => {
+                self.phase = if n > 1 {
+                    Phase::Columns(n - 1)
+                } else {
+                    Phase::ColumnsEof
+                };
+                return None;
+            }
+            Phase::ColumnsEof => {
+                self.phase = Phase::Rows;
+                if packet.is_eof() {
+                    return None;
+                }
+                return self.on_row(packet);
+            }
+            Phase::Rows => return self.on_row(packet),
+            Phase::Defs(n) => {
+                if !packet.is_eof() {
+                    self.phase = Phase::Defs(n.saturating_sub(1));
+                }
+                return None;
+            }
+            Phase::Idle => {}
+        }
+
+        match (first, self.command.as_ref()?) {
+            (ERR_PACKET, _) => Some(self.finish_err(packet)),
+            (OK_PACKET, Command::Prepare(sql)) => {
+                // COM_STMT_PREPARE_OK: 0x00 + statement_id(4) + num_columns(2) + num_params(2) + ...
+                let id = u32_le(body, 1)?;
+                let columns = u16_le(body, 5).unwrap_or(0);
+                let params = u16_le(body, 7).unwrap_or(0);
+                if self.statements.len() >= MAX_STATEMENTS {
+                    self.statements.clear();
+                }
+                self.statements.insert(id, sql.clone());
+                let mut response = self.finish_ok();
+                response
+                    .attributes
+                    .push(("db.mysql.statement_id", i64::from(id).into()));
+                self.phase = Phase::Defs(u64::from(columns) + u64::from(params));
+                Some(response)
+            }
+            (OK_PACKET, Command::InitDb(db)) => {
+                self.database = Some(db.clone());
+                Some(self.finish_ok())
+            }
+            (OK_PACKET, Command::Query | Command::Execute) => {
+                // OK: 0x00 + affected_rows + last_insert_id + status(2) + ...
+                let (affected, status) = ok_fields(body);
+                if status & SERVER_MORE_RESULTS_EXISTS != 0 {
+                    return None;
+                }
+                let mut response = self.finish_ok();
+                if let Some(affected) = affected {
+                    response
+                        .attributes
+                        .push(("db.mysql.affected_rows", (affected as i64).into()));
+                }
+                Some(response)
+            }
+            (OK_PACKET, _) => Some(self.finish_ok()),
+            // 认证方式切换 / 额外认证数据，之后才是 OK / ERR
+            (EOF_PACKET | AUTH_MORE_DATA, Command::Login) => None,
+            // LOAD DATA LOCAL: 客户端随后发送文件内容，服务端最后回 OK / ERR
+            (LOCAL_INFILE, _) => None,
+            (_, Command::Query | Command::Execute) => {
+                // 结果集第一个包是列数
+                let (columns, _) = lenenc(body)?;
+                self.result_sets = true;
+                self.phase = if columns > 0 {
+                    Phase::Columns(columns)
+                } else {
+                    Phase::Rows
+                };
+                None
+            }
+            _ => None,
+        }
+    }
+
+    // 结果集的行，遇到 EOF / OK (0xfe) 或 ERR 结束
+    fn on_row(&mut self, packet: &Packet) -> Option<Response> {
+        match *packet.body.first()? {
+            ERR_PACKET => Some(self.finish_err(packet)),
+            // 0xfe 开头的行只可能是 >= 16MB 的字段，此时包长度为 0xffffff
+            EOF_PACKET if packet.len < MAX_PACKET_LEN => {
+                let status = if packet.is_eof() {
+                    u16_le(packet.body, 3).unwrap_or(0)
+                } else {
+                    ok_fields(packet.body).1
+                };
+                if status & SERVER_MORE_RESULTS_EXISTS != 0 {
+                    self.phase = Phase::Idle;
+                    return None;
+                }
+                Some(self.finish_ok())
+            }
+            _ => {
+                self.rows += 1;
+                None
+            }
+        }
+    }
+
+    fn finish_ok(&mut self) -> Response {
+        let mut response = Response::new("OK");
+        if self.result_sets {
+            response
+                .attributes
+                .push(("db.response.returned_rows", (self.rows as i64).into()));
+        }
+        self.finish();
+        response
+    }
+
+    // ERR: 0xff + error_code(2) + ['#' + sql_state(5)] + message
+    fn finish_err(&mut self, packet: &Packet) -> Response {
+        let body = packet.body;
+        let code = u16_le(body, 1).unwrap_or(0);
+        let mut attributes: Attributes = vec![("db.response.status_code", code.to_string().into())];
+        let status = if body.get(3) == Some(&b'#') && body.len() >= 9 {
+            let state = String::from_utf8_lossy(&body[4..9]).into_owned();
+            attributes.push(("db.mysql.error_message", packet.text(9).into()));
+            let status = format!("ERR {} ({})", code, state);
+            attributes.push(("db.mysql.sqlstate", state.into()));
+            status
+        } else {
+            attributes.push(("db.mysql.error_message", packet.text(3).into()));
+            format!("ERR {}", code)
+        };
+        self.finish();
+        Response {
+            attributes,
+            ..Response::new(status)
+        }
+    }
+
+    fn finish(&mut self) {
+        self.command = None;
+        self.phase = Phase::Idle;
+    }
+}
+
+#[derive(Default)]
+pub struct MysqlState {
+    requests: PacketReader,
+    responses: PacketReader,
+    conn: Connection,
+}
+
+pub struct MysqlDecoder;
+
+impl ProtocolDecoder for MysqlDecoder {
+    type State = MysqlState;
+
+    fn protocol(&self) -> Protocol {
+        Protocol::Mysql
+    }
+
+    fn default_ports(&self) -> &'static [u16] {
+        &[3306]
+    }
+
+    fn detect(&self, payload: &Payload) -> Option<MessageKind> {
+        // 命令周期 / 握手的第一个包 seq 必为 0
+        if !header_matches(payload) || payload[3] != 0 {
+            return None;
+        }
+        match payload[4] {
+            // 服务端握手包: 协议版本 10 + 以 \0 结尾的版本号字符串，如 "8.0.36\0"
+            HANDSHAKE_V10 if payload[5..].contains(&0) => Some(MessageKind::Response),
+            COM_QUIT | COM_INIT_DB | COM_QUERY | COM_PING | COM_STMT_PREPARE | COM_STMT_EXECUTE => {
+                Some(MessageKind::Request)
+            }
+            _ => None,
+        }
+    }
+
+    fn parse_request(&self, state: &mut MysqlState, payload: &Payload) -> Vec<Request> {
+        // 请求方向每次系统调用都从包边界开始
+        if state.requests.lost {
+            state.requests.reset();
+        }
+        let mut requests = Vec::new();
+        let MysqlState {
+            requests: reader,
+            responses,
+            conn,
+        } = state;
+        reader.feed(payload, |packet| {
+            if let Some(request) = conn.on_request(&packet) {
+                // 上一个命令的响应必然已经结束
+                responses.reset();
+                requests.push(request);
+            }
+        });
+        requests
+    }
+
+    fn parse_response(&self, state: &mut MysqlState, payload: &Payload) -> Vec<Response> {
+        let mut responses = Vec::new();
+        let conn = &mut state.conn;
+        state.responses.feed(payload, |packet| {
+            responses.extend(conn.on_response(&packet));
+        });
+        responses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::AttrValue, *};
+
+    fn packet(seq: u8, body: &[u8]) -> Vec<u8> {
+        let len = body.len() as u32;
+        let mut out = len.to_le_bytes()[..3].to_vec();
+        out.push(seq);
+        out.extend_from_slice(body);
+        out
+    }
+
+    fn query(sql: &str) -> Vec<u8> {
+        let mut body = vec![COM_QUERY];
+        body.extend_from_slice(sql.as_bytes());
+        packet(0, &body)
+    }
+
+    const EOF: [u8; 5] = [EOF_PACKET, 0, 0, 0x02, 0];
+
+    // SELECT 的完整结果集: 列数 + 列定义 + EOF + rows 行 + EOF
+    fn result_set(rows: usize) -> Vec<u8> {
+        let mut out = packet(1, &[1]);
+        out.extend(packet(2, b"\x03def\x00\x00\x00\x01a"));
+        out.extend(packet(3, &EOF));
+        for i in 0..rows {
+            out.extend(packet(4 + i as u8, &[1, b'0' + i as u8]));
+        }
+        out.extend(packet(4 + rows as u8, &EOF));
+        out
+    }
+
+    fn requests(state: &mut MysqlState, data: &[u8]) -> Vec<Request> {
+        MysqlDecoder.parse_request(state, &Payload::new(data, data.len() as u64))
+    }
+
+    fn responses(state: &mut MysqlState, data: &[u8]) -> Vec<Response> {
+        MysqlDecoder.parse_response(state, &Payload::new(data, data.len() as u64))
+    }
+
+    fn attr<'a>(attributes: &'a Attributes, key: &str) -> Option<&'a AttrValue> {
+        attributes.iter().find(|(k, _)| *k == key).map(|(_, v)| v)
+    }
+
+    #[test]
+    fn detect_query_and_handshake() {
+        let q = query("SELECT 1");
+        assert_eq!(
+            MysqlDecoder.detect(&Payload::new(&q, q.len() as u64)),
+            Some(MessageKind::Request)
+        );
+        let hs = packet(0, b"\x0a8.0.36\x00\x07\x00\x00\x00");
+        assert_eq!(
+            MysqlDecoder.detect(&Payload::new(&hs, hs.len() as u64)),
+            Some(MessageKind::Response)
+        );
+        assert_eq!(
+            MysqlDecoder.detect(&Payload::new(b"GET / HTTP/1.1\r\n", 16)),
+            None
+        );
+    }
+
+    #[test]
+    fn login_and_query_session() {
+        let mut state = MysqlState::default();
+        assert!(responses(&mut state, &packet(0, b"\x0a8.0.36\x00\x2a\x00\x00\x00")).is_empty());
+
+        let caps = CLIENT_PROTOCOL_41 | CLIENT_SECURE_CONNECTION | CLIENT_CONNECT_WITH_DB;
+        let mut login = caps.to_le_bytes().to_vec();
+        login.extend_from_slice(&[0, 0, 0, 1, 33]);
+        login.extend_from_slice(&[0; 23]);
+        login.extend_from_slice(b"root\x00\x00shop\x00");
+        let reqs = requests(&mut state, &packet(1, &login));
+        assert_eq!(reqs[0].summary, "LOGIN root");
+        assert_eq!(
+            attr(&reqs[0].attributes, "db.mysql.server_version"),
+            Some(&AttrValue::from("8.0.36"))
+        );
+        assert_eq!(
+            attr(&reqs[0].attributes, "db.mysql.connection_id"),
+            Some(&AttrValue::Int(42))
+        );
+        assert_eq!(
+            responses(&mut state, &packet(2, &[0, 0, 0, 2, 0, 0, 0]))[0].status,
+            "OK"
+        );
+
+        let reqs = requests(&mut state, &query("SELECT a FROM t"));
+        assert_eq!(reqs[0].summary, "SELECT a FROM t");
+        assert_eq!(
+            attr(&reqs[0].attributes, "db.namespace"),
+            Some(&AttrValue::from("shop"))
+        );
+        let resps = responses(&mut state, &result_set(3));
+        assert_eq!(resps.len(), 1);
+        assert_eq!(
+            attr(&resps[0].attributes, "db.response.returned_rows"),
+            Some(&AttrValue::Int(3))
+        );
+    }
+
+    #[test]
+    fn error_packet() {
+        let mut state = MysqlState::default();
+        requests(&mut state, &query("SELECT * FROM missing"));
+        let mut err = vec![ERR_PACKET, 0x7a, 0x04, b'#'];
+        err.extend_from_slice(b"42S02Table 'missing' doesn't exist");
+        let resps = responses(&mut state, &packet(1, &err));
+        assert_eq!(resps[0].status, "ERR 1146 (42S02)");
+        assert_eq!(
+            attr(&resps[0].attributes, "db.mysql.error_message"),
+            Some(&AttrValue::from("Table 'missing' doesn't exist"))
+        );
+    }
+
+    #[test]
+    fn response_split_across_reads() {
+        let mut state = MysqlState::default();
+        requests(&mut state, &query("SELECT a FROM t"));
+        let data = result_set(2);
+        // 第一次 read 在列定义包中间结束，剩下的包在第二次 read
+        let (first, second) = data.split_at(9);
+        assert!(responses(&mut state, first).is_empty());
+        let resps = responses(&mut state, second);
+        assert_eq!(resps.len(), 1);
+        assert_eq!(
+            attr(&resps[0].attributes, "db.response.returned_rows"),
+            Some(&AttrValue::Int(2))
+        );
+    }
+
+    #[test]
+    fn truncated_query_and_large_row() {
+        let mut state = MysqlState::default();
+        // 只拷贝到超长 SQL 的前 32 字节
+        let sql = format!("SELECT * FROM t WHERE id IN ({})", "1,".repeat(1000));
+        let full = query(&sql);
+        let reqs =
+            MysqlDecoder.parse_request(&mut state, &Payload::new(&full[..32], full.len() as u64));
+        assert_eq!(reqs.len(), 1);
+        assert!(reqs[0].summary.starts_with("SELECT * FROM t WHERE"));
+        assert!(reqs[0].summary.ends_with("..."));
+
+        // 一行 64KB 的大字段: 第一次 read 只拷贝到行的开头，剩余部分按长度跳过
+        let mut head = packet(1, &[1]);
+        head.extend(packet(2, b"\x03def"));
+        head.extend(packet(3, &EOF));
+        let row_len = 64 * 1024;
+        let first_total = head.len() as u64 + 4 + 32 * 1024;
+        head.extend_from_slice(&(row_len as u32).to_le_bytes()[..3]);
+        head.push(4);
+        head.extend_from_slice(&[0xfc, 0xfd, 0xff]);
+        assert!(
+            MysqlDecoder
+                .parse_response(&mut state, &Payload::new(&head, first_total))
+                .is_empty()
+        );
+
+        // 第二次 read: 行剩余的 32KB + 结束 EOF
+        let mut tail = vec![b'x'; row_len - 32 * 1024];
+        tail.extend(packet(5, &EOF));
+        let resps =
+            MysqlDecoder.parse_response(&mut state, &Payload::new(&tail, tail.len() as u64));
+        assert_eq!(resps.len(), 1);
+        assert_eq!(
+            attr(&resps[0].attributes, "db.response.returned_rows"),
+            Some(&AttrValue::Int(1))
+        );
+    }
+
+    #[test]
+    fn lost_boundary_recovers_on_next_command() {
+        let mut state = MysqlState::default();
+        requests(&mut state, &query("SELECT a FROM t"));
+        // 只拷贝到前 6 字节，之后的包头都落在未拷贝的部分: 响应方向的包边界丢失
+        let data = result_set(3);
+        assert!(
+            MysqlDecoder
+                .parse_response(&mut state, &Payload::new(&data[..6], data.len() as u64))
+                .is_empty()
+        );
+        assert!(state.responses.lost);
+        assert!(responses(&mut state, &packet(9, &EOF)).is_empty());
+
+        // 新命令让响应方向从包边界重新开始
+        requests(&mut state, &query("SELECT a FROM t"));
+        assert!(!state.responses.lost);
+        let resps = responses(&mut state, &result_set(1));
+        assert_eq!(
+            attr(&resps[0].attributes, "db.response.returned_rows"),
+            Some(&AttrValue::Int(1))
+        );
+    }
+
+    #[test]
+    fn prepared_statement_in_one_write() {
+        let mut state = MysqlState::default();
+        let mut prepare = vec![COM_STMT_PREPARE];
+        prepare.extend_from_slice(b"SELECT * FROM t WHERE id = ?");
+        requests(&mut state, &packet(0, &prepare));
+        // PREPARE_OK (1 列 1 参数) + 参数定义 + EOF + 列定义 + EOF 在同一次 read 中
+        let mut resp = packet(1, &[OK_PACKET, 7, 0, 0, 0, 1, 0, 1, 0, 0, 0, 0]);
+        resp.extend(packet(2, b"\x03def"));
+        resp.extend(packet(3, &EOF));
+        resp.extend(packet(4, b"\x03def"));
+        resp.extend(packet(5, &EOF));
+        assert_eq!(responses(&mut state, &resp)[0].status, "OK");
+
+        // 同一次 write 中先关闭一个旧语句，再执行新语句: 只有 EXECUTE 是请求
+        let mut write = packet(0, &[COM_STMT_CLOSE, 3, 0, 0, 0]);
+        write.extend(packet(0, &[COM_STMT_EXECUTE, 7, 0, 0, 0, 0, 1, 0, 0, 0]));
+        let reqs = requests(&mut state, &write);
+        assert_eq!(reqs.len(), 1);
+        assert_eq!(reqs[0].summary, "SELECT * FROM t WHERE id = ?");
+        assert_eq!(responses(&mut state, &result_set(1))[0].status, "OK");
+    }
+}
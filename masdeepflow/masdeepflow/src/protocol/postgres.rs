@@ -0,0 +1,856 @@
+// PostgreSQL (Frontend/Backend v3) 协议
+// 消息格式: Type(1) + Len(4, 含自身) + Body；StartupMessage / SSLRequest / CancelRequest 没有 Type 字节
+//
+// 简单查询: Q -> [RowDescription DataRow* CommandComplete]* -> ReadyForQuery，出错时 ErrorResponse 之后直接 ReadyForQuery。
+// 扩展查询: 驱动 (JDBC / pgx / asyncpg / sqlx) 通常在一次 write 中发出 Parse / Bind / Describe / Execute / Sync，
+// 每个 Execute 是一个请求，在它的 CommandComplete / EmptyQueryResponse / PortalSuspended / ErrorResponse 处结束。
+// Parse 记录 语句名 -> SQL，Bind 记录 portal -> 语句，Execute 由此还原出 SQL (空名字为未命名语句 / portal)。
+// 扩展查询出错后服务端丢弃后续消息直到 Sync，被丢弃的 Execute 没有响应，所以请求带连接内递增的 ID，响应按 ID 配对。
+
+use std::{
+    borrow::Cow,
+    collections::{HashMap, VecDeque},
+};
+
+use super::{Attributes, MessageKind, Payload, ProtocolDecoder, Request, Response};
+use crate::record::Protocol;
+
+// StartupMessage 没有 Type 字节: Len(4) + 协议版本 3.0
+const PROTOCOL_V3: u32 = 0x0003_0000;
+// 客户端在 StartupMessage 前可能先发 SSLRequest / GSSENCRequest，服务端回一个字节 'S' / 'G' (接受) 或 'N'
+const SSL_REQUEST: u32 = 80877103;
+const GSSENC_REQUEST: u32 = 80877104;
+const MAX_STARTUP_LEN: usize = 10000;
+const MAX_QUERY_LEN: u32 = 1 << 24;
+// 跨系统调用缓存的单条消息上限，更大的消息 (大字段的行) 只看已拷贝的前缀
+const MAX_BUFFERED: usize = 16 * 1024;
+// 单个连接上记住的预编译语句 / portal 数上限
+const MAX_STATEMENTS: usize = 1024;
+// 单个连接上等待响应的命令数上限 (批量 Execute)
+const MAX_EXPECTS: usize = 256;
+
+fn be_u32(bytes: &[u8]) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(..4)?.try_into().ok()?))
+}
+
+// 以 \0 结尾的字符串，返回 (字符串, 含 \0 的长度)
+fn c_str(data: &[u8]) -> Option<(String, usize)> {
+    let end = data.iter().position(|&b| b == 0)?;
+    Some((String::from_utf8_lossy(&data[..end]).into_owned(), end + 1))
+}
+
+struct Message<'a> {
+    tag: u8,        // 0 表示没有 Type 字节的启动类消息
+    body: &'a [u8], // 已拷贝的部分
+    complete: bool,
+}
+
+impl Message<'_> {
+    fn text(&self) -> String {
+        let mut text = String::from_utf8_lossy(self.body)
+            .trim_matches('\0')
+            .to_string();
+        if !self.complete {
+            text.push_str("...");
+        }
+        text
+    }
+}
+
+// 一个方向的消息切分状态
+#[derive(Default)]
+struct MessageReader {
+    partial: Vec<u8>, // 跨系统调用的不完整消息
+    skip: u64,        // 当前消息尚未到达的字节数
+    lost: bool,       // 消息头落在未拷贝的部分，消息边界丢失
+}
+
+impl MessageReader {
+    // untyped: 允许没有 Type 字节的启动类消息 (仅客户端方向)。
+    // 它们的长度不超过 MAX_STARTUP_LEN，首字节必为 0，而带 Type 的消息首字节是字母，据此区分。
+    fn feed(&mut self, payload: &Payload, untyped: bool, mut on_message: impl FnMut(Message)) {
+        if self.lost {
+            return;
+        }
+        let mut data: &[u8] = payload;
+        let mut total = payload.total_len().max(data.len() as u64);
+        if self.skip > 0 {
+            let n = self.skip.min(total);
+            self.skip -= n;
+            total -= n;
+            data = data.get(n as usize..).unwrap_or_default();
+        }
+
+        let input: Cow<[u8]> = if self.partial.is_empty() {
+            Cow::Borrowed(data)
+        } else {
+            let mut buf = std::mem::take(&mut self.partial);
+            buf.extend_from_slice(data);
+            Cow::Owned(buf)
+        };
+        let visible = input.len() as u64;
+        let end = visible - data.len() as u64 + total;
+        let mut pos = 0usize;
+        while (pos as u64) < end {
+            let (tag, header) = match input.get(pos) {
+                Some(0) if untyped => (0, 4),
+                Some(&tag) => (tag, 5),
+                None => {
+                    self.lost = true;
+                    return;
+                }
+            };
+            let Some(len) = input.get(pos + header - 4..pos + header).and_then(be_u32) else {
+                if visible == end {
+                    self.partial = input[pos..].to_vec();
+                } else {
+                    self.lost = true;
+                }
+                return;
+            };
+            let Some(len) = (len as usize).checked_sub(4) else {
+                self.lost = true;
+                return;
+            };
+            let body = pos + header;
+            let message_end = (body + len) as u64;
+            if message_end <= visible {
+                on_message(Message {
+                    tag,
+                    body: &input[body..message_end as usize],
+                    complete: true,
+                });
+                pos = message_end as usize;
+                continue;
+            }
+            if visible == end && len <= MAX_BUFFERED {
+                self.partial = input[pos..].to_vec();
+                return;
+            }
+            on_message(Message {
+                tag,
+                body: &input[body.min(input.len())..],
+                complete: false,
+            });
+            if message_end >= end {
+                self.skip = message_end - end;
+            } else {
+                self.lost = true;
+            }
+            return;
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = MessageReader::default();
+    }
+}
+
+// 等待响应的命令，与客户端发出的顺序一致
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Expect {
+    Startup(u64),
+    Query(u64),
+    Execute(u64),
+    Sync, // 等待 ReadyForQuery，不输出响应
+}
+
+#[derive(Default)]
+struct Connection {
+    database: Option<String>,
+    server_version: Option<String>,
+    backend_pid: Option<u32>,
+    ssl_requested: bool, // 已发出 SSLRequest / GSSENCRequest，等待服务端的单字节回复
+    tls: bool,           // 之后是 TLS / GSS 密文，不再解析
+    statements: HashMap<String, String>, // 语句名 -> SQL
+    portals: HashMap<String, (String, Option<String>)>, // portal -> (语句名, SQL)
+    expects: VecDeque<Expect>,
+    next_id: u64,
+    command_tag: Option<String>, // 简单查询中最后一个 CommandComplete
+    rows: u64,                   // 当前 Execute 已返回的行数 (PortalSuspended 时使用)
+}
+
+impl Connection {
+    fn on_request(&mut self, message: &Message) -> Option<Request> {
+        if self.tls {
+            return None;
+        }
+        let body = message.body;
+        let (expect, mut request) = match message.tag {
+            0 => match be_u32(body)? {
+                PROTOCOL_V3 => return self.on_startup(message),
+                SSL_REQUEST | GSSENC_REQUEST => {
+                    self.ssl_requested = true;
+                    return None;
+                }
+                // CancelRequest 走单独的连接，没有响应
+                _ => return None,
+            },
+            // Query: SQL (\0 结尾)
+            b'Q' => (Expect::Query(self.next_id), Request::new(message.text())),
+            // Parse: 语句名 + SQL + 参数类型
+            b'P' => {
+                let (name, n) = c_str(body)?;
+                let sql = match c_str(&body[n..]) {
+                    Some((sql, _)) => sql,
+                    None => Message {
+                        body: &body[n..],
+                        ..*message
+                    }
+                    .text(),
+                };
+                if self.statements.len() >= MAX_STATEMENTS {
+                    self.statements.clear();
+                }
+                self.statements.insert(name, sql);
+                return None;
+            }
+            // Bind: portal + 语句名 + 参数
+            b'B' => {
+                let (portal, n) = c_str(body)?;
+                let (name, _) = c_str(&body[n..])?;
+                let sql = self.statements.get(&name).cloned();
+                if self.portals.len() >= MAX_STATEMENTS {
+                    self.portals.clear();
+                }
+                self.portals.insert(portal, (name, sql));
+                return None;
+            }
+            // Execute: portal + 最大行数
+            b'E' => {
+                let (portal, _) = c_str(body)?;
+                let mut request = match self.portals.get(&portal) {
+                    Some((_, Some(sql))) => Request::new(sql.clone()),
+                    _ if portal.is_empty() => Request::new("EXECUTE"),
+                    _ => Request::new(format!("EXECUTE {}", portal)),
+                };
+                if let Some((name, _)) = self.portals.get(&portal)
+                    && !name.is_empty()
+                {
+                    request
+                        .attributes
+                        .push(("db.postgresql.statement_name", name.as_str().into()));
+                }
+                (Expect::Execute(self.next_id), request)
+            }
+            b'S' => {
+                self.expect(Expect::Sync);
+                return None;
+            }
+            // Close: 'S' (语句) / 'P' (portal) + 名字
+            b'C' => {
+                let (name, _) = c_str(body.get(1..)?)?;
+                match body[0] {
+                    b'S' => {
+                        self.statements.remove(&name);
+                    }
+                    b'P' => {
+                        self.portals.remove(&name);
+                    }
+                    _ => {}
+                }
+                return None;
+            }
+            // Describe / Flush / Terminate / 密码 / COPY 数据等不单独成为请求
+            _ => return None,
+        };
+        if let Some(db) = &self.database {
+            request
+                .attributes
+                .push(("db.namespace", db.as_str().into()));
+        }
+        request.id = Some(self.next_id);
+        self.next_id += 1;
+        self.expect(expect);
+        Some(request)
+    }
+
+    // StartupMessage: 协议版本(4) + (参数名, 参数值)* + \0，database 缺省与 user 相同
+    fn on_startup(&mut self, message: &Message) -> Option<Request> {
+        let mut params = HashMap::new();
+        let mut rest = message.body.get(4..)?;
+        while let Some((key, n)) = c_str(rest)
+            && !key.is_empty()
+            && let Some((value, m)) = c_str(&rest[n..])
+        {
+            params.insert(key, value);
+            rest = &rest[n + m..];
+        }
+        let user = params.remove("user");
+        self.database = params.remove("database").or_else(|| user.clone());
+
+        let mut request = Request::new(match &user {
+            Some(user) => format!("STARTUP {}", user),
+            None => "STARTUP".to_string(),
+        });
+        if let Some(user) = user {
+            request.attributes.push(("db.user", user.into()));
+        }
+        if let Some(db) = &self.database {
+            request
+                .attributes
+                .push(("db.namespace", db.as_str().into()));
+        }
+        if let Some(app) = params.remove("application_name") {
+            request
+                .attributes
+                .push(("db.postgresql.application_name", app.into()));
+        }
+        // 新的会话，之前的命令不会再有响应
+        self.expects.clear();
+        request.id = Some(self.next_id);
+        self.expect(Expect::Startup(self.next_id));
+        self.next_id += 1;
+        Some(request)
+    }
+
+    fn expect(&mut self, expect: Expect) {
+        if self.expects.len() >= MAX_EXPECTS {
+            self.expects.pop_front();
+        }
+        self.expects.push_back(expect);
+    }
+
+    fn on_response(&mut self, message: &Message) -> Option<Response> {
+        if self.tls {
+            return None;
+        }
+        let body = message.body;
+        let expect = *self.expects.front()?;
+        match (message.tag, expect) {
+            // ErrorResponse: 整个命令失败 (简单查询 / 启动) 或当前 Execute 失败。
+            // 简单查询之后还有 ReadyForQuery 要等，扩展查询则由客户端的 Sync 负责
+            (b'E', Expect::Startup(id) | Expect::Query(id) | Expect::Execute(id)) => {
+                if matches!(expect, Expect::Execute(_)) {
+                    self.expects.pop_front();
+                } else {
+                    self.expects[0] = Expect::Sync;
+                }
+                let mut response = error_response(message);
+                response.id = Some(id);
+                Some(self.finish(response))
+            }
+            // ReadyForQuery: 之前的命令都已结束，其中没有响应的 Execute 是出错后被服务端丢弃的
+            (b'Z', _) => {
+                while let Some(expect) = self.expects.pop_front() {
+                    match expect {
+                        Expect::Startup(id) => {
+                            let mut response = Response::new("OK");
+                            if let Some(version) = &self.server_version {
+                                response.attributes.push((
+                                    "db.postgresql.server_version",
+                                    version.as_str().into(),
+                                ));
+                            }
+                            if let Some(pid) = self.backend_pid {
+                                response
+                                    .attributes
+                                    .push(("db.postgresql.backend_pid", i64::from(pid).into()));
+                            }
+                            response.id = Some(id);
+                            return Some(self.finish(response));
+                        }
+                        Expect::Query(id) => {
+                            let mut response = match self.command_tag.take() {
+                                Some(tag) => tag_response(&tag),
+                                None => Response::new("OK"),
+                            };
+                            response.id = Some(id);
+                            return Some(self.finish(response));
+                        }
+                        Expect::Sync => break,
+                        Expect::Execute(_) => {}
+                    }
+                }
+                None
+            }
+            // CommandComplete: 简单查询中可能有多条语句，以最后一条为准
+            (b'C', Expect::Query(_)) => {
+                self.command_tag = Some(message.text());
+                None
+            }
+            (b'C', Expect::Execute(id)) => {
+                self.expects.pop_front();
+                let mut response = tag_response(&message.text());
+                response.id = Some(id);
+                Some(self.finish(response))
+            }
+            // EmptyQueryResponse: 空 SQL
+            (b'I', Expect::Execute(id)) => {
+                self.expects.pop_front();
+                let mut response = Response::new("OK");
+                response.id = Some(id);
+                Some(self.finish(response))
+            }
+            // PortalSuspended: Execute 的最大行数已满，portal 仍可继续 Execute
+            (b's', Expect::Execute(id)) => {
+                self.expects.pop_front();
+                let mut response = Response::new("SUSPENDED");
+                response
+                    .attributes
+                    .push(("db.response.returned_rows", (self.rows as i64).into()));
+                response.id = Some(id);
+                Some(self.finish(response))
+            }
+            (b'D', _) => {
+                self.rows += 1;
+                None
+            }
+            // ParameterStatus: 参数名 + 值
+            (b'S', Expect::Startup(_)) => {
+                let (name, n) = c_str(body)?;
+                if name == "server_version" {
+                    self.server_version = c_str(&body[n..]).map(|(version, _)| version);
+                }
+                None
+            }
+            // BackendKeyData: 进程 ID(4) + 密钥
+            (b'K', Expect::Startup(_)) => {
+                self.backend_pid = be_u32(body);
+                None
+            }
+            // Authentication / RowDescription / ParseComplete / BindComplete / NoticeResponse 等
+            _ => None,
+        }
+    }
+
+    fn finish(&mut self, response: Response) -> Response {
+        self.command_tag = None;
+        self.rows = 0;
+        response
+    }
+}
+
+// CommandComplete 的命令标签，如 "SELECT 3"、"INSERT 0 1"、"UPDATE 2"、"CREATE TABLE"，最后一个数字是行数
+fn tag_response(tag: &str) -> Response {
+    let mut response = Response::new("OK");
+    let mut words = tag.split_whitespace();
+    let command = words.next().unwrap_or_default();
+    if let Some(rows) = words.next_back().and_then(|n| n.parse::<i64>().ok()) {
+        let key = match command {
+            "SELECT" | "FETCH" | "MOVE" => "db.response.returned_rows",
+            _ => "db.postgresql.affected_rows",
+        };
+        response.attributes.push((key, rows.into()));
+    }
+    response
+}
+
+// ErrorResponse: (字段类型(1) + 值 (\0 结尾))* + \0
+// S: 严重级别 (可能被本地化)，V: 未本地化的严重级别 (9.6+)，C: SQLSTATE，M: 错误信息
+fn error_response(message: &Message) -> Response {
+    let mut fields = HashMap::new();
+    let mut rest = message.body;
+    while let Some((&kind, tail)) = rest.split_first()
+        && kind != 0
+        && let Some((value, n)) = c_str(tail)
+    {
+        fields.insert(kind, value);
+        rest = &tail[n..];
+    }
+    let severity = fields
+        .remove(&b'V')
+        .or_else(|| fields.remove(&b'S'))
+        .unwrap_or_else(|| "ERROR".to_string());
+    let mut attributes: Attributes = Vec::new();
+    let status = match fields.remove(&b'C') {
+        Some(code) => {
+            let status = format!("{} {}", severity, code);
+            attributes.push(("db.response.status_code", code.into()));
+            status
+        }
+        None => severity.clone(),
+    };
+    attributes.push(("db.postgresql.severity", severity.into()));
+    if let Some(text) = fields.remove(&b'M') {
+        attributes.push(("db.postgresql.error_message", text.into()));
+    }
+    Response {
+        attributes,
+        ..Response::new(status)
+    }
+}
+
+#[derive(Default)]
+pub struct PostgresState {
+    requests: MessageReader,
+    responses: MessageReader,
+    conn: Connection,
+}
+
+pub struct PostgresDecoder;
+
+impl ProtocolDecoder for PostgresDecoder {
+    type State = PostgresState;
+
+    fn protocol(&self) -> Protocol {
+        Protocol::Postgres
+    }
+
+    fn default_ports(&self) -> &'static [u16] {
+        &[5432]
+    }
+
+    fn pipelined(&self) -> bool {
+        true
+    }
+
+    fn max_pending(&self) -> usize {
+        MAX_EXPECTS
+    }
+
+    fn detect(&self, payload: &Payload) -> Option<MessageKind> {
+        // 载荷可能被 eBPF 截断 (capture.payload_len)，所以 Len 只要求不小于已拷贝的部分
+        // Startup / SSLRequest: Len(4) + 协议版本或请求码
+        if let (Some(len), Some(code)) = (be_u32(payload), payload.get(4..).and_then(be_u32))
+            && (payload.len()..MAX_STARTUP_LEN).contains(&(len as usize))
+            && (code == PROTOCOL_V3 || code == SSL_REQUEST || code == GSSENC_REQUEST)
+        {
+            return Some(MessageKind::Request);
+        }
+        // Simple Query: Q | Len(4)，Len 高位为 0 可以排除以 "Q" 开头的文本协议
+        // Parse: P | Len(4) | 语句名 (\0 结尾) | SQL，扩展查询的第一条消息
+        let len = payload.get(1..).and_then(be_u32)?;
+        if !(5..MAX_QUERY_LEN).contains(&len) {
+            return None;
+        }
+        match payload[0] {
+            b'Q' => Some(MessageKind::Request),
+            b'P' if payload[5..].contains(&0) => Some(MessageKind::Request),
+            _ => None,
+        }
+    }
+
+    fn parse_request(&self, state: &mut PostgresState, payload: &Payload) -> Vec<Request> {
+        // 请求方向每次系统调用都从消息边界开始
+        if state.requests.lost {
+            state.requests.reset();
+        }
+        // 响应方向的边界丢失后 (如超出拷贝长度的大结果集)，等待中的命令都无法再配对，从新的命令重新开始
+        if state.responses.lost {
+            state.responses.reset();
+            state.conn.expects.clear();
+        }
+        let mut requests = Vec::new();
+        let conn = &mut state.conn;
+        state.requests.feed(payload, true, |message| {
+            requests.extend(conn.on_request(&message));
+        });
+        requests
+    }
+
+    fn parse_response(&self, state: &mut PostgresState, payload: &Payload) -> Vec<Response> {
+        let conn = &mut state.conn;
+        let mut input = *payload;
+        // SSLRequest / GSSENCRequest 的回复只有一个字节，没有消息头
+        if conn.ssl_requested
+            && let Some(&answer) = payload.first()
+        {
+            conn.ssl_requested = false;
+            conn.tls = answer != b'N';
+            input = Payload::new(&payload[1..], payload.total_len() - 1);
+        }
+        let mut responses = Vec::new();
+        state.responses.feed(&input, false, |message| {
+            responses.extend(conn.on_response(&message));
+        });
+        responses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::AttrValue, *};
+
+    fn msg(tag: u8, body: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag];
+        out.extend_from_slice(&(body.len() as u32 + 4).to_be_bytes());
+        out.extend_from_slice(body);
+        out
+    }
+
+    fn cstrs(parts: &[&str]) -> Vec<u8> {
+        parts
+            .iter()
+            .flat_map(|p| [p.as_bytes(), b"\0"].concat())
+            .collect()
+    }
+
+    fn query(sql: &str) -> Vec<u8> {
+        msg(b'Q', &cstrs(&[sql]))
+    }
+
+    // Parse + Bind (未命名 portal) + Execute
+    fn extended(statement: &str, sql: &str) -> Vec<u8> {
+        let mut out = msg(b'P', &[cstrs(&[statement, sql]), vec![0, 0]].concat());
+        out.extend(msg(b'B', &[cstrs(&["", statement]), vec![0; 6]].concat()));
+        out.extend(msg(b'E', &[cstrs(&[""]), vec![0; 4]].concat()));
+        out
+    }
+
+    fn ready() -> Vec<u8> {
+        msg(b'Z', b"I")
+    }
+
+    fn requests(state: &mut PostgresState, data: &[u8]) -> Vec<Request> {
+        PostgresDecoder.parse_request(state, &Payload::new(data, data.len() as u64))
+    }
+
+    fn responses(state: &mut PostgresState, data: &[u8]) -> Vec<Response> {
+        PostgresDecoder.parse_response(state, &Payload::new(data, data.len() as u64))
+    }
+
+    fn attr<'a>(response: &'a Response, key: &str) -> Option<&'a AttrValue> {
+        response
+            .attributes
+            .iter()
+            .find(|(k, _)| *k == key)
+            .map(|(_, v)| v)
+    }
+
+    #[test]
+    fn detect_startup_query_and_parse() {
+        let q = query("SELECT 1");
+        assert_eq!(
+            PostgresDecoder.detect(&Payload::new(&q, q.len() as u64)),
+            Some(MessageKind::Request)
+        );
+        let p = extended("", "SELECT 1");
+        assert_eq!(
+            PostgresDecoder.detect(&Payload::new(&p, p.len() as u64)),
+            Some(MessageKind::Request)
+        );
+        let mut ssl = 8u32.to_be_bytes().to_vec();
+        ssl.extend_from_slice(&SSL_REQUEST.to_be_bytes());
+        assert_eq!(
+            PostgresDecoder.detect(&Payload::new(&ssl, 8)),
+            Some(MessageKind::Request)
+        );
+        assert_eq!(PostgresDecoder.detect(&Payload::new(b"QUIT\r\n", 6)), None);
+    }
+
+    #[test]
+    fn startup_after_ssl_refused() {
+        let mut state = PostgresState::default();
+        let mut ssl = 8u32.to_be_bytes().to_vec();
+        ssl.extend_from_slice(&SSL_REQUEST.to_be_bytes());
+        assert!(requests(&mut state, &ssl).is_empty());
+        assert!(responses(&mut state, b"N").is_empty());
+
+        let params = [
+            PROTOCOL_V3.to_be_bytes().to_vec(),
+            cstrs(&["user", "app", "database", "shop", ""]),
+        ]
+        .concat();
+        let mut startup = (params.len() as u32 + 4).to_be_bytes().to_vec();
+        startup.extend(params);
+        let reqs = requests(&mut state, &startup);
+        assert_eq!(reqs[0].summary, "STARTUP app");
+
+        let mut resp = msg(b'R', &[0, 0, 0, 0]);
+        resp.extend(msg(b'S', &cstrs(&["server_version", "16.2"])));
+        resp.extend(msg(b'K', &[0, 0, 0x30, 0x39, 1, 2, 3, 4]));
+        resp.extend(ready());
+        let resps = responses(&mut state, &resp);
+        assert_eq!(resps[0].id, reqs[0].id);
+        assert_eq!(
+            attr(&resps[0], "db.postgresql.server_version"),
+            Some(&AttrValue::from("16.2"))
+        );
+        assert_eq!(
+            attr(&resps[0], "db.postgresql.backend_pid"),
+            Some(&AttrValue::Int(12345))
+        );
+
+        // 之后的请求带上 StartupMessage 中的库名
+        let reqs = requests(&mut state, &query("SELECT 1"));
+        assert!(
+            reqs[0]
+                .attributes
+                .contains(&("db.namespace", AttrValue::from("shop")))
+        );
+    }
+
+    #[test]
+    fn simple_query_multi_statement_and_error() {
+        let mut state = PostgresState::default();
+        requests(
+            &mut state,
+            &query("INSERT INTO t VALUES (1); SELECT * FROM t"),
+        );
+        let mut resp = msg(b'C', &cstrs(&["INSERT 0 1"]));
+        resp.extend(msg(b'T', &[0, 1]));
+        resp.extend(msg(b'D', &[0, 1, 0, 0, 0, 1, b'1']));
+        resp.extend(msg(b'D', &[0, 1, 0, 0, 0, 1, b'2']));
+        resp.extend(msg(b'C', &cstrs(&["SELECT 2"])));
+        resp.extend(ready());
+        let resps = responses(&mut state, &resp);
+        assert_eq!(resps.len(), 1);
+        assert_eq!(
+            attr(&resps[0], "db.response.returned_rows"),
+            Some(&AttrValue::Int(2))
+        );
+
+        requests(&mut state, &query("SELECT * FROM missing"));
+        let mut resp = msg(
+            b'E',
+            &[
+                b"SERROR\0VERROR\0C42P01\0Mrelation \"missing\" does not exist\0".as_slice(),
+                b"\0",
+            ]
+            .concat(),
+        );
+        resp.extend(ready());
+        let resps = responses(&mut state, &resp);
+        assert_eq!(resps.len(), 1);
+        assert_eq!(resps[0].status, "ERROR 42P01");
+    }
+
+    #[test]
+    fn pipelined_executes_in_one_write() {
+        let mut state = PostgresState::default();
+        let mut batch = extended("s1", "SELECT id FROM t");
+        batch.extend(extended("", "UPDATE t SET n = n + 1"));
+        batch.extend(msg(b'S', &[]));
+        let reqs = requests(&mut state, &batch);
+        let summaries: Vec<_> = reqs.iter().map(|r| (r.summary.as_str(), r.id)).collect();
+        assert_eq!(
+            summaries,
+            [
+                ("SELECT id FROM t", Some(0)),
+                ("UPDATE t SET n = n + 1", Some(1))
+            ]
+        );
+
+        let mut resp = msg(b'1', &[]);
+        resp.extend(msg(b'2', &[]));
+        resp.extend(msg(b'D', &[0, 1, 0, 0, 0, 1, b'1']));
+        resp.extend(msg(b'C', &cstrs(&["SELECT 1"])));
+        resp.extend(msg(b'1', &[]));
+        resp.extend(msg(b'2', &[]));
+        resp.extend(msg(b'C', &cstrs(&["UPDATE 3"])));
+        resp.extend(ready());
+        let resps = responses(&mut state, &resp);
+        assert_eq!(resps.len(), 2);
+        assert_eq!(resps[0].id, Some(0));
+        assert_eq!(resps[1].id, Some(1));
+        assert_eq!(
+            attr(&resps[1], "db.postgresql.affected_rows"),
+            Some(&AttrValue::Int(3))
+        );
+    }
+
+    #[test]
+    fn execute_error_discards_rest_of_batch() {
+        let mut state = PostgresState::default();
+        let mut batch = extended("", "SELECT 1/0");
+        batch.extend(extended("", "SELECT 2"));
+        batch.extend(msg(b'S', &[]));
+        requests(&mut state, &batch);
+
+        // 第一个 Execute 出错，服务端丢弃之后的消息直到 Sync，第二个 Execute 没有响应
+        let mut resp = msg(b'1', &[]);
+        resp.extend(msg(b'2', &[]));
+        resp.extend(msg(b'E', b"SERROR\0C22012\0Mdivision by zero\0\0"));
+        resp.extend(ready());
+        let resps = responses(&mut state, &resp);
+        assert_eq!(resps.len(), 1);
+        assert_eq!(
+            (resps[0].status.as_str(), resps[0].id),
+            ("ERROR 22012", Some(0))
+        );
+
+        // 下一批正常配对
+        requests(
+            &mut state,
+            &[extended("", "SELECT 3"), msg(b'S', &[])].concat(),
+        );
+        let resps = responses(
+            &mut state,
+            &[msg(b'C', &cstrs(&["SELECT 1"])), ready()].concat(),
+        );
+        assert_eq!(resps[0].id, Some(2));
+    }
+
+    #[test]
+    fn messages_split_across_reads() {
+        let mut state = PostgresState::default();
+        let q = query("SELECT 1");
+        // 请求在消息头中间被切开
+        assert!(requests(&mut state, &q[..3]).is_empty());
+        assert_eq!(requests(&mut state, &q[3..])[0].summary, "SELECT 1");
+
+        let resp = [
+            msg(b'T', &[0, 1]),
+            msg(b'D', &[0, 1, 0, 0, 0, 1, b'1']),
+            msg(b'C', &cstrs(&["SELECT 1"])),
+            ready(),
+        ]
+        .concat();
+        let (first, second) = resp.split_at(12);
+        assert!(responses(&mut state, first).is_empty());
+        let resps = responses(&mut state, second);
+        assert_eq!(
+            attr(&resps[0], "db.response.returned_rows"),
+            Some(&AttrValue::Int(1))
+        );
+    }
+
+    #[test]
+    fn truncated_sql_and_large_row() {
+        let mut state = PostgresState::default();
+        let sql = format!("SELECT * FROM t WHERE id IN ({})", "1,".repeat(5000));
+        let q = query(&sql);
+        let reqs =
+            PostgresDecoder.parse_request(&mut state, &Payload::new(&q[..40], q.len() as u64));
+        assert!(reqs[0].summary.starts_with("SELECT * FROM t WHERE"));
+        assert!(reqs[0].summary.ends_with("..."));
+
+        // 256KB 的 DataRow 只拷贝到开头，剩余部分在下一次 read 中按长度跳过
+        let row = msg(b'D', &vec![b'x'; 256 * 1024]);
+        let mut first = msg(b'T', &[0, 1]);
+        let first_total = (first.len() + 100 * 1024) as u64;
+        first.extend_from_slice(&row[..64]);
+        assert!(
+            PostgresDecoder
+                .parse_response(&mut state, &Payload::new(&first, first_total))
+                .is_empty()
+        );
+        let mut second = row[100 * 1024..].to_vec();
+        second.extend(msg(b'C', &cstrs(&["SELECT 1"])));
+        second.extend(ready());
+        let resps = responses(&mut state, &second);
+        assert_eq!(resps[0].id, reqs[0].id);
+        assert_eq!(
+            attr(&resps[0], "db.response.returned_rows"),
+            Some(&AttrValue::Int(1))
+        );
+    }
+
+    #[test]
+    fn lost_boundary_recovers_on_next_command() {
+        let mut state = PostgresState::default();
+        requests(&mut state, &query("SELECT * FROM t"));
+        // 拷贝到的只有 RowDescription，之后的消息头都在未拷贝的部分
+        let resp = [
+            msg(b'T', &[0, 1]),
+            msg(b'D', &[0, 1, 0, 0, 0, 1, b'1']),
+            msg(b'C', &cstrs(&["SELECT 1"])),
+            ready(),
+        ]
+        .concat();
+        assert!(
+            PostgresDecoder
+                .parse_response(&mut state, &Payload::new(&resp[..7], resp.len() as u64))
+                .is_empty()
+        );
+        assert!(state.responses.lost);
+        assert!(responses(&mut state, &ready()).is_empty());
+
+        // 下一个命令: 清空等待中的命令，响应方向从消息边界重新开始
+        let reqs = requests(&mut state, &query("SELECT 1"));
+        let resps = responses(&mut state, &resp);
+        assert_eq!(resps.len(), 1);
+        assert_eq!(resps[0].id, reqs[0].id);
+    }
+}
@@ -0,0 +1,592 @@
+// Redis (RESP2 / RESP3) 协议
+// 请求: Array "*" of Bulk String，如 *2\r\n$3\r\nGET\r\n$3\r\nfoo\r\n；也可以是内联命令 "PING\r\n"
+// 响应: + Simple String / - Error / : Integer / $ Bulk String / * Array，
+//       RESP3 另有 _ Null / # Boolean / , Double / ( Big Number / ! Bulk Error / = Verbatim / % Map / ~ Set / > Push / | Attribute
+//
+// 两个方向各自用 RespReader 按类型前缀逐行切分，只跟踪聚合类型的嵌套层数和 Bulk 长度，不缓存整个值，
+// 所以大 Value 超出拷贝长度时可以按长度跳过。每个顶层值只保留前几个直接元素 (命令名、Key、错误信息等)。
+// 客户端可以 pipelining: N 个命令按顺序对应 N 个回复。Pub/Sub 的推送消息、RESP3 Push 和 Attribute 不是回复。
+
+use std::{borrow::Cow, collections::VecDeque};
+
+use super::{Attributes, MessageKind, Payload, ProtocolDecoder, Request, Response};
+use crate::record::Protocol;
+
+// 每个顶层值保留的直接元素个数: 命令名 + Key，或 Pub/Sub 的 (类型, 频道, 订阅数)
+const MAX_HEAD: usize = 3;
+// 保留的单个元素长度上限
+const MAX_ARG_LEN: usize = 128;
+// 跨系统调用缓存的单行上限 (类型前缀行、内联命令)
+const MAX_LINE_LEN: usize = 64 * 1024;
+// 单个连接上等待回复的命令数上限
+const MAX_EXPECTS: usize = 1024;
+
+// 一个完整的顶层值
+struct Frame {
+    kind: u8,          // 类型前缀，内联命令为 0
+    head: Vec<String>, // 标量本身，或聚合类型的前几个直接元素
+    null: bool,        // $-1 / *-1 / _
+}
+
+// 一个方向的 RESP 切分状态
+#[derive(Default)]
+struct RespReader {
+    partial: Vec<u8>,     // 跨系统调用的不完整行
+    bulk: u64,            // 当前 Bulk 尚未读取的字节数 (含结尾 \r\n)
+    arg: Option<Vec<u8>>, // 当前 Bulk 需要保留时的内容
+    stack: Vec<i64>,      // 每层聚合类型剩余的元素个数
+    frame: Option<Frame>, // 正在读取的顶层值
+    lost: bool,           // 行落在未拷贝的部分，边界丢失
+}
+
+impl RespReader {
+    // inline: 允许内联命令 (仅客户端方向)，即顶层不以 '*' 开头的一行
+    fn feed(&mut self, payload: &Payload, inline: bool, mut on_frame: impl FnMut(Frame)) {
+        if self.lost {
+            return;
+        }
+        let data: &[u8] = payload;
+        let total = payload.total_len().max(data.len() as u64);
+        let input: Cow<[u8]> = if self.partial.is_empty() {
+            Cow::Borrowed(data)
+        } else {
+            let mut buf = std::mem::take(&mut self.partial);
+            buf.extend_from_slice(data);
+            Cow::Owned(buf)
+        };
+        let visible = input.len();
+        let end = (visible - data.len()) as u64 + total;
+        let mut pos = 0usize;
+        while (pos as u64) < end {
+            if self.bulk > 0 {
+                let n = self.bulk.min(end - pos as u64) as usize;
+                // 最后两个字节是结尾的 \r\n，不属于内容
+                let content = self.bulk.saturating_sub(2).min(n as u64) as usize;
+                if let Some(arg) = &mut self.arg
+                    && let Some(bytes) = input.get(pos..(pos + content).min(visible))
+                {
+                    let room = MAX_ARG_LEN.saturating_sub(arg.len());
+                    arg.extend_from_slice(&bytes[..bytes.len().min(room)]);
+                }
+                self.bulk -= n as u64;
+                pos += n;
+                if self.bulk > 0 {
+                    return;
+                }
+                let arg = self.arg.take().unwrap_or_default();
+                self.leaf(Some(&arg), false, &mut on_frame);
+                continue;
+            }
+            if pos >= visible {
+                self.lost = true;
+                return;
+            }
+            let Some(len) = input[pos..].windows(2).position(|w| w == b"\r\n") else {
+                if visible as u64 == end && visible - pos <= MAX_LINE_LEN {
+                    self.partial = input[pos..].to_vec();
+                } else {
+                    self.lost = true;
+                }
+                return;
+            };
+            let line = &input[pos..pos + len];
+            pos += len + 2;
+            if !self.line(line, inline, &mut on_frame) {
+                self.lost = true;
+                return;
+            }
+        }
+    }
+
+    // 处理一行类型前缀，格式错误时返回 false
+    fn line(&mut self, line: &[u8], inline: bool, on_frame: &mut impl FnMut(Frame)) -> bool {
+        let Some((&kind, rest)) = line.split_first() else {
+            // 内联命令之间的空行
+            return self.stack.is_empty() && inline;
+        };
+        if self.stack.is_empty() {
+            if inline && kind != b'*' {
+                let head = String::from_utf8_lossy(line)
+                    .split_whitespace()
+                    .take(MAX_HEAD)
+                    .map(str::to_string)
+                    .collect();
+                on_frame(Frame {
+                    kind: 0,
+                    head,
+                    null: false,
+                });
+                return true;
+            }
+            self.frame = Some(Frame {
+                kind,
+                head: Vec::new(),
+                null: false,
+            });
+        }
+        let count = || -> Option<i64> { std::str::from_utf8(rest).ok()?.parse().ok() };
+        match kind {
+            b'$' | b'!' | b'=' => {
+                let Some(len) = count() else {
+                    return false;
+                };
+                if len < 0 {
+                    self.leaf(None, true, on_frame);
+                } else {
+                    self.bulk = len as u64 + 2;
+                    self.arg = self.wants_arg().then(Vec::new);
+                }
+            }
+            b'*' | b'~' | b'>' | b'%' | b'|' => {
+                let Some(n) = count() else {
+                    return false;
+                };
+                // Map / Attribute 的每个条目是 Key + Value 两个元素
+                let n = if matches!(kind, b'%' | b'|') {
+                    n * 2
+                } else {
+                    n
+                };
+                if n > 0 {
+                    self.stack.push(n);
+                } else {
+                    self.leaf(None, n < 0, on_frame);
+                }
+            }
+            b'_' => self.leaf(None, true, on_frame),
+            b'+' | b'-' | b':' | b'#' | b',' | b'(' => {
+                let value = &rest[..rest.len().min(MAX_ARG_LEN)];
+                self.leaf(Some(value), false, on_frame);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    // 顶层标量或顶层聚合的直接元素才需要保留
+    fn wants_arg(&self) -> bool {
+        self.stack.len() <= 1 && self.frame.as_ref().is_some_and(|f| f.head.len() < MAX_HEAD)
+    }
+
+    // 一个元素读完: 记录内容，并逐层结束已经读满的聚合类型
+    fn leaf(&mut self, value: Option<&[u8]>, null: bool, on_frame: &mut impl FnMut(Frame)) {
+        let wants = self.wants_arg();
+        if let Some(frame) = &mut self.frame {
+            if let Some(value) = value
+                && wants
+            {
+                frame.head.push(String::from_utf8_lossy(value).into_owned());
+            }
+            if self.stack.is_empty() {
+                frame.null = null;
+            }
+        }
+        while let Some(remaining) = self.stack.last_mut() {
+            *remaining -= 1;
+            if *remaining > 0 {
+                return;
+            }
+            self.stack.pop();
+        }
+        if let Some(frame) = self.frame.take() {
+            on_frame(frame);
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = RespReader::default();
+    }
+}
+
+// 等待回复的命令
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Plain,
+    PubSub, // (P|S)(UN)SUBSCRIBE: 每个频道一条确认，第一条作为回复
+    Exec,   // EXEC: 回复 Null 表示 WATCH 的 Key 被修改，事务被放弃
+}
+
+const PUBSUB_REPLIES: [&str; 9] = [
+    "subscribe",
+    "psubscribe",
+    "ssubscribe",
+    "unsubscribe",
+    "punsubscribe",
+    "sunsubscribe",
+    "message",
+    "pmessage",
+    "smessage",
+];
+
+#[derive(Default)]
+struct Connection {
+    expects: VecDeque<(u64, Command)>,
+    next_id: u64,
+    subscribed: bool, // RESP2 的订阅模式下，推送消息与普通回复同为 Array
+}
+
+impl Connection {
+    fn on_request(&mut self, frame: Frame) -> Option<Request> {
+        if frame.kind != b'*' && frame.kind != 0 {
+            return None;
+        }
+        let mut args = frame.head.into_iter();
+        let command = args.next()?.to_ascii_uppercase();
+        let kind = match command.as_str() {
+            "SUBSCRIBE" | "PSUBSCRIBE" | "SSUBSCRIBE" => {
+                self.subscribed = true;
+                Command::PubSub
+            }
+            "UNSUBSCRIBE" | "PUNSUBSCRIBE" | "SUNSUBSCRIBE" => Command::PubSub,
+            "EXEC" => Command::Exec,
+            "RESET" => {
+                self.subscribed = false;
+                Command::Plain
+            }
+            _ => Command::Plain,
+        };
+        // 命令名 + 第一个 Key (子命令)；AUTH 的参数是凭据，不输出
+        let mut request = match args.next() {
+            Some(key) if command != "AUTH" => Request::new(format!("{} {}", command, key)),
+            _ => Request::new(command),
+        };
+        request.id = Some(self.next_id);
+        if self.expects.len() >= MAX_EXPECTS {
+            self.expects.pop_front();
+        }
+        self.expects.push_back((self.next_id, kind));
+        self.next_id += 1;
+        Some(request)
+    }
+
+    fn on_reply(&mut self, frame: Frame) -> Option<Response> {
+        // Attribute 是附在下一个回复前的元数据
+        if frame.kind == b'|' {
+            return None;
+        }
+        let pubsub = match frame.kind {
+            b'>' => true,
+            b'*' => self.subscribed,
+            _ => false,
+        } && frame
+            .head
+            .first()
+            .is_some_and(|kind| PUBSUB_REPLIES.contains(&kind.to_ascii_lowercase().as_str()));
+        if pubsub {
+            return self.on_pubsub(&frame);
+        }
+        // 其他 RESP3 Push (如 client tracking 的 invalidate) 不对应任何命令
+        if frame.kind == b'>' {
+            return None;
+        }
+
+        let (id, command) = self.expects.pop_front()?;
+        let mut response = match frame.kind {
+            b'-' | b'!' => error_response(frame.head.first().map_or("", String::as_str)),
+            _ if frame.null && command == Command::Exec => Response::new("ABORTED"),
+            _ if frame.null => Response::new("NIL"),
+            b'+' => Response::new(frame.head.first().map_or("OK", String::as_str)),
+            _ => Response::new("OK"),
+        };
+        response.id = Some(id);
+        Some(response)
+    }
+
+    // 订阅确认 ["subscribe", 频道, 当前订阅数] 作为 (UN)SUBSCRIBE 的回复；推送的消息没有对应的命令
+    fn on_pubsub(&mut self, frame: &Frame) -> Option<Response> {
+        let kind = frame.head[0].to_ascii_lowercase();
+        if !kind.ends_with("subscribe") {
+            return None;
+        }
+        if kind.ends_with("unsubscribe") && frame.head.get(2).is_some_and(|n| n == "0") {
+            self.subscribed = false;
+        }
+        // 一个命令订阅多个频道时有多条确认，只有第一条对应等待中的命令
+        let &(id, Command::PubSub) = self.expects.front()? else {
+            return None;
+        };
+        self.expects.pop_front();
+        let mut response = Response::new("OK");
+        if let Some(channel) = frame.head.get(1) {
+            response
+                .attributes
+                .push(("messaging.destination.name", channel.as_str().into()));
+        }
+        response.id = Some(id);
+        Some(response)
+    }
+}
+
+// 错误回复以大写的错误类型开头，如 "ERR unknown command"、"WRONGTYPE Operation against a key ..."；
+// 集群重定向 "MOVED <slot> <host:port>" / "ASK <slot> <host:port>" 不是服务端错误，单独输出
+fn error_response(message: &str) -> Response {
+    let code = message
+        .split(' ')
+        .next()
+        .filter(|code| !code.is_empty() && code.bytes().all(|b| b.is_ascii_uppercase()))
+        .unwrap_or("ERR");
+    let mut attributes: Attributes = vec![("db.response.status_code", code.into())];
+    let status = match code {
+        "MOVED" | "ASK" => {
+            let mut parts = message.split(' ').skip(1);
+            if let Some(slot) = parts.next().and_then(|slot| slot.parse::<i64>().ok()) {
+                attributes.push(("db.redis.cluster.slot", slot.into()));
+            }
+            if let Some(target) = parts.next() {
+                attributes.push(("db.redis.cluster.redirect", target.into()));
+            }
+            code.to_string()
+        }
+        "ERR" => {
+            attributes.push(("db.redis.error_message", message.into()));
+            "ERR".to_string()
+        }
+        _ => {
+            attributes.push(("db.redis.error_message", message.into()));
+            format!("ERR {}", code)
+        }
+    };
+    Response {
+        attributes,
+        ..Response::new(status)
+    }
+}
+
+#[derive(Default)]
+pub struct RedisState {
+    requests: RespReader,
+    responses: RespReader,
+    conn: Connection,
+}
+
+pub struct RedisDecoder;
+
+impl ProtocolDecoder for RedisDecoder {
+    type State = RedisState;
+
+    fn protocol(&self) -> Protocol {
+        Protocol::Redis
+    }
+
+    fn default_ports(&self) -> &'static [u16] {
+        &[6379]
+    }
+
+    fn pipelined(&self) -> bool {
+        true
+    }
+
+    fn max_pending(&self) -> usize {
+        MAX_EXPECTS
+    }
+
+    fn detect(&self, payload: &Payload) -> Option<MessageKind> {
+        let text = payload.text()?;
+        let (first, rest) = text.split_once("\r\n")?;
+        match first.as_bytes().first()? {
+            // "*<元素个数>\r\n$<长度>\r\n..."
+            b'*' if first[1..].parse::<u32>().is_ok_and(|n| n > 0) && rest.starts_with('$') => {
+                Some(MessageKind::Request)
+            }
+            b'+' | b'-' => Some(MessageKind::Response),
+            _ => None,
+        }
+    }
+
+    fn parse_request(&self, state: &mut RedisState, payload: &Payload) -> Vec<Request> {
+        // 请求方向每次系统调用都从命令边界开始
+        if state.requests.lost {
+            state.requests.reset();
+        }
+        // 回复方向的边界丢失后，等待中的命令都无法再配对，从新的命令重新开始
+        if state.responses.lost {
+            state.responses.reset();
+            state.conn.expects.clear();
+        }
+        let mut requests = Vec::new();
+        let conn = &mut state.conn;
+        state.requests.feed(payload, true, |frame| {
+            requests.extend(conn.on_request(frame));
+        });
+        requests
+    }
+
+    fn parse_response(&self, state: &mut RedisState, payload: &Payload) -> Vec<Response> {
+        let mut responses = Vec::new();
+        let conn = &mut state.conn;
+        state.responses.feed(payload, false, |frame| {
+            responses.extend(conn.on_reply(frame));
+        });
+        responses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{super::AttrValue, *};
+
+    fn requests(state: &mut RedisState, data: &[u8]) -> Vec<Request> {
+        RedisDecoder.parse_request(state, &Payload::new(data, data.len() as u64))
+    }
+
+    fn responses(state: &mut RedisState, data: &[u8]) -> Vec<Response> {
+        RedisDecoder.parse_response(state, &Payload::new(data, data.len() as u64))
+    }
+
+    fn statuses(responses: &[Response]) -> Vec<(&str, Option<u64>)> {
+        responses
+            .iter()
+            .map(|r| (r.status.as_str(), r.id))
+            .collect()
+    }
+
+    #[test]
+    fn detect_array_and_reply() {
+        let get = b"*2\r\n$3\r\nGET\r\n$3\r\nfoo\r\n";
+        assert_eq!(
+            RedisDecoder.detect(&Payload::new(get, get.len() as u64)),
+            Some(MessageKind::Request)
+        );
+        assert_eq!(
+            RedisDecoder.detect(&Payload::new(b"+OK\r\n", 5)),
+            Some(MessageKind::Response)
+        );
+        assert_eq!(
+            RedisDecoder.detect(&Payload::new(b"GET / HTTP/1.1\r\n", 16)),
+            None
+        );
+    }
+
+    #[test]
+    fn pipelined_commands_in_one_write() {
+        let mut state = RedisState::default();
+        let reqs = requests(
+            &mut state,
+            b"*3\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n*2\r\n$3\r\nGET\r\n$1\r\nb\r\nPING\r\n*2\r\n$5\r\nLPUSH\r\n$1\r\na\r\n",
+        );
+        let summaries: Vec<_> = reqs.iter().map(|r| (r.summary.as_str(), r.id)).collect();
+        assert_eq!(
+            summaries,
+            [
+                ("SET a", Some(0)),
+                ("GET b", Some(1)),
+                ("PING", Some(2)),
+                ("LPUSH a", Some(3))
+            ]
+        );
+
+        let resps = responses(
+            &mut state,
+            b"+OK\r\n$-1\r\n+PONG\r\n-WRONGTYPE Operation against a key holding the wrong kind of value\r\n",
+        );
+        assert_eq!(
+            statuses(&resps),
+            [
+                ("OK", Some(0)),
+                ("NIL", Some(1)),
+                ("PONG", Some(2)),
+                ("ERR WRONGTYPE", Some(3))
+            ]
+        );
+    }
+
+    #[test]
+    fn frames_split_across_reads() {
+        let mut state = RedisState::default();
+        // 命令在 Bulk 长度行中间被切开
+        assert!(requests(&mut state, b"*2\r\n$3\r\nGET\r\n$").is_empty());
+        let reqs = requests(&mut state, b"3\r\nfoo\r\n");
+        assert_eq!(reqs[0].summary, "GET foo");
+
+        // 回复在 Bulk 内容中间被切开，第二次 read 还带着下一个回复的前半部分
+        assert!(responses(&mut state, b"$5\r\nhel").is_empty());
+        requests(&mut state, b"*1\r\n$4\r\nPING\r\n");
+        let resps = responses(&mut state, b"lo\r\n+PO");
+        assert_eq!(statuses(&resps), [("OK", Some(0))]);
+        assert_eq!(
+            statuses(&responses(&mut state, b"NG\r\n")),
+            [("PONG", Some(1))]
+        );
+    }
+
+    #[test]
+    fn truncated_large_value_is_skipped() {
+        let mut state = RedisState::default();
+        requests(
+            &mut state,
+            b"*2\r\n$3\r\nGET\r\n$3\r\nbig\r\n*1\r\n$4\r\nPING\r\n",
+        );
+
+        // 1MB 的 Value 只拷贝到开头，按 Bulk 长度跳过剩余部分
+        let value_len = 1 << 20;
+        let header = format!("${}\r\n", value_len);
+        let mut captured = header.clone().into_bytes();
+        captured.extend_from_slice(&[b'v'; 64]);
+        let total = (header.len() + value_len + 2) as u64;
+        let resps = RedisDecoder.parse_response(&mut state, &Payload::new(&captured, total));
+        assert_eq!(statuses(&resps), [("OK", Some(0))]);
+        assert_eq!(
+            statuses(&responses(&mut state, b"+PONG\r\n")),
+            [("PONG", Some(1))]
+        );
+    }
+
+    #[test]
+    fn lost_boundary_recovers_on_next_command() {
+        let mut state = RedisState::default();
+        requests(&mut state, b"*2\r\n$6\r\nLRANGE\r\n$1\r\nl\r\n");
+        // 数组回复只拷贝到前几个元素，后面的类型前缀行落在未拷贝的部分
+        let captured = b"*3\r\n$1\r\na\r\n";
+        assert!(
+            RedisDecoder
+                .parse_response(&mut state, &Payload::new(captured, 40))
+                .is_empty()
+        );
+        assert!(state.responses.lost);
+        assert!(responses(&mut state, b"+OK\r\n").is_empty());
+
+        // 下一个命令到来时丢弃等待中的命令，回复方向从头开始
+        let reqs = requests(&mut state, b"*2\r\n$3\r\nGET\r\n$1\r\nk\r\n");
+        let resps = responses(&mut state, b"$1\r\nv\r\n");
+        assert_eq!(statuses(&resps), [("OK", reqs[0].id)]);
+    }
+
+    #[test]
+    fn transactions_redirects_and_pubsub() {
+        let mut state = RedisState::default();
+        requests(
+            &mut state,
+            b"*1\r\n$5\r\nMULTI\r\n*2\r\n$4\r\nINCR\r\n$1\r\nn\r\n*1\r\n$4\r\nEXEC\r\n",
+        );
+        let resps = responses(&mut state, b"+OK\r\n+QUEUED\r\n*-1\r\n");
+        assert_eq!(
+            statuses(&resps),
+            [("OK", Some(0)), ("QUEUED", Some(1)), ("ABORTED", Some(2))]
+        );
+
+        requests(&mut state, b"*2\r\n$3\r\nGET\r\n$1\r\nk\r\n");
+        let resps = responses(&mut state, b"-MOVED 3999 127.0.0.1:6381\r\n");
+        assert_eq!(resps[0].status, "MOVED");
+        assert!(
+            resps[0]
+                .attributes
+                .contains(&("db.redis.cluster.slot", AttrValue::Int(3999)))
+        );
+
+        // 订阅两个频道: 第一条确认是回复，第二条确认和推送的消息都不是
+        requests(
+            &mut state,
+            b"*3\r\n$9\r\nSUBSCRIBE\r\n$1\r\na\r\n$1\r\nb\r\n",
+        );
+        let resps = responses(
+            &mut state,
+            b"*3\r\n$9\r\nsubscribe\r\n$1\r\na\r\n:1\r\n*3\r\n$9\r\nsubscribe\r\n$1\r\nb\r\n:2\r\n*3\r\n$7\r\nmessage\r\n$1\r\na\r\n$2\r\nhi\r\n",
+        );
+        assert_eq!(resps.len(), 1);
+        assert!(
+            resps[0]
+                .attributes
+                .contains(&("messaging.destination.name", AttrValue::from("a")))
+        );
+    }
+}
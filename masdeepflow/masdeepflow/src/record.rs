@@ -0,0 +1,211 @@
+// [Output] 结构化 L7 记录
+//
+// 每一对匹配成功的 请求/响应 产出一条 L7Record，这是 Agent 对所有下游消费者的数据契约。
+// 连接关闭时另外产出一条 FlowRecord (流量汇总)。
+// --output log  : 以 info! 日志打印 (默认，兼容原来的 grep 习惯)
+// --output json : 每条记录一行 JSON (JSON Lines)，写到 stdout 或 --output-file 指定的文件
+
+use std::{
+    collections::BTreeMap,
+    fs::OpenOptions,
+    io::{self, LineWriter, Write},
+    net::IpAddr,
+    path::Path,
+    sync::Mutex,
+};
+
+use clap::ValueEnum;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::AttrValue;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    Http,
+    Http2,
+    Grpc,
+    Mysql,
+    Redis,
+    Postgres,
+    Kafka,
+}
+
+impl Protocol {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Protocol::Http => "HTTP",
+            Protocol::Http2 => "HTTP2",
+            Protocol::Grpc => "gRPC",
+            Protocol::Mysql => "MySQL",
+            Protocol::Redis => "Redis",
+            Protocol::Postgres => "PG",
+            Protocol::Kafka => "Kafka",
+        }
+    }
+
+    // 小写机器名，与 JSON / 配置文件 / 指标标签中的取值一致
+    pub fn name(&self) -> &'static str {
+        match self {
+            Protocol::Http => "http",
+            Protocol::Http2 => "http2",
+            Protocol::Grpc => "grpc",
+            Protocol::Mysql => "mysql",
+            Protocol::Redis => "redis",
+            Protocol::Postgres => "postgres",
+            Protocol::Kafka => "kafka",
+        }
+    }
+}
+
+// 本端在这次调用中的角色: 请求由本端发出 (TX) 为 client，由本端接收 (RX) 为 server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Client,
+    Server,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct L7Record {
+    pub timestamp_ns: u64, // 请求开始时间 (Unix 时间戳，纳秒)
+    pub pid: u32,
+    pub comm: String,
+    pub pod: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub workload: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peer: Option<String>, // 对端 Pod / Service (需启用 K8s 元数据)
+    pub saddr: IpAddr,
+    pub sport: u16,
+    pub daddr: IpAddr,
+    pub dport: u16,
+    pub protocol: Protocol,
+    pub role: Role,
+    pub request: String,          // 请求摘要，如 "GET /index.html"、"SELECT 1"
+    pub response: Option<String>, // 响应状态，如 "200 OK"、"OK"、"ERR"
+    pub latency_us: Option<u64>,
+    pub req_bytes: u64,
+    pub resp_bytes: u64,
+    // 解码器提取的结构化字段 (请求 + 响应)，如 http.request.method、http.response.status_code
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub attributes: BTreeMap<&'static str, AttrValue>,
+}
+
+// 连接关闭的触发来源，对应内核 FlowEvent.reason
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CloseReason {
+    Close,    // close() 系统调用
+    TcpState, // TCP 状态变为 TCP_CLOSE (RST、进程退出等)
+}
+
+// 连接关闭时输出一条流量汇总。JSON 中带 "type": "flow"，与 L7Record 区分
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename = "flow")]
+pub struct FlowRecord {
+    pub timestamp_ns: u64, // 关闭时间 (Unix 时间戳，纳秒)
+    pub pid: u32,
+    pub comm: String,
+    pub pod: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub workload: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peer: Option<String>,
+    pub saddr: IpAddr,
+    pub sport: u16,
+    pub daddr: IpAddr,
+    pub dport: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<Role>, // connect 为 client，accept 为 server；Agent 启动前的连接未知
+    pub duration_us: u64,
+    pub bytes_sent: u64,
+    pub bytes_recv: u64,
+    pub packets_sent: u64, // 发送系统调用次数
+    pub packets_recv: u64, // 接收系统调用次数
+    pub l7_requests: u64,  // 配对成功的 L7 请求数
+    pub reason: CloseReason,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    Log,
+    Json,
+}
+
+pub struct RecordSink {
+    format: OutputFormat,
+    writer: Mutex<Box<dyn Write + Send>>,
+}
+
+impl RecordSink {
+    // path 为空时写 stdout；文件以追加模式打开，便于外部 tail -f
+    pub fn open(format: OutputFormat, path: Option<&Path>) -> io::Result<Self> {
+        let writer: Box<dyn Write + Send> = match path {
+            Some(path) => Box::new(LineWriter::new(
+                OpenOptions::new().create(true).append(true).open(path)?,
+            )),
+            None => Box::new(io::stdout()),
+        };
+        Ok(RecordSink {
+            format,
+            writer: Mutex::new(writer),
+        })
+    }
+
+    pub fn emit(&self, record: &L7Record) {
+        match self.format {
+            OutputFormat::Log => info!(
+                "[L7] {}, Pod: {}, {}:{} -> {}:{}, Request: {}, Response: {}, Latency: {}",
+                record.protocol.as_str(),
+                record.pod,
+                record.saddr,
+                record.sport,
+                record.daddr,
+                record.dport,
+                record.request,
+                record.response.as_deref().unwrap_or("-"),
+                match record.latency_us {
+                    Some(us) => format!("{:.3}ms", us as f64 / 1000.0),
+                    None => "-".to_string(),
+                }
+            ),
+            OutputFormat::Json => self.write_json(record),
+        }
+    }
+
+    pub fn emit_flow(&self, record: &FlowRecord) {
+        match self.format {
+            OutputFormat::Log => info!(
+                "[FLOW] Pod: {}, {}:{} -> {}:{}, Duration: {:.3}ms, Sent: {}B/{}, Recv: {}B/{}, L7: {}",
+                record.pod,
+                record.saddr,
+                record.sport,
+                record.daddr,
+                record.dport,
+                record.duration_us as f64 / 1000.0,
+                record.bytes_sent,
+                record.packets_sent,
+                record.bytes_recv,
+                record.packets_recv,
+                record.l7_requests
+            ),
+            OutputFormat::Json => self.write_json(record),
+        }
+    }
+
+    fn write_json<T: Serialize>(&self, record: &T) {
+        let Ok(mut writer) = self.writer.lock() else {
+            return;
+        };
+        let result = serde_json::to_writer(&mut *writer, record)
+            .map_err(io::Error::from)
+            .and_then(|_| writer.write_all(b"\n"))
+            .and_then(|_| writer.flush());
+        if let Err(e) = result {
+            warn!("failed to write record: {}", e);
+        }
+    }
+}
@@ -0,0 +1,152 @@
+// [Reorder] 按内核时间戳合并事件流
+//
+// perf 模式下每个 CPU 一个读取任务，同一连接的请求 (CPU 1 上 write) 和响应 (CPU 3 上 read)
+// 到达用户态的先后顺序并不确定；RingBuf 中记录按提交顺序排列，时间戳取在提交之前，也可能有少量乱序。
+// 所有记录先在这里停留 window 时长，按 timestamp_ns 从小到大放行，
+// 只要跨 CPU 的延迟不超过 window，处理顺序就与内核中发生的顺序一致。
+
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use masdeepflow_common::RECORD_TIMESTAMP_OFFSET;
+
+// 缓冲的记录数上限，事件速率极高时不再等满 window，直接放行最早的记录
+const MAX_BUFFERED: usize = 65536;
+
+pub struct ReorderBuffer {
+    window_ns: u64,
+    // (timestamp_ns, 到达序号, 记录)，序号保证时间戳相同的记录保持到达顺序
+    heap: BinaryHeap<Reverse<(u64, u64, Vec<u8>)>>,
+    seq: u64,
+    released_ns: u64, // 已放行的最大时间戳
+    late: u64,        // 超出窗口才到达的记录数
+}
+
+impl ReorderBuffer {
+    pub fn new(window_ns: u64) -> Self {
+        ReorderBuffer {
+            window_ns,
+            heap: BinaryHeap::new(),
+            seq: 0,
+            released_ns: 0,
+            late: 0,
+        }
+    }
+
+    // 放入一条记录；返回 false 表示它来得太晚 (更新的记录已经放行)，顺序无法保证，记入 late。
+    // 迟到的记录不会被丢弃，仍会尽快放行
+    pub fn push(&mut self, record: Vec<u8>) -> bool {
+        let timestamp_ns = record_timestamp(&record);
+        self.seq += 1;
+        self.heap.push(Reverse((timestamp_ns, self.seq, record)));
+        let in_order = timestamp_ns >= self.released_ns;
+        if !in_order {
+            self.late += 1;
+        }
+        in_order
+    }
+
+    pub fn late(&self) -> u64 {
+        self.late
+    }
+
+    // 取出下一条可以处理的记录: 时间戳早于 now_ns - window，或缓冲区已满
+    pub fn pop_ready(&mut self, now_ns: u64) -> Option<Vec<u8>> {
+        let Reverse((timestamp_ns, _, _)) = self.heap.peek()?;
+        if timestamp_ns.saturating_add(self.window_ns) > now_ns && self.heap.len() <= MAX_BUFFERED {
+            return None;
+        }
+        let Reverse((timestamp_ns, _, record)) = self.heap.pop()?;
+        self.released_ns = self.released_ns.max(timestamp_ns);
+        Some(record)
+    }
+}
+
+// 记录过短 (不应出现) 时按 0 处理，立即放行
+fn record_timestamp(record: &[u8]) -> u64 {
+    record
+        .get(RECORD_TIMESTAMP_OFFSET..RECORD_TIMESTAMP_OFFSET + 8)
+        .and_then(|b| b.try_into().ok())
+        .map(u64::from_ne_bytes)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: u64 = 1_000_000;
+
+    // 只填充 kind / pid / timestamp_ns 头部，tag 放在时间戳之后用来区分记录
+    fn record(timestamp_ns: u64, tag: u8) -> Vec<u8> {
+        let mut record = vec![0u8; RECORD_TIMESTAMP_OFFSET];
+        record.extend_from_slice(&timestamp_ns.to_ne_bytes());
+        record.push(tag);
+        record
+    }
+
+    fn drain(buffer: &mut ReorderBuffer, now_ns: u64) -> Vec<(u64, u8)> {
+        std::iter::from_fn(|| buffer.pop_ready(now_ns))
+            .map(|r| (record_timestamp(&r), *r.last().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn releases_in_timestamp_order_after_window() {
+        let mut buffer = ReorderBuffer::new(10 * MS);
+        // 不同 CPU 上的记录交错到达
+        for (ts, tag) in [(105, 1), (101, 2), (110, 3), (103, 4)] {
+            assert!(buffer.push(record(ts * MS, tag)));
+        }
+        // 窗口未过，什么都不放行
+        assert!(drain(&mut buffer, 110 * MS).is_empty());
+        // 只放行时间戳早于 now - window 的记录
+        assert_eq!(
+            drain(&mut buffer, 115 * MS),
+            [(101 * MS, 2), (103 * MS, 4), (105 * MS, 1)]
+        );
+        assert_eq!(drain(&mut buffer, 120 * MS), [(110 * MS, 3)]);
+        assert_eq!(buffer.late(), 0);
+    }
+
+    #[test]
+    fn equal_timestamps_keep_arrival_order() {
+        let mut buffer = ReorderBuffer::new(MS);
+        for tag in 1..=5 {
+            buffer.push(record(7 * MS, tag));
+        }
+        let tags: Vec<u8> = drain(&mut buffer, 10 * MS)
+            .into_iter()
+            .map(|(_, t)| t)
+            .collect();
+        assert_eq!(tags, [1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn late_record_is_reported_and_still_released() {
+        let mut buffer = ReorderBuffer::new(MS);
+        buffer.push(record(50 * MS, 1));
+        assert_eq!(drain(&mut buffer, 60 * MS).len(), 1);
+
+        // 比已放行的记录更早，顺序已无法保证
+        assert!(!buffer.push(record(40 * MS, 2)));
+        assert_eq!(buffer.late(), 1);
+        // 时间戳相同不算迟到
+        assert!(buffer.push(record(50 * MS, 3)));
+        assert_eq!(buffer.late(), 1);
+        assert_eq!(drain(&mut buffer, 60 * MS), [(40 * MS, 2), (50 * MS, 3)]);
+    }
+
+    #[test]
+    fn full_buffer_forces_release() {
+        let mut buffer = ReorderBuffer::new(1_000 * MS);
+        for i in 0..=MAX_BUFFERED as u64 {
+            buffer.push(record(1_000 + i, 0));
+        }
+        // 窗口远未结束，但超出上限的部分按时间顺序被挤出，缓冲区回到上限
+        let now = 1_000;
+        let forced = drain(&mut buffer, now);
+        assert_eq!(forced, [(1_000, 0)]);
+        assert_eq!(buffer.heap.len(), MAX_BUFFERED);
+        assert!(buffer.pop_ready(now).is_none());
+    }
+}
@@ -0,0 +1,273 @@
+// [Transport] 内核 -> 用户态事件通道
+//
+// ringbuf (默认，内核 5.8+): 所有 CPU 共享一个 BPF RingBuf，单个任务按提交顺序读取，
+//   记录全局有序、按实际长度存放，内存占用与 CPU 数无关。
+// perf (降级): 每个 CPU 一个 perf 缓冲区、一个读取任务，同一连接的事件可能跨 CPU 乱序。
+// 两种模式使用不同的 eBPF 对象 (见 build.rs)，auto 按内核版本选择。
+// 读取任务只负责搬运记录，统一交给一个合并任务，经重排序缓冲区 (reorder.rs) 按内核时间戳排序后处理。
+//
+// stats_interval_secs > 0 时周期性打印事件速率、丢失数和 Agent 自身 CPU 占用，
+// 用于对比两种模式在 traffic_gen benchmark-client 压测下的开销。
+// late 为超出重排序窗口才到达、无法保证顺序的事件数，持续增长时应调大 reorder_window_ms。
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::Context as _;
+use aya::{
+    Ebpf,
+    maps::{MapData, PerCpuArray, RingBuf, perf::AsyncPerfEventArray},
+    util::{KernelVersion, online_cpus},
+};
+use bytes::BytesMut;
+use clap::ValueEnum;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::unix::AsyncFd,
+    sync::mpsc,
+    task,
+    time::{MissedTickBehavior, interval},
+};
+
+use crate::{
+    clock,
+    events::{EventHandler, MAX_RECORD_LEN},
+    reorder::ReorderBuffer,
+};
+
+// 读取任务 -> 合并任务的队列长度，队列满时读取任务等待，压力传回内核缓冲区 (计入 lost)
+const MERGE_QUEUE_LEN: usize = 65536;
+
+// perf 模式每个 CPU 的缓冲区页数，需要能容纳带满载荷 (MAX_PAYLOAD_LEN) 的记录
+const PERF_PAGE_COUNT: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportKind {
+    Auto,    // 内核支持 RingBuf 时用 ringbuf，否则 perf
+    Ringbuf, // BPF_MAP_TYPE_RINGBUF
+    Perf,    // BPF_MAP_TYPE_PERF_EVENT_ARRAY
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransportConfig {
+    pub kind: TransportKind,
+    pub ringbuf_size_kb: u32, // RingBuf 大小，会被向上取整为页大小的 2 的幂倍数
+    pub stats_interval_secs: u64, // 0 表示不打印统计
+    pub reorder_window_ms: u64, // 事件按内核时间戳排序的等待窗口，0 表示只在已到达的记录之间排序
+}
+
+impl Default for TransportConfig {
+    fn default() -> Self {
+        TransportConfig {
+            kind: TransportKind::Auto,
+            ringbuf_size_kb: 8192,
+            stats_interval_secs: 0,
+            reorder_window_ms: 10,
+        }
+    }
+}
+
+impl TransportKind {
+    // 把 Auto 解析为具体模式: BPF RingBuf 从 5.8 开始提供
+    pub fn resolve(self) -> TransportKind {
+        if self != TransportKind::Auto {
+            return self;
+        }
+        match KernelVersion::current() {
+            Ok(version) if version < KernelVersion::new(5, 8, 0) => TransportKind::Perf,
+            Ok(_) => TransportKind::Ringbuf,
+            Err(e) => {
+                warn!("Failed to detect kernel version ({}), assuming RingBuf", e);
+                TransportKind::Ringbuf
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Stats {
+    events: AtomicU64,
+    bytes: AtomicU64,
+    lost: AtomicU64, // perf 模式: 读取时报告的丢失数；ringbuf 模式由内核 DROPPED 计数
+    late: AtomicU64, // 超出重排序窗口才到达的事件
+}
+
+impl Stats {
+    fn record(&self, len: usize) {
+        self.events.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(len as u64, Ordering::Relaxed);
+    }
+}
+
+// 接管 EVENTS map 并启动读取任务，事件交给 handler 处理
+pub fn spawn(
+    bpf: &mut Ebpf,
+    kind: TransportKind,
+    config: &TransportConfig,
+    handler: Arc<EventHandler>,
+) -> anyhow::Result<()> {
+    let stats = Arc::new(Stats::default());
+    let (tx, rx) = mpsc::channel(MERGE_QUEUE_LEN);
+    let window = Duration::from_millis(config.reorder_window_ms);
+    task::spawn(merge(rx, handler, window, stats.clone()));
+
+    let events = bpf.take_map("EVENTS").context("EVENTS map not found")?;
+    match kind {
+        TransportKind::Perf => {
+            let mut events: AsyncPerfEventArray<_> = events.try_into()?;
+            let cpus = online_cpus()
+                .map_err(|(_, e)| anyhow::anyhow!("Failed to get online cpus: {}", e))?;
+            for cpu_id in cpus {
+                let mut buf = events.open(cpu_id, Some(PERF_PAGE_COUNT))?;
+                let tx = tx.clone();
+                let stats = stats.clone();
+                task::spawn(async move {
+                    let mut buffers = (0..16)
+                        .map(|_| BytesMut::with_capacity(MAX_RECORD_LEN))
+                        .collect::<Vec<_>>();
+                    loop {
+                        let events = match buf.read_events(&mut buffers).await {
+                            Ok(events) => events,
+                            Err(e) => {
+                                warn!("[Transport] perf buffer on cpu {} failed: {}", cpu_id, e);
+                                return;
+                            }
+                        };
+                        stats.lost.fetch_add(events.lost as u64, Ordering::Relaxed);
+                        for buffer in buffers.iter().take(events.read) {
+                            stats.record(buffer.len());
+                            if tx.send(buffer.to_vec()).await.is_err() {
+                                return;
+                            }
+                        }
+                    }
+                });
+            }
+        }
+        _ => {
+            let ring = RingBuf::try_from(events)?;
+            let mut fd = AsyncFd::new(ring).context("Failed to poll RingBuf")?;
+            let stats = stats.clone();
+            task::spawn(async move {
+                loop {
+                    let mut guard = match fd.readable_mut().await {
+                        Ok(guard) => guard,
+                        Err(e) => {
+                            warn!("[Transport] RingBuf poll failed: {}", e);
+                            return;
+                        }
+                    };
+                    let ring = guard.get_inner_mut();
+                    while let Some(item) = ring.next() {
+                        stats.record(item.len());
+                        let record = item.to_vec();
+                        drop(item);
+                        if tx.send(record).await.is_err() {
+                            return;
+                        }
+                    }
+                    guard.clear_ready();
+                }
+            });
+        }
+    }
+
+    if config.stats_interval_secs > 0 {
+        let dropped = match kind {
+            TransportKind::Perf => None,
+            _ => Some(PerCpuArray::<_, u64>::try_from(
+                bpf.take_map("DROPPED").context("DROPPED map not found")?,
+            )?),
+        };
+        let interval = Duration::from_secs(config.stats_interval_secs);
+        task::spawn(report(kind, stats, dropped, interval));
+    }
+    Ok(())
+}
+
+// 按内核时间戳合并所有读取任务的记录，依次交给 handler
+async fn merge(
+    mut rx: mpsc::Receiver<Vec<u8>>,
+    handler: Arc<EventHandler>,
+    window: Duration,
+    stats: Arc<Stats>,
+) {
+    let mut buffer = ReorderBuffer::new(window.as_nanos() as u64);
+    // 没有新记录到达时也要按时放行缓冲区中的记录
+    let mut ticker = interval(window.max(Duration::from_millis(1)));
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            record = rx.recv() => {
+                let Some(record) = record else {
+                    return;
+                };
+                if !buffer.push(record) {
+                    stats.late.store(buffer.late(), Ordering::Relaxed);
+                }
+            }
+            _ = ticker.tick() => {}
+        }
+        let now = clock::monotonic_ns();
+        while let Some(record) = buffer.pop_ready(now) {
+            handler.handle(&record);
+        }
+    }
+}
+
+// 周期性打印: 事件数/s、字节数/s、累计丢失、Agent 进程 CPU 占用 (用户态 + 内核态)
+async fn report(
+    kind: TransportKind,
+    stats: Arc<Stats>,
+    dropped: Option<PerCpuArray<MapData, u64>>,
+    interval: Duration,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.tick().await;
+    let mut last = Instant::now();
+    let mut last_cpu = cpu_time();
+    let (mut last_events, mut last_bytes) = (0, 0);
+    loop {
+        ticker.tick().await;
+        let elapsed = last.elapsed().as_secs_f64();
+        let cpu = cpu_time();
+        let events = stats.events.load(Ordering::Relaxed);
+        let bytes = stats.bytes.load(Ordering::Relaxed);
+        let lost = match &dropped {
+            Some(map) => map
+                .get(&0, 0)
+                .map(|values| values.iter().sum())
+                .unwrap_or(0),
+            None => stats.lost.load(Ordering::Relaxed),
+        };
+        info!(
+            "[Transport] {:?}: {:.0} events/s, {:.2} MB/s, lost {}, late {}, agent CPU {:.1}%",
+            kind,
+            (events - last_events) as f64 / elapsed,
+            (bytes - last_bytes) as f64 / elapsed / 1024.0 / 1024.0,
+            lost,
+            stats.late.load(Ordering::Relaxed),
+            (cpu - last_cpu).as_secs_f64() / elapsed * 100.0
+        );
+        last = Instant::now();
+        last_cpu = cpu;
+        last_events = events;
+        last_bytes = bytes;
+    }
+}
+
+// 本进程累计 CPU 时间
+fn cpu_time() -> Duration {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) };
+    let tv = |t: libc::timeval| Duration::new(t.tv_sec as u64, t.tv_usec as u32 * 1000);
+    tv(usage.ru_utime) + tv(usage.ru_stime)
+}